@@ -0,0 +1 @@
+ALTER TABLE "l_visits_nurses" DROP COLUMN IF EXISTS "conflicts";
@@ -0,0 +1 @@
+ALTER TABLE "l_visits_nurses" ADD COLUMN "conflicts" text[] NOT NULL DEFAULT '{}';
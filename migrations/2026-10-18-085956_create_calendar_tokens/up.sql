@@ -0,0 +1,6 @@
+-- Only the SHA-256 hash of a calendar token is stored
+CREATE TABLE "calendar_tokens" (
+  "id_user" bigint PRIMARY KEY REFERENCES "users" ("id") ON DELETE CASCADE,
+  "token" text UNIQUE NOT NULL,
+  "created_at" timestamp NOT NULL DEFAULT now()
+);
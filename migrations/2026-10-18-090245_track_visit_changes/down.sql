@@ -0,0 +1,16 @@
+DROP TRIGGER IF EXISTS visit_nurse_unassigned ON "l_visits_nurses";
+DROP TRIGGER IF EXISTS visit_nurse_assigned ON "l_visits_nurses";
+DROP TRIGGER IF EXISTS mission_updated ON "missions";
+DROP TRIGGER IF EXISTS patient_deleted ON "patients";
+DROP TRIGGER IF EXISTS mission_deleted ON "missions";
+DROP TRIGGER IF EXISTS visit_deleted ON "visits";
+DROP TRIGGER IF EXISTS visit_updated ON "visits";
+DROP FUNCTION IF EXISTS visit_nurse_unassigned;
+DROP FUNCTION IF EXISTS visit_nurse_assigned;
+DROP FUNCTION IF EXISTS mission_updated;
+DROP FUNCTION IF EXISTS patient_deleted;
+DROP FUNCTION IF EXISTS mission_deleted;
+DROP FUNCTION IF EXISTS visit_deleted;
+DROP FUNCTION IF EXISTS visit_updated;
+DROP TABLE IF EXISTS "cancelled_visits";
+ALTER TABLE "visits" DROP COLUMN IF EXISTS "sequence", DROP COLUMN IF EXISTS "updated_at";
@@ -0,0 +1,124 @@
+-- Revision of the visits, for calendar clients to pick up changes
+ALTER TABLE "visits"
+  ADD COLUMN "sequence" int NOT NULL DEFAULT 0,
+  ADD COLUMN "updated_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+
+-- Visits removed from a calendar, kept for some time so calendar clients can remove them
+CREATE TABLE "cancelled_visits" (
+  "id" bigserial PRIMARY KEY,
+  "id_visit" bigint NOT NULL,
+  -- Nurse the visit was removed from, NULL when the visit itself was deleted
+  "id_nurse" bigint REFERENCES "nurses" ("id") ON DELETE CASCADE,
+  "start" timestamp NOT NULL,
+  "end" timestamp NOT NULL,
+  "summary" text NOT NULL,
+  "sequence" int NOT NULL,
+  "id_zone" bigint REFERENCES "zones" ("id") ON DELETE CASCADE,
+  "id_center" bigint REFERENCES "centers" ("id") ON DELETE CASCADE,
+  "cancelled_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX ON "cancelled_visits" ("cancelled_at");
+
+CREATE FUNCTION visit_updated() RETURNS trigger AS $$
+BEGIN
+  NEW.updated_at := now() AT TIME ZONE 'utc';
+  IF NEW."start" <> OLD."start" OR NEW."end" <> OLD."end" OR NEW.id_mission <> OLD.id_mission THEN
+    NEW.sequence := GREATEST(NEW.sequence, OLD.sequence + 1);
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER visit_updated BEFORE UPDATE ON "visits"
+  FOR EACH ROW EXECUTE FUNCTION visit_updated();
+
+-- Records the cancellation of a visit for its center and each of its nurses
+CREATE FUNCTION visit_deleted() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO "cancelled_visits" ("id_visit", "id_nurse", "start", "end", "summary", "sequence", "id_zone", "id_center")
+  SELECT OLD.id, n.id_nurse, OLD."start", OLD."end", mt.name, OLD.sequence + 1, a.id_zone, z.id_center
+  FROM "missions" m
+  JOIN "mission_types" mt ON mt.id = m.id_mission_type
+  JOIN "patients" p ON p.id = m.id_patient
+  JOIN "addresses" a ON a.id = p.id_address
+  JOIN "zones" z ON z.id = a.id_zone
+  CROSS JOIN (
+    SELECT NULL::bigint AS id_nurse
+    UNION ALL
+    SELECT id_nurse FROM "l_visits_nurses" WHERE id_visit = OLD.id
+  ) n
+  WHERE m.id = OLD.id_mission;
+  RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER visit_deleted BEFORE DELETE ON "visits"
+  FOR EACH ROW EXECUTE FUNCTION visit_deleted();
+
+-- Deletes the visits before their mission, so their cancellation can still be located
+CREATE FUNCTION mission_deleted() RETURNS trigger AS $$
+BEGIN
+  DELETE FROM "visits" WHERE id_mission = OLD.id;
+  RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER mission_deleted BEFORE DELETE ON "missions"
+  FOR EACH ROW EXECUTE FUNCTION mission_deleted();
+
+CREATE FUNCTION patient_deleted() RETURNS trigger AS $$
+BEGIN
+  DELETE FROM "missions" WHERE id_patient = OLD.id;
+  RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER patient_deleted BEFORE DELETE ON "patients"
+  FOR EACH ROW EXECUTE FUNCTION patient_deleted();
+
+-- Changing the mission changes the description of its visits
+CREATE FUNCTION mission_updated() RETURNS trigger AS $$
+BEGIN
+  UPDATE "visits" SET sequence = sequence + 1 WHERE id_mission = NEW.id;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER mission_updated AFTER UPDATE ON "missions"
+  FOR EACH ROW WHEN (OLD.* IS DISTINCT FROM NEW.*) EXECUTE FUNCTION mission_updated();
+
+-- Changing the nurses of a visit changes its attendees
+CREATE FUNCTION visit_nurse_assigned() RETURNS trigger AS $$
+BEGIN
+  UPDATE "visits" SET sequence = sequence + 1 WHERE id = NEW.id_visit;
+  DELETE FROM "cancelled_visits" WHERE id_visit = NEW.id_visit AND id_nurse = NEW.id_nurse;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER visit_nurse_assigned AFTER INSERT ON "l_visits_nurses"
+  FOR EACH ROW EXECUTE FUNCTION visit_nurse_assigned();
+
+-- When the visit is deleted, its cancellation is already recorded and it is not found anymore
+CREATE FUNCTION visit_nurse_unassigned() RETURNS trigger AS $$
+BEGIN
+  WITH v AS (
+    UPDATE "visits" SET sequence = sequence + 1 WHERE id = OLD.id_visit RETURNING *
+  )
+  INSERT INTO "cancelled_visits" ("id_visit", "id_nurse", "start", "end", "summary", "sequence", "id_zone", "id_center")
+  SELECT v.id, OLD.id_nurse, v."start", v."end", mt.name, v.sequence, a.id_zone, z.id_center
+  FROM v
+  JOIN "missions" m ON m.id = v.id_mission
+  JOIN "mission_types" mt ON mt.id = m.id_mission_type
+  JOIN "patients" p ON p.id = m.id_patient
+  JOIN "addresses" a ON a.id = p.id_address
+  JOIN "zones" z ON z.id = a.id_zone
+  -- The nurse itself may be the one being deleted
+  WHERE EXISTS (SELECT 1 FROM "nurses" WHERE id = OLD.id_nurse);
+  RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER visit_nurse_unassigned AFTER DELETE ON "l_visits_nurses"
+  FOR EACH ROW EXECUTE FUNCTION visit_nurse_unassigned();
@@ -0,0 +1,4 @@
+DROP TRIGGER IF EXISTS "user_password_changed" ON "users";
+DROP FUNCTION IF EXISTS revoke_user_sessions;
+DROP TABLE IF EXISTS "refresh_tokens";
+DROP TABLE IF EXISTS "sessions";
@@ -0,0 +1,34 @@
+CREATE TABLE "sessions" (
+  "id" bigserial PRIMARY KEY,
+  "id_user" bigint NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+  "created_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+  "expires_at" timestamp NOT NULL,
+  "revoked_at" timestamp
+);
+
+CREATE INDEX ON "sessions" ("id_user");
+
+-- Only the SHA-256 hash of a refresh token is stored
+CREATE TABLE "refresh_tokens" (
+  "token" text PRIMARY KEY,
+  "id_session" bigint NOT NULL REFERENCES "sessions" ("id") ON DELETE CASCADE,
+  "created_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+  "used_at" timestamp
+);
+
+CREATE INDEX ON "refresh_tokens" ("id_session");
+
+-- Changing the password logs the user out of every device
+CREATE FUNCTION revoke_user_sessions() RETURNS trigger AS $$
+BEGIN
+  UPDATE "sessions" SET "revoked_at" = now() AT TIME ZONE 'utc'
+  WHERE "id_user" = NEW."id" AND "revoked_at" IS NULL;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "user_password_changed"
+AFTER UPDATE OF "password" ON "users"
+FOR EACH ROW
+WHEN (OLD."password" IS DISTINCT FROM NEW."password")
+EXECUTE FUNCTION revoke_user_sessions();
@@ -0,0 +1,9 @@
+-- Only the SHA-256 hash of a reset token is stored
+CREATE TABLE "password_reset_tokens" (
+  "token" text PRIMARY KEY,
+  "id_user" bigint NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+  "created_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+  "expires_at" timestamp NOT NULL
+);
+
+CREATE INDEX ON "password_reset_tokens" ("id_user");
@@ -0,0 +1,8 @@
+DROP TRIGGER IF EXISTS "manager_created" ON "managers";
+DROP TRIGGER IF EXISTS "nurse_created" ON "nurses";
+DROP TRIGGER IF EXISTS "patient_created" ON "patients";
+DROP FUNCTION IF EXISTS assign_default_role;
+DROP TABLE IF EXISTS "l_users_roles";
+DROP TABLE IF EXISTS "l_roles_permissions";
+DROP TABLE IF EXISTS "roles";
+DROP TABLE IF EXISTS "permissions";
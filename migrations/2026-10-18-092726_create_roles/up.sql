@@ -0,0 +1,91 @@
+CREATE TABLE "permissions" (
+  "name" text PRIMARY KEY,
+  "description" text NOT NULL
+);
+
+INSERT INTO "permissions" ("name", "description") VALUES
+  ('centers:read', 'Read centers'),
+  ('zones:read', 'Read zones'),
+  ('zones:write', 'Create, update and delete zones'),
+  ('managers:read', 'Read managers'),
+  ('managers:write', 'Create and delete managers, reset their password'),
+  ('nurses:read', 'Read nurses, their availabilities and schedule'),
+  ('nurses:write', 'Create, update and delete nurses, manage their skills and tokens'),
+  ('patients:read', 'Read patients'),
+  ('patients:write', 'Create, update and delete patients'),
+  ('missions:read', 'Read missions'),
+  ('missions:write', 'Create, update and delete missions'),
+  ('mission_types:read', 'Read mission types'),
+  ('mission_types:write', 'Create, update and delete mission types'),
+  ('skills:read', 'Read skills'),
+  ('skills:write', 'Create, update and delete skills'),
+  ('visits:read', 'Read visits'),
+  ('visits:write', 'Create, update and delete visits, generate them and assign nurses'),
+  ('reports:read', 'Read the reports of the visits'),
+  ('reports:write', 'Write reports for the visits of the nurse'),
+  ('planning:read', 'Read the planning suggestions'),
+  ('planning:write', 'Apply the planning suggestions'),
+  ('availabilities:write', 'Manage the availabilities of nurses'),
+  ('roles:read', 'Read roles and the roles of users'),
+  ('roles:write', 'Create, update and delete roles, assign them to users');
+
+-- Roles without center are available to every center and cannot be modified from the API
+CREATE TABLE "roles" (
+  "id" bigserial PRIMARY KEY,
+  "name" text NOT NULL,
+  "description" text,
+  "id_center" bigint REFERENCES "centers" ("id") ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX ON "roles" (COALESCE("id_center", 0), "name");
+
+CREATE TABLE "l_roles_permissions" (
+  "id_role" bigint NOT NULL REFERENCES "roles" ("id") ON DELETE CASCADE,
+  "permission" text NOT NULL REFERENCES "permissions" ("name") ON DELETE CASCADE,
+  PRIMARY KEY ("id_role", "permission")
+);
+
+CREATE TABLE "l_users_roles" (
+  "id_user" bigint NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+  "id_role" bigint NOT NULL REFERENCES "roles" ("id") ON DELETE CASCADE,
+  PRIMARY KEY ("id_user", "id_role")
+);
+
+INSERT INTO "roles" ("name", "description") VALUES
+  ('manager', 'Manages a center'),
+  ('nurse', 'Visits patients'),
+  ('patient', 'Receives care');
+
+INSERT INTO "l_roles_permissions" ("id_role", "permission")
+SELECT "roles"."id", "permissions"."name" FROM "roles", "permissions"
+WHERE "roles"."name" = 'manager' AND "permissions"."name" <> 'reports:write';
+
+INSERT INTO "l_roles_permissions" ("id_role", "permission")
+SELECT "id", unnest(ARRAY['skills:read', 'reports:write']) FROM "roles" WHERE "name" = 'nurse';
+
+-- Users are given the default role of their kind
+CREATE FUNCTION assign_default_role() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO "l_users_roles" ("id_user", "id_role")
+  SELECT NEW."id_user", "id" FROM "roles" WHERE "id_center" IS NULL AND "name" = TG_ARGV[0]
+  ON CONFLICT DO NOTHING;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "manager_created" AFTER INSERT ON "managers"
+FOR EACH ROW EXECUTE FUNCTION assign_default_role('manager');
+CREATE TRIGGER "nurse_created" AFTER INSERT ON "nurses"
+FOR EACH ROW EXECUTE FUNCTION assign_default_role('nurse');
+CREATE TRIGGER "patient_created" AFTER INSERT ON "patients"
+FOR EACH ROW EXECUTE FUNCTION assign_default_role('patient');
+
+INSERT INTO "l_users_roles" ("id_user", "id_role")
+SELECT "managers"."id_user", "roles"."id" FROM "managers", "roles"
+WHERE "roles"."id_center" IS NULL AND "roles"."name" = 'manager';
+INSERT INTO "l_users_roles" ("id_user", "id_role")
+SELECT "nurses"."id_user", "roles"."id" FROM "nurses", "roles"
+WHERE "roles"."id_center" IS NULL AND "roles"."name" = 'nurse';
+INSERT INTO "l_users_roles" ("id_user", "id_role")
+SELECT "patients"."id_user", "roles"."id" FROM "patients", "roles"
+WHERE "roles"."id_center" IS NULL AND "roles"."name" = 'patient';
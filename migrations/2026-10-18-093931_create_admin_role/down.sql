@@ -0,0 +1,3 @@
+DELETE FROM "roles" WHERE "name" = 'admin' AND "id_center" IS NULL;
+
+DELETE FROM "permissions" WHERE "name" = 'centers:write';
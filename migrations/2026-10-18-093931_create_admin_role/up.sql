@@ -0,0 +1,11 @@
+INSERT INTO "permissions" ("name", "description") VALUES
+  ('centers:write', 'Create, update and delete every center, create and move their managers');
+
+-- Administrators are managers given this role, the first one has to be promoted by hand
+INSERT INTO "roles" ("name", "description") VALUES
+  ('admin', 'Administrates every center');
+
+INSERT INTO "l_roles_permissions" ("id_role", "permission")
+SELECT "roles"."id", "permissions"."name" FROM "roles", "permissions"
+WHERE "roles"."name" = 'admin' AND "roles"."id_center" IS NULL
+  AND "permissions"."name" <> 'reports:write';
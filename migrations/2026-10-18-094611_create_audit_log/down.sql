@@ -0,0 +1,17 @@
+DO $$
+DECLARE
+  t text;
+BEGIN
+  FOR t IN SELECT DISTINCT event_object_table FROM information_schema.triggers
+           WHERE trigger_name = 'audit' LOOP
+    EXECUTE format('DROP TRIGGER "audit" ON %I', t);
+  END LOOP;
+END;
+$$;
+
+DROP FUNCTION audit_changes;
+DROP FUNCTION audit_center;
+
+DELETE FROM "permissions" WHERE "name" = 'audit:read';
+
+DROP TABLE "audit_log";
@@ -0,0 +1,139 @@
+-- Changes made to the records. The actor is given by the `audit.*` settings of the transaction,
+-- it is NULL for the changes made outside of the API.
+CREATE TABLE "audit_log" (
+  "id" bigserial PRIMARY KEY,
+  -- Not a reference, entries outlive the users
+  "id_user" bigint,
+  "role" text,
+  -- Center of the user, which may differ from the one of the record for administrators
+  "id_actor_center" bigint,
+  -- Center of the changed record, NULL for shared records or once its parents are deleted
+  "id_center" bigint,
+  "table_name" text NOT NULL,
+  "id_record" bigint,
+  "action" text NOT NULL CHECK ("action" IN ('insert', 'update', 'delete')),
+  -- Changed columns only
+  "before" jsonb,
+  "after" jsonb,
+  -- Generated by the server for each request
+  "request_id" text NOT NULL,
+  -- Given by the client in the `X-Request-Id` header
+  "correlation_id" text,
+  "created_at" timestamp NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX ON "audit_log" ("table_name", "id_record");
+CREATE INDEX ON "audit_log" ("id_user");
+CREATE INDEX ON "audit_log" ("id_center", "created_at");
+CREATE INDEX ON "audit_log" ("id_actor_center", "created_at");
+
+INSERT INTO "permissions" ("name", "description") VALUES
+  ('audit:read', 'Read the changes made in the center');
+
+INSERT INTO "l_roles_permissions" ("id_role", "permission")
+SELECT "id", 'audit:read' FROM "roles"
+WHERE "id_center" IS NULL AND "name" IN ('manager', 'admin');
+
+-- Finds the center of a record of the given table from its parents
+CREATE FUNCTION audit_center(tbl text, rec jsonb) RETURNS bigint AS $$
+DECLARE
+  parent text;
+  parent_key text;
+  uid bigint;
+  res bigint;
+BEGIN
+  CASE tbl
+    WHEN 'centers' THEN RETURN (rec ->> 'id')::bigint;
+    WHEN 'zones', 'managers', 'roles' THEN RETURN (rec ->> 'id_center')::bigint;
+    WHEN 'addresses' THEN parent := 'zones'; parent_key := 'id_zone';
+    WHEN 'patients', 'nurses' THEN parent := 'addresses'; parent_key := 'id_address';
+    WHEN 'missions' THEN parent := 'patients'; parent_key := 'id_patient';
+    WHEN 'visits' THEN parent := 'missions'; parent_key := 'id_mission';
+    WHEN 'availabilities', 'l_nurses_skills' THEN parent := 'nurses'; parent_key := 'id_nurse';
+    WHEN 'l_visits_nurses', 'report_revisions' THEN parent := 'visits'; parent_key := 'id_visit';
+    WHEN 'l_roles_permissions' THEN parent := 'roles'; parent_key := 'id_role';
+    WHEN 'users', 'l_users_roles' THEN
+      uid := (rec ->> CASE tbl WHEN 'users' THEN 'id' ELSE 'id_user' END)::bigint;
+      -- A user is a manager, a nurse or a patient
+      RETURN COALESCE(
+        (SELECT m.id_center FROM "managers" m WHERE m.id_user = uid),
+        (SELECT audit_center('nurses', to_jsonb(n)) FROM "nurses" n WHERE n.id_user = uid),
+        (SELECT audit_center('patients', to_jsonb(p)) FROM "patients" p WHERE p.id_user = uid)
+      );
+    ELSE RETURN NULL;
+  END CASE;
+
+  EXECUTE format('SELECT audit_center(%L, to_jsonb(t)) FROM %I t WHERE t.id = $1', parent, parent)
+  INTO res USING (rec ->> parent_key)::bigint;
+
+  RETURN res;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+-- The argument is the column identifying the record
+CREATE FUNCTION audit_changes() RETURNS trigger AS $$
+DECLARE
+  old_row jsonb := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+  new_row jsonb := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    SELECT jsonb_object_agg(o.key, o.value), jsonb_object_agg(n.key, n.value)
+    INTO old_row, new_row
+    FROM jsonb_each(old_row) o JOIN jsonb_each(new_row) n ON o.key = n.key
+    WHERE o.value IS DISTINCT FROM n.value;
+
+    IF old_row IS NULL THEN
+      RETURN NULL;
+    END IF;
+  END IF;
+
+  -- Password hashes are secret, only their change is recorded
+  IF old_row ->> 'password' IS NOT NULL THEN
+    old_row := old_row || '{"password": "********"}';
+  END IF;
+  IF new_row ->> 'password' IS NOT NULL THEN
+    new_row := new_row || '{"password": "********"}';
+  END IF;
+
+  INSERT INTO "audit_log"
+    ("id_user", "role", "id_actor_center", "id_center", "table_name", "id_record", "action",
+     "before", "after", "request_id", "correlation_id")
+  VALUES (
+    NULLIF(current_setting('audit.id_user', true), '')::bigint,
+    NULLIF(current_setting('audit.role', true), ''),
+    NULLIF(current_setting('audit.id_center', true), '')::bigint,
+    audit_center(TG_TABLE_NAME, COALESCE(to_jsonb(NEW), to_jsonb(OLD))),
+    TG_TABLE_NAME,
+    (COALESCE(to_jsonb(NEW), to_jsonb(OLD)) ->> TG_ARGV[0])::bigint,
+    lower(TG_OP),
+    old_row,
+    new_row,
+    COALESCE(NULLIF(current_setting('audit.request_id', true), ''), txid_current()::text),
+    NULLIF(current_setting('audit.correlation_id', true), '')
+  );
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DO $$
+DECLARE
+  audited text[][] := ARRAY[
+    ['addresses', 'id'], ['availabilities', 'id'], ['centers', 'id'], ['managers', 'id'],
+    ['mission_types', 'id'], ['missions', 'id'], ['nurses', 'id'], ['patients', 'id'],
+    ['roles', 'id'], ['skills', 'id'], ['users', 'id'], ['visits', 'id'], ['zones', 'id'],
+    ['l_missions_skills', 'id_mission_type'], ['l_nurses_skills', 'id_nurse'],
+    ['l_roles_permissions', 'id_role'], ['l_users_roles', 'id_user'],
+    ['l_visits_nurses', 'id_visit']
+  ];
+  i int;
+BEGIN
+  FOR i IN 1 .. array_length(audited, 1) LOOP
+    EXECUTE format(
+      'CREATE TRIGGER "audit" AFTER INSERT OR UPDATE OR DELETE ON %I
+       FOR EACH ROW EXECUTE FUNCTION audit_changes(%L)',
+      audited[i][1], audited[i][2]
+    );
+  END LOOP;
+END;
+$$;
@@ -0,0 +1,7 @@
+DELETE FROM "permissions" WHERE "name" = 'reports:sign';
+
+ALTER TABLE "l_visits_nurses"
+  DROP COLUMN "signed_by",
+  DROP COLUMN "signed_at";
+
+DROP TABLE "report_revisions";
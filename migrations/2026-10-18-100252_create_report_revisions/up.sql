@@ -0,0 +1,35 @@
+-- Every version of a report, the current one is kept in "l_visits_nurses"."report"
+CREATE TABLE "report_revisions" (
+  "id" bigserial PRIMARY KEY,
+  "id_visit" bigint NOT NULL,
+  "id_nurse" bigint NOT NULL,
+  "id_author" bigint REFERENCES "users" ("id") ON DELETE SET NULL,
+  "content" text NOT NULL,
+  -- Addenda are added to a locked report without changing it
+  "addendum" boolean NOT NULL DEFAULT false,
+  "created_at" timestamp NOT NULL DEFAULT now(),
+  -- The history of a report is kept, the nurse cannot be unassigned once it wrote one
+  FOREIGN KEY ("id_visit", "id_nurse") REFERENCES "l_visits_nurses" ("id_visit", "id_nurse") ON DELETE RESTRICT
+);
+
+CREATE INDEX ON "report_revisions" ("id_visit", "id_nurse", "created_at");
+
+ALTER TABLE "l_visits_nurses"
+  ADD COLUMN "signed_by" bigint REFERENCES "users" ("id") ON DELETE SET NULL,
+  ADD COLUMN "signed_at" timestamp;
+
+-- Existing reports become the first revision, written by their nurse
+INSERT INTO "report_revisions" ("id_visit", "id_nurse", "id_author", "content")
+SELECT "l_visits_nurses"."id_visit", "l_visits_nurses"."id_nurse", "nurses"."id_user", "l_visits_nurses"."report"
+FROM "l_visits_nurses" JOIN "nurses" ON "nurses"."id" = "l_visits_nurses"."id_nurse"
+WHERE "l_visits_nurses"."report" IS NOT NULL AND "l_visits_nurses"."report" <> '';
+
+CREATE TRIGGER "audit" AFTER INSERT OR UPDATE OR DELETE ON "report_revisions"
+FOR EACH ROW EXECUTE FUNCTION audit_changes('id');
+
+INSERT INTO "permissions" ("name", "description") VALUES
+  ('reports:sign', 'Sign off the reports of the center');
+
+INSERT INTO "l_roles_permissions" ("id_role", "permission")
+SELECT "id", 'reports:sign' FROM "roles"
+WHERE "id_center" IS NULL AND "name" IN ('manager', 'admin');
@@ -0,0 +1,5 @@
+ALTER TABLE "report_revisions" DROP COLUMN "data";
+
+ALTER TABLE "l_visits_nurses" DROP COLUMN "report_data";
+
+ALTER TABLE "mission_types" DROP COLUMN "report_template";
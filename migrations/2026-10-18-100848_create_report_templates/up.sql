@@ -0,0 +1,7 @@
+-- Vital signs and acts the reports of a mission type must record, see "src/reports.rs"
+ALTER TABLE "mission_types" ADD COLUMN "report_template" jsonb;
+
+-- Structured part of a report, alongside its free text
+ALTER TABLE "l_visits_nurses" ADD COLUMN "report_data" jsonb;
+
+ALTER TABLE "report_revisions" ADD COLUMN "data" jsonb;
@@ -0,0 +1,16 @@
+CREATE OR REPLACE FUNCTION visit_updated() RETURNS trigger AS $$
+BEGIN
+  NEW.updated_at := now() AT TIME ZONE 'utc';
+  IF NEW."start" <> OLD."start" OR NEW."end" <> OLD."end" OR NEW.id_mission <> OLD.id_mission THEN
+    NEW.sequence := GREATEST(NEW.sequence, OLD.sequence + 1);
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE "visits" DROP COLUMN "cancelled_at";
+
+ALTER TABLE "l_visits_nurses"
+  DROP CONSTRAINT "left_after_arrival",
+  DROP COLUMN "arrived_at",
+  DROP COLUMN "left_at";
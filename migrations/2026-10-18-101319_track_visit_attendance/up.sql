@@ -0,0 +1,19 @@
+-- Actual times of each nurse, in the same local time as the visits
+ALTER TABLE "l_visits_nurses"
+  ADD COLUMN "arrived_at" timestamp,
+  ADD COLUMN "left_at" timestamp,
+  ADD CONSTRAINT "left_after_arrival" CHECK ("left_at" IS NULL OR "left_at" >= "arrived_at");
+
+-- A cancelled visit is kept, unlike a deleted one, to reconcile planned and performed care
+ALTER TABLE "visits" ADD COLUMN "cancelled_at" timestamp;
+
+CREATE OR REPLACE FUNCTION visit_updated() RETURNS trigger AS $$
+BEGIN
+  NEW.updated_at := now() AT TIME ZONE 'utc';
+  IF NEW."start" <> OLD."start" OR NEW."end" <> OLD."end" OR NEW.id_mission <> OLD.id_mission
+     OR NEW.cancelled_at IS DISTINCT FROM OLD.cancelled_at THEN
+    NEW.sequence := GREATEST(NEW.sequence, OLD.sequence + 1);
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+ALTER TABLE "addresses"
+  DROP CONSTRAINT "complete_coordinates",
+  DROP COLUMN "latitude",
+  DROP COLUMN "longitude";
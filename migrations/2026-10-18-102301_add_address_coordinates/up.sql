@@ -0,0 +1,5 @@
+-- WGS 84 coordinates, set by hand or geocoded, see "src/geo.rs"
+ALTER TABLE "addresses"
+  ADD COLUMN "latitude" double precision CHECK ("latitude" BETWEEN -90 AND 90),
+  ADD COLUMN "longitude" double precision CHECK ("longitude" BETWEEN -180 AND 180),
+  ADD CONSTRAINT "complete_coordinates" CHECK (("latitude" IS NULL) = ("longitude" IS NULL));
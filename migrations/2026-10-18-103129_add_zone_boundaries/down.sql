@@ -0,0 +1,3 @@
+ALTER TABLE "zones"
+  DROP COLUMN "boundary",
+  DROP COLUMN "postcodes";
@@ -0,0 +1,4 @@
+-- Area covered by a zone, used to assign addresses to zones, see "src/zoning.rs"
+ALTER TABLE "zones"
+  ADD COLUMN "boundary" jsonb,
+  ADD COLUMN "postcodes" text[] NOT NULL DEFAULT '{}';
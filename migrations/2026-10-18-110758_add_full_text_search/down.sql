@@ -0,0 +1,57 @@
+-- Restores the audit of every column
+CREATE OR REPLACE FUNCTION audit_changes() RETURNS trigger AS $$
+DECLARE
+  old_row jsonb := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+  new_row jsonb := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    SELECT jsonb_object_agg(o.key, o.value), jsonb_object_agg(n.key, n.value)
+    INTO old_row, new_row
+    FROM jsonb_each(old_row) o JOIN jsonb_each(new_row) n ON o.key = n.key
+    WHERE o.value IS DISTINCT FROM n.value;
+
+    IF old_row IS NULL THEN
+      RETURN NULL;
+    END IF;
+  END IF;
+
+  -- Password hashes are secret, only their change is recorded
+  IF old_row ->> 'password' IS NOT NULL THEN
+    old_row := old_row || '{"password": "********"}';
+  END IF;
+  IF new_row ->> 'password' IS NOT NULL THEN
+    new_row := new_row || '{"password": "********"}';
+  END IF;
+
+  INSERT INTO "audit_log"
+    ("id_user", "role", "id_actor_center", "id_center", "table_name", "id_record", "action",
+     "before", "after", "request_id", "correlation_id")
+  VALUES (
+    NULLIF(current_setting('audit.id_user', true), '')::bigint,
+    NULLIF(current_setting('audit.role', true), ''),
+    NULLIF(current_setting('audit.id_center', true), '')::bigint,
+    audit_center(TG_TABLE_NAME, COALESCE(to_jsonb(NEW), to_jsonb(OLD))),
+    TG_TABLE_NAME,
+    (COALESCE(to_jsonb(NEW), to_jsonb(OLD)) ->> TG_ARGV[0])::bigint,
+    lower(TG_OP),
+    old_row,
+    new_row,
+    COALESCE(NULLIF(current_setting('audit.request_id', true), ''), txid_current()::text),
+    NULLIF(current_setting('audit.correlation_id', true), '')
+  );
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP INDEX "users_search_name_idx";
+DROP FUNCTION search_name;
+DROP FUNCTION search_unaccent;
+DROP FUNCTION search_excerpt;
+DROP FUNCTION search_query;
+
+ALTER TABLE "l_visits_nurses" DROP COLUMN "search";
+ALTER TABLE "missions" DROP COLUMN "search";
+ALTER TABLE "addresses" DROP COLUMN "search";
+ALTER TABLE "users" DROP COLUMN "search";
+DROP TEXT SEARCH CONFIGURATION "french_unaccent";
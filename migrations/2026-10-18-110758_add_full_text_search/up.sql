@@ -0,0 +1,92 @@
+CREATE EXTENSION IF NOT EXISTS "unaccent";
+CREATE EXTENSION IF NOT EXISTS "pg_trgm";
+
+CREATE TEXT SEARCH CONFIGURATION "french_unaccent" (COPY = "french");
+ALTER TEXT SEARCH CONFIGURATION "french_unaccent"
+  ALTER MAPPING FOR "hword", "hword_part", "word" WITH "unaccent", "french_stem";
+
+ALTER TABLE "users" ADD COLUMN "search" tsvector GENERATED ALWAYS AS (
+  to_tsvector('french_unaccent', "fname" || ' ' || "lname" || ' ' || "mail")
+) STORED;
+ALTER TABLE "addresses" ADD COLUMN "search" tsvector GENERATED ALWAYS AS (
+  to_tsvector('french_unaccent', coalesce("number"::text, '') || ' ' || "street_name" || ' ' || "postcode" || ' ' || "city_name")
+) STORED;
+ALTER TABLE "missions" ADD COLUMN "search" tsvector GENERATED ALWAYS AS (
+  to_tsvector('french_unaccent', coalesce("desc", ''))
+) STORED;
+ALTER TABLE "l_visits_nurses" ADD COLUMN "search" tsvector GENERATED ALWAYS AS (
+  to_tsvector('french_unaccent', coalesce("report", ''))
+) STORED;
+
+CREATE INDEX ON "users" USING gin ("search");
+CREATE INDEX ON "addresses" USING gin ("search");
+CREATE INDEX ON "missions" USING gin ("search");
+CREATE INDEX ON "l_visits_nurses" USING gin ("search");
+
+-- Searches written by users, e.g. `"soins palliatifs" -domicile`
+CREATE FUNCTION search_query(text) RETURNS tsquery AS $$
+  SELECT websearch_to_tsquery('french_unaccent', $1)
+$$ LANGUAGE sql STABLE;
+
+-- Excerpt of a document around the matches of a search
+CREATE FUNCTION search_excerpt(text, tsquery) RETURNS text AS $$
+  SELECT ts_headline('french_unaccent', $1, $2, 'MaxWords=20, MinWords=5, StartSel=«, StopSel=»')
+$$ LANGUAGE sql STABLE;
+
+-- Names are compared by trigrams, catching the typos stemming cannot. unaccent is only stable as
+-- its dictionary could change, it is pinned to be used in an index.
+CREATE FUNCTION search_unaccent(text) RETURNS text AS $$
+  SELECT lower(public.unaccent('public.unaccent', $1))
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE;
+
+CREATE FUNCTION search_name(fname text, lname text) RETURNS text AS $$
+  SELECT search_unaccent(fname || ' ' || lname)
+$$ LANGUAGE sql IMMUTABLE PARALLEL SAFE;
+
+CREATE INDEX ON "users" USING gin (search_name("fname", "lname") gin_trgm_ops);
+
+-- The search columns are derived from the others, they are left out of the audit log
+CREATE OR REPLACE FUNCTION audit_changes() RETURNS trigger AS $$
+DECLARE
+  old_row jsonb := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) - 'search' END;
+  new_row jsonb := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) - 'search' END;
+BEGIN
+  IF TG_OP = 'UPDATE' THEN
+    SELECT jsonb_object_agg(o.key, o.value), jsonb_object_agg(n.key, n.value)
+    INTO old_row, new_row
+    FROM jsonb_each(old_row) o JOIN jsonb_each(new_row) n ON o.key = n.key
+    WHERE o.value IS DISTINCT FROM n.value;
+
+    IF old_row IS NULL THEN
+      RETURN NULL;
+    END IF;
+  END IF;
+
+  -- Password hashes are secret, only their change is recorded
+  IF old_row ->> 'password' IS NOT NULL THEN
+    old_row := old_row || '{"password": "********"}';
+  END IF;
+  IF new_row ->> 'password' IS NOT NULL THEN
+    new_row := new_row || '{"password": "********"}';
+  END IF;
+
+  INSERT INTO "audit_log"
+    ("id_user", "role", "id_actor_center", "id_center", "table_name", "id_record", "action",
+     "before", "after", "request_id", "correlation_id")
+  VALUES (
+    NULLIF(current_setting('audit.id_user', true), '')::bigint,
+    NULLIF(current_setting('audit.role', true), ''),
+    NULLIF(current_setting('audit.id_center', true), '')::bigint,
+    audit_center(TG_TABLE_NAME, COALESCE(to_jsonb(NEW), to_jsonb(OLD))),
+    TG_TABLE_NAME,
+    (COALESCE(to_jsonb(NEW), to_jsonb(OLD)) ->> TG_ARGV[0])::bigint,
+    lower(TG_OP),
+    old_row,
+    new_row,
+    COALESCE(NULLIF(current_setting('audit.request_id', true), ''), txid_current()::text),
+    NULLIF(current_setting('audit.correlation_id', true), '')
+  );
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,49 @@
+//! Audit trail of the changes made to the records.
+//!
+//! Every insert, update and delete on the records is stored in the `audit_log` table by a
+//! database trigger, along with the changed columns. The trigger reads the user making the
+//! change from settings local to the transaction, which [`Auth::audited`] defines. Changes made
+//! without them, such as through `psql`, have no author.
+//!
+//! The changes are grouped by an ID the server generates for each request, or by the ID of the
+//! transaction for the changes made outside of the API. The `X-Request-Id` header of the request,
+//! typically set by a reverse proxy, is kept as well to correlate the changes with its logs.
+
+use diesel::{select, Connection, PgConnection, RunQueryDsl};
+
+use crate::{auth::Auth, database::set_config};
+
+/// Header giving the ID of the request for the client
+pub static REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longer request IDs are truncated
+pub static REQUEST_ID_MAX_LENGTH: usize = 64;
+
+impl Auth {
+    /// Runs the given writes in a transaction, attributing them to the user in the audit log.
+    pub fn audited<T, E, F>(&self, conn: &mut PgConnection, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<T, E>,
+        E: From<diesel::result::Error>,
+    {
+        conn.transaction(|conn| {
+            select((
+                set_config("audit.id_user", self.id_user.to_string(), true),
+                set_config("audit.role", format!("{:?}", self.role), true),
+                set_config("audit.id_center", self.id_center.to_string(), true),
+                set_config(
+                    "audit.request_id",
+                    self.request_id.clone().unwrap_or_default(),
+                    true,
+                ),
+                set_config(
+                    "audit.correlation_id",
+                    self.correlation_id.clone().unwrap_or_default(),
+                    true,
+                ),
+            ))
+            .execute(conn)?;
+
+            f(conn)
+        })
+    }
+}
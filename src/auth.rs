@@ -1,23 +1,34 @@
 use std::{borrow::Cow, env, future::Ready};
 
-use actix_web::{cookie::SameSite, dev::ServiceRequest, FromRequest, HttpRequest};
+use actix_web::{
+    cookie::SameSite, dev::ServiceRequest, web, FromRequest, HttpMessage, HttpRequest,
+};
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, DecodingKey, EncodingKey, Validation};
 use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 use crate::{
+    audit::{REQUEST_ID_HEADER, REQUEST_ID_MAX_LENGTH},
+    database::DbPool,
     error::{Error, Result},
     models::LoggedUser,
+    permissions,
+    sessions::{self, REFRESH_VALIDITY},
 };
 
-type Hours = i64;
+type Minutes = i64;
 
-/// Time the token is valid for
-pub static TOKEN_VALIDITY: Hours = 4;
+/// Time the token is valid for, it is then renewed with the refresh token
+pub static TOKEN_VALIDITY: Minutes = 15;
 /// The name of the cookie that contains the token
 pub static COOKIE_TOKEN_NAME: &str = "token";
+/// The name of the cookie that contains the refresh token
+pub static COOKIE_REFRESH_NAME: &str = "refresh_token";
+/// The path the refresh token is sent to, it is only needed by the auth routes
+static COOKIE_REFRESH_PATH: &str = "/api/auth";
 /// The passphrase used to encode and decode the JWT
 static JWT_SECRET: OnceCell<String> = OnceCell::new();
 /// The JWT encoding key
@@ -58,50 +69,98 @@ pub fn initialize_jwt() -> std::result::Result<(), String> {
     Ok(())
 }
 
+/// Kind of user, telling which table [`Auth::id`] references.
+///
+/// What a user can do is given by its permissions, see [`permissions`]. The kind is also given as
+/// a `ROLE_<KIND>` grant to the routes only meant for a kind of user, such as `me` routes.
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, ToSchema)]
 pub enum Role {
     Manager,
     Nurse,
+    Patient,
+}
+
+impl Role {
+    /// Returns the grant of the kind, checked with `has_roles`.
+    pub fn grant(self) -> &'static str {
+        match self {
+            Role::Manager => "ROLE_MANAGER",
+            Role::Nurse => "ROLE_NURSE",
+            Role::Patient => "ROLE_PATIENT",
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Auth {
     exp: u64,
     iat: u64,
-    /// User's id, this references `nurses` or `managers` table depending on its role
+    /// Session the token belongs to, see [`sessions`]
+    pub sid: i64,
+    /// User's id, this references `nurses`, `managers` or `patients` table depending on its role
     pub id: i64,
     /// The real user ID, references `users` table
     pub id_user: i64,
     pub id_center: i64,
     pub id_zone: Option<i64>,
     pub role: Role,
+    /// Permissions of the user, they are loaded on every request rather than stored in the token
+    #[serde(skip)]
+    pub permissions: Vec<String>,
+    /// ID of the request generated by the server, see [`crate::audit`]
+    #[serde(skip)]
+    pub request_id: Option<String>,
+    /// ID of the request given by the client in the `X-Request-Id` header
+    #[serde(skip)]
+    pub correlation_id: Option<String>,
 }
 
 impl Auth {
     /// Creates an auth structure from id and a logged user.
     ///
-    /// `id` may reference a nurse or a manager depending on the role in `user`
-    pub fn new(id: i64, user: &LoggedUser) -> Self {
+    /// `id` may reference a nurse, a manager or a patient depending on the role in `user`, `sid` is
+    /// the session opened for the user.
+    pub fn new(id: i64, user: &LoggedUser, sid: i64) -> Self {
         let now = Utc::now();
         let LoggedUser {
             role,
             user,
             id_center,
             id_zone,
-            ..
+            permissions,
         } = user;
 
         Self {
-            exp: (now + Duration::hours(TOKEN_VALIDITY)).timestamp() as u64,
+            exp: (now + Duration::minutes(TOKEN_VALIDITY)).timestamp() as u64,
             iat: now.timestamp() as u64,
+            sid,
             id,
             id_user: user.id,
             id_center: *id_center,
             id_zone: *id_zone,
             role: *role,
+            permissions: permissions.clone(),
+            request_id: None,
+            correlation_id: None,
         }
     }
 
+    /// Checks the user has the given permission.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    /// Checks the user has the given permission or is the record `id` of the given kind, users
+    /// can access their own records without permission.
+    pub fn is_or_has(&self, role: Role, id: i64, permission: &str) -> bool {
+        (self.role == role && self.id == id) || self.has_permission(permission)
+    }
+
+    /// Checks the user administrates every center.
+    pub fn is_admin(&self) -> bool {
+        self.has_permission("centers:write")
+    }
+
     /// Builds the base of an authentication cookie.
     pub fn build_cookie<'c, V>(value: V) -> actix_web::cookie::CookieBuilder<'c>
     where
@@ -113,6 +172,25 @@ impl Auth {
             .secure(true)
             .http_only(true)
     }
+
+    /// Builds the base of a refresh token cookie.
+    pub fn build_refresh_cookie<'c, V>(value: V) -> actix_web::cookie::CookieBuilder<'c>
+    where
+        V: Into<Cow<'c, str>>,
+    {
+        actix_web::cookie::Cookie::build(COOKIE_REFRESH_NAME, value)
+            .path(COOKIE_REFRESH_PATH)
+            .same_site(SameSite::Strict)
+            .secure(true)
+            .http_only(true)
+    }
+
+    /// Builds the cookie holding a refresh token.
+    pub fn refresh_cookie(token: String) -> actix_web::cookie::Cookie<'static> {
+        Self::build_refresh_cookie(token)
+            .max_age(actix_web::cookie::time::Duration::days(REFRESH_VALIDITY))
+            .finish()
+    }
 }
 
 impl TryFrom<Auth> for actix_web::cookie::Cookie<'_> {
@@ -122,7 +200,7 @@ impl TryFrom<Auth> for actix_web::cookie::Cookie<'_> {
         let token = jsonwebtoken::encode(&jsonwebtoken::Header::default(), &value, &ENCODING_KEY)?;
 
         Ok(Auth::build_cookie(token)
-            .max_age(actix_web::cookie::time::Duration::hours(TOKEN_VALIDITY))
+            .max_age(actix_web::cookie::time::Duration::minutes(TOKEN_VALIDITY))
             .finish())
     }
 }
@@ -137,20 +215,32 @@ impl FromRequest for Auth {
     }
 }
 
-/// Extracts the roles from the token.
+/// Extracts the permissions of the user and the grant of its kind.
 ///
 /// See [actix_web_grants].
 pub async fn extract_permissions(
     req: &ServiceRequest,
-) -> std::result::Result<Vec<Role>, actix_web::Error> {
+) -> std::result::Result<Vec<String>, actix_web::Error> {
     match get_token(req.request()) {
-        Ok(auth) => Ok(vec![auth.role]),
+        Ok(auth) => {
+            let mut grants = auth.permissions;
+            grants.push(auth.role.grant().into());
+
+            Ok(grants)
+        }
         Err(_) => Ok(Vec::new()),
     }
 }
 
-/// Get the token from a request if it exists and decode it.
+/// Get the token from a request if it exists, decode it, check its session is still active and
+/// load the permissions of the user.
+///
+/// The result is kept in the request extensions so this is done only once per request.
 fn get_token(req: &HttpRequest) -> Result<Auth> {
+    if let Some(auth) = req.extensions().get::<Auth>() {
+        return Ok(auth.clone());
+    }
+
     let Some(received_token) = req.cookie(COOKIE_TOKEN_NAME) else {
         return Err(Error::TokenNotProvided);
     };
@@ -160,10 +250,24 @@ fn get_token(req: &HttpRequest) -> Result<Auth> {
     let mut validation = Validation::default();
     validation.set_required_spec_claims(&["exp"]);
 
-    let decoded_token = decode::<Auth>(received_token.value(), &DECODING_KEY, &validation);
+    let mut auth = decode::<Auth>(received_token.value(), &DECODING_KEY, &validation)?.claims;
 
-    match decoded_token {
-        Ok(t) => Ok(t.claims),
-        Err(err) => Err(err.into()),
+    let pool = req
+        .app_data::<web::Data<DbPool>>()
+        .expect("DbPool is not registered");
+    let conn = &mut pool.get()?;
+    if !sessions::is_active(conn, auth.sid)? {
+        return Err(Error::SessionClosed);
     }
+    auth.permissions = permissions::of_user(conn, auth.id_user)?;
+    auth.request_id = Some(Uuid::new_v4().to_string());
+    auth.correlation_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.chars().take(REQUEST_ID_MAX_LENGTH).collect());
+
+    req.extensions_mut().insert(auth.clone());
+
+    Ok(auth)
 }
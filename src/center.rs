@@ -0,0 +1,121 @@
+//! Restricts data access to the center of the current user.
+//!
+//! Records belong to a center through their zone, address, patient or mission. [`CenterScoped`]
+//! resolves this center so routes can compare it to the one of the current user with
+//! [`Auth::check_center`]. Skills and mission types are shared by every center.
+
+use actix_web::error::ErrorForbidden;
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, QueryResult, RunQueryDsl};
+
+use crate::{
+    auth::Auth,
+    error::Result,
+    models::*,
+    schema::{addresses, availabilities, managers, missions, nurses, patients, visits, zones},
+};
+
+/// A model whose records belong to a center.
+pub trait CenterScoped {
+    /// Returns the ID of the center the record belongs to.
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64>;
+}
+
+impl CenterScoped for ZoneRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        zones::table
+            .filter(zones::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for Address {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        addresses::table
+            .inner_join(zones::table)
+            .filter(addresses::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for ManagerRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        managers::table
+            .filter(managers::id.eq(id))
+            .select(managers::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for NurseRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        nurses::table
+            .inner_join(addresses::table.inner_join(zones::table))
+            .filter(nurses::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for Availability {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        availabilities::table
+            .inner_join(nurses::table.inner_join(addresses::table.inner_join(zones::table)))
+            .filter(availabilities::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for PatientRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        patients::table
+            .inner_join(addresses::table.inner_join(zones::table))
+            .filter(patients::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for MissionRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        missions::table
+            .inner_join(patients::table.inner_join(addresses::table.inner_join(zones::table)))
+            .filter(missions::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl CenterScoped for VisitRecord {
+    fn id_center(conn: &mut PgConnection, id: i64) -> QueryResult<i64> {
+        visits::table
+            .inner_join(
+                missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                ),
+            )
+            .filter(visits::id.eq(id))
+            .select(zones::id_center)
+            .first(conn)
+    }
+}
+
+impl Auth {
+    /// Checks the given center is the one of the current user, giving a `403` otherwise.
+    pub fn same_center(&self, id_center: i64) -> Result<()> {
+        if id_center == self.id_center {
+            Ok(())
+        } else {
+            Err(ErrorForbidden("This record belongs to another center").into())
+        }
+    }
+
+    /// Checks a record belongs to the center of the current user.
+    ///
+    /// Gives a `404` if the record does not exist and a `403` if it belongs to another center.
+    pub fn check_center<T: CenterScoped>(&self, conn: &mut PgConnection, id: i64) -> Result<()> {
+        self.same_center(T::id_center(conn, id)?)
+    }
+}
@@ -3,9 +3,9 @@
 use diesel::{
     migration::MigrationVersion,
     r2d2::{self},
-    sql_function,
-    sql_types::{Nullable, Text},
-    PgConnection,
+    select, sql_function,
+    sql_types::{Binary, Bool, Integer, Nullable, Text},
+    PgConnection, QueryResult, RunQueryDsl,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
@@ -32,10 +32,53 @@ pub fn create_pool() -> DbPool {
 /// Migrations are embed in the binary.
 pub fn run_migrations<DB: diesel::backend::Backend>(
     con: &mut impl MigrationHarness<DB>,
-) -> diesel::migration::Result<Vec<MigrationVersion>> {
+) -> diesel::migration::Result<Vec<MigrationVersion<'_>>> {
     con.run_pending_migrations(MIGRATIONS)
 }
 
+/// Stores a type as `jsonb` through its serde representation.
+///
+/// The type must also derive `AsExpression` and `FromSqlRow` with `#[diesel(sql_type = Jsonb)]`.
+macro_rules! jsonb {
+    ($type:ty) => {
+        impl diesel::deserialize::FromSql<diesel::sql_types::Jsonb, diesel::pg::Pg> for $type {
+            fn from_sql(bytes: diesel::pg::PgValue<'_>) -> diesel::deserialize::Result<Self> {
+                let value = <serde_json::Value as diesel::deserialize::FromSql<
+                    diesel::sql_types::Jsonb,
+                    diesel::pg::Pg,
+                >>::from_sql(bytes)?;
+                Ok(serde_json::from_value(value)?)
+            }
+        }
+
+        impl diesel::serialize::ToSql<diesel::sql_types::Jsonb, diesel::pg::Pg> for $type {
+            fn to_sql<'b>(
+                &'b self,
+                out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+            ) -> diesel::serialize::Result {
+                use std::io::Write;
+
+                // Version of the jsonb binary format
+                out.write_all(&[1])?;
+                serde_json::to_writer(out, self)?;
+                Ok(diesel::serialize::IsNull::No)
+            }
+        }
+    };
+}
+
+pub(crate) use jsonb;
+
+/// Generates a random token of 32 bytes, encoded in hexadecimal.
+pub fn random_token(conn: &mut PgConnection) -> QueryResult<String> {
+    select(encode(gen_random_bytes(32), "hex")).get_result(conn)
+}
+
+/// SQL expression hashing a token, secret tokens are only stored hashed.
+pub fn hash_token(token: &str) -> encode::HelperType<digest::HelperType<String, &str>, &str> {
+    encode(digest(token.to_string(), "sha256"), "hex")
+}
+
 sql_function!(
     /// See [the PostgreSQL crypt documentation](https://www.postgresql.org/docs/current/pgcrypto.html#PGCRYPTO-PASSWORD-HASHING-FUNCS-CRYPT)
     ///
@@ -53,3 +96,23 @@ sql_function!(
     /// nullable.
     fn gen_salt(r#type: Text) -> Nullable<Text>
 );
+
+sql_function!(
+    /// See [the PostgreSQL gen_random_bytes documentation](https://www.postgresql.org/docs/current/pgcrypto.html#PGCRYPTO-RANDOM-DATA-FUNCS)
+    fn gen_random_bytes(count: Integer) -> Binary
+);
+
+sql_function!(
+    /// See [the PostgreSQL digest documentation](https://www.postgresql.org/docs/current/pgcrypto.html#PGCRYPTO-GENERAL-HASHING-FUNCS-DIGEST)
+    fn digest(data: Text, r#type: Text) -> Binary
+);
+
+sql_function!(
+    /// See [the PostgreSQL encode documentation](https://www.postgresql.org/docs/current/functions-binarystring.html#FUNCTIONS-BINARYSTRING-CONVERSIONS)
+    fn encode(data: Binary, format: Text) -> Text
+);
+
+sql_function!(
+    /// See [the PostgreSQL set_config documentation](https://www.postgresql.org/docs/current/functions-admin.html#FUNCTIONS-ADMIN-SET)
+    fn set_config(setting_name: Text, new_value: Text, is_local: Bool) -> Text
+);
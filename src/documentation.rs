@@ -21,12 +21,18 @@ pub fn doc() -> utoipa::openapi::OpenApi {
     doc.merge(centers::Doc::openapi());
     doc.merge(mission_types::Doc::openapi());
     doc.merge(nurses::Doc::openapi());
+    doc.merge(availabilities::Doc::openapi());
     doc.merge(patients::Doc::openapi());
     doc.merge(missions::Doc::openapi());
     doc.merge(visits::Doc::openapi());
+    doc.merge(planning::Doc::openapi());
     doc.merge(managers::Doc::openapi());
     doc.merge(auth::Doc::openapi());
     doc.merge(zones::Doc::openapi());
+    doc.merge(roles::Doc::openapi());
+    doc.merge(audit::Doc::openapi());
+    doc.merge(search::Doc::openapi());
+    doc.merge(import::Doc::openapi());
 
     SecurityAddon.modify(&mut doc);
 
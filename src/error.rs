@@ -4,6 +4,8 @@ use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::Serialize;
 use utoipa::ToSchema;
 
+use crate::planning::Conflict;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Serialize, ToSchema)]
@@ -17,6 +19,14 @@ impl JsonError {
     }
 }
 
+/// Error returned when a nurse can't be assigned to a visit.
+#[derive(Serialize, ToSchema)]
+pub struct ConflictsError {
+    pub message: String,
+    /// Reasons preventing the assignment
+    pub conflicts: Vec<Conflict>,
+}
+
 /// A general wrapper around errors that could be produced by the different crates.
 #[non_exhaustive]
 #[derive(Debug)]
@@ -34,6 +44,10 @@ pub enum Error {
 
     /// The auth token has not been provided
     TokenNotProvided,
+    /// The session of the auth or refresh token has been revoked or has expired
+    SessionClosed,
+    /// A nurse can't be assigned to a visit for the given reasons
+    Conflicts(Vec<Conflict>),
 }
 
 impl Display for Error {
@@ -44,7 +58,9 @@ impl Display for Error {
             Error::Blocking(err) => err.fmt(f),
             Error::JwtError(err) => std::fmt::Debug::fmt(&err, f),
             Error::TokenNotProvided => write!(f, "Token not provided"),
+            Error::SessionClosed => write!(f, "Session revoked or expired"),
             Error::ActixWeb(err) => err.fmt(f),
+            Error::Conflicts(_) => write!(f, "The nurse can't be assigned to this visit"),
         }
     }
 }
@@ -53,16 +69,27 @@ impl ResponseError for Error {
     fn status_code(&self) -> StatusCode {
         match self {
             Error::Diesel(diesel::result::Error::NotFound) => StatusCode::NOT_FOUND,
-            Error::TokenNotProvided | Error::JwtError(_) => StatusCode::UNAUTHORIZED,
+            Error::TokenNotProvided | Error::SessionClosed | Error::JwtError(_) => {
+                StatusCode::UNAUTHORIZED
+            }
             Error::ActixWeb(err) => err.error_response().status(),
+            Error::Conflicts(_) => StatusCode::CONFLICT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        if let Error::Conflicts(conflicts) = self {
+            return HttpResponse::build(self.status_code()).json(ConflictsError {
+                message: self.to_string(),
+                conflicts: conflicts.clone(),
+            });
+        }
+
         let message = match self {
             Error::Diesel(diesel::result::Error::NotFound)
             | Error::TokenNotProvided
+            | Error::SessionClosed
             | Error::JwtError(_)
             | Error::ActixWeb(_) => self.to_string(),
             #[cfg(debug_assertions)]
@@ -0,0 +1,211 @@
+//! Coordinates of addresses and travel between them.
+//!
+//! Addresses without coordinates are geocoded when saved. The geocoder is chosen with the
+//! following environment variables, no address is geocoded if none is set:
+//!
+//! - `GEOCODER_POSTCODES`: CSV file of `postcode,latitude,longitude` lines, addresses are located
+//!   at the center of their postcode
+//!
+//! Travel times are estimated from the straight distance between two points, with
+//! `TRAVEL_SPEED_KMH` the average speed, defaults to `30`.
+
+use std::{collections::HashMap, env, fs};
+
+use actix_web::error::ErrorBadRequest;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::Result;
+
+/// Mean radius of the Earth in kilometers
+const EARTH_RADIUS: f64 = 6371.;
+
+/// Roads are longer than the straight line between two points
+const DETOUR_FACTOR: f64 = 1.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Great-circle distance to another point, in kilometers.
+    pub fn distance(&self, other: &Coordinates) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+
+        let a = (dlat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.).sin().powi(2);
+
+        2. * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+/// Checks the coordinates of an address are both given or both missing, and valid.
+pub fn check(latitude: Option<f64>, longitude: Option<f64>) -> Result<()> {
+    match (latitude, longitude) {
+        (None, None) => Ok(()),
+        (Some(lat), Some(lon))
+            if (-90. ..=90.).contains(&lat) && (-180. ..=180.).contains(&lon) =>
+        {
+            Ok(())
+        }
+        (Some(_), Some(_)) => Err(ErrorBadRequest("The coordinates are out of range").into()),
+        _ => Err(ErrorBadRequest("The latitude and longitude must be given together").into()),
+    }
+}
+
+static SPEED: Lazy<f64> = Lazy::new(|| {
+    env::var("TRAVEL_SPEED_KMH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&v: &f64| v > 0.)
+        .unwrap_or(30.)
+});
+
+/// Estimated minutes to travel a straight distance in kilometers.
+pub fn travel_minutes(distance: f64) -> i64 {
+    (distance * DETOUR_FACTOR / *SPEED * 60.).ceil() as i64
+}
+
+/// Textual address to locate.
+pub struct Query<'a> {
+    pub number: Option<i32>,
+    pub street_name: &'a str,
+    pub postcode: &'a str,
+    pub city_name: &'a str,
+}
+
+/// Locates addresses.
+///
+/// Implementations must not rely on a remote service, addresses are personal data.
+pub trait Geocoder: Send + Sync {
+    /// Returns the coordinates of an address, `None` if it cannot be located.
+    fn locate(&self, query: &Query) -> Option<Coordinates>;
+}
+
+/// Locates nothing, used when no geocoder is configured.
+pub struct NoGeocoder;
+
+impl Geocoder for NoGeocoder {
+    fn locate(&self, _: &Query) -> Option<Coordinates> {
+        None
+    }
+}
+
+/// Locates addresses at the center of their postcode.
+pub struct PostcodeGeocoder {
+    postcodes: HashMap<String, Coordinates>,
+}
+
+impl PostcodeGeocoder {
+    /// Reads `postcode,latitude,longitude` lines, invalid lines such as a header are skipped.
+    pub fn parse(csv: &str) -> Self {
+        let postcodes = csv
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(',').map(str::trim);
+                let postcode = fields.next()?;
+                let latitude = fields.next()?.parse().ok()?;
+                let longitude = fields.next()?.parse().ok()?;
+
+                Some((
+                    postcode.to_string(),
+                    Coordinates {
+                        latitude,
+                        longitude,
+                    },
+                ))
+            })
+            .collect();
+
+        Self { postcodes }
+    }
+}
+
+impl Geocoder for PostcodeGeocoder {
+    fn locate(&self, query: &Query) -> Option<Coordinates> {
+        self.postcodes.get(query.postcode.trim()).copied()
+    }
+}
+
+static GEOCODER: OnceCell<Box<dyn Geocoder>> = OnceCell::new();
+
+/// Loads the geocoder given by the environment variables.
+///
+/// This function should be called at the start of the program, so that an invalid configuration
+/// is not only noticed when an address is saved. No address is located until it is.
+pub fn initialize_geocoder() -> std::result::Result<(), String> {
+    let geocoder: Box<dyn Geocoder> = match env::var("GEOCODER_POSTCODES") {
+        Ok(path) => {
+            let csv = fs::read_to_string(&path)
+                .map_err(|e| format!("GEOCODER_POSTCODES cannot be read from {path}: {e}"))?;
+            let geocoder = PostcodeGeocoder::parse(&csv);
+            if geocoder.postcodes.is_empty() {
+                return Err(format!("GEOCODER_POSTCODES {path} has no valid line"));
+            }
+            Box::new(geocoder)
+        }
+        Err(_) => Box::new(NoGeocoder),
+    };
+
+    // Already initialized geocoders are kept
+    let _ = GEOCODER.set(geocoder);
+
+    Ok(())
+}
+
+/// Locates an address with the configured geocoder.
+pub fn geocode(query: &Query) -> Option<Coordinates> {
+    GEOCODER.get().and_then(|g| g.locate(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        let paris = Coordinates {
+            latitude: 48.8566,
+            longitude: 2.3522,
+        };
+        let lyon = Coordinates {
+            latitude: 45.764,
+            longitude: 4.8357,
+        };
+
+        assert!((paris.distance(&lyon) - 391.5).abs() < 1.);
+        assert_eq!(paris.distance(&paris), 0.);
+    }
+
+    #[test]
+    fn incomplete_coordinates() {
+        assert!(check(None, None).is_ok());
+        assert!(check(Some(48.), Some(2.)).is_ok());
+        assert!(check(Some(48.), None).is_err());
+        assert!(check(Some(91.), Some(2.)).is_err());
+    }
+
+    #[test]
+    fn postcodes() {
+        let geocoder = PostcodeGeocoder::parse("postcode,latitude,longitude\n90000, 47.64, 6.86\n");
+        let query = |postcode| Query {
+            number: None,
+            street_name: "",
+            postcode,
+            city_name: "",
+        };
+
+        assert_eq!(
+            geocoder.locate(&query("90000")),
+            Some(Coordinates {
+                latitude: 47.64,
+                longitude: 6.86
+            })
+        );
+        assert_eq!(geocoder.locate(&query("25000")), None);
+    }
+}
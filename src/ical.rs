@@ -0,0 +1,377 @@
+//! Builds the iCalendar feeds of the visits.
+//!
+//! Feeds are subscribed to from calendar applications, they are configured with the following
+//! environment variables:
+//!
+//! - `ICAL_TIMEZONE`: time zone of the dates of the visits, defaults to `Europe/Paris`, they are
+//!   given in UTC in the feeds
+//! - `ICAL_DOMAIN`: domain name of the server, making the IDs of the events globally unique,
+//!   defaults to `ta72-project.github.io`
+//! - `ICAL_ALARM_MINUTES`: minutes before a visit to trigger a reminder, no reminder by default
+//! - `ICAL_CANCELLED_RETENTION_DAYS`: days a removed visit stays in the feeds as cancelled so
+//!   calendar applications remove it, defaults to `30`
+
+use std::{collections::HashMap, env};
+
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::{
+    insert_into, BoolExpressionMethods, Connection, ExpressionMethods, PgConnection, QueryDsl,
+    QueryResult, RunQueryDsl, SelectableHelper,
+};
+use icalendar::{
+    Alarm, Calendar, CalendarDateTime, Component, Event, EventLike, EventStatus, Property, Trigger,
+};
+use once_cell::sync::Lazy;
+
+use crate::{
+    database::{hash_token, random_token},
+    models::{CalendarToken, CancelledVisit, Visit},
+    schema::{
+        addresses, calendar_tokens, cancelled_visits, l_visits_nurses, managers, mission_types,
+        missions, nurses, patients, users, visits, zones,
+    },
+};
+
+/// Configuration of the feeds.
+struct Config {
+    timezone: Tz,
+    domain: String,
+    alarm_minutes: Option<u32>,
+    retention_days: i64,
+}
+
+static CONFIG: Lazy<Config> = Lazy::new(|| Config {
+    timezone: timezone().unwrap(),
+    domain: env::var("ICAL_DOMAIN").unwrap_or_else(|_| "ta72-project.github.io".into()),
+    alarm_minutes: env::var("ICAL_ALARM_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&m| m > 0),
+    retention_days: env::var("ICAL_CANCELLED_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30),
+});
+
+/// Reads the `ICAL_TIMEZONE` env variable.
+fn timezone() -> std::result::Result<Tz, String> {
+    let name = env::var("ICAL_TIMEZONE").unwrap_or_else(|_| "Europe/Paris".into());
+
+    name.parse()
+        .map_err(|_| format!("ICAL_TIMEZONE `{name}` is not a known time zone"))
+}
+
+/// Checks the configuration of the feeds.
+///
+/// This function should be called at the start of the program, so that an invalid configuration
+/// is not only noticed when a feed is requested.
+pub fn initialize_ical() -> std::result::Result<(), String> {
+    timezone()?;
+    Lazy::force(&CONFIG);
+
+    Ok(())
+}
+
+/// The visits a feed is made of.
+#[derive(Clone, Copy)]
+pub enum Feed {
+    /// Visits of a nurse
+    Nurse(i64),
+    /// Visits of the patients of a center
+    Center(i64),
+    /// Visits of the patients of a zone
+    Zone(i64),
+}
+
+/// A nurse assigned to a visit.
+struct Attendee {
+    fname: String,
+    lname: String,
+    mail: String,
+}
+
+/// Builds the calendar of a feed.
+///
+/// `alarm` is the number of minutes before each visit to be reminded, `0` disables the reminder.
+/// The server configuration is used if it is `None`.
+pub fn calendar(
+    conn: &mut PgConnection,
+    feed: Feed,
+    name: &str,
+    alarm: Option<u32>,
+) -> QueryResult<Calendar> {
+    let alarm = alarm.or(CONFIG.alarm_minutes).filter(|&m| m > 0);
+
+    let mut query = visits::table
+        .inner_join(
+            missions::table.inner_join(mission_types::table).inner_join(
+                patients::table
+                    .inner_join(users::table)
+                    .inner_join(addresses::table.inner_join(zones::table)),
+            ),
+        )
+        .select((Visit::as_select(), visits::sequence, visits::updated_at))
+        .order(visits::start)
+        .into_boxed();
+
+    query = match feed {
+        Feed::Nurse(id) => query.filter(
+            visits::id.eq_any(
+                l_visits_nurses::table
+                    .filter(l_visits_nurses::id_nurse.eq(id))
+                    .select(l_visits_nurses::id_visit),
+            ),
+        ),
+        Feed::Center(id) => query.filter(zones::id_center.eq(id)),
+        Feed::Zone(id) => query.filter(addresses::id_zone.eq(id)),
+    };
+
+    let visits: Vec<(Visit, i32, NaiveDateTime)> = query.load(conn)?;
+
+    let ids: Vec<i64> = visits.iter().map(|(v, _, _)| v.visit.id).collect();
+    let mut attendees: HashMap<i64, Vec<Attendee>> = HashMap::new();
+    for (id_visit, fname, lname, mail) in l_visits_nurses::table
+        .inner_join(nurses::table.inner_join(users::table))
+        .filter(l_visits_nurses::id_visit.eq_any(&ids))
+        .select((
+            l_visits_nurses::id_visit,
+            users::fname,
+            users::lname,
+            users::mail,
+        ))
+        .load::<(i64, String, String, String)>(conn)?
+    {
+        attendees
+            .entry(id_visit)
+            .or_default()
+            .push(Attendee { fname, lname, mail });
+    }
+
+    let mut query = cancelled_visits::table
+        .select(CancelledVisit::as_select())
+        .into_boxed();
+
+    query = match feed {
+        Feed::Nurse(id) => query.filter(cancelled_visits::id_nurse.eq(id)),
+        Feed::Center(id) => query.filter(
+            cancelled_visits::id_nurse
+                .is_null()
+                .and(cancelled_visits::id_center.eq(id)),
+        ),
+        Feed::Zone(id) => query.filter(
+            cancelled_visits::id_nurse
+                .is_null()
+                .and(cancelled_visits::id_zone.eq(id)),
+        ),
+    };
+
+    let cancelled: Vec<CancelledVisit> = query.load(conn)?;
+
+    let mut calendar = Calendar::new();
+    calendar.name(name).timezone(CONFIG.timezone.name());
+
+    for (visit, sequence, updated_at) in visits {
+        let attendees = attendees.remove(&visit.visit.id).unwrap_or_default();
+        calendar.push(visit_event(visit, sequence, updated_at, &attendees, alarm));
+    }
+    for visit in cancelled {
+        calendar.push(cancelled_event(visit));
+    }
+
+    Ok(calendar.done())
+}
+
+/// Deletes the cancelled visits older than the retention window, returning their number.
+///
+/// Calendar applications had the time to remove them, this is meant to be run periodically.
+pub fn purge_cancelled(conn: &mut PgConnection) -> QueryResult<usize> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(CONFIG.retention_days);
+
+    diesel::delete(cancelled_visits::table)
+        .filter(cancelled_visits::cancelled_at.lt(cutoff))
+        .execute(conn)
+}
+
+/// Creates the calendar token of a user, replacing the previous one if any.
+pub fn create_token(conn: &mut PgConnection, id_user: i64) -> QueryResult<CalendarToken> {
+    conn.transaction(|conn| {
+        revoke_token(conn, id_user)?;
+
+        let token = random_token(conn)?;
+        let created_at = insert_into(calendar_tokens::table)
+            .values((
+                calendar_tokens::id_user.eq(id_user),
+                calendar_tokens::token.eq(hash_token(&token)),
+            ))
+            .returning(calendar_tokens::created_at)
+            .get_result(conn)?;
+
+        Ok(CalendarToken { token, created_at })
+    })
+}
+
+/// Deletes the calendar token of a user, returning the number of deleted tokens.
+pub fn revoke_token(conn: &mut PgConnection, id_user: i64) -> QueryResult<usize> {
+    diesel::delete(calendar_tokens::table)
+        .filter(calendar_tokens::id_user.eq(id_user))
+        .execute(conn)
+}
+
+/// Returns the center of the manager owning the given calendar token.
+pub fn manager_center(conn: &mut PgConnection, token: &str) -> QueryResult<i64> {
+    calendar_tokens::table
+        .inner_join(users::table.inner_join(managers::table))
+        .filter(calendar_tokens::token.eq(hash_token(token)))
+        .select(managers::id_center)
+        .first(conn)
+}
+
+/// Formats a date the way `LAST-MODIFIED` expects it, `date` being in UTC.
+fn utc(date: NaiveDateTime) -> String {
+    date.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Converts a date of a visit, in the configured time zone, to UTC.
+///
+/// A date skipped by a change to daylight saving time is moved forward like the clocks are.
+fn local(date_time: NaiveDateTime) -> CalendarDateTime {
+    let tz = CONFIG.timezone;
+    let date_time = tz
+        .from_local_datetime(&date_time)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(date_time + Duration::hours(1)))
+                .earliest()
+        })
+        .map_or_else(
+            || Utc.from_utc_datetime(&date_time),
+            |d| d.with_timezone(&Utc),
+        );
+
+    CalendarDateTime::Utc(date_time)
+}
+
+/// Globally unique ID of the event of a visit.
+fn uid(id_visit: i64) -> String {
+    format!("visit-{id_visit}@{}", CONFIG.domain)
+}
+
+fn visit_event(
+    visit: Visit,
+    sequence: i32,
+    updated_at: NaiveDateTime,
+    attendees: &[Attendee],
+    alarm: Option<u32>,
+) -> Event {
+    let Visit { visit, mission } = visit;
+    let mut event = Event::new();
+
+    event
+        .uid(&uid(visit.id))
+        .sequence(sequence as u32)
+        .add_property("LAST-MODIFIED", &utc(updated_at))
+        .summary(&mission.mission_type.name)
+        .description(&format!(
+            "Patient: {} {}\n\nDescription: {}\n\n",
+            mission.patient.user.fname,
+            mission.patient.user.lname.to_uppercase(),
+            mission.mission.desc.unwrap_or_default()
+        ))
+        .starts(local(visit.start))
+        .ends(local(visit.end))
+        .location(&mission.patient.address.to_string())
+        .status(if visit.cancelled_at.is_some() {
+            EventStatus::Cancelled
+        } else {
+            EventStatus::Confirmed
+        });
+
+    for attendee in attendees {
+        event.append_multi_property(
+            Property::new("ATTENDEE", &format!("mailto:{}", attendee.mail))
+                .add_parameter(
+                    "CN",
+                    &format!("{} {}", attendee.fname, attendee.lname.to_uppercase()),
+                )
+                .done(),
+        );
+    }
+
+    if let Some(minutes) = alarm {
+        event.alarm(Alarm::display(
+            &mission.mission_type.name,
+            Trigger::before_start(Duration::minutes(minutes.into())),
+        ));
+    }
+
+    event.done()
+}
+
+fn cancelled_event(visit: CancelledVisit) -> Event {
+    Event::new()
+        .uid(&uid(visit.id_visit))
+        .sequence(visit.sequence as u32)
+        .summary(&visit.summary)
+        .starts(local(visit.start))
+        .ends(local(visit.end))
+        .status(EventStatus::Cancelled)
+        .done()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn cancelled_visit_event() {
+        let start = NaiveDate::from_ymd_opt(2023, 11, 6)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        let event = cancelled_event(CancelledVisit {
+            id_visit: 12,
+            start,
+            end: start + Duration::minutes(30),
+            summary: "Injection".into(),
+            sequence: 3,
+        });
+
+        assert_eq!(event.get_uid(), Some("visit-12@ta72-project.github.io"));
+        assert_eq!(event.get_sequence(), Some(3));
+        assert_eq!(event.get_status(), Some(EventStatus::Cancelled));
+        assert_eq!(
+            event.get_start(),
+            Some(CalendarDateTime::Utc(Utc.with_ymd_and_hms(2023, 11, 6, 7, 0, 0).unwrap()).into()),
+            "dates should be converted from the configured time zone"
+        );
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        let date = |d, h, m| {
+            NaiveDate::from_ymd_opt(2024, 3, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        let utc = |d, h, m| CalendarDateTime::Utc(Utc.from_utc_datetime(&date(d, h, m)));
+
+        assert_eq!(local(date(30, 8, 0)), utc(30, 7, 0));
+        assert_eq!(local(date(31, 8, 0)), utc(31, 6, 0));
+        // The clocks go from 2:00 to 3:00
+        assert_eq!(local(date(31, 2, 30)), utc(31, 1, 30));
+    }
+
+    #[test]
+    fn utc_format() {
+        let date = NaiveDate::from_ymd_opt(2023, 11, 6)
+            .unwrap()
+            .and_hms_opt(8, 5, 9)
+            .unwrap();
+
+        assert_eq!(utc(date), "20231106T080509Z");
+    }
+}
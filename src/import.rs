@@ -0,0 +1,296 @@
+//! Bulk import of records from CSV or JSON lines files.
+//!
+//! Every row of a file holds the fields of a record flat, the ones of the user, of its address and
+//! of the record itself, named like in the JSON bodies of the API. A CSV file starts with a header
+//! naming its columns, empty cells are missing optional fields. The zone of an address can be
+//! given by its name in a `zone` field.
+//!
+//! Rows are only parsed here, each part of a record is then deserialized from the same row with
+//! [`Row::part`].
+
+use std::rc::Rc;
+
+use csv::{ReaderBuilder, StringRecord, Trim};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+/// Kind of records that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    /// Patients with their user and address
+    Patients,
+    /// Nurses with their user and address, they need a `minutes_per_week` field
+    Nurses,
+}
+
+impl Entity {
+    /// Permission needed to import the records.
+    pub fn permission(self) -> &'static str {
+        match self {
+            Entity::Patients => "patients:write",
+            Entity::Nurses => "nurses:write",
+        }
+    }
+}
+
+/// Format of an imported file, given by its content type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+}
+
+impl Format {
+    /// Finds the format of a content type, without its parameters.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type {
+            "text/csv" => Some(Format::Csv),
+            "application/x-ndjson" | "application/jsonl" => Some(Format::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ImportParam {
+    /// Validates every row without saving anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Reason a row cannot be imported.
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct RowError {
+    /// Line of the row in the file, starting at 1
+    pub line: u64,
+    pub message: String,
+}
+
+impl RowError {
+    pub fn new(line: u64, message: impl ToString) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Outcome of an import.
+#[derive(Serialize, ToSchema)]
+pub struct ImportReport {
+    /// Number of rows in the file
+    pub rows: usize,
+    /// Whether the records were saved, never for a dry run nor when a row is invalid
+    pub committed: bool,
+    /// IDs of the created records, in the order of the rows
+    pub ids: Vec<i64>,
+    /// Invalid rows, nothing is saved if there are any
+    pub errors: Vec<RowError>,
+}
+
+/// Fields of a row.
+enum Fields {
+    Csv {
+        headers: Rc<StringRecord>,
+        record: StringRecord,
+    },
+    Json(Value),
+}
+
+/// A row of an imported file.
+pub struct Row {
+    /// Line of the row in the file, starting at 1
+    pub line: u64,
+    fields: Fields,
+}
+
+impl Row {
+    /// Deserializes a part of a record from the row, the fields of the other parts are ignored.
+    pub fn part<T: DeserializeOwned>(&self) -> Result<T, RowError> {
+        match &self.fields {
+            Fields::Csv { headers, record } => record
+                .deserialize(Some(headers))
+                .map_err(|e| RowError::new(self.line, csv_message(&e, headers))),
+            Fields::Json(value) => T::deserialize(value).map_err(|e| RowError::new(self.line, e)),
+        }
+    }
+}
+
+/// Parses the rows of a file, a row which cannot be read is an error.
+pub fn parse(format: Format, body: &[u8]) -> Vec<Result<Row, RowError>> {
+    match format {
+        Format::Csv => parse_csv(body),
+        Format::JsonLines => parse_json_lines(body),
+    }
+}
+
+/// Message of a CSV error, without its position.
+fn csv_message(e: &csv::Error, headers: &StringRecord) -> String {
+    match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+            Some(i) => format!("`{}`: {}", &headers[i as usize], err.kind()),
+            None => err.kind().to_string(),
+        },
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!("The row has {len} fields instead of {expected_len}"),
+        _ => e.to_string(),
+    }
+}
+
+fn parse_csv(body: &[u8]) -> Vec<Result<Row, RowError>> {
+    // Positions do not count the empty lines, which are skipped, a record starts after them
+    let line = |position: Option<&csv::Position>| {
+        let mut start = position.map_or(0, |p| p.byte() as usize).min(body.len());
+        while body.get(start).is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            start += 1;
+        }
+        body[..start].iter().filter(|b| **b == b'\n').count() as u64 + 1
+    };
+
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(body);
+    let headers = match reader.headers() {
+        Ok(headers) => Rc::new(headers.clone()),
+        Err(e) => return vec![Err(RowError::new(1, e))],
+    };
+
+    reader
+        .into_records()
+        .map(|record| {
+            let record =
+                record.map_err(|e| RowError::new(line(e.position()), csv_message(&e, &headers)))?;
+
+            Ok(Row {
+                line: line(record.position()),
+                fields: Fields::Csv {
+                    headers: headers.clone(),
+                    record,
+                },
+            })
+        })
+        .collect()
+}
+
+fn parse_json_lines(body: &[u8]) -> Vec<Result<Row, RowError>> {
+    body.split(|b| *b == b'\n')
+        .zip(1..)
+        .filter(|(line, _)| !line.trim_ascii().is_empty())
+        .map(|(line, number)| match serde_json::from_slice(line) {
+            Ok(value @ Value::Object(_)) => Ok(Row {
+                line: number,
+                fields: Fields::Json(value),
+            }),
+            Ok(_) => Err(RowError::new(number, "A row must be an object")),
+            Err(e) => Err(RowError::new(number, e)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Person {
+        name: String,
+        age: Option<i32>,
+    }
+
+    fn people(format: Format, body: &str) -> Vec<Result<(u64, Person), RowError>> {
+        parse(format, body.as_bytes())
+            .into_iter()
+            .map(|row| row.and_then(|row| Ok((row.line, row.part()?))))
+            .collect()
+    }
+
+    #[test]
+    fn csv_rows() {
+        let res = people(
+            Format::Csv,
+            "name, age, city\n\"Doe, Jane\", 42, Belfort\n\nJohn,,Paris\n",
+        );
+
+        assert_eq!(
+            res,
+            [
+                Ok((
+                    2,
+                    Person {
+                        name: "Doe, Jane".into(),
+                        age: Some(42)
+                    }
+                )),
+                Ok((
+                    4,
+                    Person {
+                        name: "John".into(),
+                        age: None
+                    }
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_invalid_rows() {
+        let res = people(
+            Format::Csv,
+            "name,age\nJane,old\nJohn\n\n\"Doe\n\",\"\"\nage,name\n",
+        );
+
+        assert_eq!(
+            res,
+            [
+                Err(RowError::new(2, "`age`: invalid digit found in string")),
+                Err(RowError::new(3, "The row has 1 fields instead of 2")),
+                Ok((
+                    5,
+                    Person {
+                        name: "Doe".into(),
+                        age: None
+                    }
+                )),
+                Err(RowError::new(7, "`age`: invalid digit found in string")),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let res = people(
+            Format::JsonLines,
+            "{\"name\": \"Jane\", \"age\": 42, \"city\": \"Belfort\"}\n\n[1]\n{\"age\": 3}\n{",
+        );
+
+        assert_eq!(
+            res,
+            [
+                Ok((
+                    1,
+                    Person {
+                        name: "Jane".into(),
+                        age: Some(42)
+                    }
+                )),
+                Err(RowError::new(3, "A row must be an object")),
+                Err(RowError::new(4, "missing field `name`")),
+                Err(RowError::new(
+                    5,
+                    "EOF while parsing an object at line 1 column 1"
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(Format::from_content_type("text/csv"), Some(Format::Csv));
+        assert_eq!(
+            Format::from_content_type("application/x-ndjson"),
+            Some(Format::JsonLines)
+        );
+        assert_eq!(Format::from_content_type("application/json"), None);
+    }
+}
@@ -1,9 +1,23 @@
+pub mod audit;
 pub mod auth;
+pub mod center;
 pub mod database;
 pub mod documentation;
 pub mod error;
+pub mod geo;
+pub mod ical;
+pub mod import;
 pub mod models;
 pub mod pagination;
 pub mod params;
+pub mod password;
+pub mod permissions;
+pub mod planning;
+pub mod reports;
 pub mod routes;
+pub mod routing;
 pub mod schema;
+pub mod search;
+pub mod sessions;
+pub mod workload;
+pub mod zoning;
@@ -1,4 +1,4 @@
-use std::io;
+use std::{io, time::Duration};
 
 use actix_web::{
     middleware::{Compress, Logger, NormalizePath},
@@ -9,7 +9,6 @@ use actix_web_grants::GrantsMiddleware;
 use backend::*;
 use env_logger::Env;
 use error::JsonError;
-use routes::*;
 use utoipa_redoc::{Redoc, Servable};
 
 #[actix_web::main]
@@ -17,7 +16,13 @@ async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(Env::default().default_filter_or("info"));
 
     if let Err(e) = backend::auth::initialize_jwt() {
-        return Err(io::Error::new(io::ErrorKind::Other, e));
+        return Err(io::Error::other(e));
+    }
+    if let Err(e) = backend::ical::initialize_ical() {
+        return Err(io::Error::other(e));
+    }
+    if let Err(e) = backend::geo::initialize_geocoder() {
+        return Err(io::Error::other(e));
     }
 
     let pool = database::create_pool();
@@ -25,12 +30,14 @@ async fn main() -> std::io::Result<()> {
     database::run_migrations(&mut pool.get().expect("Unable to get connection"))
         .expect("Unable to run migrations");
 
+    actix_web::rt::spawn(purge_cancelled_visits(pool.clone()));
+
     HttpServer::new(move || {
         let app = App::new()
             .configure(json_config)
             .configure(query_config)
             .app_data(web::Data::new(pool.clone()))
-            .wrap(Logger::new("\"%r\" -> %s in %D ms"))
+            .wrap(Logger::new("\"%r\" -> %s in %D ms (%{X-Request-Id}i)"))
             .wrap(NormalizePath::trim())
             .wrap(Compress::default())
             .wrap(GrantsMiddleware::with_extractor(
@@ -41,26 +48,27 @@ async fn main() -> std::io::Result<()> {
         let app = app.wrap(actix_cors::Cors::permissive());
 
         app.service(Redoc::with_url("/doc", documentation::doc()))
-            .service(
-                web::scope("/api")
-                    .service(skills::routes())
-                    .service(centers::routes())
-                    .service(mission_types::routes())
-                    .service(nurses::routes())
-                    .service(patients::routes())
-                    .service(missions::routes())
-                    .service(visits::routes())
-                    .service(managers::routes())
-                    .service(zones::routes())
-                    .service(routes::auth::routes())
-                    .service(version::routes()),
-            )
+            .service(routes::api())
     })
     .bind(("0.0.0.0", 8000))?
     .run()
     .await
 }
 
+/// Deletes the cancelled visits out of the calendar feeds every hour.
+async fn purge_cancelled_visits(pool: database::DbPool) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(60 * 60));
+
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        // A failed purge is retried on the next tick
+        let _ =
+            web::block(move || pool.get().map(|mut conn| ical::purge_cancelled(&mut conn))).await;
+    }
+}
+
 /// Configures the [Json](actix_web::web::Json) extractor error response to be JSON.
 fn json_config(app: &mut ServiceConfig) {
     let json_config = JsonConfig::default().error_handler(|err, _| {
@@ -1,16 +1,22 @@
 use std::fmt::Display;
 
+use actix_web::error::ErrorBadRequest;
 use backend_derive::HasColumn;
 use diesel::{prelude::AsChangeset, Identifiable, Insertable, Queryable, Selectable};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use utoipa::ToSchema;
 
-use crate::schema::addresses;
+use crate::{
+    error::Result,
+    geo::{self, Coordinates},
+    schema::addresses,
+    zoning::{self, ZoneArea},
+};
 
 #[derive(Clone, Serialize, Queryable, Identifiable, Selectable, HasColumn, ToSchema)]
 #[diesel(table_name = addresses)]
 pub struct Address {
-    id: i64,
+    pub id: i64,
     /// Street number
     number: Option<i32>,
     street_name: String,
@@ -18,7 +24,23 @@ pub struct Address {
     city_name: String,
     /// Address complement
     complement: Option<String>,
-    id_zone: i64,
+    pub id_zone: i64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Address {
+    pub fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+        })
+    }
+
+    /// Finds the zone covering the address, which may not be its current one.
+    pub fn covering_zone(&self, zones: &[ZoneArea]) -> Option<i64> {
+        zoning::find(zones, &self.postcode, self.coordinates())
+    }
 }
 
 impl Display for Address {
@@ -47,7 +69,53 @@ pub struct NewAddress {
     city_name: String,
     /// Address complement
     complement: Option<String>,
-    id_zone: i64,
+    /// Found from the location of the address if not given, see [`crate::zoning`]
+    pub id_zone: Option<i64>,
+    /// Geocoded if not given along the longitude
+    latitude: Option<f64>,
+    /// Geocoded if not given along the latitude
+    longitude: Option<f64>,
+}
+
+impl NewAddress {
+    /// Checks the given coordinates, geocoding the address if there are none.
+    pub fn locate(&mut self) -> Result<()> {
+        geo::check(self.latitude, self.longitude)?;
+
+        if self.latitude.is_none() {
+            let coordinates = geo::geocode(&geo::Query {
+                number: self.number,
+                street_name: &self.street_name,
+                postcode: &self.postcode,
+                city_name: &self.city_name,
+            });
+            self.latitude = coordinates.map(|c| c.latitude);
+            self.longitude = coordinates.map(|c| c.longitude);
+        }
+
+        Ok(())
+    }
+
+    /// Finds the zone of the address if none is given, once located.
+    pub fn assign_zone(&mut self, zones: &[ZoneArea]) -> Result<()> {
+        if self.id_zone.is_some() {
+            return Ok(());
+        }
+
+        let coordinates = self
+            .latitude
+            .zip(self.longitude)
+            .map(|(latitude, longitude)| Coordinates {
+                latitude,
+                longitude,
+            });
+        self.id_zone = Some(
+            zoning::find(zones, &self.postcode, coordinates)
+                .ok_or_else(|| ErrorBadRequest("No zone covers the address, it must be given"))?,
+        );
+
+        Ok(())
+    }
 }
 
 #[derive(Deserialize, AsChangeset, ToSchema)]
@@ -60,5 +128,78 @@ pub struct UpdateAddress {
     city_name: Option<String>,
     /// Address complement
     complement: Option<Option<String>>,
-    id_zone: Option<i64>,
+    /// Found again if the address moves without a new zone
+    pub id_zone: Option<i64>,
+    /// Geocoded again if the address changes without new coordinates, `null` clears them
+    #[serde(default, deserialize_with = "nullable")]
+    latitude: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    longitude: Option<Option<f64>>,
+}
+
+/// Tells a field set to `null`, given as `Some(None)`, from a missing one.
+fn nullable<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+impl UpdateAddress {
+    /// Checks the given coordinates, geocoding the address again if it changes without new ones.
+    ///
+    /// The latitude and longitude can only be set or cleared together.
+    pub fn locate(&mut self, current: &Address) -> Result<()> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => return geo::check(latitude, longitude),
+            (None, None) => {}
+            _ => {
+                return Err(
+                    ErrorBadRequest("The latitude and longitude must be given together").into(),
+                )
+            }
+        }
+
+        if self.number.is_some()
+            || self.street_name.is_some()
+            || self.postcode.is_some()
+            || self.city_name.is_some()
+        {
+            let coordinates = geo::geocode(&geo::Query {
+                number: self.number.unwrap_or(current.number),
+                street_name: self.street_name.as_ref().unwrap_or(&current.street_name),
+                postcode: self.postcode.as_ref().unwrap_or(&current.postcode),
+                city_name: self.city_name.as_ref().unwrap_or(&current.city_name),
+            });
+            self.latitude = Some(coordinates.map(|c| c.latitude));
+            self.longitude = Some(coordinates.map(|c| c.longitude));
+        }
+
+        Ok(())
+    }
+
+    /// Finds the zone of the address again if it moves without a new one, once located.
+    ///
+    /// The zone is kept if no other one covers the new location.
+    pub fn assign_zone(&mut self, zones: &[ZoneArea], current: &Address) {
+        if self.id_zone.is_some() || (self.postcode.is_none() && self.latitude.is_none()) {
+            return;
+        }
+
+        let coordinates = match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => {
+                latitude
+                    .zip(longitude)
+                    .map(|(latitude, longitude)| Coordinates {
+                        latitude,
+                        longitude,
+                    })
+            }
+            _ => current.coordinates(),
+        };
+        let postcode = self.postcode.as_ref().unwrap_or(&current.postcode);
+
+        self.id_zone = zoning::find(zones, postcode, coordinates);
+    }
 }
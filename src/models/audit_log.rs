@@ -0,0 +1,50 @@
+use backend_derive::HasColumn;
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::schema::audit_log;
+
+/// A change made to a record.
+#[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
+#[diesel(table_name = audit_log)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// User who made the change, `None` for changes made outside of the API
+    pub id_user: Option<i64>,
+    /// Kind of the user who made the change
+    pub role: Option<String>,
+    /// Center of the user who made the change
+    pub id_actor_center: Option<i64>,
+    /// Center of the changed record, `None` for records shared by every center or whose parents
+    /// were deleted along
+    pub id_center: Option<i64>,
+    pub table_name: String,
+    /// ID of the changed record, for link tables the first column of the key
+    pub id_record: Option<i64>,
+    /// `insert`, `update` or `delete`
+    pub action: String,
+    /// Values of the changed columns before the change
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<serde_json::Value>,
+    /// Values of the changed columns after the change
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<serde_json::Value>,
+    /// Changes made by the same request share the same ID
+    pub request_id: String,
+    /// ID given by the client in the `X-Request-Id` header of the request
+    pub correlation_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Filters of the audit log.
+#[derive(Deserialize, IntoParams)]
+pub struct AuditParam {
+    /// Only the changes made to this table
+    pub table: Option<String>,
+    /// Only the changes made to this record, usually used along `table`
+    pub record: Option<i64>,
+    /// Only the changes made by this user, references `users` table
+    pub actor: Option<i64>,
+}
@@ -1,17 +1,347 @@
 use backend_derive::HasColumn;
-use chrono::NaiveDateTime;
-use diesel::{Queryable, Selectable};
-use serde::Serialize;
-use utoipa::ToSchema;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::{AsChangeset, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::schema::availabilities;
 
 #[derive(Serialize, Selectable, Queryable, HasColumn, ToSchema)]
 #[diesel(table_name = availabilities)]
 pub struct Availability {
-    id: i64,
-    start: NaiveDateTime,
-    end: NaiveDateTime,
-    recurrent: bool,
-    id_nurse: i64,
+    pub id: i64,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// A recurrent availability is repeated every week from `start`, with the same duration.
+    pub recurrent: bool,
+    pub id_nurse: i64,
+}
+
+impl Availability {
+    /// Tells whether the nurse is available during the whole time from `start` to `end`.
+    ///
+    /// A recurrent availability is repeated every week from its start.
+    pub fn covers(&self, start: NaiveDateTime, end: NaiveDateTime) -> bool {
+        if !self.recurrent {
+            return self.start <= start && end <= self.end;
+        }
+
+        if start < self.start {
+            return false;
+        }
+
+        let shift = Duration::weeks((start - self.start).num_weeks());
+
+        self.start + shift <= start && end <= self.end + shift
+    }
+
+    /// Returns the time slots of this availability between `from` and `to`.
+    ///
+    /// Slots are cut so they stay inside the given period.
+    pub fn slots(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<AvailabilitySlot> {
+        let mut res = Vec::new();
+
+        let mut week = if self.recurrent && from > self.end {
+            (from - self.end).num_weeks()
+        } else {
+            0
+        };
+
+        loop {
+            let shift = Duration::weeks(week);
+            let start = self.start + shift;
+            let end = self.end + shift;
+
+            if start >= to {
+                break;
+            }
+
+            if end > from {
+                res.push(AvailabilitySlot {
+                    start: start.max(from),
+                    end: end.min(to),
+                });
+            }
+
+            if !self.recurrent {
+                break;
+            }
+
+            week += 1;
+        }
+
+        res
+    }
+}
+
+/// A concrete time slot during which a nurse is available.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, ToSchema)]
+pub struct AvailabilitySlot {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+/// Sorts slots and merges the ones overlapping or following each other.
+pub fn merge_slots(mut slots: Vec<AvailabilitySlot>) -> Vec<AvailabilitySlot> {
+    slots.sort_by_key(|s| (s.start, s.end));
+
+    let mut res: Vec<AvailabilitySlot> = Vec::with_capacity(slots.len());
+
+    for slot in slots {
+        match res.last_mut() {
+            Some(last) if slot.start <= last.end => last.end = last.end.max(slot.end),
+            _ => res.push(slot),
+        }
+    }
+
+    res
+}
+
+#[derive(Deserialize, Insertable, ToSchema)]
+#[diesel(table_name = availabilities)]
+pub struct NewAvailability {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// A recurrent availability is repeated every week from `start`, with the same duration.
+    pub recurrent: bool,
+    /// ID of the nurse, defaults to the current nurse.
+    pub id_nurse: Option<i64>,
+}
+
+impl NewAvailability {
+    /// Checks the availability is consistent, returning the reason if it is not.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.start >= self.end {
+            Err("`start` must be before `end`")
+        } else if self.recurrent && self.end - self.start > Duration::weeks(1) {
+            Err("A recurrent availability can't last more than a week")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Merges an existing availability of the same kind into this one if they overlap or follow
+    /// each other, returning whether they did.
+    ///
+    /// Recurrent availabilities are compared modulo a week, whatever week they start on. The
+    /// merged availability is repeated from the week of the earliest one and lasts at most a
+    /// week, it then covers the whole week.
+    pub fn merge(&mut self, other: &Availability) -> bool {
+        if !self.recurrent {
+            if other.start > self.end || other.end < self.start {
+                return false;
+            }
+
+            self.start = self.start.min(other.start);
+            self.end = self.end.max(other.end);
+            return true;
+        }
+
+        let week = Duration::weeks(1);
+        let offset = (other.start - self.start)
+            .num_seconds()
+            .rem_euclid(week.num_seconds());
+        let other_start = self.start + Duration::seconds(offset);
+        let other_end = other_start + (other.end - other.start);
+
+        // The occurrence of `other` starting the week before can run past the week boundary
+        let mut merged = false;
+        let (mut start, mut end) = (self.start, self.end);
+        for (s, e) in [
+            (other_start - week, other_end - week),
+            (other_start, other_end),
+        ] {
+            if s <= self.end && e >= self.start {
+                start = start.min(s);
+                end = end.max(e);
+                merged = true;
+            }
+        }
+
+        if !merged {
+            return false;
+        }
+
+        // Weeks to go back for the merged availability to start no later than the earliest one
+        let earliest = self.start.min(other.start);
+        let weeks = -(earliest - start)
+            .num_seconds()
+            .div_euclid(week.num_seconds());
+        self.start = start - Duration::weeks(weeks);
+        self.end = (end - Duration::weeks(weeks)).min(self.start + week);
+
+        true
+    }
+}
+
+#[derive(Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = availabilities)]
+pub struct UpdateAvailability {
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    /// A recurrent availability is repeated every week from `start`, with the same duration.
+    pub recurrent: Option<bool>,
+}
+
+/// Designates a week by any of its days.
+#[derive(Deserialize, IntoParams)]
+pub struct WeekParam {
+    /// Any day of the week, defaults to today
+    pub date: Option<NaiveDate>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::*;
+
+    fn datetime(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn availability(start: NaiveDateTime, end: NaiveDateTime, recurrent: bool) -> Availability {
+        Availability {
+            id: 1,
+            start,
+            end,
+            recurrent,
+            id_nurse: 1,
+        }
+    }
+
+    fn slot(start: NaiveDateTime, end: NaiveDateTime) -> AvailabilitySlot {
+        AvailabilitySlot { start, end }
+    }
+
+    /// A recurrent availability covers the same time the following weeks only
+    #[test]
+    fn recurrent_covers() {
+        let a = availability(datetime(8, 8), datetime(8, 12), true);
+
+        assert!(a.covers(datetime(15, 9), datetime(15, 10)));
+        assert!(!a.covers(datetime(16, 9), datetime(16, 10)));
+        assert!(!a.covers(datetime(1, 9), datetime(1, 10)));
+    }
+
+    /// A non recurrent availability has one slot
+    #[test]
+    fn single_slot() {
+        let a = availability(datetime(8, 8), datetime(8, 12), false);
+
+        assert_eq!(
+            a.slots(datetime(1, 0), datetime(31, 0)),
+            vec![slot(datetime(8, 8), datetime(8, 12))]
+        );
+    }
+
+    /// A recurrent availability has one slot per week
+    #[test]
+    fn recurrent_slots() {
+        let a = availability(datetime(1, 8), datetime(1, 12), true);
+
+        assert_eq!(
+            a.slots(datetime(8, 0), datetime(22, 0)),
+            vec![
+                slot(datetime(8, 8), datetime(8, 12)),
+                slot(datetime(15, 8), datetime(15, 12))
+            ]
+        );
+    }
+
+    /// Slots are cut to the period
+    #[test]
+    fn slots_cut() {
+        let a = availability(datetime(1, 8), datetime(1, 12), false);
+
+        assert_eq!(
+            a.slots(datetime(1, 10), datetime(1, 11)),
+            vec![slot(datetime(1, 10), datetime(1, 11))]
+        );
+    }
+
+    /// Overlapping and contiguous slots are merged
+    #[test]
+    fn merge() {
+        let slots = vec![
+            slot(datetime(1, 14), datetime(1, 16)),
+            slot(datetime(1, 8), datetime(1, 10)),
+            slot(datetime(1, 9), datetime(1, 12)),
+            slot(datetime(1, 12), datetime(1, 13)),
+        ];
+
+        assert_eq!(
+            merge_slots(slots),
+            vec![
+                slot(datetime(1, 8), datetime(1, 13)),
+                slot(datetime(1, 14), datetime(1, 16))
+            ]
+        );
+    }
+
+    fn new(start: NaiveDateTime, end: NaiveDateTime, recurrent: bool) -> NewAvailability {
+        NewAvailability {
+            start,
+            end,
+            recurrent,
+            id_nurse: None,
+        }
+    }
+
+    /// Availabilities following each other are merged, distant ones are not
+    #[test]
+    fn merge_single() {
+        let mut n = new(datetime(1, 8), datetime(1, 12), false);
+
+        assert!(!n.merge(&availability(datetime(8, 8), datetime(8, 12), false)));
+        assert!(n.merge(&availability(datetime(1, 12), datetime(1, 14), false)));
+        assert_eq!((n.start, n.end), (datetime(1, 8), datetime(1, 14)));
+    }
+
+    /// Recurrent availabilities starting on different weeks are merged from the earliest week
+    #[test]
+    fn merge_recurrent_other_week() {
+        let mut n = new(datetime(15, 10), datetime(15, 14), true);
+
+        assert!(n.merge(&availability(datetime(1, 8), datetime(1, 12), true)));
+        assert_eq!((n.start, n.end), (datetime(1, 8), datetime(1, 14)));
+    }
+
+    /// A recurrent availability running past the end of the week is merged with the ones at the
+    /// start of the next week
+    #[test]
+    fn merge_recurrent_wrap_around() {
+        // Sunday night to Monday morning, then Monday morning
+        let mut n = new(datetime(8, 6), datetime(8, 12), true);
+
+        assert!(n.merge(&availability(datetime(7, 20), datetime(8, 8), true)));
+        assert_eq!((n.start, n.end), (datetime(7, 20), datetime(8, 12)));
+        assert!(n.validate().is_ok());
+    }
+
+    /// Merged recurrent availabilities last at most a week
+    #[test]
+    fn merge_recurrent_whole_week() {
+        let mut n = new(datetime(2, 0), datetime(8, 12), true);
+
+        assert!(n.merge(&availability(datetime(8, 0), datetime(9, 12), true)));
+        assert_eq!((n.start, n.end), (datetime(1, 0), datetime(8, 0)));
+        assert!(n.validate().is_ok());
+    }
+
+    /// An availability must end after it starts
+    #[test]
+    fn validate_end_after_start() {
+        let new = NewAvailability {
+            start: datetime(1, 12),
+            end: datetime(1, 8),
+            recurrent: false,
+            id_nurse: None,
+        };
+
+        assert!(new.validate().is_err());
+    }
 }
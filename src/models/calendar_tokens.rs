@@ -0,0 +1,27 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// Secret giving access to the calendar feed of a user.
+///
+/// Only its hash is stored, the token cannot be read again once created.
+#[derive(Serialize, ToSchema)]
+pub struct CalendarToken {
+    /// Value to give in the `token` query parameter of the feed
+    pub token: String,
+    /// Date and time the token was created
+    pub created_at: NaiveDateTime,
+}
+
+/// Query parameters of a calendar feed.
+///
+/// Calendar applications cannot send the authentication cookie, the feed is protected by a
+/// token instead.
+#[derive(Deserialize, IntoParams)]
+pub struct CalendarParam {
+    /// Calendar token of the user
+    pub token: String,
+    /// Minutes before each visit to be reminded, `0` disables reminders.
+    /// Defaults to the server configuration.
+    pub alarm: Option<u32>,
+}
@@ -0,0 +1,22 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Selectable};
+
+use crate::schema::cancelled_visits;
+
+/// A visit removed from a calendar, either deleted or unassigned from a nurse.
+///
+/// These are recorded by the database and kept for a while so calendar clients can remove the
+/// visit on their side.
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = cancelled_visits)]
+pub struct CancelledVisit {
+    pub id_visit: i64,
+    /// Date and time the visit began
+    pub start: NaiveDateTime,
+    /// Date and time the visit ended
+    pub end: NaiveDateTime,
+    /// Name of the mission type of the visit
+    pub summary: String,
+    /// Revision of the visit once cancelled
+    pub sequence: i32,
+}
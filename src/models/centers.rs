@@ -1,17 +1,37 @@
 use backend_derive::HasColumn;
 use chrono::NaiveTime;
-use diesel::Queryable;
-use serde::Serialize;
+use diesel::{AsChangeset, Insertable, Queryable};
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::schema::centers;
+
 #[derive(Clone, Serialize, Queryable, HasColumn, ToSchema)]
 #[diesel(table_name = centers)]
 pub struct CenterRecord {
-    id: i64,
+    pub id: i64,
     name: String,
     desc: Option<String>,
     /// The time the center starts working
-    workday_start: NaiveTime,
+    pub workday_start: NaiveTime,
     /// The time the center stops working
-    workday_end: NaiveTime,
+    pub workday_end: NaiveTime,
+}
+
+#[derive(Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = centers)]
+pub struct UpdateCenter {
+    name: Option<String>,
+    desc: Option<Option<String>>,
+    workday_start: Option<NaiveTime>,
+    workday_end: Option<NaiveTime>,
+}
+
+#[derive(Deserialize, Insertable, ToSchema)]
+#[diesel(table_name = centers)]
+pub struct NewCenter {
+    name: String,
+    desc: Option<String>,
+    pub workday_start: NaiveTime,
+    pub workday_end: NaiveTime,
 }
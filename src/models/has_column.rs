@@ -1,5 +1,68 @@
+/// SQL type of a column, as far as filters are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Bool,
+    Text,
+    Timestamp,
+    Date,
+    Time,
+}
+
+/// A column data can be filtered on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    /// Table to qualify the column with, `None` if the model has no table
+    pub table: Option<&'static str>,
+    pub name: &'static str,
+    pub sql_type: ColumnType,
+    pub nullable: bool,
+}
+
 /// Defines what column exists for a given model.
 pub trait HasColumn {
     /// Returns true if the given column exists for this model.
     fn has_column(col: &str) -> bool;
+
+    /// Returns the columns of this model which can be filtered on.
+    fn columns() -> Vec<Column>;
+
+    /// Returns the names of the columns identifying a record of this model.
+    fn primary_key() -> Vec<&'static str>;
+}
+
+/// Models joined together, a column is looked for in the first model before the next ones.
+///
+/// Records are identified by the primary key of the first model.
+macro_rules! tuple {
+    ($first:ident $(, $model:ident)+) => {
+        tuple!(@impl $first, $first $(, $model)+);
+    };
+    (@impl $first:ident, $($model:ident),+) => {
+        impl<$($model: HasColumn),+> HasColumn for ($($model,)+) {
+            fn has_column(col: &str) -> bool {
+                $($model::has_column(col))||+
+            }
+
+            fn columns() -> Vec<Column> {
+                let mut res: Vec<Column> = Vec::new();
+                $(
+                    for column in $model::columns() {
+                        if !res.iter().any(|c| c.name == column.name) {
+                            res.push(column);
+                        }
+                    }
+                )+
+                res
+            }
+
+            fn primary_key() -> Vec<&'static str> {
+                $first::primary_key()
+            }
+        }
+    };
 }
+
+tuple!(A, B);
+tuple!(A, B, C);
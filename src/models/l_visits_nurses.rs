@@ -1,27 +1,61 @@
 use backend_derive::HasColumn;
-use diesel::{AsChangeset, Insertable, Queryable};
+use chrono::NaiveDateTime;
+use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::schema::l_visits_nurses;
+use crate::{reports::ReportData, schema::l_visits_nurses};
 
-#[derive(Serialize, Queryable, HasColumn, ToSchema)]
+#[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
 #[diesel(table_name = l_visits_nurses)]
 #[diesel(primary_key(id_visit, id_nurse))]
 pub struct LVisitNurse {
     id_visit: i64,
     id_nurse: i64,
     report: Option<String>,
+    /// Conflicts that were overridden when assigning the nurse to the visit
+    conflicts: Vec<Option<String>>,
+    /// Manager who signed off the report, references `users` table
+    signed_by: Option<i64>,
+    /// A signed off report is locked
+    signed_at: Option<NaiveDateTime>,
+    report_data: Option<ReportData>,
+    /// Date and time the nurse actually arrived
+    arrived_at: Option<NaiveDateTime>,
+    /// Date and time the nurse actually left
+    left_at: Option<NaiveDateTime>,
+}
+
+/// Actual times of a nurse assigned to a visit.
+#[derive(Debug, Serialize, Queryable, Selectable, ToSchema)]
+#[diesel(table_name = l_visits_nurses)]
+pub struct NurseAttendance {
+    pub id_nurse: i64,
+    /// Date and time the nurse actually arrived
+    pub arrived_at: Option<NaiveDateTime>,
+    /// Date and time the nurse actually left
+    pub left_at: Option<NaiveDateTime>,
+}
+
+/// Query parameters to assign a nurse to a visit.
+#[derive(Deserialize, IntoParams)]
+pub struct AssignNurseParam {
+    /// Assigns the nurse even if there are conflicts, recording them.
+    #[serde(default)]
+    pub force: bool,
 }
 
 #[derive(Deserialize, AsChangeset, ToSchema)]
 #[diesel(table_name = l_visits_nurses)]
 #[diesel(primary_key(id_visit, id_nurse))]
+#[diesel(treat_none_as_null = true)]
 pub struct UpdateLVisitNurse {
-    report: String,
+    pub report: String,
+    /// Checked against the template of the mission type, see [`crate::reports`]
+    pub report_data: Option<ReportData>,
 }
 
-#[derive(Deserialize, Insertable)]
+#[derive(Serialize, Deserialize, Insertable, ToSchema)]
 #[diesel(table_name = l_visits_nurses)]
 #[diesel(primary_key(id_visit, id_nurse))]
 pub struct NewLVisitNurse {
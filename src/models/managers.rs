@@ -1,23 +1,26 @@
 use backend_derive::HasColumn;
-use diesel::{Insertable, Queryable};
+use diesel::{Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use super::{NewUser, User};
 use crate::schema::managers;
 
-#[derive(Serialize, Queryable, HasColumn, ToSchema)]
+#[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
+#[diesel(table_name = managers)]
 pub struct ManagerRecord {
     id: i64,
-    id_user: i64,
-    id_center: i64,
+    pub id_user: i64,
+    pub id_center: i64,
 }
 
-#[derive(Serialize, Queryable, ToSchema)]
+#[derive(Serialize, Queryable, Selectable, ToSchema)]
 pub struct Manager {
     #[serde(flatten)]
+    #[diesel(embed)]
     manager: ManagerRecord,
     #[serde(flatten)]
+    #[diesel(embed)]
     user: User,
 }
 
@@ -36,3 +39,9 @@ pub struct NewManager {
     #[serde(flatten)]
     pub user: NewUser,
 }
+
+#[derive(Deserialize, ToSchema)]
+pub struct MoveManager {
+    /// Center the manager is moved to
+    pub id_center: i64,
+}
@@ -3,7 +3,7 @@ use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::schema::mission_types;
+use crate::{reports::Template, schema::mission_types};
 
 #[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
 #[diesel(table_name = mission_types)]
@@ -14,6 +14,8 @@ pub struct MissionType {
     people_required: i16,
     /// Mission type duration in minutes
     minutes_duration: i32,
+    /// What the reports of this kind of mission record
+    pub report_template: Option<Template>,
 }
 
 #[derive(Deserialize, AsChangeset, ToSchema)]
@@ -24,6 +26,8 @@ pub struct UpdateMissionType {
     people_required: Option<i16>,
     /// Mission type duration in minutes
     minutes_duration: Option<i32>,
+    /// What the reports of this kind of mission record
+    pub report_template: Option<Option<Template>>,
 }
 
 #[derive(Deserialize, Insertable, ToSchema, IntoParams)]
@@ -34,4 +38,6 @@ pub struct NewMissionType {
     people_required: Option<i16>,
     /// Mission type duration in minutes
     minutes_duration: i32,
+    /// What the reports of this kind of mission record
+    pub report_template: Option<Template>,
 }
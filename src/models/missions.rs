@@ -1,16 +1,16 @@
 use backend_derive::HasColumn;
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime, NaiveTime};
 use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use super::{MissionType, Patient};
+use super::{MissionType, NewVisit, Patient};
 use crate::schema::missions;
 
 #[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
 #[diesel(table_name = missions)]
 pub struct MissionRecord {
-    id: i64,
+    pub id: i64,
     /// Mission description
     pub desc: Option<String>,
     /// Start of the time window the mission should be fulfilled in
@@ -35,6 +35,109 @@ pub struct MissionRecord {
     id_patient: i64,
 }
 
+/// A single occurrence of a mission and the visit planned to fulfill it.
+pub struct Occurrence {
+    /// Start of the time window this occurrence should be fulfilled in
+    pub window_start: NaiveDateTime,
+    /// End of the time window this occurrence should be fulfilled in
+    pub window_end: NaiveDateTime,
+    /// Visit fulfilling this occurrence
+    pub visit: NewVisit,
+}
+
+impl MissionRecord {
+    /// Expands the mission into its occurrences between `from` and `until`.
+    ///
+    /// The mission time window is repeated every `recurrence_days` days. Each occurrence gets a
+    /// visit placed at the earliest moment of its window that is not before `from` and where
+    /// `minutes_duration` fits inside the center workday. Occurrences without such a moment are
+    /// left out. An archived mission has no occurrences.
+    pub fn occurrences(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+        workday_start: NaiveTime,
+        workday_end: NaiveTime,
+    ) -> Vec<Occurrence> {
+        let mut res = Vec::new();
+
+        if self.archived || from > until {
+            return res;
+        }
+
+        let duration = Duration::minutes(self.minutes_duration.into());
+        let period = self
+            .recurrence_days
+            .filter(|d| *d > 0)
+            .map(|d| Duration::days(d.into()));
+
+        // Skip the occurrences that are entirely before `from`
+        let mut n = match period {
+            Some(p) if from > self.end => ((from - self.end).num_days() / p.num_days()) as i32,
+            _ => 0,
+        };
+
+        loop {
+            let shift = period.map_or(Duration::zero(), |p| p * n);
+            let window_start = self.start + shift;
+            let window_end = self.end + shift;
+
+            if window_start > until {
+                break;
+            }
+
+            if let Some(start) = first_slot(
+                window_start.max(from),
+                window_end,
+                duration,
+                workday_start,
+                workday_end,
+            ) {
+                res.push(Occurrence {
+                    window_start,
+                    window_end,
+                    visit: NewVisit {
+                        start,
+                        end: start + duration,
+                        id_mission: self.id,
+                    },
+                });
+            }
+
+            if period.is_none() {
+                break;
+            }
+
+            n += 1;
+        }
+
+        res
+    }
+}
+
+/// Finds the earliest time between `earliest` and `latest` where `duration` fits inside a workday.
+fn first_slot(
+    earliest: NaiveDateTime,
+    latest: NaiveDateTime,
+    duration: Duration,
+    workday_start: NaiveTime,
+    workday_end: NaiveTime,
+) -> Option<NaiveDateTime> {
+    let mut day = earliest.date();
+
+    while day <= latest.date() {
+        let start = earliest.max(day.and_time(workday_start));
+
+        if start + duration <= latest.min(day.and_time(workday_end)) {
+            return Some(start);
+        }
+
+        day = day.succ_opt()?;
+    }
+
+    None
+}
+
 #[derive(Serialize, Queryable, Selectable, ToSchema)]
 pub struct Mission {
     #[serde(flatten)]
@@ -95,5 +198,119 @@ pub struct NewMission {
     /// ID of the type of mission
     id_mission_type: i64,
     /// ID of the patient related to this mission
-    id_patient: i64,
+    pub id_patient: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+    use super::MissionRecord;
+
+    fn datetime(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn mission(
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        recurrence_days: Option<i16>,
+    ) -> MissionRecord {
+        MissionRecord {
+            id: 1,
+            desc: None,
+            start,
+            end,
+            recurrence_days,
+            people_required: 1,
+            minutes_duration: 30,
+            archived: false,
+            id_mission_type: 1,
+            id_patient: 1,
+        }
+    }
+
+    fn workday() -> (NaiveTime, NaiveTime) {
+        (
+            NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+        )
+    }
+
+    /// A non recurrent mission has a single occurrence
+    #[test]
+    fn single_occurrence() {
+        let (ws, we) = workday();
+        let m = mission(datetime(1, 9, 0), datetime(1, 12, 0), None);
+
+        let occ = m.occurrences(datetime(1, 0, 0), datetime(31, 0, 0), ws, we);
+
+        assert_eq!(occ.len(), 1);
+        assert_eq!(occ[0].visit.start, datetime(1, 9, 0));
+        assert_eq!(occ[0].visit.end, datetime(1, 9, 30));
+    }
+
+    /// A recurrent mission is repeated until the horizon
+    #[test]
+    fn recurrent_until_horizon() {
+        let (ws, we) = workday();
+        let m = mission(datetime(1, 9, 0), datetime(1, 12, 0), Some(7));
+
+        let occ = m.occurrences(datetime(1, 0, 0), datetime(20, 0, 0), ws, we);
+
+        let starts: Vec<_> = occ.iter().map(|o| o.visit.start).collect();
+        assert_eq!(
+            starts,
+            vec![datetime(1, 9, 0), datetime(8, 9, 0), datetime(15, 9, 0)]
+        );
+    }
+
+    /// Occurrences that ended before `from` are skipped and visits never start before `from`
+    #[test]
+    fn starts_after_from() {
+        let (ws, we) = workday();
+        let m = mission(datetime(1, 9, 0), datetime(1, 12, 0), Some(1));
+
+        let occ = m.occurrences(datetime(3, 10, 0), datetime(4, 0, 0), ws, we);
+
+        let starts: Vec<_> = occ.iter().map(|o| o.visit.start).collect();
+        assert_eq!(starts, vec![datetime(3, 10, 0)]);
+    }
+
+    /// Visits are moved inside the workday
+    #[test]
+    fn moved_to_workday_start() {
+        let (ws, we) = workday();
+        let m = mission(datetime(1, 6, 0), datetime(1, 12, 0), None);
+
+        let occ = m.occurrences(datetime(1, 0, 0), datetime(2, 0, 0), ws, we);
+
+        assert_eq!(occ[0].visit.start, datetime(1, 8, 0));
+    }
+
+    /// An occurrence whose visit can't fit inside the workday is left out
+    #[test]
+    fn not_fitting_left_out() {
+        let (ws, we) = workday();
+        let m = mission(datetime(1, 17, 45), datetime(1, 20, 0), None);
+
+        assert!(m
+            .occurrences(datetime(1, 0, 0), datetime(2, 0, 0), ws, we)
+            .is_empty());
+    }
+
+    /// An archived mission has no occurrences
+    #[test]
+    fn archived_no_occurrence() {
+        let (ws, we) = workday();
+        let mut m = mission(datetime(1, 9, 0), datetime(1, 12, 0), Some(1));
+        m.archived = true;
+
+        assert!(m
+            .occurrences(datetime(1, 0, 0), datetime(31, 0, 0), ws, we)
+            .is_empty());
+    }
 }
@@ -1,10 +1,11 @@
 use backend_derive::HasColumn;
+use chrono::NaiveDate;
 use diesel::{AsChangeset, Associations, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 use super::*;
-use crate::schema::nurses;
+use crate::schema::{nurses, users};
 
 #[derive(
     Clone, Copy, Identifiable, Selectable, Serialize, Queryable, Associations, HasColumn, ToSchema,
@@ -33,6 +34,30 @@ pub struct Nurse {
     address: Address,
 }
 
+/// Contact information of a nurse, as shown to the nurses it shares a visit with.
+#[derive(Serialize, Selectable, Queryable, ToSchema)]
+#[diesel(table_name = nurses)]
+pub struct Colleague {
+    /// Nurse ID
+    id: i64,
+    #[diesel(select_expression = users::fname, select_expression_type = users::fname)]
+    fname: String,
+    #[diesel(select_expression = users::lname, select_expression_type = users::lname)]
+    lname: String,
+    #[diesel(select_expression = users::phone, select_expression_type = users::phone)]
+    phone: Option<String>,
+}
+
+/// Name of a nurse, as shown to the patients it visits.
+#[derive(Serialize, Selectable, Queryable, ToSchema)]
+#[diesel(table_name = nurses)]
+pub struct AssignedNurse {
+    #[diesel(select_expression = users::fname, select_expression_type = users::fname)]
+    fname: String,
+    #[diesel(select_expression = users::lname, select_expression_type = users::lname)]
+    lname: String,
+}
+
 #[derive(Serialize, Queryable, ToSchema)]
 pub struct SkilledNurse {
     #[serde(flatten)]
@@ -85,3 +110,20 @@ pub struct NewNurse {
     pub user: NewUser,
     pub address: NewAddress,
 }
+
+/// Week of the workload report and its order.
+#[derive(Deserialize, IntoParams)]
+pub struct WorkloadParam {
+    /// Any day of the week, defaults to today
+    pub date: Option<NaiveDate>,
+    /// Lists the least busy nurses first instead of the busiest
+    #[serde(default)]
+    pub ascending: bool,
+}
+
+/// Designates a day.
+#[derive(Deserialize, IntoParams)]
+pub struct DayParam {
+    /// Defaults to today
+    pub date: Option<NaiveDate>,
+}
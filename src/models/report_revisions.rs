@@ -0,0 +1,27 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{reports::ReportData, schema::report_revisions};
+
+/// A version of a report or an addendum to it.
+#[derive(Serialize, Queryable, Selectable, ToSchema)]
+#[diesel(table_name = report_revisions)]
+pub struct ReportRevision {
+    pub id: i64,
+    pub id_visit: i64,
+    pub id_nurse: i64,
+    /// User who wrote the revision, references `users` table
+    pub id_author: Option<i64>,
+    pub content: String,
+    pub data: Option<ReportData>,
+    /// Addenda are written once the report is locked and do not replace it
+    pub addendum: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewAddendum {
+    pub content: String,
+}
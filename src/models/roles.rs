@@ -0,0 +1,72 @@
+use backend_derive::HasColumn;
+use diesel::{AsChangeset, Insertable, Queryable, Selectable};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::schema::{permissions, roles};
+
+/// A named bundle of permissions assigned to users.
+#[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
+#[diesel(table_name = roles)]
+pub struct RoleRecord {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    /// Center the role belongs to. Roles without center are the default roles, they are
+    /// available to every center and cannot be modified.
+    pub id_center: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RoleWithPermissions {
+    #[serde(flatten)]
+    pub role: RoleRecord,
+    /// Permissions granted by the role
+    pub permissions: Vec<String>,
+}
+
+#[derive(Serialize, Queryable, Selectable, ToSchema)]
+#[diesel(table_name = permissions)]
+pub struct Permission {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Deserialize, Insertable, ToSchema)]
+#[diesel(table_name = roles)]
+pub struct NewRoleRecord {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(skip_deserializing)]
+    pub id_center: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewRole {
+    #[serde(flatten)]
+    pub role: NewRoleRecord,
+    /// Permissions granted by the role
+    pub permissions: Vec<String>,
+}
+
+#[derive(Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = roles)]
+pub struct UpdateRoleRecord {
+    name: Option<String>,
+    description: Option<Option<String>>,
+}
+
+impl UpdateRoleRecord {
+    /// Whether there is nothing to update.
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.description.is_none()
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateRole {
+    #[serde(flatten)]
+    pub role: UpdateRoleRecord,
+    /// Permissions granted by the role, they replace the current ones
+    pub permissions: Option<Vec<String>>,
+}
@@ -1,6 +1,7 @@
 use std::option::Option;
 
 use backend_derive::HasColumn;
+use chrono::NaiveDateTime;
 use diesel::{AsChangeset, ExpressionMethods, Identifiable, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -35,6 +36,8 @@ pub struct LoggedUser {
     /// ID of the zone the user is attached to.
     /// Managers are not attached to any zone, only to a center.
     pub id_zone: Option<i64>,
+    /// Permissions granted by the roles of the user
+    pub permissions: Vec<String>,
 }
 
 #[derive(Deserialize, ToSchema)]
@@ -47,9 +50,10 @@ pub struct LoginUser {
 pub struct NewUser {
     fname: String,
     lname: String,
-    mail: String,
+    pub mail: String,
     phone: Option<String>,
-    password: String,
+    /// Users created without a password set it with a reset token on their first login
+    pub password: Option<String>,
 }
 
 /// Implements [`Insertable`] in such a way that the password is always and automatically hashed.
@@ -73,7 +77,8 @@ impl Insertable<users::table> for NewUser {
             Some(users::lname.eq(self.lname)),
             Some(users::mail.eq(self.mail)),
             self.phone.map(|x| users::phone.eq(x)),
-            Some(users::password.eq(crypt(self.password, gen_salt(String::from("bf"))))),
+            self.password
+                .map(|x| users::password.eq(crypt(x, gen_salt(String::from("bf"))))),
         )
             .values()
     }
@@ -87,3 +92,25 @@ pub struct UpdateUser {
     mail: Option<String>,
     phone: Option<Option<String>>,
 }
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdatePassword {
+    pub current: String,
+    pub new: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ResetPassword {
+    /// Token given by a manager
+    pub token: String,
+    pub password: String,
+}
+
+/// Single-use secret allowing a user to set its password.
+#[derive(Serialize, ToSchema)]
+pub struct PasswordResetToken {
+    /// Value to give to the user
+    pub token: String,
+    /// Date and time the token stops working, in UTC
+    pub expires_at: NaiveDateTime,
+}
@@ -2,52 +2,130 @@ use backend_derive::HasColumn;
 use chrono::NaiveDateTime;
 use diesel::{AsChangeset, Insertable, Queryable, Selectable};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use super::Mission;
+use super::{AssignedNurse, Colleague, Mission, MissionType, NurseAttendance};
 use crate::schema::visits;
 
 #[derive(Serialize, Queryable, Selectable, HasColumn, ToSchema)]
 #[diesel(table_name = visits)]
 pub struct VisitRecord {
-    id: i64,
+    pub id: i64,
     /// Date and time the visit begins
-    start: NaiveDateTime,
+    pub start: NaiveDateTime,
     /// Date and time the visit ends
-    end: NaiveDateTime,
+    pub end: NaiveDateTime,
     /// ID of the associated mission
     id_mission: i64,
+    /// Date and time the visit was cancelled
+    pub cancelled_at: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Queryable, Selectable, ToSchema)]
 pub struct Visit {
     #[serde(flatten)]
     #[diesel(embed)]
-    visit: VisitRecord,
+    pub visit: VisitRecord,
     #[diesel(embed)]
     pub mission: Mission,
 }
 
-impl From<Visit> for icalendar::Event {
-    fn from(value: Visit) -> Self {
-        use icalendar::{Component, EventLike};
-
-        icalendar::Event::new()
-            .uid(&value.visit.id.to_string())
-            .summary(&value.mission.mission_type.name)
-            .description(&format!(
-                "Patient: {} {}\n\nDescription: {}\n\n",
-                value.mission.patient.user.fname,
-                value.mission.patient.user.lname.to_uppercase(),
-                value.mission.mission.desc.unwrap_or_default()
-            ))
-            .starts(value.visit.start)
-            .ends(value.visit.end)
-            .location(&value.mission.patient.address.to_string())
-            .done()
+/// A visit from the schedule of a nurse.
+#[derive(Serialize, ToSchema)]
+pub struct PlannedVisit {
+    #[serde(flatten)]
+    pub visit: Visit,
+    /// Other nurses assigned to this visit
+    pub colleagues: Vec<Colleague>,
+    /// Whether the nurse has written its report for this visit
+    pub report_filled: bool,
+}
+
+/// Progress of a visit, derived from the actual times of its nurses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VisitStatus {
+    /// Nobody has arrived yet and the visit has not ended
+    Planned,
+    /// A nurse has arrived and not left yet
+    InProgress,
+    /// Every nurse who arrived has left
+    Done,
+    /// The visit ended without anybody arriving
+    Missed,
+    Cancelled,
+}
+
+impl VisitStatus {
+    pub fn of(visit: &VisitRecord, nurses: &[NurseAttendance], now: NaiveDateTime) -> Self {
+        let arrived = || nurses.iter().filter(|n| n.arrived_at.is_some());
+
+        if visit.cancelled_at.is_some() {
+            Self::Cancelled
+        } else if arrived().any(|n| n.left_at.is_none()) {
+            Self::InProgress
+        } else if arrived().next().is_some() {
+            Self::Done
+        } else if visit.end < now {
+            Self::Missed
+        } else {
+            Self::Planned
+        }
+    }
+}
+
+/// A visit along with what was actually performed.
+#[derive(Serialize, ToSchema)]
+pub struct VisitAttendance {
+    #[serde(flatten)]
+    pub visit: VisitRecord,
+    pub status: VisitStatus,
+    /// Minutes between the planned start and the first arrival, if late
+    pub delay: Option<i64>,
+    pub nurses: Vec<NurseAttendance>,
+}
+
+impl VisitAttendance {
+    pub fn new(visit: VisitRecord, nurses: Vec<NurseAttendance>, now: NaiveDateTime) -> Self {
+        let delay = nurses
+            .iter()
+            .filter_map(|n| n.arrived_at)
+            .min()
+            .map(|arrival| (arrival - visit.start).num_minutes())
+            .filter(|&d| d > 0);
+
+        Self {
+            status: VisitStatus::of(&visit, &nurses, now),
+            delay,
+            nurses,
+            visit,
+        }
     }
 }
 
+/// Period and filter of the attendance of visits.
+#[derive(Deserialize, IntoParams)]
+pub struct AttendanceParam {
+    /// Only the visits beginning after this date, defaults to a week ago
+    pub from: Option<NaiveDateTime>,
+    /// Only the visits beginning before this date, defaults to now
+    pub until: Option<NaiveDateTime>,
+    /// Lists every visit of the period instead of only the late and missed ones
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// A visit from the care of a patient.
+#[derive(Serialize, ToSchema)]
+pub struct PatientVisit {
+    #[serde(flatten)]
+    pub visit: VisitRecord,
+    /// Kind of care given during the visit
+    pub mission_type: MissionType,
+    /// Nurses assigned to the visit
+    pub nurses: Vec<AssignedNurse>,
+}
+
 #[derive(Deserialize, AsChangeset, ToSchema)]
 #[diesel(table_name = visits)]
 pub struct UpdateVisit {
@@ -61,9 +139,100 @@ pub struct UpdateVisit {
 #[diesel(table_name = visits)]
 pub struct NewVisit {
     /// Date and time the visit begins
-    start: NaiveDateTime,
+    pub start: NaiveDateTime,
     /// Date and time the visit ends
-    end: NaiveDateTime,
+    pub end: NaiveDateTime,
     /// ID of the associated mission
-    id_mission: i64,
+    pub id_mission: i64,
+}
+
+/// Defines the time horizon over which visits are generated from missions.
+#[derive(Deserialize, IntoParams)]
+pub struct GenerateVisitsParam {
+    /// Visits are not generated before this date, defaults to now
+    pub from: Option<NaiveDateTime>,
+    /// Visits are generated up to this date
+    pub until: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn datetime(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 8)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn visit(cancelled: bool) -> VisitRecord {
+        VisitRecord {
+            id: 1,
+            start: datetime(8, 0),
+            end: datetime(9, 0),
+            id_mission: 1,
+            cancelled_at: cancelled.then(|| datetime(7, 0)),
+        }
+    }
+
+    fn nurse(arrived_at: Option<NaiveDateTime>, left_at: Option<NaiveDateTime>) -> NurseAttendance {
+        NurseAttendance {
+            id_nurse: 1,
+            arrived_at,
+            left_at,
+        }
+    }
+
+    #[test]
+    fn statuses() {
+        let now = datetime(8, 30);
+        let arrived = nurse(Some(datetime(8, 10)), None);
+        let left = nurse(Some(datetime(8, 10)), Some(datetime(8, 40)));
+        let absent = nurse(None, None);
+
+        assert_eq!(
+            VisitStatus::of(&visit(false), &[], now),
+            VisitStatus::Planned
+        );
+        assert_eq!(
+            VisitStatus::of(&visit(false), &[absent, arrived], now),
+            VisitStatus::InProgress
+        );
+        assert_eq!(
+            VisitStatus::of(&visit(false), &[left], now),
+            VisitStatus::Done
+        );
+        assert_eq!(
+            VisitStatus::of(&visit(false), &[nurse(None, None)], datetime(10, 0)),
+            VisitStatus::Missed
+        );
+        assert_eq!(
+            VisitStatus::of(&visit(true), &[], now),
+            VisitStatus::Cancelled
+        );
+    }
+
+    /// The delay is taken from the first nurse to arrive
+    #[test]
+    fn delay() {
+        let nurses = vec![
+            nurse(Some(datetime(8, 20)), None),
+            nurse(Some(datetime(8, 5)), None),
+        ];
+        let attendance = VisitAttendance::new(visit(false), nurses, datetime(8, 30));
+
+        assert_eq!(attendance.delay, Some(5));
+        assert_eq!(
+            VisitAttendance::new(
+                visit(false),
+                vec![nurse(Some(datetime(7, 55)), None)],
+                datetime(8, 30)
+            )
+            .delay,
+            None
+        );
+    }
 }
@@ -3,13 +3,18 @@ use diesel::{AsChangeset, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::schema::zones;
+use crate::{schema::zones, zoning::Boundary};
 
 #[derive(Serialize, Queryable, HasColumn, ToSchema)]
+#[diesel(table_name = zones)]
 pub struct ZoneRecord {
     id: i64,
-    name: String,
+    pub name: String,
     pub id_center: i64,
+    /// Area covered by the zone, see [`crate::zoning`]
+    pub boundary: Option<Boundary>,
+    /// Postcodes covered by the zone
+    pub postcodes: Vec<Option<String>>,
 }
 
 #[derive(Deserialize, Insertable, ToSchema)]
@@ -18,11 +23,34 @@ pub struct NewZone {
     pub name: String,
     #[serde(skip_deserializing)]
     pub id_center: i64,
+    /// Area covered by the zone, addresses inside are assigned to it
+    pub boundary: Option<Boundary>,
+    /// Addresses with these postcodes are assigned to the zone, unless located inside the
+    /// boundary of another one
+    #[serde(default)]
+    pub postcodes: Vec<String>,
 }
 
 #[derive(Deserialize, AsChangeset, ToSchema)]
 #[diesel(table_name = zones)]
 pub struct UpdateZone {
     name: Option<String>,
+    #[serde(skip_deserializing)]
     pub id_center: Option<i64>,
+    pub boundary: Option<Option<Boundary>>,
+    pub postcodes: Option<Vec<String>>,
+}
+
+/// Address whose zone does not cover it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MisplacedAddress {
+    pub id_address: i64,
+    /// Nurse living at the address, if any
+    pub id_nurse: Option<i64>,
+    /// Patient living at the address, if any
+    pub id_patient: Option<i64>,
+    /// Current zone of the address
+    pub id_zone: i64,
+    /// Zone covering the address, `null` if no zone does
+    pub expected_zone: Option<i64>,
 }
@@ -1,9 +1,12 @@
 //! Contains everything related to pagination, the query parameters and the response.
 
+use actix_web::error::ErrorBadRequest;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::{IntoParams, ToSchema};
 
-use crate::models::*;
+use crate::{error::Result, models::*};
 
 /// Defines the query parameters that can be received for pagination.
 #[derive(Clone, Deserialize, IntoParams)]
@@ -15,6 +18,9 @@ pub struct PaginationParam {
     /// Number of record per page
     #[serde(alias = "perPage")]
     pub per_page: u8,
+
+    /// Whether to count the total number of records, which takes another query
+    pub count: bool,
 }
 
 const DEFAULT_PAGE: u32 = 1;
@@ -25,6 +31,7 @@ impl Default for PaginationParam {
         Self {
             page: DEFAULT_PAGE,
             per_page: DEFAULT_PER_PAGE,
+            count: true,
         }
     }
 }
@@ -39,15 +46,124 @@ impl PaginationParam {
     pub fn limit(&self) -> u8 {
         self.per_page
     }
+
+    /// Gets the total number of records from `count`, unless it was not requested.
+    pub fn total(&self, count: impl FnOnce() -> Result<i64>) -> Result<Option<u32>> {
+        if self.count {
+            Ok(Some(count()? as u32))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the records to load, following the cursor if any rather than the page number.
+    pub fn page(&self, cursor: &CursorParam) -> Result<Page> {
+        let Some(raw) = &cursor.cursor else {
+            return Ok(Page {
+                params: self.clone(),
+                keyset: false,
+                cursor: None,
+            });
+        };
+
+        Ok(Page {
+            params: self.clone(),
+            keyset: true,
+            cursor: if raw.is_empty() {
+                None
+            } else {
+                Some(Cursor::decode(raw)?)
+            },
+        })
+    }
+}
+
+/// Defines the query parameter of the keyset pagination.
+///
+/// Records are then paginated from the last one received rather than a page number, so pages do
+/// not skip nor repeat records inserted or deleted meanwhile.
+#[derive(Clone, Default, Deserialize, IntoParams)]
+pub struct CursorParam {
+    /// Cursor of the page to get, the `next` or `prev` of another page, empty for the first one.
+    /// `page` is ignored when given.
+    pub cursor: Option<String>,
+}
+
+/// Position in a sorted list of records, given to clients as an opaque string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Whether the page is before the record rather than after
+    #[serde(rename = "b", default)]
+    pub backward: bool,
+    /// Values of the sort keys of the record
+    #[serde(rename = "k")]
+    pub keys: Vec<Value>,
+}
+
+impl Cursor {
+    fn new(backward: bool, keys: &Value) -> Self {
+        Self {
+            backward,
+            keys: keys.as_array().cloned().unwrap_or_default(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(raw: &str) -> Result<Self> {
+        URL_SAFE_NO_PAD
+            .decode(raw)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ErrorBadRequest("Invalid cursor").into())
+    }
+}
+
+/// Records to load for a page, see [`PaginationParam::page`].
+///
+/// Records are loaded with the values of their sort keys, see
+/// [`crate::params::SortParam::keys`], and filtered and sorted with
+/// [`crate::params::SortParam::seek`] given [`Self::cursor`].
+#[derive(Clone)]
+pub struct Page {
+    params: PaginationParam,
+    /// Whether the keyset pagination is used
+    keyset: bool,
+    /// Cursor of the page, `None` for the first one
+    cursor: Option<Cursor>,
+}
+
+impl Page {
+    pub fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
+
+    pub fn offset(&self) -> i64 {
+        if self.keyset {
+            0
+        } else {
+            self.params.offset().into()
+        }
+    }
+
+    /// Get the limit of records to retrieve, one more than a page with a cursor to know whether
+    /// there is a next one.
+    pub fn limit(&self) -> i64 {
+        i64::from(self.params.limit()) + i64::from(self.keyset)
+    }
 }
 
 /// A paginated response format.
 ///
 /// Contains paging metadata and the inner data. `page` and `per_page` can be set from
-/// [PaginationParam]. `total` and `total_page` can be set using [Self::total].
+/// [PaginationParam]. `total` and `total_page` can be set using [Self::total]. `next` and `prev`
+/// are set with the keyset pagination, see [Self::page].
 #[derive(Serialize, ToSchema)]
 #[aliases(
     PaginatedSkills = PaginatedResponse<Skill>,
+    PaginatedAuditEntries = PaginatedResponse<AuditEntry>,
     PaginatedCenters = PaginatedResponse<CenterRecord>,
     PaginatedMissionTypes = PaginatedResponse<MissionType>,
     PaginatedNurses = PaginatedResponse<Nurse>,
@@ -55,6 +171,8 @@ impl PaginationParam {
     PaginatedPatients = PaginatedResponse<Patient>,
     PaginatedMissions = PaginatedResponse<Mission>,
     PaginatedVisits = PaginatedResponse<Visit>,
+    PaginatedVisitAttendances = PaginatedResponse<VisitAttendance>,
+    PaginatedPatientVisits = PaginatedResponse<PatientVisit>,
     PaginatedManagers = PaginatedResponse<Manager>,
     PaginatedAvailabilities = PaginatedResponse<Availability>,
     PaginatedZones = PaginatedResponse<ZoneRecord>,
@@ -71,6 +189,10 @@ pub struct PaginatedResponse<T: Serialize> {
     total: Option<u32>,
     /// Total number of pages
     total_page: Option<u32>,
+    /// Cursor of the next page, if any
+    next: Option<String>,
+    /// Cursor of the previous page, if any
+    prev: Option<String>,
 }
 
 impl<T: Serialize> PaginatedResponse<T> {
@@ -82,22 +204,115 @@ impl<T: Serialize> PaginatedResponse<T> {
             per_page: params.per_page,
             total: None,
             total_page: None,
+            next: None,
+            prev: None,
         }
     }
 
+    /// Creates a paginated response from records loaded with the values of their sort keys.
+    pub fn page(rows: Vec<(T, Value)>, page: &Page) -> Self {
+        let mut rows = rows;
+        let more = rows.len() > page.params.per_page as usize;
+        rows.truncate(page.params.per_page as usize);
+
+        let mut res = Self {
+            prev: None,
+            next: None,
+            ..Self::new(Vec::new(), &page.params)
+        };
+        if page.keyset {
+            let backward = page.cursor.as_ref().is_some_and(|c| c.backward);
+            if backward {
+                rows.reverse();
+            }
+
+            // Without records, the previous and next pages are around the cursor
+            let around = |backward: bool| {
+                page.cursor.as_ref().map(|c| Cursor {
+                    backward,
+                    keys: c.keys.clone(),
+                })
+            };
+            let before = rows
+                .first()
+                .map(|(_, keys)| Cursor::new(true, keys))
+                .or_else(|| around(true));
+            let after = rows
+                .last()
+                .map(|(_, keys)| Cursor::new(false, keys))
+                .or_else(|| around(false));
+
+            let (prev, next) = if backward {
+                (before.filter(|_| more), after)
+            } else {
+                (
+                    before.filter(|_| page.cursor.is_some()),
+                    after.filter(|_| more),
+                )
+            };
+            res.prev = prev.as_ref().map(Cursor::encode);
+            res.next = next.as_ref().map(Cursor::encode);
+        }
+
+        res.data = rows.into_iter().map(|(record, _)| record).collect();
+        res
+    }
+
     /// Sets the total number of records.
     ///
     /// The total number of pages is computed from this value.
-    pub fn total(mut self, total: u32) -> Self {
-        self.total = Some(total);
-        self.total_page = Some((total as f32 / self.per_page as f32).ceil() as u32);
+    pub fn total(mut self, total: impl Into<Option<u32>>) -> Self {
+        self.total = total.into();
+        self.total_page = self
+            .total
+            .map(|total| (total as f32 / self.per_page as f32).ceil() as u32);
         self
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{PaginatedResponse, PaginationParam};
+    use serde_json::json;
+
+    use super::{Cursor, CursorParam, PaginatedResponse, PaginationParam};
+
+    /// Loads the page following `cursor` of records `0..total`, sorted by themselves
+    fn keyset_page(cursor: Option<&str>, total: i64) -> PaginatedResponse<i64> {
+        let params = PaginationParam {
+            page: 1,
+            per_page: 2,
+            count: false,
+        };
+        let page = params
+            .page(&CursorParam {
+                cursor: cursor.map(String::from),
+            })
+            .unwrap();
+        let mut records: Vec<i64> = (0..total).collect();
+        let rows = match page.cursor() {
+            Some(Cursor {
+                backward: true,
+                keys,
+            }) => {
+                records.reverse();
+                records.retain(|&r| r < keys[0].as_i64().unwrap());
+                records
+            }
+            Some(Cursor { keys, .. }) => {
+                records.retain(|&r| r > keys[0].as_i64().unwrap());
+                records
+            }
+            None => records,
+        };
+
+        PaginatedResponse::page(
+            rows.into_iter()
+                .take(page.limit() as usize)
+                .map(|r| (r, json!([r])))
+                .collect(),
+            &page,
+        )
+    }
 
     #[test]
     fn pagination_total() {
@@ -106,6 +321,7 @@ mod tests {
             &PaginationParam {
                 page: 1,
                 per_page: 15,
+                count: true,
             },
         )
         .total(30);
@@ -120,10 +336,54 @@ mod tests {
             &PaginationParam {
                 page: 1,
                 per_page: 15,
+                count: true,
             },
         )
         .total(32);
 
         assert_eq!(pag.total_page, Some(3));
     }
+
+    #[test]
+    fn cursor_encoding() {
+        let cursor = Cursor {
+            backward: true,
+            keys: vec![json!("2030-01-07T08:00:00"), json!(3)],
+        };
+
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+        assert!(Cursor::decode("not a cursor").is_err());
+    }
+
+    /// Going forward then backward gives back the same pages
+    #[test]
+    fn keyset_pagination() {
+        let first = keyset_page(Some(""), 5);
+        assert_eq!(first.data, [0, 1]);
+        assert_eq!(first.prev, None);
+
+        let second = keyset_page(first.next.as_deref(), 5);
+        assert_eq!(second.data, [2, 3]);
+
+        let last = keyset_page(second.next.as_deref(), 5);
+        assert_eq!(last.data, [4]);
+        assert_eq!(last.next, None);
+
+        let back = keyset_page(last.prev.as_deref(), 5);
+        assert_eq!(back.data, [2, 3]);
+        assert_eq!(back.next, second.next);
+
+        let start = keyset_page(back.prev.as_deref(), 5);
+        assert_eq!(start.data, [0, 1]);
+        assert_eq!(start.prev, None);
+    }
+
+    /// Without cursor, pages are numbered
+    #[test]
+    fn offset_pagination() {
+        let page = keyset_page(None, 5);
+
+        assert_eq!(page.data, [0, 1]);
+        assert_eq!((page.prev, page.next), (None, None));
+    }
 }
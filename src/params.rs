@@ -1,5 +1,9 @@
+mod filter;
+mod period;
 mod search;
 mod sort;
 
+pub use filter::*;
+pub use period::*;
 pub use search::*;
 pub use sort::*;
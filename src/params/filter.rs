@@ -0,0 +1,417 @@
+//! Holds the filters a route can receive to restrict data.
+use std::marker::PhantomData;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use diesel::{
+    expression::{is_aggregate, AppearsOnTable, Expression, SelectableExpression, ValidGrouping},
+    pg::Pg,
+    query_builder::{AstPass, QueryFragment, QueryId},
+    sql_types::{BigInt, Bool, Date, Double, Text, Time, Timestamp},
+    QueryResult,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::models::{Column, ColumnType, HasColumn};
+
+/// Operators, the longest first so `>=` is not read as `>`.
+const OPERATORS: [(&str, Operator); 7] = [
+    (">=", Operator::Ge),
+    ("<=", Operator::Le),
+    ("!=", Operator::Ne),
+    (">", Operator::Gt),
+    ("<", Operator::Lt),
+    ("=", Operator::Eq),
+    ("~", Operator::Contains),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// Case insensitive substring, for text only
+    Contains,
+}
+
+impl Operator {
+    fn sql(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "<>",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Contains => "ILIKE",
+        }
+    }
+}
+
+/// A value parsed according to the type of its column.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Value {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Timestamp(NaiveDateTime),
+    Date(NaiveDate),
+    Time(NaiveTime),
+}
+
+impl Value {
+    pub(super) fn parse(sql_type: ColumnType, value: &str) -> Option<Self> {
+        let value = value.trim();
+
+        Some(match sql_type {
+            ColumnType::Integer => Self::Integer(value.parse().ok()?),
+            ColumnType::Float => Self::Float(value.parse().ok()?),
+            ColumnType::Bool => Self::Bool(value.parse().ok()?),
+            ColumnType::Text => Self::Text(value.to_string()),
+            // A date alone is the beginning of the day
+            ColumnType::Timestamp => Self::Timestamp(
+                value
+                    .parse()
+                    .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+                    .or_else(|_| {
+                        value
+                            .parse::<NaiveDate>()
+                            .map(|d| d.and_time(NaiveTime::MIN))
+                    })
+                    .ok()?,
+            ),
+            ColumnType::Date => Self::Date(value.parse().ok()?),
+            ColumnType::Time => Self::Time(value.parse().ok()?),
+        })
+    }
+
+    pub(super) fn bind<'b>(&'b self, out: &mut AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        match self {
+            Self::Integer(v) => out.push_bind_param::<BigInt, _>(v),
+            Self::Float(v) => out.push_bind_param::<Double, _>(v),
+            Self::Bool(v) => out.push_bind_param::<Bool, _>(v),
+            Self::Text(v) => out.push_bind_param::<Text, _>(v),
+            Self::Timestamp(v) => out.push_bind_param::<Timestamp, _>(v),
+            Self::Date(v) => out.push_bind_param::<Date, _>(v),
+            Self::Time(v) => out.push_bind_param::<Time, _>(v),
+        }
+    }
+}
+
+/// Condition on a column.
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    /// Compares to a value, a `LIKE` pattern for [`Operator::Contains`]
+    Compare(Operator, Value),
+    /// Equal to any of the values
+    In(Vec<Value>),
+    IsNull,
+    IsNotNull,
+}
+
+/// A filter validated against the columns of a model.
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    column: Column,
+    condition: Condition,
+}
+
+impl Filter {
+    /// Parses a filter such as `start>=2024-01-01`.
+    fn parse(columns: &[Column], raw: &str) -> Result<Self, String> {
+        let name_end = raw
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(raw.len());
+        let (name, rest) = raw.split_at(name_end);
+
+        let column = *columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("column '{name}' cannot be filtered on"))?;
+        let (op, value) = OPERATORS
+            .iter()
+            .find_map(|(s, op)| rest.strip_prefix(s).map(|value| (*op, value)))
+            .ok_or_else(|| format!("missing operator after '{name}'"))?;
+
+        let parse = |value: &str| {
+            Value::parse(column.sql_type, value)
+                .ok_or_else(|| format!("invalid value '{value}' for '{name}'"))
+        };
+
+        let condition = match (op, value.strip_prefix("in:")) {
+            (Operator::Eq | Operator::Ne, _) if value == "null" => {
+                if !column.nullable {
+                    return Err(format!("'{name}' cannot be null"));
+                }
+                if op == Operator::Eq {
+                    Condition::IsNull
+                } else {
+                    Condition::IsNotNull
+                }
+            }
+            (Operator::Eq, Some(value)) => Condition::In(vec![parse(value)?]),
+            (Operator::Contains, _) if column.sql_type != ColumnType::Text => {
+                return Err(format!("'{name}' is not text, '~' cannot be used"));
+            }
+            (Operator::Contains, _) => Condition::Compare(op, Value::Text(format!("%{value}%"))),
+            (Operator::Gt | Operator::Ge | Operator::Lt | Operator::Le, _)
+                if column.sql_type == ColumnType::Bool =>
+            {
+                return Err(format!("'{name}' is a boolean, it cannot be compared"));
+            }
+            _ => Condition::Compare(op, parse(value)?),
+        };
+
+        Ok(Self { column, condition })
+    }
+
+    fn walk_ast<'b>(&'b self, out: &mut AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        if let Some(table) = self.column.table {
+            out.push_identifier(table)?;
+            out.push_sql(".");
+        }
+        out.push_identifier(self.column.name)?;
+
+        match &self.condition {
+            Condition::Compare(op, value) => {
+                out.push_sql(&format!(" {} ", op.sql()));
+                value.bind(out)
+            }
+            Condition::In(values) => {
+                out.push_sql(" IN (");
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push_sql(", ");
+                    }
+                    value.bind(out)?;
+                }
+                out.push_sql(")");
+                Ok(())
+            }
+            Condition::IsNull => {
+                out.push_sql(" IS NULL");
+                Ok(())
+            }
+            Condition::IsNotNull => {
+                out.push_sql(" IS NOT NULL");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FilterQuery {
+    #[serde(default)]
+    filter: String,
+}
+
+/// Parameter to filter data on the columns of the model `T`.
+///
+/// Filters are `,` separated and all have to match, such as
+/// `start>=2024-01-01,archived=false,id_mission_type=in:3,4`. Each one is a column, an operator
+/// and a value of the type of the column:
+///
+/// - `=`, `!=`, `>`, `>=`, `<` and `<=` compare the column to the value, `null` being allowed
+///   with `=` and `!=` for nullable columns
+/// - `=in:` followed by `,` separated values matches any of them
+/// - `~` matches text containing the value, case insensitive
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "FilterQuery", bound = "T: HasColumn")]
+pub struct FilterParam<T> {
+    filters: Vec<Filter>,
+    model: PhantomData<T>,
+}
+
+impl<T> Default for FilterParam<T> {
+    fn default() -> Self {
+        Self {
+            filters: Vec::new(),
+            model: PhantomData,
+        }
+    }
+}
+
+impl<T: HasColumn> TryFrom<FilterQuery> for FilterParam<T> {
+    type Error = String;
+
+    fn try_from(query: FilterQuery) -> Result<Self, Self::Error> {
+        let columns = T::columns();
+        let mut filters: Vec<Filter> = Vec::new();
+
+        for raw in query.filter.split(',').filter(|f| !f.trim().is_empty()) {
+            // Values of `in:` lists are separated like filters
+            if let Some(Filter {
+                column,
+                condition: Condition::In(values),
+            }) = filters.last_mut()
+            {
+                if !raw.contains(|c| OPERATORS.iter().any(|(s, _)| s.starts_with(c))) {
+                    values.push(
+                        Value::parse(column.sql_type, raw).ok_or_else(|| {
+                            format!("invalid value '{raw}' for '{}'", column.name)
+                        })?,
+                    );
+                    continue;
+                }
+            }
+
+            filters.push(Filter::parse(&columns, raw.trim())?);
+        }
+
+        Ok(Self {
+            filters,
+            model: PhantomData,
+        })
+    }
+}
+
+impl<T> FilterParam<T> {
+    /// Returns an expression usable by [`diesel::query_dsl::QueryDsl::filter`], true if there is
+    /// no filter.
+    ///
+    /// Columns are qualified with their table, values are bound as parameters.
+    pub fn expression(&self) -> FilterExpression {
+        FilterExpression(self.filters.clone())
+    }
+}
+
+/// SQL expression matching every filter of a [`FilterParam`].
+#[derive(Debug, Clone)]
+pub struct FilterExpression(Vec<Filter>);
+
+impl Expression for FilterExpression {
+    type SqlType = Bool;
+}
+
+impl QueryFragment<Pg> for FilterExpression {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("TRUE");
+        for filter in &self.0 {
+            out.push_sql(" AND ");
+            filter.walk_ast(&mut out)?;
+        }
+        Ok(())
+    }
+}
+
+impl QueryId for FilterExpression {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<QS> AppearsOnTable<QS> for FilterExpression {}
+
+impl<QS> SelectableExpression<QS> for FilterExpression {}
+
+impl<GB> ValidGrouping<GB> for FilterExpression {
+    type IsAggregate = is_aggregate::Never;
+}
+
+impl<T: HasColumn> IntoParams for FilterParam<T> {
+    fn into_params(
+        parameter_in_provider: impl Fn() -> Option<utoipa::openapi::path::ParameterIn>,
+    ) -> Vec<utoipa::openapi::path::Parameter> {
+        let columns: Vec<String> = T::columns()
+            .iter()
+            .map(|c| format!("`{}`", c.name))
+            .collect();
+
+        vec![utoipa::openapi::path::ParameterBuilder::new()
+            .name("filter")
+            .description(Some(format!(
+                "Filters to match, `,` separated.\n\nEach takes the form \
+                 `<column><operator><value>`, the operator being one of `=`, `!=`, `>`, `>=`, \
+                 `<`, `<=` or `~` for text containing the value. `=in:<value>,<value>` matches any \
+                 of the values, `=null` and `!=null` nullable columns.\n\nColumns: {}.",
+                columns.join(", ")
+            )))
+            .parameter_in(parameter_in_provider().unwrap_or_default())
+            .required(utoipa::openapi::Required::False)
+            .schema(Some(
+                utoipa::openapi::ObjectBuilder::new()
+                    .schema_type(utoipa::openapi::SchemaType::String)
+                    .example(Some("start>=2024-01-01,id_mission=in:3,4".into())),
+            ))
+            .build()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::Query;
+    use diesel::{debug_query, QueryDsl};
+
+    use super::*;
+    use crate::{
+        models::{MissionRecord, VisitRecord},
+        schema::{missions, visits},
+    };
+
+    fn filter(query: &str) -> Result<FilterParam<(VisitRecord, MissionRecord)>, String> {
+        Query::from_query(query)
+            .map(Query::into_inner)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn no_filter() {
+        assert!(filter("").unwrap().filters.is_empty());
+        assert!(filter("page=2").unwrap().filters.is_empty());
+    }
+
+    #[test]
+    fn typed_values() {
+        let f =
+            filter("filter=start>=2024-01-01,cancelled_at=null,id_mission_type=in:3,4").unwrap();
+
+        assert_eq!(
+            f.filters[0].condition,
+            Condition::Compare(
+                Operator::Ge,
+                Value::Timestamp(
+                    NaiveDate::from_ymd_opt(2024, 1, 1)
+                        .unwrap()
+                        .and_time(NaiveTime::MIN)
+                )
+            )
+        );
+        assert_eq!(f.filters[0].column.table, Some("visits"));
+        assert_eq!(f.filters[1].condition, Condition::IsNull);
+        assert_eq!(
+            f.filters[2].condition,
+            Condition::In(vec![Value::Integer(3), Value::Integer(4)])
+        );
+        assert_eq!(f.filters[2].column.table, Some("missions"));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(filter("filter=foo=1").is_err());
+        assert!(filter("filter=start>=yesterday").is_err());
+        assert!(filter("filter=start").is_err());
+        assert!(filter("filter=id=null").is_err());
+        assert!(filter("filter=id~1").is_err());
+    }
+
+    /// Values are bound rather than written in the query
+    #[test]
+    fn bound_values() {
+        let f = filter("filter=desc~'; DROP TABLE visits; --").unwrap();
+        let query = visits::table
+            .inner_join(missions::table)
+            .select(visits::id)
+            .filter(f.expression());
+        let sql = debug_query::<Pg, _>(&query).to_string();
+
+        assert!(
+            sql.contains(r#"TRUE AND "missions"."desc" ILIKE $1"#),
+            "{sql}"
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Represents a period of time to restrict data to.
+#[derive(Clone, Deserialize, IntoParams)]
+pub struct PeriodParam {
+    /// Start of the period
+    pub from: NaiveDateTime,
+    /// End of the period
+    pub to: NaiveDateTime,
+}
+
+impl PeriodParam {
+    /// Returns true if the period ends after it starts.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.from < self.to
+    }
+}
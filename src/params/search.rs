@@ -25,3 +25,28 @@ impl SearchParam {
         &self.search
     }
 }
+
+/// Represents the parameters of a full-text search, see [`crate::search`].
+#[derive(Clone, Deserialize, IntoParams)]
+pub struct FullTextSearchParam {
+    /// Words to search for, accents are ignored and words are matched whatever their form.
+    ///
+    /// Phrases can be quoted and words excluded with `-`, e.g. `"soins palliatifs" -domicile`.
+    q: String,
+
+    /// Maximum number of hits
+    #[serde(default = "default_limit")]
+    pub limit: u8,
+}
+
+fn default_limit() -> u8 {
+    20
+}
+
+impl FullTextSearchParam {
+    /// Returns the search without surrounding spaces.
+    #[must_use]
+    pub fn value(&self) -> &str {
+        self.q.trim()
+    }
+}
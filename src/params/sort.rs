@@ -1,16 +1,30 @@
-/// Holds the parameters a route can receive to sort data.
+//! Holds the parameters a route can receive to sort data.
 use std::{
     fmt::{self, Display},
+    marker::PhantomData,
     str::FromStr,
 };
 
-use serde::de;
+use actix_web::error::ErrorBadRequest;
+use diesel::{
+    expression::{is_aggregate, AppearsOnTable, Expression, SelectableExpression, ValidGrouping},
+    pg::Pg,
+    query_builder::{AstPass, QueryFragment, QueryId},
+    sql_types::{Bool, Jsonb, Text},
+    QueryResult,
+};
+use serde::Deserialize;
 use utoipa::IntoParams;
 
-use crate::models::{self};
+use super::filter::Value;
+use crate::{
+    error,
+    models::{Column, HasColumn},
+    pagination::Cursor,
+};
 
 /// Direction in which to sort data.
-#[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 enum OrderByDirection {
     #[default]
     Asc,
@@ -18,18 +32,27 @@ enum OrderByDirection {
 }
 
 impl Display for OrderByDirection {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "{}",
             match self {
-                Self::Asc => "asc",
-                Self::Desc => "desc",
+                Self::Asc => "ASC",
+                Self::Desc => "DESC",
             }
         )
     }
 }
 
+impl OrderByDirection {
+    fn reversed(self) -> Self {
+        match self {
+            Self::Asc => Self::Desc,
+            Self::Desc => Self::Asc,
+        }
+    }
+}
+
 impl FromStr for OrderByDirection {
     type Err = String;
 
@@ -37,174 +60,519 @@ impl FromStr for OrderByDirection {
         match s.to_lowercase().trim() {
             "asc" => Ok(Self::Asc),
             "desc" => Ok(Self::Desc),
-            _ => Err(format!("invalid direction '{}'", s)),
+            _ => Err(format!(
+                "invalid direction '{}', expected 'asc' or 'desc'",
+                s
+            )),
         }
     }
 }
 
-/// Parameter to sort data.
-///
-/// `sort` is for the query while `col` and `direction` are the validated data.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SortParam {
-    /// Field and direction, `:` separated.
-    sort: String,
-    /// Column to sort by.
-    col: String,
-    /// Direction by which to sort.
+/// A column to sort by, validated against the columns of a model.
+#[derive(Debug, Clone, PartialEq)]
+struct SortKey {
+    column: Column,
     direction: OrderByDirection,
 }
 
-impl SortParam {
-    /// Returns an sql statement usable by [`diesel::query_dsl::QueryDsl::order`].
-    pub fn raw_sql(&self) -> diesel::expression::SqlLiteral<diesel::sql_types::Text> {
-        diesel::dsl::sql::<diesel::sql_types::Text>(&format!("{} {}", self.col, self.direction))
+impl SortKey {
+    /// Parses a key such as `lname:desc`.
+    fn parse(columns: &[Column], raw: &str) -> Result<Self, String> {
+        let mut data = raw.splitn(2, ':');
+        let name = data.next().unwrap_or_default().trim();
+
+        let column = *columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("column '{name}' cannot be sorted on"))?;
+        let direction = data
+            .next()
+            .map(OrderByDirection::from_str)
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self { column, direction })
+    }
+
+    fn reversed(&self) -> Self {
+        Self {
+            column: self.column,
+            direction: self.direction.reversed(),
+        }
+    }
+
+    fn walk_column(&self, out: &mut AstPass<'_, '_, Pg>) -> QueryResult<()> {
+        if let Some(table) = self.column.table {
+            out.push_identifier(table)?;
+            out.push_sql(".");
+        }
+        out.push_identifier(self.column.name)
+    }
+
+    fn walk_ast(&self, out: &mut AstPass<'_, '_, Pg>) -> QueryResult<()> {
+        self.walk_column(out)?;
+        out.push_sql(&format!(" {}", self.direction));
+        Ok(())
     }
+
+    /// Writes the condition of a column equal to a value.
+    fn walk_equal<'b>(
+        &self,
+        value: &'b Option<Value>,
+        out: &mut AstPass<'_, 'b, Pg>,
+    ) -> QueryResult<()> {
+        self.walk_column(out)?;
+        match value {
+            Some(value) => {
+                out.push_sql(" = ");
+                value.bind(out)
+            }
+            None => {
+                out.push_sql(" IS NULL");
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the condition of a column coming after a value in this order.
+    ///
+    /// `NULL` comes last in ascending order and first in descending order, like in PostgreSQL.
+    fn walk_after<'b>(
+        &self,
+        value: &'b Option<Value>,
+        out: &mut AstPass<'_, 'b, Pg>,
+    ) -> QueryResult<()> {
+        match (self.direction, value) {
+            (OrderByDirection::Asc, Some(value)) if self.column.nullable => {
+                out.push_sql("(");
+                self.walk_column(out)?;
+                out.push_sql(" > ");
+                value.bind(out)?;
+                out.push_sql(" OR ");
+                self.walk_column(out)?;
+                out.push_sql(" IS NULL)");
+            }
+            (OrderByDirection::Asc, Some(value)) => {
+                self.walk_column(out)?;
+                out.push_sql(" > ");
+                value.bind(out)?;
+            }
+            (OrderByDirection::Asc, None) => out.push_sql("FALSE"),
+            (OrderByDirection::Desc, Some(value)) => {
+                self.walk_column(out)?;
+                out.push_sql(" < ");
+                value.bind(out)?;
+            }
+            (OrderByDirection::Desc, None) => {
+                self.walk_column(out)?;
+                out.push_sql(" IS NOT NULL");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct SortQuery {
+    sort: Option<String>,
+}
+
+/// Parameter to sort data on the columns of the model `T`.
+///
+/// Keys are `,` separated such as `lname:asc,fname:desc`, each being a column and an optional
+/// direction, ascending by default. The primary key of the first model is always added last so
+/// pages do not overlap when sorted values are equal.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "SortQuery", bound = "T: HasColumn")]
+pub struct SortParam<T> {
+    keys: Vec<SortKey>,
+    model: PhantomData<T>,
 }
 
-impl Default for SortParam {
+impl<T: HasColumn> Default for SortParam<T> {
     fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T: HasColumn> SortParam<T> {
+    /// Adds the tiebreaker to the keys.
+    fn new(mut keys: Vec<SortKey>) -> Self {
+        let columns = T::columns();
+        for name in T::primary_key() {
+            let Some(column) = columns.iter().find(|c| c.name == name) else {
+                continue;
+            };
+            if !keys.iter().any(|k| k.column == *column) {
+                keys.push(SortKey {
+                    column: *column,
+                    direction: OrderByDirection::Asc,
+                });
+            }
+        }
+
         Self {
-            sort: format!("1:{}", OrderByDirection::default()),
-            col: String::from("1"),
-            direction: OrderByDirection::default(),
+            keys,
+            model: PhantomData,
         }
     }
 }
 
-impl IntoParams for SortParam {
+impl<T: HasColumn> TryFrom<SortQuery> for SortParam<T> {
+    type Error = String;
+
+    fn try_from(query: SortQuery) -> Result<Self, Self::Error> {
+        let Some(sort) = query.sort else {
+            return Ok(Self::default());
+        };
+
+        let columns = T::columns();
+        let keys = sort
+            .split(',')
+            .map(|raw| SortKey::parse(&columns, raw))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(keys))
+    }
+}
+
+impl<T> SortParam<T> {
+    /// Returns an expression usable by [`diesel::query_dsl::QueryDsl::order`].
+    ///
+    /// Columns are qualified with their table so they are not ambiguous in joins.
+    pub fn expression(&self) -> SortExpression {
+        SortExpression(self.keys.clone())
+    }
+
+    /// Returns an expression to select along with the records, the values of their keys to make
+    /// a [`Cursor`] from.
+    pub fn keys(&self) -> KeysExpression {
+        KeysExpression(self.keys.clone())
+    }
+
+    /// Returns the condition selecting the records following a cursor in this order, and the
+    /// order to load them in.
+    ///
+    /// The order is reversed for a cursor going backward, the records coming from the nearest.
+    pub fn seek(&self, cursor: Option<&Cursor>) -> error::Result<(SeekExpression, SortExpression)> {
+        let keys: Vec<SortKey> = match cursor {
+            Some(cursor) if cursor.backward => self.keys.iter().map(SortKey::reversed).collect(),
+            _ => self.keys.clone(),
+        };
+        let Some(cursor) = cursor else {
+            return Ok((SeekExpression(Vec::new()), SortExpression(keys)));
+        };
+
+        if cursor.keys.len() != keys.len() {
+            return Err(ErrorBadRequest("The cursor does not match the sort").into());
+        }
+        let values = keys
+            .iter()
+            .zip(&cursor.keys)
+            .map(|(key, value)| match value {
+                serde_json::Value::Null => Some(None),
+                serde_json::Value::String(value) => {
+                    Value::parse(key.column.sql_type, value).map(Some)
+                }
+                value => Value::parse(key.column.sql_type, &value.to_string()).map(Some),
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| ErrorBadRequest("The cursor does not match the sort"))?;
+
+        Ok((
+            SeekExpression(keys.iter().cloned().zip(values).collect()),
+            SortExpression(keys),
+        ))
+    }
+}
+
+/// Implements the traits making a type usable in any query as an SQL expression.
+macro_rules! expression {
+    ($name:ident, $sql_type:ty) => {
+        impl Expression for $name {
+            type SqlType = $sql_type;
+        }
+
+        impl QueryId for $name {
+            type QueryId = ();
+
+            const HAS_STATIC_QUERY_ID: bool = false;
+        }
+
+        impl<QS> AppearsOnTable<QS> for $name {}
+
+        impl<QS> SelectableExpression<QS> for $name {}
+
+        impl<GB> ValidGrouping<GB> for $name {
+            type IsAggregate = is_aggregate::Never;
+        }
+    };
+}
+
+/// SQL `ORDER BY` list of a [`SortParam`].
+#[derive(Debug, Clone)]
+pub struct SortExpression(Vec<SortKey>);
+
+expression!(SortExpression, Text);
+
+impl QueryFragment<Pg> for SortExpression {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        if self.0.is_empty() {
+            // Models without a primary key
+            out.push_sql("1");
+        }
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            key.walk_ast(&mut out)?;
+        }
+        Ok(())
+    }
+}
+
+/// SQL condition selecting the records after the values of a [`Cursor`].
+///
+/// Records come after if their first key does, or if it is equal and their next key does, and
+/// so on. Without cursor, every record is selected.
+#[derive(Debug, Clone)]
+pub struct SeekExpression(Vec<(SortKey, Option<Value>)>);
+
+expression!(SeekExpression, Bool);
+
+impl QueryFragment<Pg> for SeekExpression {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        if self.0.is_empty() {
+            out.push_sql("TRUE");
+            return Ok(());
+        }
+
+        out.push_sql("(");
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(" OR ");
+            }
+            out.push_sql("(");
+            for (previous, value) in &self.0[..i] {
+                previous.walk_equal(value, &mut out)?;
+                out.push_sql(" AND ");
+            }
+            key.walk_after(value, &mut out)?;
+            out.push_sql(")");
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+/// SQL array of the values of the keys of a [`SortParam`].
+#[derive(Debug, Clone)]
+pub struct KeysExpression(Vec<SortKey>);
+
+expression!(KeysExpression, Jsonb);
+
+impl QueryFragment<Pg> for KeysExpression {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("jsonb_build_array(");
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            key.walk_column(&mut out)?;
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+}
+
+impl<T: HasColumn> IntoParams for SortParam<T> {
     fn into_params(
         parameter_in_provider: impl Fn() -> Option<utoipa::openapi::path::ParameterIn>,
     ) -> Vec<utoipa::openapi::path::Parameter> {
+        let columns: Vec<String> = T::columns()
+            .iter()
+            .map(|c| format!("`{}`", c.name))
+            .collect();
+
         vec![utoipa::openapi::path::ParameterBuilder::new()
             .name("sort")
-            .description(Some(
-                "Field and direction to sort by.\n\nTakes the form `<column>[:asc|desc]`.",
-            ))
+            .description(Some(format!(
+                "Fields and directions to sort by, `,` separated.\n\nEach takes the form \
+                 `<column>[:asc|desc]`, ascending by default. Ties are sorted by `id`.\n\n\
+                 Columns: {}.",
+                columns.join(", ")
+            )))
             .parameter_in(parameter_in_provider().unwrap_or_default())
             .required(utoipa::openapi::Required::False)
             .schema(Some(
                 utoipa::openapi::ObjectBuilder::new()
                     .schema_type(utoipa::openapi::SchemaType::String)
-                    .pattern(Some(r"[a-z_]+(:(asc|desc))?")),
+                    .pattern(Some(r"[a-z_]+(:(asc|desc))?(,[a-z_]+(:(asc|desc))?)*"))
+                    .example(Some("lname:asc,fname:asc".into())),
             ))
             .build()]
     }
 }
 
-impl<'de> de::Deserialize<'de> for SortParam {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: de::Deserializer<'de>,
-    {
-        struct SortParamVisitor;
-
-        impl<'de> de::Visitor<'de> for SortParamVisitor {
-            type Value = SortParam;
-
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("sort parameters")
-            }
-
-            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-            where
-                A: de::MapAccess<'de>,
-            {
-                if let Some("sort") = map.next_key::<&str>()? {
-                    let value: String = map.next_value()?;
-                    let mut data = value.splitn(2, ':');
-
-                    let col: String = data
-                        .next()
-                        .expect("sort cannot be empty, expected column name")
-                        .into();
-
-                    if !models::has_column(&col) {
-                        return Err(de::Error::custom(format!(
-                            "column '{}' does not exist",
-                            &col
-                        )));
-                    }
-
-                    let direction = if let Some(dir) = data.next() {
-                        OrderByDirection::from_str(dir).map_err(|e| {
-                            de::Error::custom(format!("{}, expected 'asc' or 'desc'", e))
-                        })?
-                    } else {
-                        OrderByDirection::default()
-                    };
-
-                    Ok(SortParam {
-                        sort: value.clone(),
-                        col,
-                        direction,
-                    })
-                } else {
-                    Ok(SortParam::default())
-                }
-            }
-        }
-
-        deserializer.deserialize_map(SortParamVisitor)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use actix_web::web::Query;
+    use diesel::{debug_query, QueryDsl};
 
     use super::*;
+    use crate::{
+        models::{Address, LVisitNurse, PatientRecord, Skill, User},
+        schema::{addresses, patients, skills, users},
+    };
+
+    type Patients = (PatientRecord, User, Address);
+
+    fn sort<T: HasColumn>(query: &str) -> Result<Vec<(&'static str, OrderByDirection)>, String> {
+        Query::<SortParam<T>>::from_query(query)
+            .map(|s| {
+                s.keys
+                    .iter()
+                    .map(|k| (k.column.name, k.direction))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    }
 
-    /// No query parameter should give default sort order
+    /// No query parameter sorts by id
     #[test]
     fn no_param_default() {
-        let params = Query::<SortParam>::from_query("").unwrap();
-        assert_eq!(params.into_inner(), SortParam::default());
+        assert_eq!(sort::<Skill>("").unwrap(), [("id", OrderByDirection::Asc)]);
+    }
+
+    /// Every column of a composite primary key is a tiebreaker
+    #[test]
+    fn composite_primary_key() {
+        assert_eq!(
+            sort::<LVisitNurse>("sort=arrived_at:desc").unwrap(),
+            [
+                ("arrived_at", OrderByDirection::Desc),
+                ("id_visit", OrderByDirection::Asc),
+                ("id_nurse", OrderByDirection::Asc)
+            ]
+        );
     }
 
     /// Only a column name sort by column name and default direction.
     #[test]
     fn only_column_name() {
-        let params = Query::<SortParam>::from_query("sort=name").unwrap();
         assert_eq!(
-            params.into_inner(),
-            SortParam {
-                sort: "name".into(),
-                col: "name".into(),
-                direction: OrderByDirection::default()
-            }
+            sort::<Skill>("sort=name").unwrap(),
+            [
+                ("name", OrderByDirection::Asc),
+                ("id", OrderByDirection::Asc)
+            ]
         );
     }
 
     /// A column name and direction gets both.
     #[test]
     fn column_and_direction() {
-        let params = Query::<SortParam>::from_query("sort=name:desc").unwrap();
         assert_eq!(
-            params.into_inner(),
-            SortParam {
-                sort: "name:desc".into(),
-                col: "name".into(),
-                direction: OrderByDirection::Desc
-            }
+            sort::<Skill>("sort=id:desc").unwrap(),
+            [("id", OrderByDirection::Desc)]
+        );
+    }
+
+    #[test]
+    fn several_keys() {
+        assert_eq!(
+            sort::<Patients>("page=2&sort=lname:asc,fname:DESC").unwrap(),
+            [
+                ("lname", OrderByDirection::Asc),
+                ("fname", OrderByDirection::Desc),
+                ("id", OrderByDirection::Asc)
+            ]
         );
     }
 
     /// No value gives error
     #[test]
     fn param_no_value() {
-        assert!(Query::<SortParam>::from_query("sort=").is_err());
+        assert!(sort::<Skill>("sort=").is_err());
+        assert!(sort::<Skill>("sort=name,").is_err());
     }
 
     /// Incorrect direction gives error
     #[test]
     fn incorrect_direction() {
-        assert!(Query::<SortParam>::from_query("sort=name:incorrect_direction").is_err());
+        assert!(sort::<Skill>("sort=name:incorrect_direction").is_err());
     }
 
-    /// Incorrect field gives error
+    /// Columns of other models give error
     #[test]
     fn incorrect_field() {
-        assert!(Query::<SortParam>::from_query("sort=foo").is_err());
+        assert!(sort::<Skill>("sort=foo").is_err());
+        assert!(sort::<Skill>("sort=fname").is_err());
+        assert!(sort::<Skill>("sort=1").is_err());
+    }
+
+    /// Records after the cursor come after on the first key or are equal on it and after on the
+    /// next one
+    #[test]
+    fn seek_cursor() {
+        let s = Query::<SortParam<Skill>>::from_query("sort=name:desc").unwrap();
+        let cursor = Cursor {
+            backward: false,
+            keys: vec!["b".into(), 3.into()],
+        };
+        let (seek, order) = s.seek(Some(&cursor)).unwrap();
+        let query = skills::table.select(skills::id).filter(seek).order(order);
+        let sql = debug_query::<Pg, _>(&query).to_string();
+
+        assert!(
+            sql.contains(
+                r#"WHERE (("skills"."name" < $1) OR ("skills"."name" = $2 AND "skills"."id" > $3)) ORDER BY "skills"."name" DESC, "skills"."id" ASC"#
+            ),
+            "{sql}"
+        );
+
+        let backward = Cursor {
+            backward: true,
+            ..cursor
+        };
+        let (_, order) = s.seek(Some(&backward)).unwrap();
+        let query = skills::table.select(skills::id).order(order);
+        let sql = debug_query::<Pg, _>(&query).to_string();
+
+        assert!(
+            sql.contains(r#"ORDER BY "skills"."name" ASC, "skills"."id" DESC"#),
+            "{sql}"
+        );
+    }
+
+    /// Cursors of another sort are rejected
+    #[test]
+    fn seek_invalid_cursor() {
+        let s = SortParam::<Skill>::default();
+        let cursor = |keys: Vec<serde_json::Value>| Cursor {
+            backward: false,
+            keys,
+        };
+
+        assert!(s.seek(Some(&cursor(vec![3.into()]))).is_ok());
+        assert!(s.seek(Some(&cursor(vec!["b".into(), 3.into()]))).is_err());
+        assert!(s.seek(Some(&cursor(vec!["b".into()]))).is_err());
+    }
+
+    /// Columns are qualified with the table of the first model having them
+    #[test]
+    fn qualified_columns() {
+        let s = Query::<SortParam<Patients>>::from_query("sort=city_name:desc").unwrap();
+        let query = patients::table
+            .inner_join(users::table)
+            .inner_join(addresses::table)
+            .select(patients::id)
+            .order(s.expression());
+        let sql = debug_query::<Pg, _>(&query).to_string();
+
+        assert!(
+            sql.contains(r#"ORDER BY "addresses"."city_name" DESC, "patients"."id" ASC"#),
+            "{sql}"
+        );
     }
 }
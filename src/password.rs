@@ -0,0 +1,231 @@
+//! Password strength rules and reset tokens.
+//!
+//! The rules new passwords must follow are configured with the following environment variables:
+//!
+//! - `PASSWORD_MIN_LENGTH`: minimum number of characters, defaults to `12`
+//! - `PASSWORD_REQUIRE_LOWERCASE`: requires a lowercase letter, defaults to `true`
+//! - `PASSWORD_REQUIRE_UPPERCASE`: requires an uppercase letter, defaults to `true`
+//! - `PASSWORD_REQUIRE_DIGIT`: requires a digit, defaults to `true`
+//! - `PASSWORD_REQUIRE_SYMBOL`: requires a character which is neither a letter nor a digit,
+//!   defaults to `false`
+//! - `PASSWORD_RESET_HOURS`: hours a reset token is valid for, defaults to `24`
+
+use std::env;
+
+use actix_web::error::ErrorBadRequest;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    insert_into, BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension,
+    PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+use once_cell::sync::Lazy;
+
+use crate::{
+    database::{crypt, gen_salt, hash_token, random_token},
+    error::Result,
+    models::PasswordResetToken,
+    schema::{password_reset_tokens, users},
+};
+
+/// Passwords are hashed with bcrypt which ignores everything after 72 bytes
+const MAX_BYTES: usize = 72;
+
+/// Rules a new password must follow.
+#[derive(Debug, PartialEq)]
+pub struct Policy {
+    pub min_length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digit: bool,
+    pub symbol: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_length: 12,
+            lowercase: true,
+            uppercase: true,
+            digit: true,
+            symbol: false,
+        }
+    }
+}
+
+impl Policy {
+    /// Reads the policy from the environment, see the [module documentation](self).
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let default = Self::default();
+
+        Self {
+            min_length: var("PASSWORD_MIN_LENGTH", default.min_length),
+            lowercase: var("PASSWORD_REQUIRE_LOWERCASE", default.lowercase),
+            uppercase: var("PASSWORD_REQUIRE_UPPERCASE", default.uppercase),
+            digit: var("PASSWORD_REQUIRE_DIGIT", default.digit),
+            symbol: var("PASSWORD_REQUIRE_SYMBOL", default.symbol),
+        }
+    }
+
+    /// Returns the requirements the password does not meet.
+    pub fn unmet(&self, password: &str) -> Vec<String> {
+        let mut unmet = Vec::new();
+        let has = |f: fn(char) -> bool| password.chars().any(f);
+
+        if password.chars().count() < self.min_length {
+            unmet.push(format!("at least {} characters", self.min_length));
+        }
+        if password.len() > MAX_BYTES {
+            unmet.push(format!("at most {MAX_BYTES} bytes"));
+        }
+        if self.lowercase && !has(char::is_lowercase) {
+            unmet.push("a lowercase letter".into());
+        }
+        if self.uppercase && !has(char::is_uppercase) {
+            unmet.push("an uppercase letter".into());
+        }
+        if self.digit && !has(|c| c.is_ascii_digit()) {
+            unmet.push("a digit".into());
+        }
+        if self.symbol && !has(|c| !c.is_alphanumeric()) {
+            unmet.push("a symbol".into());
+        }
+
+        unmet
+    }
+}
+
+static POLICY: Lazy<Policy> = Lazy::new(Policy::from_env);
+
+static RESET_HOURS: Lazy<i64> = Lazy::new(|| {
+    env::var("PASSWORD_RESET_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(24)
+});
+
+/// Checks a new password follows the configured policy.
+///
+/// Returns a `400 Bad Request` error listing the unmet requirements otherwise.
+pub fn check(password: &str) -> Result<()> {
+    let unmet = POLICY.unmet(password);
+
+    if unmet.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorBadRequest(format!("The password must have {}", unmet.join(", "))).into())
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Issues a single-use token to set the password of a user, replacing the previous one if any.
+pub fn issue_reset_token(conn: &mut PgConnection, id_user: i64) -> QueryResult<PasswordResetToken> {
+    conn.transaction(|conn| {
+        diesel::delete(password_reset_tokens::table)
+            .filter(
+                password_reset_tokens::id_user
+                    .eq(id_user)
+                    .or(password_reset_tokens::expires_at.le(now())),
+            )
+            .execute(conn)?;
+
+        let token = random_token(conn)?;
+        let expires_at = insert_into(password_reset_tokens::table)
+            .values((
+                password_reset_tokens::token.eq(hash_token(&token)),
+                password_reset_tokens::id_user.eq(id_user),
+                password_reset_tokens::expires_at.eq(now() + Duration::hours(*RESET_HOURS)),
+            ))
+            .returning(password_reset_tokens::expires_at)
+            .get_result(conn)?;
+
+        Ok(PasswordResetToken { token, expires_at })
+    })
+}
+
+/// Sets the password of the user a reset token was issued to and consumes the token.
+///
+/// Returns `false` if the token is unknown or has expired.
+pub fn reset(conn: &mut PgConnection, token: &str, password: String) -> QueryResult<bool> {
+    conn.transaction(|conn| {
+        let Some(id_user) = diesel::delete(password_reset_tokens::table)
+            .filter(password_reset_tokens::token.eq(hash_token(token)))
+            .filter(password_reset_tokens::expires_at.gt(now()))
+            .returning(password_reset_tokens::id_user)
+            .get_result::<i64>(conn)
+            .optional()?
+        else {
+            return Ok(false);
+        };
+
+        diesel::update(users::table.find(id_user))
+            .set(users::password.eq(crypt(password, gen_salt(String::from("bf")))))
+            .execute(conn)?;
+
+        Ok(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy() {
+        let policy = Policy::default();
+
+        assert!(policy.unmet("Correct horse 1").is_empty());
+        assert_eq!(
+            policy.unmet("pass"),
+            ["at least 12 characters", "an uppercase letter", "a digit"]
+        );
+    }
+
+    #[test]
+    fn symbol_required() {
+        let policy = Policy {
+            symbol: true,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.unmet("Correcthorse1"), ["a symbol"]);
+        assert!(policy.unmet("Correct-horse1").is_empty());
+    }
+
+    #[test]
+    fn length_counts_characters() {
+        let policy = Policy {
+            min_length: 4,
+            ..Default::default()
+        };
+
+        assert!(policy.unmet("Éé1é").is_empty());
+        assert_eq!(
+            policy.unmet(&format!("Aa1{}", "é".repeat(40))),
+            ["at most 72 bytes"]
+        );
+    }
+
+    #[test]
+    fn disabled_rules() {
+        let policy = Policy {
+            min_length: 1,
+            lowercase: false,
+            uppercase: false,
+            digit: false,
+            symbol: false,
+        };
+
+        assert!(policy.unmet("x").is_empty());
+        assert_eq!(policy.unmet(""), ["at least 1 characters"]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Permissions granted to users through their roles.
+//!
+//! A role is a named bundle of permissions, such as `visits:write` or `reports:read`. Users are
+//! given the default role of their kind when they are created, other roles can be created by the
+//! managers of a center and assigned to its users. The permissions are resolved on every request
+//! and checked by the `actix_web_grants` attributes of the routes.
+
+use std::collections::HashSet;
+
+use actix_web::error::{ErrorBadRequest, ErrorForbidden};
+use diesel::{
+    insert_into, BoolExpressionMethods, Connection, ExpressionMethods, JoinOnDsl, PgConnection,
+    QueryDsl, QueryResult, RunQueryDsl, SelectableHelper,
+};
+
+use crate::{
+    auth::Auth,
+    error::Result,
+    models::RoleRecord,
+    schema::{l_roles_permissions, l_users_roles, permissions, roles},
+};
+
+/// Returns the permissions of a user, in alphabetical order.
+pub fn of_user(conn: &mut PgConnection, id_user: i64) -> QueryResult<Vec<String>> {
+    l_users_roles::table
+        .inner_join(
+            l_roles_permissions::table.on(l_roles_permissions::id_role.eq(l_users_roles::id_role)),
+        )
+        .filter(l_users_roles::id_user.eq(id_user))
+        .select(l_roles_permissions::permission)
+        .distinct()
+        .order(l_roles_permissions::permission)
+        .load(conn)
+}
+
+/// Returns the permissions granted by a role, in alphabetical order.
+pub fn of_role(conn: &mut PgConnection, id_role: i64) -> QueryResult<Vec<String>> {
+    l_roles_permissions::table
+        .filter(l_roles_permissions::id_role.eq(id_role))
+        .select(l_roles_permissions::permission)
+        .order(l_roles_permissions::permission)
+        .load(conn)
+}
+
+/// Checks the current user can grant or revoke the given permissions.
+///
+/// Permissions must exist and a user cannot grant nor revoke permissions it does not have.
+pub fn check_grantable(conn: &mut PgConnection, auth: &Auth, granted: &[String]) -> Result<()> {
+    let known: HashSet<String> = permissions::table
+        .filter(permissions::name.eq_any(granted))
+        .select(permissions::name)
+        .load(conn)?
+        .into_iter()
+        .collect();
+
+    if let Some(unknown) = granted.iter().find(|p| !known.contains(*p)) {
+        return Err(ErrorBadRequest(format!("Unknown permission `{unknown}`")).into());
+    }
+
+    if let Some(missing) = granted.iter().find(|p| !auth.has_permission(p)) {
+        return Err(ErrorForbidden(format!(
+            "You cannot grant nor revoke the `{missing}` permission"
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Checks the current user can take over the account of a user, by resetting its password or
+/// deleting it.
+///
+/// The user must not have permissions the current user could not grant nor revoke.
+pub fn check_manageable(conn: &mut PgConnection, auth: &Auth, id_user: i64) -> Result<()> {
+    let permissions = of_user(conn, id_user)?;
+
+    check_grantable(conn, auth, &permissions)
+}
+
+/// Replaces the permissions granted by a role.
+pub fn set_role_permissions(
+    conn: &mut PgConnection,
+    id_role: i64,
+    granted: &[String],
+) -> QueryResult<()> {
+    conn.transaction(|conn| {
+        diesel::delete(l_roles_permissions::table)
+            .filter(l_roles_permissions::id_role.eq(id_role))
+            .execute(conn)?;
+
+        insert_into(l_roles_permissions::table)
+            .values(
+                granted
+                    .iter()
+                    .map(|p| {
+                        (
+                            l_roles_permissions::id_role.eq(id_role),
+                            l_roles_permissions::permission.eq(p),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// Returns the roles of a user, by name.
+pub fn roles_of_user(conn: &mut PgConnection, id_user: i64) -> QueryResult<Vec<RoleRecord>> {
+    l_users_roles::table
+        .inner_join(roles::table)
+        .filter(l_users_roles::id_user.eq(id_user))
+        .select(RoleRecord::as_select())
+        .order(roles::name)
+        .load(conn)
+}
+
+/// Replaces the roles of a user of the center of the current user.
+///
+/// Roles must be default roles or roles of the center, and the current user must have every
+/// permission granted or revoked by the change, so that a user cannot be stripped of permissions
+/// stronger than the ones of the current user.
+pub fn set_user_roles(
+    conn: &mut PgConnection,
+    auth: &Auth,
+    id_user: i64,
+    ids: &[i64],
+) -> Result<()> {
+    let assigned: Vec<RoleRecord> = roles::table
+        .filter(roles::id.eq_any(ids))
+        .filter(
+            roles::id_center
+                .is_null()
+                .or(roles::id_center.eq(auth.id_center)),
+        )
+        .select(RoleRecord::as_select())
+        .load(conn)?;
+
+    if assigned.len() != ids.iter().collect::<HashSet<_>>().len() {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    let current: HashSet<String> = of_user(conn, id_user)?.into_iter().collect();
+    let kept: HashSet<String> = l_roles_permissions::table
+        .filter(l_roles_permissions::id_role.eq_any(ids))
+        .select(l_roles_permissions::permission)
+        .distinct()
+        .load::<String>(conn)?
+        .into_iter()
+        .collect();
+    let changed: Vec<String> = kept.symmetric_difference(&current).cloned().collect();
+    check_grantable(conn, auth, &changed)?;
+
+    conn.transaction(|conn| {
+        diesel::delete(l_users_roles::table)
+            .filter(l_users_roles::id_user.eq(id_user))
+            .execute(conn)?;
+
+        insert_into(l_users_roles::table)
+            .values(
+                assigned
+                    .iter()
+                    .map(|r| {
+                        (
+                            l_users_roles::id_user.eq(id_user),
+                            l_users_roles::id_role.eq(r.id),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)?;
+
+        QueryResult::Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,395 @@
+//! Automatic assignment of nurses to visits.
+//!
+//! The planner works on data loaded beforehand and never touches the database. It proposes
+//! assignments that a manager reviews before committing them.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use chrono::{Datelike, IsoWeek, NaiveDateTime};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::models::{Availability, NewLVisitNurse};
+
+/// A visit needing nurses.
+pub struct VisitNeed {
+    pub id_visit: i64,
+    /// Date and time the visit begins
+    pub start: NaiveDateTime,
+    /// Date and time the visit ends
+    pub end: NaiveDateTime,
+    /// Zone of the patient
+    pub id_zone: i64,
+    /// Skills every assigned nurse must have
+    pub required_skills: HashSet<i64>,
+    /// Skills an assigned nurse should preferably have
+    pub preferred_skills: HashSet<i64>,
+    /// Number of nurses required
+    pub people_required: i16,
+    /// Nurses already assigned to the visit
+    pub assigned: HashSet<i64>,
+}
+
+impl VisitNeed {
+    fn minutes(&self) -> i64 {
+        (self.end - self.start).num_minutes()
+    }
+}
+
+/// A nurse that can be assigned to visits.
+pub struct NurseCapacity {
+    pub id_nurse: i64,
+    /// Zone the nurse lives in
+    pub id_zone: i64,
+    /// Minutes of working time per week
+    pub minutes_per_week: i32,
+    pub skills: HashSet<i64>,
+    pub availabilities: Vec<Availability>,
+    /// Start and end of the visits the nurse is assigned to
+    pub visits: Vec<(NaiveDateTime, NaiveDateTime)>,
+}
+
+/// A reason preventing a nurse from being assigned to a visit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Conflict {
+    /// The nurse already has a visit at the same time
+    OverlappingVisit,
+    /// The visit is outside the nurse's availabilities
+    Unavailable,
+    /// The nurse lacks a skill required by the mission type
+    MissingSkill,
+    /// The nurse belongs to another center than the visit
+    OtherCenter,
+    /// The visit already has all the nurses it requires
+    VisitFull,
+}
+
+impl Conflict {
+    /// Returns the name of the conflict, as serialized.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Conflict::OverlappingVisit => "overlapping_visit",
+            Conflict::Unavailable => "unavailable",
+            Conflict::MissingSkill => "missing_skill",
+            Conflict::OtherCenter => "other_center",
+            Conflict::VisitFull => "visit_full",
+        }
+    }
+}
+
+impl NurseCapacity {
+    /// Lists the reasons preventing the nurse from being assigned to the visit.
+    ///
+    /// Centers are not checked as the nurse center is unknown here.
+    pub fn conflicts(&self, visit: &VisitNeed) -> Vec<Conflict> {
+        let mut res = Vec::new();
+
+        if self
+            .visits
+            .iter()
+            .any(|(start, end)| *start < visit.end && visit.start < *end)
+        {
+            res.push(Conflict::OverlappingVisit);
+        }
+
+        if !self
+            .availabilities
+            .iter()
+            .any(|a| a.covers(visit.start, visit.end))
+        {
+            res.push(Conflict::Unavailable);
+        }
+
+        if !visit.required_skills.is_subset(&self.skills) {
+            res.push(Conflict::MissingSkill);
+        }
+
+        if visit.assigned.len() >= visit.people_required.max(0) as usize {
+            res.push(Conflict::VisitFull);
+        }
+
+        res
+    }
+
+    /// Minutes the nurse is assigned to during the given week.
+    fn minutes_in_week(&self, week: IsoWeek) -> i64 {
+        self.visits
+            .iter()
+            .filter(|(start, _)| start.iso_week() == week)
+            .map(|(start, end)| (*end - *start).num_minutes())
+            .sum()
+    }
+
+    /// Tells whether the nurse can be assigned to the visit.
+    fn can_take(&self, visit: &VisitNeed) -> bool {
+        !visit.assigned.contains(&self.id_nurse)
+            && self.conflicts(visit).is_empty()
+            && self.minutes_in_week(visit.start.iso_week()) + visit.minutes()
+                <= self.minutes_per_week.into()
+    }
+
+    /// Orders nurses from the most to the least suited for a visit.
+    ///
+    /// Nurses having more preferred skills come first, then nurses from the visit zone, then the
+    /// least busy nurses of the week.
+    fn rank(&self, visit: &VisitNeed) -> (Reverse<usize>, bool, i64) {
+        let preferred = visit.preferred_skills.intersection(&self.skills).count();
+        let load = self.minutes_in_week(visit.start.iso_week()) * 1000
+            / i64::from(self.minutes_per_week.max(1));
+
+        (Reverse(preferred), self.id_zone != visit.id_zone, load)
+    }
+}
+
+/// A visit that could not be given all the nurses it requires.
+#[derive(Serialize, ToSchema)]
+pub struct UnderstaffedVisit {
+    pub id_visit: i64,
+    /// Number of nurses still missing
+    pub missing: i16,
+}
+
+/// Assignments proposed by the planner.
+#[derive(Serialize, ToSchema)]
+pub struct Plan {
+    /// Nurses to assign to visits
+    pub assignments: Vec<NewLVisitNurse>,
+    /// Visits still lacking nurses once the assignments are applied
+    pub understaffed: Vec<UnderstaffedVisit>,
+}
+
+/// Assigns nurses to the visits lacking some.
+///
+/// Visits are handled chronologically and each gets the best ranked nurses that have the required
+/// skills, are available, are not already busy and have time left in their week.
+pub fn plan(mut visits: Vec<VisitNeed>, nurses: Vec<NurseCapacity>) -> Plan {
+    let mut nurses: HashMap<i64, NurseCapacity> =
+        nurses.into_iter().map(|n| (n.id_nurse, n)).collect();
+    let mut assignments = Vec::new();
+    let mut understaffed = Vec::new();
+
+    visits.sort_by_key(|v| (v.start, v.id_visit));
+
+    for visit in visits {
+        let mut missing = visit.people_required - visit.assigned.len() as i16;
+
+        if missing <= 0 {
+            continue;
+        }
+
+        let mut candidates: Vec<_> = nurses.values().filter(|n| n.can_take(&visit)).collect();
+        candidates.sort_by_key(|n| (n.rank(&visit), n.id_nurse));
+
+        let chosen: Vec<i64> = candidates
+            .into_iter()
+            .take(missing as usize)
+            .map(|n| n.id_nurse)
+            .collect();
+
+        for id_nurse in chosen {
+            if let Some(nurse) = nurses.get_mut(&id_nurse) {
+                nurse.visits.push((visit.start, visit.end));
+            }
+
+            assignments.push(NewLVisitNurse {
+                id_visit: visit.id_visit,
+                id_nurse,
+            });
+            missing -= 1;
+        }
+
+        if missing > 0 {
+            understaffed.push(UnderstaffedVisit {
+                id_visit: visit.id_visit,
+                missing,
+            });
+        }
+    }
+
+    Plan {
+        assignments,
+        understaffed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::*;
+
+    fn datetime(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn visit(id_visit: i64, day: u32, hour: u32) -> VisitNeed {
+        VisitNeed {
+            id_visit,
+            start: datetime(day, hour),
+            end: datetime(day, hour + 1),
+            id_zone: 1,
+            required_skills: HashSet::new(),
+            preferred_skills: HashSet::new(),
+            people_required: 1,
+            assigned: HashSet::new(),
+        }
+    }
+
+    fn nurse(id_nurse: i64) -> NurseCapacity {
+        NurseCapacity {
+            id_nurse,
+            id_zone: 1,
+            minutes_per_week: 35 * 60,
+            skills: HashSet::new(),
+            availabilities: vec![Availability {
+                id: id_nurse,
+                start: datetime(1, 8),
+                end: datetime(1, 18),
+                recurrent: true,
+                id_nurse,
+            }],
+            visits: Vec::new(),
+        }
+    }
+
+    fn assigned(plan: &Plan) -> Vec<(i64, i64)> {
+        plan.assignments
+            .iter()
+            .map(|a| (a.id_visit, a.id_nurse))
+            .collect()
+    }
+
+    /// Overlapping visits are given to different nurses
+    #[test]
+    fn no_overlap() {
+        let plan = plan(
+            vec![visit(1, 1, 9), visit(2, 1, 9)],
+            vec![nurse(1), nurse(2)],
+        );
+
+        assert_eq!(assigned(&plan), vec![(1, 1), (2, 2)]);
+        assert!(plan.understaffed.is_empty());
+    }
+
+    /// A nurse lacking a required skill is never chosen
+    #[test]
+    fn required_skill() {
+        let mut v = visit(1, 1, 9);
+        v.required_skills.insert(1);
+        let mut skilled = nurse(2);
+        skilled.skills.insert(1);
+
+        let plan = plan(vec![v], vec![nurse(1), skilled]);
+
+        assert_eq!(assigned(&plan), vec![(1, 2)]);
+    }
+
+    /// A nurse with a preferred skill is chosen first
+    #[test]
+    fn preferred_skill() {
+        let mut v = visit(1, 1, 9);
+        v.preferred_skills.insert(1);
+        let mut skilled = nurse(2);
+        skilled.skills.insert(1);
+
+        let plan = plan(vec![v], vec![nurse(1), skilled]);
+
+        assert_eq!(assigned(&plan), vec![(1, 2)]);
+    }
+
+    /// A nurse from the visit zone is chosen first
+    #[test]
+    fn same_zone() {
+        let mut far = nurse(1);
+        far.id_zone = 2;
+
+        let plan = plan(vec![visit(1, 1, 9)], vec![far, nurse(2)]);
+
+        assert_eq!(assigned(&plan), vec![(1, 2)]);
+    }
+
+    /// Visits outside availabilities are left understaffed
+    #[test]
+    fn outside_availabilities() {
+        let plan = plan(vec![visit(1, 1, 19)], vec![nurse(1)]);
+
+        assert!(plan.assignments.is_empty());
+        assert_eq!(plan.understaffed[0].missing, 1);
+    }
+
+    /// Recurrent availabilities are repeated every week
+    #[test]
+    fn recurrent_availabilities() {
+        let plan = plan(vec![visit(1, 15, 9)], vec![nurse(1)]);
+
+        assert_eq!(assigned(&plan), vec![(1, 1)]);
+    }
+
+    /// Every reason preventing an assignment is listed
+    #[test]
+    fn conflicts() {
+        let mut v = visit(1, 1, 19);
+        v.required_skills.insert(1);
+        v.assigned.insert(2);
+        let mut n = nurse(1);
+        n.visits.push((datetime(1, 18), datetime(1, 20)));
+
+        assert_eq!(
+            n.conflicts(&v),
+            vec![
+                Conflict::OverlappingVisit,
+                Conflict::Unavailable,
+                Conflict::MissingSkill,
+                Conflict::VisitFull
+            ]
+        );
+        assert!(nurse(1).conflicts(&visit(1, 1, 9)).is_empty());
+    }
+
+    /// A nurse is not assigned beyond its weekly working time
+    #[test]
+    fn weekly_minutes() {
+        let mut n = nurse(1);
+        n.minutes_per_week = 60;
+
+        let plan = plan(vec![visit(1, 1, 9), visit(2, 1, 11)], vec![n]);
+
+        assert_eq!(assigned(&plan), vec![(1, 1)]);
+        assert_eq!(plan.understaffed[0].id_visit, 2);
+    }
+
+    /// Already assigned nurses count towards the required people
+    #[test]
+    fn already_assigned() {
+        let mut v = visit(1, 1, 9);
+        v.people_required = 2;
+        v.assigned.insert(1);
+
+        let plan = plan(vec![v], vec![nurse(1), nurse(2)]);
+
+        assert_eq!(assigned(&plan), vec![(1, 2)]);
+    }
+
+    /// Conflicts are stored under their serialized name
+    #[test]
+    fn conflict_names() {
+        for conflict in [
+            Conflict::OverlappingVisit,
+            Conflict::Unavailable,
+            Conflict::MissingSkill,
+            Conflict::OtherCenter,
+            Conflict::VisitFull,
+        ] {
+            assert_eq!(serde_json::json!(conflict), conflict.name());
+        }
+    }
+}
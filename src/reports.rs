@@ -0,0 +1,301 @@
+//! Structured reports.
+//!
+//! Besides its free text, a report records the vital signs taken, the acts performed from the
+//! checklist of its mission type, the medication administered and whether an incident happened.
+//! A mission type may define a [`Template`] listing the vital signs its reports must record and
+//! the acts that can be checked, reports are validated against it with [`check`].
+
+use std::collections::HashSet;
+
+use actix_web::error::{ErrorBadRequest, ErrorConflict};
+use diesel::{
+    deserialize::FromSqlRow,
+    dsl::{exists, select},
+    expression::AsExpression,
+    pg::Pg,
+    sql_types::Jsonb,
+    BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    database::jsonb,
+    error::Result,
+    schema::{l_visits_nurses, report_revisions},
+};
+
+/// A vital sign a template can require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Vital {
+    BloodPressure,
+    HeartRate,
+    Temperature,
+    Glycaemia,
+    Weight,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BloodPressure {
+    /// In mmHg
+    pub systolic: i16,
+    /// In mmHg
+    pub diastolic: i16,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Vitals {
+    pub blood_pressure: Option<BloodPressure>,
+    /// In beats per minute
+    pub heart_rate: Option<i16>,
+    /// In °C
+    pub temperature: Option<f32>,
+    /// In g/L
+    pub glycaemia: Option<f32>,
+    /// In kg
+    pub weight: Option<f32>,
+}
+
+impl Vitals {
+    fn has(&self, vital: Vital) -> bool {
+        match vital {
+            Vital::BloodPressure => self.blood_pressure.is_some(),
+            Vital::HeartRate => self.heart_rate.is_some(),
+            Vital::Temperature => self.temperature.is_some(),
+            Vital::Glycaemia => self.glycaemia.is_some(),
+            Vital::Weight => self.weight.is_some(),
+        }
+    }
+
+    /// Lists the values which cannot be right, most likely typos.
+    fn implausible(&self) -> Vec<&'static str> {
+        fn outside<T: PartialOrd>(value: Option<T>, min: T, max: T) -> bool {
+            value.is_some_and(|v| v < min || v > max)
+        }
+
+        let mut res = Vec::new();
+
+        if let Some(bp) = &self.blood_pressure {
+            if !(40..=300).contains(&bp.systolic)
+                || !(20..=200).contains(&bp.diastolic)
+                || bp.systolic <= bp.diastolic
+            {
+                res.push("blood pressure");
+            }
+        }
+        if outside(self.heart_rate, 20, 300) {
+            res.push("heart rate");
+        }
+        if outside(self.temperature, 30., 45.) {
+            res.push("temperature");
+        }
+        if outside(self.glycaemia, 0.1, 10.) {
+            res.push("glycaemia");
+        }
+        if outside(self.weight, 0.5, 500.) {
+            res.push("weight");
+        }
+
+        res
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Medication {
+    pub name: String,
+    /// Dose with its unit, such as `500 mg`
+    pub dose: String,
+}
+
+/// Structured part of a report.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+pub struct ReportData {
+    #[serde(default)]
+    pub vitals: Vitals,
+    /// Acts performed, from the checklist of the mission type
+    #[serde(default)]
+    pub acts: Vec<String>,
+    /// Medication administered
+    #[serde(default)]
+    pub medications: Vec<Medication>,
+    /// An incident has to be described in the free text of the report
+    #[serde(default)]
+    pub incident: bool,
+}
+
+jsonb!(ReportData);
+
+/// What the reports of a mission type record.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+pub struct Template {
+    /// Vital signs every report must record
+    #[serde(default)]
+    pub vitals: Vec<Vital>,
+    /// Checklist of the acts which can be performed
+    #[serde(default)]
+    pub acts: Vec<String>,
+}
+
+jsonb!(Template);
+
+impl Template {
+    /// Checks a template can be attached to a mission type.
+    pub fn check(&self) -> Result<()> {
+        let mut acts = HashSet::new();
+
+        if self.acts.iter().any(|a| a.trim().is_empty()) {
+            Err(ErrorBadRequest("The acts of a template must be named").into())
+        } else if !self.acts.iter().all(|a| acts.insert(a)) {
+            Err(ErrorBadRequest("The acts of a template must be unique").into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Lists the problems of a report written with this template.
+    pub fn problems(&self, report: &str, data: &ReportData) -> Vec<String> {
+        let mut res = Vec::new();
+
+        for vital in &self.vitals {
+            if !data.vitals.has(*vital) {
+                res.push(format!("missing {}", name(*vital)));
+            }
+        }
+        for vital in data.vitals.implausible() {
+            res.push(format!("implausible {vital}"));
+        }
+        for act in &data.acts {
+            if !self.acts.contains(act) {
+                res.push(format!("unknown act \"{act}\""));
+            }
+        }
+        if data
+            .medications
+            .iter()
+            .any(|m| m.name.trim().is_empty() || m.dose.trim().is_empty())
+        {
+            res.push("medication without a name or a dose".into());
+        }
+        if data.incident && report.trim().is_empty() {
+            res.push("undescribed incident".into());
+        }
+
+        res
+    }
+}
+
+fn name(vital: Vital) -> &'static str {
+    match vital {
+        Vital::BloodPressure => "blood pressure",
+        Vital::HeartRate => "heart rate",
+        Vital::Temperature => "temperature",
+        Vital::Glycaemia => "glycaemia",
+        Vital::Weight => "weight",
+    }
+}
+
+/// Checks the structured part of a report follows the template of its mission type.
+///
+/// Mission types without a template require no vital sign and have an empty checklist. Returns a
+/// `400 Bad Request` error listing the problems otherwise.
+pub fn check(template: Option<&Template>, report: &str, data: &ReportData) -> Result<()> {
+    let problems = template.cloned().unwrap_or_default().problems(report, data);
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorBadRequest(format!("The report has {}", problems.join(", "))).into())
+    }
+}
+
+/// Checks no report was written in the given assignments of nurses to visits.
+///
+/// Reports are kept along with their history, the records they belong to cannot be removed once
+/// one is written. Returns a `409 Conflict` error with `message` otherwise.
+pub fn check_no_report(
+    conn: &mut PgConnection,
+    assignments: l_visits_nurses::BoxedQuery<'_, Pg>,
+    message: &'static str,
+) -> Result<()> {
+    let written = assignments.filter(
+        l_visits_nurses::report.ne("").or(exists(
+            report_revisions::table
+                .filter(report_revisions::id_visit.eq(l_visits_nurses::id_visit))
+                .filter(report_revisions::id_nurse.eq(l_visits_nurses::id_nurse)),
+        )),
+    );
+
+    if select(exists(written)).get_result(conn)? {
+        Err(ErrorConflict(message).into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> Template {
+        Template {
+            vitals: vec![Vital::BloodPressure, Vital::Temperature],
+            acts: vec!["Dressing".into(), "Injection".into()],
+        }
+    }
+
+    fn data() -> ReportData {
+        ReportData {
+            vitals: Vitals {
+                blood_pressure: Some(BloodPressure {
+                    systolic: 120,
+                    diastolic: 80,
+                }),
+                temperature: Some(37.2),
+                ..Default::default()
+            },
+            acts: vec!["Dressing".into()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_report() {
+        assert!(template().problems("", &data()).is_empty());
+    }
+
+    #[test]
+    fn missing_and_implausible_vitals() {
+        let mut data = data();
+        data.vitals.temperature = None;
+        data.vitals.heart_rate = Some(800);
+
+        assert_eq!(
+            template().problems("", &data),
+            ["missing temperature", "implausible heart rate"]
+        );
+    }
+
+    #[test]
+    fn unknown_acts_and_incidents() {
+        let mut data = data();
+        data.acts.push("Surgery".into());
+        data.incident = true;
+
+        assert_eq!(
+            template().problems(" ", &data),
+            ["unknown act \"Surgery\"", "undescribed incident"]
+        );
+        assert_eq!(template().problems("The patient fell", &data).len(), 1);
+    }
+
+    #[test]
+    fn duplicated_acts() {
+        let mut template = template();
+        template.acts.push("Dressing".into());
+
+        assert!(template.check().is_err());
+    }
+}
@@ -3,14 +3,42 @@
 //! It is recommended that each model have its own submodule. Each submodule export a `routes`
 //! function which returns an [actix_web::Scope] with the routes defined.
 
+pub mod audit;
 pub mod auth;
+pub mod availabilities;
 pub mod centers;
+pub mod import;
 pub mod managers;
 pub mod mission_types;
 pub mod missions;
 pub mod nurses;
 pub mod patients;
+pub mod planning;
+pub mod roles;
+pub mod search;
 pub mod skills;
 pub mod version;
 pub mod visits;
 pub mod zones;
+
+/// Returns the `/api` scope gathering the routes of every submodule.
+pub fn api() -> actix_web::Scope {
+    actix_web::web::scope("/api")
+        .service(skills::routes())
+        .service(centers::routes())
+        .service(mission_types::routes())
+        .service(nurses::routes())
+        .service(availabilities::routes())
+        .service(patients::routes())
+        .service(missions::routes())
+        .service(visits::routes())
+        .service(planning::routes())
+        .service(managers::routes())
+        .service(zones::routes())
+        .service(roles::routes())
+        .service(audit::routes())
+        .service(search::routes())
+        .service(import::routes())
+        .service(auth::routes())
+        .service(version::routes())
+}
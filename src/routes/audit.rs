@@ -0,0 +1,92 @@
+use actix_web::{
+    get,
+    web::{self, Json},
+    Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_permissions;
+use diesel::{
+    sql_types::Bool, BoolExpressionMethods, ExpressionMethods, IntoSql, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+
+use crate::{
+    auth::Auth,
+    database::DbPool,
+    error::{JsonError, Result},
+    models::{AuditEntry, AuditParam},
+    pagination::{PaginatedResponse, PaginationParam},
+    params::FilterParam,
+    schema::audit_log,
+};
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(all),
+    components(schemas(AuditEntry, crate::pagination::PaginatedAuditEntries, JsonError)),
+    security(
+        ("token" = ["manager"])
+    )
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/audit").service(all)
+}
+
+/// List changes
+///
+/// Lists the changes made to the records of the center of the current user, the most recent first.
+/// An administrator sees the changes made to every record.
+#[utoipa::path(
+    context_path = "/audit",
+    params(PaginationParam, AuditParam, FilterParam<AuditEntry>),
+    responses(
+        (status = 200, description = "Paginated list of changes", body = PaginatedAuditEntries),
+    ),
+    tag = "audit"
+)]
+#[get("")]
+#[has_permissions("audit:read")]
+async fn all(
+    pagination: web::Query<PaginationParam>,
+    params: web::Query<AuditParam>,
+    filter: web::Query<FilterParam<AuditEntry>>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+
+    let req = || {
+        let mut req = audit_log::table
+            .filter(
+                audit_log::id_center
+                    .eq(auth.id_center)
+                    .or(auth.is_admin().into_sql::<Bool>()),
+            )
+            .filter(filter.expression())
+            .into_boxed();
+
+        if let Some(table) = &params.table {
+            req = req.filter(audit_log::table_name.eq(table));
+        }
+        if let Some(record) = params.record {
+            req = req.filter(audit_log::id_record.eq(record));
+        }
+        if let Some(actor) = params.actor {
+            req = req.filter(audit_log::id_user.eq(actor));
+        }
+
+        req
+    };
+
+    let res: Vec<AuditEntry> = req()
+        .select(AuditEntry::as_select())
+        .order((audit_log::created_at.desc(), audit_log::id.desc()))
+        .offset(pagination.offset().into())
+        .limit(pagination.limit().into())
+        .load(conn)?;
+
+    let total = pagination.total(|| Ok(req().count().get_result(conn)?))?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
@@ -1,34 +1,50 @@
 use std::sync::Arc;
 
 use actix_web::{
-    cookie, get,
+    cookie,
+    error::ErrorForbidden,
+    get,
     http::StatusCode,
-    post,
+    post, put,
     web::{self, Json},
-    HttpResponseBuilder, Responder, Scope,
+    HttpRequest, HttpResponse, HttpResponseBuilder, Responder, Scope,
 };
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SelectableHelper};
 
 use crate::{
-    auth::{Auth, Role},
-    database::{crypt, DbPool},
-    error::{JsonError, Result},
-    models::{LoggedUser, LoginUser, User},
-    schema::{addresses, managers, nurses, users, zones},
+    auth::{Auth, Role, COOKIE_REFRESH_NAME},
+    database::{crypt, gen_salt, DbPool},
+    error::{Error, JsonError, Result},
+    models::{LoggedUser, LoginUser, ResetPassword, UpdatePassword, User},
+    password, permissions,
+    schema::{addresses, managers, nurses, patients, users, zones},
+    sessions,
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(login, info, logout),
-    components(schemas(LoginUser, Role, LoggedUser, JsonError))
+    paths(
+        login,
+        refresh,
+        info,
+        logout,
+        logout_all,
+        update_password,
+        reset_password
+    ),
+    components(schemas(LoginUser, Role, LoggedUser, UpdatePassword, ResetPassword, JsonError))
 )]
 pub struct Doc;
 
 pub fn routes() -> Scope {
     web::scope("auth")
         .service(login)
+        .service(refresh)
         .service(info)
         .service(logout)
+        .service(logout_all)
+        .service(update_password)
+        .service(reset_password)
 }
 
 mod helper {
@@ -49,27 +65,82 @@ mod helper {
             .first(&mut pool.get()?)
             .optional()?;
 
-        Ok(if let Some((id, id_center, id_zone)) = nurse {
-            UserInfo {
+        if let Some((id, id_center, id_zone)) = nurse {
+            return Ok(UserInfo {
                 id,
                 id_center,
                 id_zone: Some(id_zone),
                 role: Role::Nurse,
-            }
-        } else {
-            let (id, id_center) = managers::table
-                .filter(managers::id_user.eq(user.id))
-                .select((managers::id, managers::id_center))
-                .first::<(i64, i64)>(&mut pool.get()?)?;
-
-            UserInfo {
+            });
+        }
+
+        let manager: Option<(i64, i64)> = managers::table
+            .filter(managers::id_user.eq(user.id))
+            .select((managers::id, managers::id_center))
+            .first(&mut pool.get()?)
+            .optional()?;
+
+        if let Some((id, id_center)) = manager {
+            return Ok(UserInfo {
                 id,
                 id_center,
                 id_zone: None,
                 role: Role::Manager,
-            }
+            });
+        }
+
+        let (id, id_center, id_zone) = patients::table
+            .inner_join(addresses::table.inner_join(zones::table))
+            .filter(patients::id_user.eq(user.id))
+            .select((patients::id, zones::id_center, addresses::id_zone))
+            .first::<(i64, i64, i64)>(&mut pool.get()?)?;
+
+        Ok(UserInfo {
+            id,
+            id_center,
+            id_zone: Some(id_zone),
+            role: Role::Patient,
         })
     }
+
+    /// Responds with the logged user and the cookies of its session.
+    pub fn logged_in(
+        user: User,
+        pool: Arc<DbPool>,
+        sid: i64,
+        refresh_token: String,
+    ) -> Result<HttpResponse> {
+        let infos = get_user_info(&user, pool.clone())?;
+        let logged_user = LoggedUser {
+            permissions: permissions::of_user(&mut *pool.get()?, user.id)?,
+            user,
+            role: infos.role,
+            id_zone: infos.id_zone,
+            id_center: infos.id_center,
+        };
+        let auth = Auth::new(infos.id, &logged_user, sid);
+
+        Ok(HttpResponseBuilder::new(StatusCode::OK)
+            .cookie(auth.try_into()?)
+            .cookie(Auth::refresh_cookie(refresh_token))
+            .json(logged_user))
+    }
+
+    /// Responds with the session cookies removed.
+    pub fn logged_out() -> HttpResponse {
+        HttpResponseBuilder::new(StatusCode::OK)
+            .cookie(
+                Auth::build_cookie("")
+                    .expires(cookie::time::OffsetDateTime::UNIX_EPOCH)
+                    .finish(),
+            )
+            .cookie(
+                Auth::build_refresh_cookie("")
+                    .expires(cookie::time::OffsetDateTime::UNIX_EPOCH)
+                    .finish(),
+            )
+            .finish()
+    }
 }
 
 #[utoipa::path(
@@ -88,27 +159,51 @@ pub async fn login(pool: web::Data<DbPool>, user: Json<LoginUser>) -> Result<imp
     let user: User = users::table
         .filter(users::mail.eq(user.mail))
         .filter(users::password.eq(crypt(user.password, users::password)))
+        .select(User::as_select())
         .first(&mut pool.get()?)?;
 
-    let infos = helper::get_user_info(&user, pool.into_inner())?;
-    let logged_user = LoggedUser {
-        user,
-        role: infos.role,
-        id_zone: infos.id_zone,
-        id_center: infos.id_center,
+    let (sid, refresh_token) = sessions::open(&mut *pool.get()?, user.id)?;
+
+    helper::logged_in(user, pool.into_inner(), sid, refresh_token)
+}
+
+/// refresh
+///
+/// Renews the authentication token using the refresh token cookie, which is replaced by a new
+/// one. A refresh token can only be used once: reusing it closes the session, the user then has
+/// to log in again.
+#[utoipa::path(
+    context_path = "/auth",
+    responses(
+        (status = 200, body = LoggedUser),
+        (status = 401),
+    ),
+    tag = "auth",
+    security(())
+)]
+#[post("/refresh")]
+pub async fn refresh(pool: web::Data<DbPool>, req: HttpRequest) -> Result<impl Responder> {
+    let Some(token) = req.cookie(COOKIE_REFRESH_NAME) else {
+        return Err(Error::TokenNotProvided);
+    };
+
+    let Some(session) = sessions::refresh(&mut *pool.get()?, token.value())? else {
+        return Err(Error::SessionClosed);
     };
-    let auth = Auth::new(infos.id, &logged_user);
 
-    Ok(HttpResponseBuilder::new(StatusCode::OK)
-        .cookie(auth.try_into()?)
-        .json(logged_user))
+    let user: User = users::table
+        .find(session.id_user)
+        .select(User::as_select())
+        .first(&mut pool.get()?)?;
+
+    helper::logged_in(user, pool.into_inner(), session.id, session.refresh_token)
 }
 
 /// info
 ///
 /// This route return the same information as `login`. It is meant for when you want general user
-/// information and are already logged in. You can get the complete information of a nurse or
-/// manager with their respective `me` routes.
+/// information and are already logged in. You can get the complete information of a nurse,
+/// manager or patient with their respective `me` routes.
 #[utoipa::path(
     context_path = "/auth",
     responses(
@@ -126,17 +221,23 @@ pub async fn info(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder>
         Role::Manager => users::table
             .inner_join(managers::table)
             .filter(managers::id.eq(auth.id))
-            .select(users::all_columns)
+            .select(User::as_select())
             .first(&mut pool.get()?)?,
         Role::Nurse => users::table
             .inner_join(nurses::table)
             .filter(nurses::id.eq(auth.id))
-            .select(users::all_columns)
+            .select(User::as_select())
+            .first(&mut pool.get()?)?,
+        Role::Patient => users::table
+            .inner_join(patients::table)
+            .filter(patients::id.eq(auth.id))
+            .select(User::as_select())
             .first(&mut pool.get()?)?,
     };
 
-    let infos = helper::get_user_info(&user, pool.into_inner())?;
+    let infos = helper::get_user_info(&user, pool.clone().into_inner())?;
     let logged_user = LoggedUser {
+        permissions: permissions::of_user(&mut *pool.get()?, user.id)?,
         user,
         role: infos.role,
         id_zone: infos.id_zone,
@@ -158,12 +259,106 @@ pub async fn info(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder>
     )
 )]
 #[get("/logout")]
-pub async fn logout(_: Auth) -> Result<impl Responder> {
-    Ok(HttpResponseBuilder::new(StatusCode::OK)
-        .cookie(
-            Auth::build_cookie("")
-                .expires(cookie::time::OffsetDateTime::UNIX_EPOCH)
-                .finish(),
-        )
-        .finish())
+pub async fn logout(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    sessions::revoke(&mut *pool.get()?, auth.sid)?;
+
+    Ok(helper::logged_out())
+}
+
+/// logout all
+///
+/// Closes every session of the user, logging them out of all their devices.
+#[utoipa::path(
+    context_path = "/auth",
+    responses(
+        (status = 200),
+        (status = 401),
+    ),
+    tag = "auth",
+    security(
+        ("token" = [])
+    )
+)]
+#[get("/logout/all")]
+pub async fn logout_all(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    sessions::revoke_all(&mut *pool.get()?, auth.id_user)?;
+
+    Ok(helper::logged_out())
+}
+
+/// Change password
+///
+/// Changes the password of the current user. Every session of the user is closed, logging them
+/// out of all their devices, and a new session is opened for the current one.
+#[utoipa::path(
+    context_path = "/auth",
+    responses(
+        (status = 200, body = LoggedUser),
+        (status = 400, body = JsonError),
+        (status = 401),
+        (status = 403, body = JsonError, description = "Wrong current password"),
+    ),
+    tag = "auth",
+    security(
+        ("token" = [])
+    )
+)]
+#[put("/password")]
+pub async fn update_password(
+    pool: web::Data<DbPool>,
+    passwords: Json<UpdatePassword>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let UpdatePassword { current, new } = passwords.into_inner();
+    password::check(&new)?;
+
+    let updated = auth.audited(&mut *pool.get()?, |conn| {
+        diesel::update(users::table)
+            .filter(users::id.eq(auth.id_user))
+            .filter(users::password.eq(crypt(current, users::password)))
+            .set(users::password.eq(crypt(new, gen_salt(String::from("bf")))))
+            .execute(conn)
+    })?;
+
+    if updated == 0 {
+        return Err(ErrorForbidden("Wrong current password").into());
+    }
+
+    let user: User = users::table
+        .find(auth.id_user)
+        .select(User::as_select())
+        .first(&mut pool.get()?)?;
+    let (sid, refresh_token) = sessions::open(&mut *pool.get()?, user.id)?;
+
+    helper::logged_in(user, pool.into_inner(), sid, refresh_token)
+}
+
+/// Reset password
+///
+/// Sets the password of a user with a token issued by a manager. This is also how users created
+/// without a password set it on their first login. A token can only be used once, every session
+/// of the user is closed.
+#[utoipa::path(
+    context_path = "/auth",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError, description = "Unknown or expired token"),
+    ),
+    tag = "auth",
+    security(())
+)]
+#[post("/password/reset")]
+pub async fn reset_password(
+    pool: web::Data<DbPool>,
+    reset: Json<ResetPassword>,
+) -> Result<impl Responder> {
+    let ResetPassword { token, password } = reset.into_inner();
+    password::check(&password)?;
+
+    if password::reset(&mut *pool.get()?, &token, password)? {
+        Ok(Json(()))
+    } else {
+        Err(diesel::result::Error::NotFound.into())
+    }
 }
@@ -0,0 +1,240 @@
+use actix_web::{
+    delete,
+    error::{ErrorBadRequest, ErrorForbidden},
+    post, put,
+    web::{self, Json},
+    Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_any_permission;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{
+    auth::{Auth, Role},
+    database::DbPool,
+    error::{JsonError, Result},
+    models::{Availability, NewAvailability, NurseRecord, UpdateAvailability},
+    schema::availabilities,
+};
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(post, put, delete),
+    components(schemas(Availability, NewAvailability, UpdateAvailability, JsonError)),
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/availabilities")
+        .service(post)
+        .service(put)
+        .service(delete)
+}
+
+mod helper {
+    use diesel::PgConnection;
+
+    use super::*;
+
+    /// Checks the current user can manage the availabilities of the given nurse.
+    ///
+    /// A nurse can always manage its own availabilities, other users need the
+    /// `availabilities:write` permission to manage the ones of their center.
+    pub fn check_nurse(conn: &mut PgConnection, auth: &Auth, id_nurse: i64) -> Result<()> {
+        if !auth.is_or_has(Role::Nurse, id_nurse, "availabilities:write") {
+            return Err(ErrorForbidden("A nurse can only manage its own availabilities").into());
+        }
+
+        auth.check_center::<NurseRecord>(conn, id_nurse)
+    }
+
+    /// Saves an availability, merging it with the availabilities of the same nurse and kind it
+    /// overlaps. Returns the ID of the resulting availability.
+    ///
+    /// `id` is the availability to update, a new one is created if `None`.
+    pub fn save(
+        conn: &mut PgConnection,
+        auth: &Auth,
+        id: Option<i64>,
+        id_nurse: i64,
+        new: NewAvailability,
+    ) -> Result<i64> {
+        auth.audited(conn, |conn| {
+            let mut query = availabilities::table
+                .filter(availabilities::id_nurse.eq(id_nurse))
+                .filter(availabilities::recurrent.eq(new.recurrent))
+                .filter(availabilities::id.ne(id.unwrap_or_default()))
+                .select(Availability::as_select())
+                .into_boxed();
+
+            // Recurrent availabilities repeat every week, any of them can overlap
+            if !new.recurrent {
+                query = query
+                    .filter(availabilities::start.le(new.end))
+                    .filter(availabilities::end.ge(new.start));
+            }
+
+            let candidates: Vec<Availability> = query.load(conn)?;
+
+            // A merge can make the availability overlap another one
+            let mut values = NewAvailability {
+                id_nurse: Some(id_nurse),
+                ..new
+            };
+            let mut merged = Vec::new();
+            loop {
+                let count = merged.len();
+                for a in &candidates {
+                    if !merged.contains(&a.id) && values.merge(a) {
+                        merged.push(a.id);
+                    }
+                }
+                if merged.len() == count {
+                    break;
+                }
+            }
+
+            values.validate().map_err(ErrorBadRequest)?;
+
+            diesel::delete(availabilities::table)
+                .filter(availabilities::id.eq_any(&merged))
+                .execute(conn)?;
+
+            let id = match id {
+                Some(id) => diesel::update(availabilities::table)
+                    .set((
+                        availabilities::start.eq(values.start),
+                        availabilities::end.eq(values.end),
+                        availabilities::recurrent.eq(values.recurrent),
+                    ))
+                    .filter(availabilities::id.eq(id))
+                    .returning(availabilities::id)
+                    .get_result(conn),
+                None => diesel::insert_into(availabilities::table)
+                    .values(&values)
+                    .returning(availabilities::id)
+                    .get_result(conn),
+            }?;
+
+            Ok(id)
+        })
+    }
+}
+
+/// Create an availability
+///
+/// Creates an availability for the given nurse, or the current nurse if none is given. A nurse
+/// can only create its own availabilities, a manager the ones of the nurses of its center.
+///
+/// The availability is merged with the availabilities of the same kind (recurrent or not) it
+/// overlaps, recurrent ones whatever week they start on. Returns the ID of the resulting
+/// availability.
+#[utoipa::path(
+    context_path = "/availabilities",
+    responses(
+        (status = 200, body = i64),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError)
+    ),
+    tag = "availabilities"
+)]
+#[post("")]
+#[has_any_permission("availabilities:write", "ROLE_NURSE")]
+async fn post(
+    new_record: Json<NewAvailability>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    new_record.validate().map_err(ErrorBadRequest)?;
+
+    let id_nurse = match (new_record.id_nurse, auth.role) {
+        (Some(id), _) => id,
+        (None, Role::Nurse) => auth.id,
+        (None, _) => return Err(ErrorBadRequest("`id_nurse` is required").into()),
+    };
+
+    let conn = &mut pool.get()?;
+
+    helper::check_nurse(conn, &auth, id_nurse)?;
+
+    let id = helper::save(conn, &auth, None, id_nurse, new_record.0)?;
+
+    Ok(Json(id))
+}
+
+/// Update an availability
+///
+/// The availability is merged with the availabilities of the same kind (recurrent or not) it
+/// overlaps.
+#[utoipa::path(
+    context_path = "/availabilities",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError)
+    ),
+    tag = "availabilities"
+)]
+#[put("/{id}")]
+#[has_any_permission("availabilities:write", "ROLE_NURSE")]
+async fn put(
+    id: web::Path<i64>,
+    update_record: Json<UpdateAvailability>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+
+    let current: Availability = availabilities::table
+        .find(*id)
+        .select(Availability::as_select())
+        .first(conn)?;
+
+    helper::check_nurse(conn, &auth, current.id_nurse)?;
+
+    let new = NewAvailability {
+        start: update_record.start.unwrap_or(current.start),
+        end: update_record.end.unwrap_or(current.end),
+        recurrent: update_record.recurrent.unwrap_or(current.recurrent),
+        id_nurse: Some(current.id_nurse),
+    };
+
+    new.validate().map_err(ErrorBadRequest)?;
+
+    helper::save(conn, &auth, Some(*id), current.id_nurse, new)?;
+
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    context_path = "/availabilities",
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError)
+    ),
+    tag = "availabilities"
+)]
+#[delete("/{id}")]
+#[has_any_permission("availabilities:write", "ROLE_NURSE")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+
+    let id_nurse: i64 = availabilities::table
+        .find(*id)
+        .select(availabilities::id_nurse)
+        .first(conn)?;
+
+    helper::check_nurse(conn, &auth, id_nurse)?;
+
+    auth.audited(conn, |conn| {
+        diesel::delete(availabilities::table)
+            .filter(availabilities::id.eq(*id))
+            .execute(conn)
+    })?;
+
+    Ok(Json(()))
+}
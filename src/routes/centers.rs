@@ -1,30 +1,43 @@
 use actix_web::{
-    get,
+    delete,
+    error::{ErrorBadRequest, ErrorForbidden},
+    get, post, put,
     web::{self, Json},
-    Responder, Scope,
+    HttpResponse, Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_permissions;
+use chrono::NaiveTime;
+use diesel::{
+    insert_into, sql_types::Bool, BoolExpressionMethods, ExpressionMethods, IntoSql,
+    PgTextExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper,
 };
-use actix_web_grants::proc_macro::has_roles;
-use diesel::{ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
-    error::{JsonError, Result},
-    models::{Address, CenterRecord, ZoneRecord},
+    error::{Error, JsonError, Result},
+    ical::{calendar, manager_center, Feed},
+    models::{
+        Address, CalendarParam, CenterRecord, Manager, ManagerRecord, NewCenter, UpdateCenter,
+        User, ZoneRecord,
+    },
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
+    params::{FilterParam, SearchParam, SortParam},
     schema::{self, centers},
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(all, get, zones),
+    paths(all, get, post, put, delete, zones, managers, ical),
     components(schemas(
         CenterRecord,
+        NewCenter,
+        UpdateCenter,
         Address,
         ZoneRecord,
         crate::pagination::PaginatedCenters,
         crate::pagination::PaginatedZones,
+        crate::pagination::PaginatedManagers,
         JsonError
     )),
     security(
@@ -37,93 +50,375 @@ pub fn routes() -> Scope {
     web::scope("/centers")
         .service(all)
         .service(get)
+        .service(post)
+        .service(put)
+        .service(delete)
         .service(zones)
+        .service(managers)
+        .service(ical)
+}
+
+mod helper {
+    use super::*;
+
+    pub fn check_workday(start: NaiveTime, end: NaiveTime) -> Result<()> {
+        if start >= end {
+            return Err(ErrorBadRequest("The workday must start before it ends").into());
+        }
+
+        Ok(())
+    }
 }
 
+/// List centers
+///
+/// A manager only sees its own center, an administrator sees every center.
 #[utoipa::path(
     context_path = "/centers",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<CenterRecord>, FilterParam<CenterRecord>),
     responses(
         (status = 200, description = "Paginated list of centers", body = PaginatedCenters),
     ),
     tag = "centers"
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("centers:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<CenterRecord>>,
+    filter: web::Query<FilterParam<CenterRecord>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
     let req = centers::table
-        .filter(centers::name.ilike(search.value()))
-        .or_filter(centers::desc.ilike(search.value()));
+        .filter(
+            centers::id
+                .eq(auth.id_center)
+                .or(auth.is_admin().into_sql::<Bool>()),
+        )
+        .filter(
+            centers::name
+                .ilike(search.value())
+                .or(centers::desc.ilike(search.value())),
+        )
+        .filter(filter.expression());
 
     let res: Vec<CenterRecord> = req
         .clone()
-        .order(sort.raw_sql())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(&mut pool.get()?)?;
 
-    let total = req.count().get_result::<i64>(&mut pool.get()?)?;
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
 
-    Ok(Json(
-        PaginatedResponse::new(res, &pagination).total(total as u32),
-    ))
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
 
 #[utoipa::path(
     context_path = "/centers",
     responses(
         (status = 200, body = CenterRecord),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "centers"
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
+#[has_permissions("centers:read")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    if !auth.is_admin() {
+        auth.same_center(*id)?;
+    }
+
     let res: CenterRecord = macros::get!(centers, pool, *id);
 
     Ok(Json(res))
 }
 
+/// Create center
+///
+/// Creates an empty center, its first manager is then created with `/managers`.
 #[utoipa::path(
     context_path = "/centers",
-    params(PaginationParam, SearchParam, SortParam),
+    responses(
+        (status = 200, body = CenterRecord),
+        (status = 400, body = JsonError),
+    ),
+    tag = "centers",
+    security(
+        ("token" = ["admin"])
+    )
+)]
+#[post("")]
+#[has_permissions("centers:write")]
+async fn post(
+    new_center: Json<NewCenter>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    helper::check_workday(new_center.workday_start, new_center.workday_end)?;
+
+    let res: CenterRecord = auth.audited(&mut *pool.get()?, |conn| {
+        insert_into(centers::table)
+            .values(&new_center.0)
+            .get_result(conn)
+    })?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    context_path = "/centers",
+    responses(
+        (status = 200, body = CenterRecord),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "centers",
+    security(
+        ("token" = ["admin"])
+    )
+)]
+#[put("/{id}")]
+#[has_permissions("centers:write")]
+async fn put(
+    id: web::Path<i64>,
+    update_center: Json<UpdateCenter>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let res = auth.audited(&mut *pool.get()?, |conn| {
+        let res: CenterRecord = diesel::update(centers::table.find(*id))
+            .set(&update_center.0)
+            .get_result(conn)?;
+        helper::check_workday(res.workday_start, res.workday_end)?;
+
+        Result::Ok(res)
+    })?;
+
+    Ok(Json(res))
+}
+
+/// Delete center
+///
+/// This will also delete everything belonging to the center: zones, users, missions, visits...
+/// The users of the center are logged out. An administrator cannot delete its own center, nor can
+/// a center where reports were written be deleted.
+#[utoipa::path(
+    context_path = "/centers",
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, body = JsonError),
+    ),
+    tag = "centers",
+    security(
+        ("token" = ["admin"])
+    )
+)]
+#[delete("/{id}")]
+#[has_permissions("centers:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let id = id.into_inner();
+    if auth.id_center == id {
+        return Err(ErrorForbidden("You cannot delete your own center").into());
+    }
+
+    auth.audited(&mut *pool.get()?, |conn| {
+        use schema::{
+            addresses, l_visits_nurses, managers, missions, nurses, patients, users, visits, zones,
+        };
+
+        crate::reports::check_no_report(
+            conn,
+            l_visits_nurses::table
+                .filter(
+                    l_visits_nurses::id_visit
+                        .eq_any(
+                            visits::table
+                                .inner_join(
+                                    missions::table.inner_join(
+                                        patients::table
+                                            .inner_join(addresses::table.inner_join(zones::table)),
+                                    ),
+                                )
+                                .filter(zones::id_center.eq(id))
+                                .select(visits::id),
+                        )
+                        .or(l_visits_nurses::id_nurse.eq_any(
+                            nurses::table
+                                .inner_join(addresses::table.inner_join(zones::table))
+                                .filter(zones::id_center.eq(id))
+                                .select(nurses::id),
+                        )),
+                )
+                .into_boxed(),
+            "Reports were written in the center, it cannot be deleted",
+        )?;
+
+        // Deleting the users closes their sessions, their records go with the center
+        diesel::delete(users::table)
+            .filter(
+                users::id
+                    .eq_any(
+                        managers::table
+                            .filter(managers::id_center.eq(id))
+                            .select(managers::id_user),
+                    )
+                    .or(users::id.eq_any(
+                        nurses::table
+                            .inner_join(addresses::table.inner_join(zones::table))
+                            .filter(zones::id_center.eq(id))
+                            .select(nurses::id_user),
+                    ))
+                    .or(users::id.eq_any(
+                        patients::table
+                            .inner_join(addresses::table.inner_join(zones::table))
+                            .filter(zones::id_center.eq(id))
+                            .select(patients::id_user),
+                    )),
+            )
+            .execute(conn)?;
+
+        diesel::delete(centers::table)
+            .filter(centers::id.eq(id))
+            .execute(conn)?;
+
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    context_path = "/centers",
+    params(PaginationParam, SearchParam, SortParam<ZoneRecord>),
     responses(
         (status = 200, body = PaginatedZones),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "centers"
 )]
 #[get("/{id}/zones")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("zones:read")]
 async fn zones(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<ZoneRecord>>,
     id: web::Path<i64>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.same_center(*id)?;
+
     let req = schema::zones::table
         .filter(schema::zones::id_center.eq(*id))
         .filter(schema::zones::name.ilike(search.value()));
 
     let res: Vec<ZoneRecord> = req
         .clone()
-        .order(sort.raw_sql())
+        .order(sort.expression())
+        .limit(pagination.limit().into())
+        .offset(pagination.offset().into())
+        .load(&mut pool.get()?)?;
+
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
+
+/// List managers of a center
+#[utoipa::path(
+    context_path = "/centers",
+    params(PaginationParam, SearchParam, SortParam<(ManagerRecord, User)>),
+    responses(
+        (status = 200, body = PaginatedManagers),
+        (status = 403, body = JsonError),
+    ),
+    tag = "centers"
+)]
+#[get("/{id}/managers")]
+#[has_permissions("managers:read")]
+async fn managers(
+    pagination: web::Query<PaginationParam>,
+    search: web::Query<SearchParam>,
+    sort: web::Query<SortParam<(ManagerRecord, User)>>,
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !auth.is_admin() {
+        auth.same_center(*id)?;
+    }
+
+    let req = schema::managers::table
+        .inner_join(schema::users::table)
+        .filter(schema::managers::id_center.eq(*id))
+        .filter(
+            schema::users::fname
+                .ilike(search.value())
+                .or(schema::users::lname.ilike(search.value()))
+                .or(schema::users::mail.ilike(search.value())),
+        );
+
+    let res: Vec<Manager> = req
+        .clone()
+        .select(Manager::as_select())
+        .order(sort.expression())
         .limit(pagination.limit().into())
         .offset(pagination.offset().into())
         .load(&mut pool.get()?)?;
 
-    let total: i64 = req.count().get_result(&mut pool.get()?)?;
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
+
+/// Center's calendar
+///
+/// Returns the visits of every patient of the center as an iCalendar feed. The feed is protected
+/// by the calendar token of a manager of the center rather than the usual authentication cookie.
+/// A wrong token gives a `404`.
+#[utoipa::path(
+    context_path = "/centers",
+    params(CalendarParam),
+    responses(
+        (status = 200, body = String, description = "Icalendar data"),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    ),
+    tag = "centers",
+    security(())
+)]
+#[get("/{id}/ical")]
+async fn ical(
+    id: web::Path<i64>,
+    query: web::Query<CalendarParam>,
+    pool: web::Data<DbPool>,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+
+    if manager_center(conn, &query.token)? != *id {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    let name: String = centers::table
+        .filter(centers::id.eq(*id))
+        .select(centers::name)
+        .first(conn)?;
+
+    let cal = calendar(
+        conn,
+        Feed::Center(*id),
+        &format!("Planning {name}"),
+        query.alarm,
+    )?;
 
-    Ok(Json(
-        PaginatedResponse::new(res, &pagination).total(total as u32),
-    ))
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(cal.to_string()))
 }
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    error::{ErrorForbidden, ErrorUnsupportedMediaType},
+    post,
+    web::{self, Bytes},
+    HttpMessage, HttpRequest, HttpResponse, Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_any_permission;
+use diesel::{
+    insert_into,
+    result::{DatabaseErrorKind, Error::DatabaseError},
+    ExpressionMethods, QueryDsl, RunQueryDsl,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::Auth,
+    database::DbPool,
+    error::{JsonError, Result},
+    import::{self, Entity, Format, ImportParam, ImportReport, Row, RowError},
+    models::{NewAddress, NewNurseRecord, NewPatientRecord, NewUser},
+    schema::{addresses, nurses, patients, users, zones},
+    zoning::{self, ZoneArea},
+};
+
+/// Largest file that can be imported
+const IMPORT_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(post),
+    components(schemas(Entity, ImportReport, RowError, JsonError)),
+    security(
+        ("token" = ["manager"])
+    )
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/import")
+        .app_data(web::PayloadConfig::new(IMPORT_MAX_BYTES))
+        .service(post)
+}
+
+mod helper {
+    use diesel::PgConnection;
+
+    use super::*;
+
+    /// Zone of an address given by its name.
+    #[derive(Deserialize)]
+    struct ZoneName {
+        zone: Option<String>,
+    }
+
+    /// A valid row, ready to be inserted.
+    pub struct Person {
+        pub user: NewUser,
+        pub address: NewAddress,
+        /// Fields of the nurse, `None` for a patient
+        pub nurse: Option<NewNurseRecord>,
+    }
+
+    /// Validates a row the way the creation routes do, resolving the zone of the address from its
+    /// name.
+    pub fn person(
+        row: &Row,
+        entity: Entity,
+        zones: &[ZoneArea],
+        names: &HashMap<String, Vec<i64>>,
+    ) -> std::result::Result<Person, RowError> {
+        let error = |message| RowError::new(row.line, message);
+
+        let user: NewUser = row.part()?;
+        let mut address: NewAddress = row.part()?;
+        let nurse = match entity {
+            Entity::Patients => None,
+            Entity::Nurses => Some(row.part::<NewNurseRecord>()?),
+        };
+
+        if let Some(name) = row.part::<ZoneName>()?.zone {
+            if address.id_zone.is_some() {
+                return Err(error(
+                    "The zone is given both by `zone` and `id_zone`".into(),
+                ));
+            }
+            address.id_zone = match names.get(&name).map(Vec::as_slice) {
+                Some([id]) => Some(*id),
+                Some(_) => return Err(error(format!("Several zones are named `{name}`"))),
+                None => return Err(error(format!("No zone is named `{name}`"))),
+            };
+        } else if let Some(id_zone) = address.id_zone {
+            if !zones.iter().any(|z| z.id == id_zone) {
+                return Err(error(format!("The zone {id_zone} is not in the center")));
+            }
+        }
+
+        if let Some(password) = &user.password {
+            crate::password::check(password).map_err(|e| error(e.to_string()))?;
+        }
+        address.locate().map_err(|e| error(e.to_string()))?;
+        address
+            .assign_zone(zones)
+            .map_err(|e| error(e.to_string()))?;
+
+        Ok(Person {
+            user,
+            address,
+            nurse,
+        })
+    }
+
+    /// Inserts the records of a person, returning the ID of the patient or nurse.
+    pub fn insert(conn: &mut PgConnection, person: Person) -> diesel::QueryResult<i64> {
+        let Person {
+            user,
+            address,
+            nurse,
+        } = person;
+
+        let id_address: i64 = insert_into(addresses::table)
+            .values(&address)
+            .returning(addresses::id)
+            .get_result(conn)?;
+
+        let id_user: i64 = insert_into(users::table)
+            .values(user)
+            .returning(users::id)
+            .get_result(conn)?;
+
+        match nurse {
+            Some(nurse) => insert_into(nurses::table)
+                .values(NewNurseRecord {
+                    id_user,
+                    id_address,
+                    ..nurse
+                })
+                .returning(nurses::id)
+                .get_result(conn),
+            None => insert_into(patients::table)
+                .values(NewPatientRecord {
+                    id_user,
+                    id_address,
+                })
+                .returning(patients::id)
+                .get_result(conn),
+        }
+    }
+}
+
+/// Import records
+///
+/// Creates patients or nurses from a CSV (`text/csv`) or JSON lines (`application/x-ndjson`)
+/// file, each row holding the fields of the user, of its address and of the nurse flat. The zone
+/// of an address can be given by name in a `zone` field or by ID in `id_zone`, not both, it is
+/// found from the address otherwise.
+///
+/// Every row is validated before anything is saved, the records are then created all at once.
+/// If any row is invalid, nothing is saved and the errors of the rows are returned with their
+/// line. A dry run only validates the rows.
+#[utoipa::path(
+    context_path = "/import",
+    params(ImportParam),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, body = ImportReport),
+        (status = 400, body = ImportReport),
+        (status = 403, body = JsonError),
+        (status = 415, body = JsonError),
+    ),
+    tag = "import"
+)]
+#[post("/{entity}")]
+#[has_any_permission("patients:write", "nurses:write")]
+async fn post(
+    entity: web::Path<Entity>,
+    params: web::Query<ImportParam>,
+    req: HttpRequest,
+    body: Bytes,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let entity = *entity;
+    if !auth.has_permission(entity.permission()) {
+        return Err(
+            ErrorForbidden(format!("Missing the {} permission", entity.permission())).into(),
+        );
+    }
+    let format = Format::from_content_type(req.content_type()).ok_or_else(|| {
+        ErrorUnsupportedMediaType(
+            "The file must be sent as `text/csv` or as JSON lines with `application/x-ndjson`",
+        )
+    })?;
+
+    let conn = &mut *pool.get()?;
+    let zones = zoning::areas(conn, auth.id_center)?;
+    let mut names: HashMap<String, Vec<i64>> = HashMap::new();
+    for (id, name) in zones::table
+        .filter(zones::id_center.eq(auth.id_center))
+        .select((zones::id, zones::name))
+        .load::<(i64, String)>(conn)?
+    {
+        names.entry(name).or_default().push(id);
+    }
+
+    let rows = import::parse(format, &body);
+    let mut report = ImportReport {
+        rows: rows.len(),
+        committed: false,
+        ids: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut people = Vec::new();
+    let mut mails: HashMap<String, u64> = HashMap::new();
+    for row in rows {
+        let person = row.and_then(|row| {
+            let person = helper::person(&row, entity, &zones, &names)?;
+            match mails.get(&person.user.mail) {
+                Some(line) => Err(RowError::new(
+                    row.line,
+                    format!("The mail is already used on line {line}"),
+                )),
+                None => {
+                    mails.insert(person.user.mail.clone(), row.line);
+                    Ok((row.line, person))
+                }
+            }
+        });
+
+        match person {
+            Ok(person) => people.push(person),
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    for mail in users::table
+        .filter(users::mail.eq_any(mails.keys()))
+        .select(users::mail)
+        .load::<String>(conn)?
+    {
+        report
+            .errors
+            .push(RowError::new(mails[&mail], "The mail is already used"));
+    }
+    report.errors.sort_by_key(|e| e.line);
+
+    if !report.errors.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(report));
+    }
+    if params.dry_run {
+        return Ok(HttpResponse::Ok().json(report));
+    }
+
+    // A mail can be taken meanwhile, the row using it is then reported
+    let mut taken = None;
+    let ids = auth.audited(conn, |conn| {
+        people
+            .into_iter()
+            .map(|(line, person)| {
+                helper::insert(conn, person).map_err(|e| {
+                    if let DatabaseError(DatabaseErrorKind::UniqueViolation, _) = e {
+                        taken = Some(line);
+                    }
+                    e
+                })
+            })
+            .collect::<diesel::QueryResult<Vec<i64>>>()
+    });
+    report.ids = match (ids, taken) {
+        (Ok(ids), _) => ids,
+        (Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)), Some(line)) => {
+            report
+                .errors
+                .push(RowError::new(line, "The mail is already used"));
+            return Ok(HttpResponse::BadRequest().json(report));
+        }
+        (Err(e), _) => return Err(e.into()),
+    };
+    report.committed = true;
+
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -5,22 +5,39 @@ use actix_web::{
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::has_roles;
-use diesel::{insert_into, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
+use actix_web_grants::proc_macro::{has_permissions, has_roles};
+use diesel::{
+    insert_into, BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
-    error::{JsonError, Result},
+    error::{Error, JsonError, Result},
     models::*,
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
-    schema::{managers, users},
+    params::{FilterParam, SearchParam, SortParam},
+    schema::{centers, l_users_roles, managers, roles, users},
+    sessions,
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(all, me, get, post, put, delete),
+    paths(
+        all,
+        me,
+        get,
+        post,
+        put,
+        delete,
+        post_ical_token,
+        delete_ical_token,
+        post_password_reset,
+        get_roles,
+        put_roles,
+        put_center
+    ),
     components(schemas(
         ManagerRecord,
         Manager,
@@ -29,6 +46,10 @@ use crate::{
         UpdateUser,
         NewManagerRecord,
         NewUser,
+        CalendarToken,
+        PasswordResetToken,
+        RoleRecord,
+        MoveManager,
         crate::pagination::PaginatedManagers,
         JsonError
     )),
@@ -46,41 +67,54 @@ pub fn routes() -> Scope {
         .service(post)
         .service(put)
         .service(delete)
+        .service(post_ical_token)
+        .service(delete_ical_token)
+        .service(post_password_reset)
+        .service(get_roles)
+        .service(put_roles)
+        .service(put_center)
 }
 
 #[utoipa::path(
     context_path = "/managers",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<(ManagerRecord, User)>, FilterParam<(ManagerRecord, User)>),
     responses(
         (status = 200, description = "Paginated list of managers", body = PaginatedManagers),
     ),
     tag = "managers"
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("managers:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<(ManagerRecord, User)>>,
+    filter: web::Query<FilterParam<(ManagerRecord, User)>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
     let pool = &mut pool.get()?;
 
     let req = managers::table
         .inner_join(users::table)
-        .filter(users::fname.ilike(search.value()))
-        .or_filter(users::lname.ilike(search.value()))
-        .or_filter(users::mail.ilike(search.value()));
+        .filter(managers::id_center.eq(auth.id_center))
+        .filter(
+            users::fname
+                .ilike(search.value())
+                .or(users::lname.ilike(search.value()))
+                .or(users::mail.ilike(search.value())),
+        )
+        .filter(filter.expression());
 
     let res: Vec<Manager> = req
         .clone()
-        .order(sort.raw_sql())
+        .select(Manager::as_select())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(pool)?;
 
-    let total = req.count().get_result::<i64>(pool)? as u32;
+    let total = pagination.total(|| Ok(req.count().get_result(pool)?))?;
 
     Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
@@ -93,9 +127,9 @@ async fn all(
     tag = "managers"
 )]
 #[get("/me")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_roles("MANAGER")]
 async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
-    let res: Manager = macros::get!(managers, pool, auth.id, users);
+    let res: Manager = macros::get!(managers, pool, auth.id, users => Manager);
 
     Ok(Json(res))
 }
@@ -104,34 +138,56 @@ async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     context_path = "/managers",
     responses(
         (status = 200, body = Manager),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "managers"
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    let res: Manager = macros::get!(managers, pool, *id, users);
+#[has_permissions("managers:read")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    auth.check_center::<ManagerRecord>(&mut *pool.get()?, *id)?;
+
+    let res: Manager = macros::get!(managers, pool, *id, users => Manager);
 
     Ok(Json(res))
 }
 
+/// Create manager
+///
+/// An administrator can create managers in every center, such as the first manager of a new
+/// center.
 #[utoipa::path(
     path = "/managers",
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
     ),
     tag = "managers"
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("managers:write")]
 async fn post(
     new_record: Json<NewManager>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    pool.get()?.build_transaction().run(|conn| {
+    let id_center = new_record.manager.id_center;
+    if auth.is_admin() {
+        centers::table
+            .find(id_center)
+            .select(centers::id)
+            .first::<i64>(&mut *pool.get()?)?;
+    } else {
+        auth.same_center(id_center)?;
+    }
+    if let Some(password) = &new_record.user.password {
+        crate::password::check(password)?;
+    }
+
+    auth.audited(&mut *pool.get()?, |conn| {
         let NewManager { manager, user } = new_record.0;
 
         let id_user: i64 = insert_into(users::table)
@@ -158,7 +214,7 @@ async fn post(
     tag = "managers"
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_roles("MANAGER")]
 async fn put(
     id: web::Path<i64>,
     update_record: Json<UpdateUser>,
@@ -169,47 +225,243 @@ async fn put(
         return Err(ErrorForbidden("").into());
     }
 
-    diesel::update(users::table)
-        .set(&update_record.0)
-        .filter(users::id.eq(auth.id_user))
-        .execute(&mut pool.get()?)?;
+    auth.audited(&mut *pool.get()?, |conn| {
+        diesel::update(users::table)
+            .set(&update_record.0)
+            .filter(users::id.eq(auth.id_user))
+            .execute(conn)
+    })?;
 
     Ok(Json(()))
 }
 
 /// Delete manager
 ///
-/// This will also delete the associated user
+/// This will also delete the associated user. A manager with permissions the current user lacks,
+/// such as an administrator, cannot be deleted.
 #[utoipa::path(
     context_path = "/managers",
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "managers"
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("managers:write")]
 async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
-    let id_center: i64 = managers::table
-        .select(managers::id_center)
-        .get_result(&mut pool.get()?)?;
+    let conn = &mut pool.get()?;
+    auth.check_center::<ManagerRecord>(conn, *id)?;
 
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.audited(conn, |conn| {
+        let id_user: i64 = managers::table
+            .find(*id)
+            .select(managers::id_user)
+            .first(conn)?;
+        crate::permissions::check_manageable(conn, &auth, id_user)?;
 
-    pool.get()?.build_transaction().run(|conn| {
-        let id_user: i64 = diesel::delete(managers::table)
+        diesel::delete(managers::table)
             .filter(managers::id.eq(*id))
-            .returning(managers::id_user)
-            .get_result(conn)?;
+            .execute(conn)?;
 
         diesel::delete(users::table)
             .filter(users::id.eq(id_user))
             .execute(conn)?;
 
-        Ok::<(), diesel::result::Error>(())
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Create calendar token
+///
+/// Creates the token giving the current manager access to the calendar feeds of its center and
+/// zones. If the manager already has a token, it is replaced and the previous one stops working.
+/// The token is only returned once.
+#[utoipa::path(
+    context_path = "/managers",
+    responses(
+        (status = 200, body = CalendarToken),
+    ),
+    tag = "managers"
+)]
+#[post("/me/ical/token")]
+#[has_roles("MANAGER")]
+async fn post_ical_token(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let res = crate::ical::create_token(&mut *pool.get()?, auth.id_user)?;
+
+    Ok(Json(res))
+}
+
+/// Revoke calendar token
+///
+/// Deletes the calendar token of the current manager, the calendar feeds are no longer accessible
+/// until a new token is created.
+#[utoipa::path(
+    context_path = "/managers",
+    responses(
+        (status = 200),
+        (status = 404, body = JsonError),
+    ),
+    tag = "managers"
+)]
+#[delete("/me/ical/token")]
+#[has_roles("MANAGER")]
+async fn delete_ical_token(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    if crate::ical::revoke_token(&mut *pool.get()?, auth.id_user)? == 0 {
+        Err(diesel::result::Error::NotFound.into())
+    } else {
+        Ok(Json(()))
+    }
+}
+
+/// Issue password reset token
+///
+/// Issues a single-use token allowing a manager of the center to set its password with
+/// `/auth/password/reset`. It replaces the previous token of the manager if any. The password of
+/// a manager with permissions the current user lacks, such as an administrator, cannot be reset.
+#[utoipa::path(
+    context_path = "/managers",
+    responses(
+        (status = 200, body = PasswordResetToken),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "managers"
+)]
+#[post("/{id}/password/reset")]
+#[has_permissions("managers:write")]
+async fn post_password_reset(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    auth.check_center::<ManagerRecord>(conn, *id)?;
+
+    let id_user = managers::table
+        .find(*id)
+        .select(managers::id_user)
+        .first(conn)?;
+    crate::permissions::check_manageable(conn, &auth, id_user)?;
+    let res = crate::password::issue_reset_token(conn, id_user)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    context_path = "/managers",
+    responses(
+        (status = 200, body = Vec<RoleRecord>),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "managers"
+)]
+#[get("/{id}/roles")]
+#[has_permissions("roles:read")]
+async fn get_roles(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    auth.check_center::<ManagerRecord>(conn, *id)?;
+
+    let id_user = managers::table
+        .find(*id)
+        .select(managers::id_user)
+        .first(conn)?;
+
+    Ok(Json(crate::permissions::roles_of_user(conn, id_user)?))
+}
+
+/// Set roles
+///
+/// Replaces the roles of the manager. Roles must be default roles or roles of the center, and the
+/// current user can only grant or revoke the permissions it has.
+#[utoipa::path(
+    context_path = "/managers",
+    request_body = Vec<i64>,
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "managers"
+)]
+#[put("/{id}/roles")]
+#[has_permissions("roles:write")]
+async fn put_roles(
+    id: web::Path<i64>,
+    ids: web::Json<Vec<i64>>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    auth.check_center::<ManagerRecord>(conn, *id)?;
+
+    let id_user = managers::table
+        .find(*id)
+        .select(managers::id_user)
+        .first(conn)?;
+    auth.audited(conn, |conn| {
+        crate::permissions::set_user_roles(conn, &auth, id_user, &ids)
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Move manager
+///
+/// Moves a manager to another center. The manager loses the roles of its previous center and is
+/// logged out.
+#[utoipa::path(
+    context_path = "/managers",
+    responses(
+        (status = 200),
+        (status = 404, body = JsonError),
+    ),
+    tag = "managers",
+    security(
+        ("token" = ["admin"])
+    )
+)]
+#[put("/{id}/center")]
+#[has_permissions("centers:write")]
+async fn put_center(
+    id: web::Path<i64>,
+    move_manager: Json<MoveManager>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let id_center = move_manager.id_center;
+
+    auth.audited(&mut *pool.get()?, |conn| {
+        centers::table
+            .find(id_center)
+            .select(centers::id)
+            .first::<i64>(conn)?;
+
+        let manager: ManagerRecord = diesel::update(managers::table.find(*id))
+            .set(managers::id_center.eq(id_center))
+            .get_result(conn)?;
+
+        diesel::delete(l_users_roles::table)
+            .filter(l_users_roles::id_user.eq(manager.id_user))
+            .filter(
+                l_users_roles::id_role.eq_any(
+                    roles::table
+                        .filter(roles::id_center.is_not_null())
+                        .filter(roles::id_center.ne(id_center))
+                        .select(roles::id),
+                ),
+            )
+            .execute(conn)?;
+
+        sessions::revoke_all(conn, manager.id_user)
     })?;
 
     Ok(Json(()))
@@ -3,16 +3,17 @@ use actix_web::{
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::has_roles;
+use actix_web_grants::proc_macro::has_permissions;
 use diesel::{insert_into, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
     error::{JsonError, Result},
     models::{MissionType, NewLMissionSkill, NewMissionType, UpdateMissionType},
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
+    params::{FilterParam, SearchParam, SortParam},
+    reports::{Template, Vital},
     schema::{l_missions_skills, mission_types},
 };
 
@@ -23,6 +24,8 @@ use crate::{
         MissionType,
         UpdateMissionType,
         NewMissionType,
+        Template,
+        Vital,
         crate::pagination::PaginatedMissionTypes,
         JsonError
     )),
@@ -45,31 +48,34 @@ pub fn routes() -> Scope {
 
 #[utoipa::path(
     context_path = "/mission_types",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<MissionType>, FilterParam<MissionType>),
     responses(
         (status = 200, description = "Paginated list of missions types", body = PaginatedMissionTypes),
     ),
     tag = "mission_types"
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<MissionType>>,
+    filter: web::Query<FilterParam<MissionType>>,
     pool: web::Data<DbPool>,
     _: Auth,
 ) -> Result<impl Responder> {
-    let req = mission_types::table.filter(mission_types::name.ilike(search.value()));
+    let req = mission_types::table
+        .filter(mission_types::name.ilike(search.value()))
+        .filter(filter.expression());
 
     let res: Vec<MissionType> = req
         .clone()
-        .order(sort.raw_sql())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(&mut pool.get()?)?;
 
-    let total = req.count().get_result::<i64>(&mut pool.get()?)? as u32;
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
 
     Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
@@ -83,7 +89,7 @@ async fn all(
     tag = "mission_types"
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:read")]
 async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
     let res: MissionType = macros::get!(mission_types, pool, *id);
 
@@ -99,16 +105,22 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     tag = "mission_types"
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:write")]
 async fn post(
     new_mission_type: Json<NewMissionType>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    if let Some(template) = &new_mission_type.report_template {
+        template.check()?;
+    }
+
     web::block(move || {
-        insert_into(mission_types::table)
-            .values(&new_mission_type.0)
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            insert_into(mission_types::table)
+                .values(&new_mission_type.0)
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -129,18 +141,20 @@ async fn post(
     )
 )]
 #[post("/{id_mission_type}/skills/{id_skill}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:write")]
 async fn post_mission_type_skill(
     ids: web::Path<(i64, i64)>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    insert_into(l_missions_skills::table)
-        .values(&NewLMissionSkill {
-            id_mission_type: ids.0,
-            id_skill: ids.1,
-        })
-        .execute(&mut pool.get()?)?;
+    auth.audited(&mut *pool.get()?, |conn| {
+        insert_into(l_missions_skills::table)
+            .values(&NewLMissionSkill {
+                id_mission_type: ids.0,
+                id_skill: ids.1,
+            })
+            .execute(conn)
+    })?;
 
     Ok(Json(()))
 }
@@ -155,18 +169,24 @@ async fn post_mission_type_skill(
     tag = "mission_types"
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:write")]
 async fn put(
     id: web::Path<i64>,
     update_skill: Json<UpdateMissionType>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    if let Some(Some(template)) = &update_skill.report_template {
+        template.check()?;
+    }
+
     web::block(move || {
-        diesel::update(mission_types::table)
-            .set(&update_skill.0)
-            .filter(mission_types::id.eq(*id))
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            diesel::update(mission_types::table)
+                .set(&update_skill.0)
+                .filter(mission_types::id.eq(*id))
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -182,9 +202,9 @@ async fn put(
     tag = "mission_types"
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    macros::delete!(mission_types, pool, *id);
+#[has_permissions("mission_types:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    macros::delete!(mission_types, pool, *id, auth);
 
     Ok(Json(()))
 }
@@ -204,16 +224,18 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<
     )
 )]
 #[delete("/{id_mission_type}/skills/{id_skill}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("mission_types:write")]
 async fn delete_mission_type_skill(
     ids: web::Path<(i64, i64)>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let rows = diesel::delete(l_missions_skills::table)
-        .filter(l_missions_skills::id_mission_type.eq(ids.0))
-        .filter(l_missions_skills::id_skill.eq(ids.1))
-        .execute(&mut pool.get()?)?;
+    let rows = auth.audited(&mut *pool.get()?, |conn| {
+        diesel::delete(l_missions_skills::table)
+            .filter(l_missions_skills::id_mission_type.eq(ids.0))
+            .filter(l_missions_skills::id_skill.eq(ids.1))
+            .execute(conn)
+    })?;
 
     if rows == 0 {
         Err(diesel::result::Error::NotFound.into())
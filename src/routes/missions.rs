@@ -1,24 +1,44 @@
+use std::env;
+
 use actix_web::{
-    delete, get, post, put,
+    delete,
+    error::ErrorBadRequest,
+    get, post, put,
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::has_roles;
-use diesel::{insert_into, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
+use actix_web_grants::proc_macro::has_permissions;
+use chrono::{Duration, Local};
+use diesel::{
+    insert_into, BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
+use once_cell::sync::Lazy;
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
-    error::{JsonError, Result},
+    error::{Error, JsonError, Result},
     models::*,
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
-    schema::{addresses, mission_types, missions, patients, users},
+    params::{FilterParam, SearchParam, SortParam},
+    schema::{
+        addresses, centers, l_visits_nurses, mission_types, missions, patients, users, visits,
+        zones,
+    },
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(all, get, post, put, delete),
+    paths(
+        all,
+        get,
+        post,
+        generate_center_visits,
+        generate_visits,
+        put,
+        delete
+    ),
     components(schemas(
         Mission,
         MissionRecord,
@@ -33,52 +53,133 @@ use crate::{
 )]
 pub struct Doc;
 
+/// Most days visits can be generated for at once, set by `VISIT_GENERATION_MAX_DAYS`.
+static GENERATION_MAX_DAYS: Lazy<i64> = Lazy::new(|| {
+    env::var("VISIT_GENERATION_MAX_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(366)
+});
+
 pub fn routes() -> Scope {
     web::scope("/missions")
         .service(all)
         .service(get)
         .service(post)
+        .service(generate_center_visits)
+        .service(generate_visits)
         .service(put)
         .service(delete)
 }
 
+mod helper {
+    use chrono::NaiveDateTime;
+    use diesel::{PgConnection, QueryResult};
+
+    use super::*;
+
+    /// Checks visits can be generated between `from` and `until`.
+    pub fn check_period(from: NaiveDateTime, until: NaiveDateTime) -> Result<()> {
+        if until < from {
+            return Err(ErrorBadRequest("`until` must be after `from`").into());
+        }
+        if until - from > Duration::days(*GENERATION_MAX_DAYS) {
+            return Err(ErrorBadRequest(format!(
+                "Visits can only be generated for {} days at once",
+                *GENERATION_MAX_DAYS
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the visits of the given missions between `from` and `until`, returning their IDs.
+    ///
+    /// Occurrences for which the mission already has a visit starting inside the occurrence time
+    /// window are skipped.
+    pub fn generate_visits(
+        conn: &mut PgConnection,
+        missions: &[MissionRecord],
+        center: &CenterRecord,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> QueryResult<Vec<i64>> {
+        let mut ids = Vec::new();
+
+        for mission in missions {
+            let existing: Vec<NaiveDateTime> = visits::table
+                .filter(visits::id_mission.eq(mission.id))
+                .select(visits::start)
+                .load(conn)?;
+
+            let new_visits: Vec<NewVisit> = mission
+                .occurrences(from, until, center.workday_start, center.workday_end)
+                .into_iter()
+                .filter(|o| {
+                    !existing
+                        .iter()
+                        .any(|start| (o.window_start..=o.window_end).contains(start))
+                })
+                .map(|o| o.visit)
+                .collect();
+
+            if !new_visits.is_empty() {
+                ids.extend(
+                    insert_into(visits::table)
+                        .values(&new_visits)
+                        .returning(visits::id)
+                        .get_results::<i64>(conn)?,
+                );
+            }
+        }
+
+        Ok(ids)
+    }
+}
+
 #[utoipa::path(
     context_path = "/missions",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<(MissionRecord, MissionType)>, FilterParam<(MissionRecord, MissionType)>),
     responses(
         (status = 200, description = "Paginated list of missions", body = PaginatedMissions),
     ),
     tag = "missions"
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("missions:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<(MissionRecord, MissionType)>>,
+    filter: web::Query<FilterParam<(MissionRecord, MissionType)>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let res: Vec<Mission> = missions::table
+    let req = missions::table
         .inner_join(mission_types::table)
         .inner_join(
             patients::table
                 .inner_join(users::table)
-                .inner_join(addresses::table),
+                .inner_join(addresses::table.inner_join(zones::table)),
         )
-        .filter(missions::desc.ilike(search.value()))
-        .or_filter(mission_types::name.ilike(search.value()))
-        .order(sort.raw_sql())
+        .filter(zones::id_center.eq(auth.id_center))
+        .filter(
+            missions::desc
+                .ilike(search.value())
+                .or(mission_types::name.ilike(search.value())),
+        )
+        .filter(filter.expression());
+
+    let res: Vec<Mission> = req
+        .clone()
+        .select(Mission::as_select())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(&mut pool.get()?)?;
 
-    let total = missions::table
-        .inner_join(mission_types::table)
-        .filter(missions::desc.ilike(search.value()))
-        .or_filter(mission_types::name.ilike(search.value()))
-        .count()
-        .get_result::<i64>(&mut pool.get()?)? as u32;
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
 
     Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
@@ -87,13 +188,16 @@ async fn all(
     context_path = "/missions",
     responses(
         (status = 200, body = Mission),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "missions"
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
+#[has_permissions("missions:read")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    auth.check_center::<MissionRecord>(&mut *pool.get()?, *id)?;
+
     let res: Mission = actix_web::web::block(move || {
         missions::table
             .inner_join(mission_types::table)
@@ -103,6 +207,7 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
                     .inner_join(addresses::table),
             )
             .filter(missions::id.eq(*id))
+            .select(Mission::as_select())
             .first(&mut pool.get().unwrap())
     })
     .await??;
@@ -115,67 +220,222 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     path = "/missions",
     responses(
         (status = 200),
-        (status = 400, body = JsonError)
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError)
     ),
     tag = "missions"
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("missions:write")]
 async fn post(
     new_record: Json<NewMission>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.check_center::<PatientRecord>(&mut *pool.get()?, new_record.id_patient)?;
+
     web::block(move || {
-        insert_into(missions::table)
-            .values(&new_record.0)
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            insert_into(missions::table)
+                .values(&new_record.0)
+                .execute(conn)
+        })
     })
     .await??;
 
     Ok(Json(()))
 }
 
+/// Generate the center's visits
+///
+/// Creates the visits of every non archived mission of the current center up to `until`,
+/// following their recurrence. Occurrences that already have a visit are skipped, so this route
+/// can safely be called multiple times. Visits are generated for at most
+/// `VISIT_GENERATION_MAX_DAYS` days at once. Returns the IDs of the created visits.
+#[utoipa::path(
+    context_path = "/missions",
+    params(GenerateVisitsParam),
+    responses(
+        (status = 200, body = Vec<i64>),
+        (status = 400, body = JsonError)
+    ),
+    tag = "missions",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[post("/visits/generate")]
+#[has_permissions("visits:write")]
+async fn generate_center_visits(
+    params: web::Query<GenerateVisitsParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let from = params.from.unwrap_or_else(|| Local::now().naive_local());
+    helper::check_period(from, params.until)?;
+
+    let ids = web::block(move || {
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            let center: CenterRecord = centers::table.find(auth.id_center).first(conn)?;
+
+            // Concurrent generations for the same missions wait for this one, so they see the
+            // visits it creates
+            let missions: Vec<MissionRecord> = missions::table
+                .filter(
+                    missions::id_patient.eq_any(
+                        patients::table
+                            .inner_join(addresses::table.inner_join(zones::table))
+                            .filter(zones::id_center.eq(auth.id_center))
+                            .select(patients::id),
+                    ),
+                )
+                .filter(missions::archived.eq(false))
+                .select(MissionRecord::as_select())
+                .order(missions::id)
+                .for_update()
+                .load(conn)?;
+
+            helper::generate_visits(conn, &missions, &center, from, params.until)
+        })
+    })
+    .await??;
+
+    Ok(Json(ids))
+}
+
+/// Generate the mission's visits
+///
+/// Creates the visits of the given mission up to `until`, following its recurrence. Each visit
+/// is placed at the earliest moment of the mission time window that fits inside the center
+/// workday. Occurrences that already have a visit are skipped, so this route can safely be called
+/// multiple times. Visits are generated for at most `VISIT_GENERATION_MAX_DAYS` days at once.
+/// Returns the IDs of the created visits, an archived mission creates none.
+#[utoipa::path(
+    context_path = "/missions",
+    params(GenerateVisitsParam),
+    responses(
+        (status = 200, body = Vec<i64>),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    ),
+    tag = "missions",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[post("/{id}/visits/generate")]
+#[has_permissions("visits:write")]
+async fn generate_visits(
+    id: web::Path<i64>,
+    params: web::Query<GenerateVisitsParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let from = params.from.unwrap_or_else(|| Local::now().naive_local());
+    helper::check_period(from, params.until)?;
+
+    auth.check_center::<MissionRecord>(&mut *pool.get()?, *id)?;
+
+    let ids = web::block(move || {
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            // Concurrent generations for the mission wait for this one, so they see the visits
+            // it creates
+            let mission: MissionRecord = missions::table
+                .find(*id)
+                .select(MissionRecord::as_select())
+                .for_update()
+                .first(conn)?;
+
+            let center: CenterRecord = missions::table
+                .inner_join(patients::table.inner_join(
+                    addresses::table.inner_join(zones::table.inner_join(centers::table)),
+                ))
+                .filter(missions::id.eq(*id))
+                .select(centers::all_columns)
+                .first(conn)?;
+
+            helper::generate_visits(conn, &[mission], &center, from, params.until)
+        })
+    })
+    .await??;
+
+    Ok(Json(ids))
+}
+
 #[utoipa::path(
     context_path = "/missions",
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "missions"
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("missions:write")]
 async fn put(
     id: web::Path<i64>,
     update_record: Json<UpdateMission>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.check_center::<MissionRecord>(&mut *pool.get()?, *id)?;
+
     web::block(move || {
-        diesel::update(missions::table)
-            .set(&update_record.0)
-            .filter(missions::id.eq(*id))
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            diesel::update(missions::table)
+                .set(&update_record.0)
+                .filter(missions::id.eq(*id))
+                .execute(conn)
+        })
     })
     .await??;
 
     Ok(Json(()))
 }
 
+/// Delete mission
+///
+/// This will also delete the visits of the mission. A mission for which a report was written
+/// cannot be deleted.
 #[utoipa::path(
     context_path = "/missions",
     responses(
         (status = 200),
-        (status = 404, body = JsonError)
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, body = JsonError)
     ),
     tag = "missions"
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    macros::delete!(missions, pool, *id);
+#[has_permissions("missions:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    auth.check_center::<MissionRecord>(conn, *id)?;
+
+    auth.audited(conn, |conn| {
+        crate::reports::check_no_report(
+            conn,
+            l_visits_nurses::table
+                .filter(
+                    l_visits_nurses::id_visit.eq_any(
+                        visits::table
+                            .filter(visits::id_mission.eq(*id))
+                            .select(visits::id),
+                    ),
+                )
+                .into_boxed(),
+            "Reports were written for the mission, it cannot be deleted",
+        )?;
+
+        diesel::delete(missions::table)
+            .filter(missions::id.eq(*id))
+            .execute(conn)?;
+
+        Ok::<(), Error>(())
+    })?;
 
     Ok(Json(()))
 }
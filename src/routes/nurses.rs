@@ -1,27 +1,32 @@
 use actix_web::{
     delete,
-    error::ErrorForbidden,
+    error::{ErrorBadRequest, ErrorForbidden},
     get, post, put,
     web::{self, Json},
-    Responder, Scope,
+    HttpResponse, Responder, Scope,
 };
-use actix_web_grants::proc_macro::{has_any_role, has_roles};
+use actix_web_grants::proc_macro::{has_any_permission, has_permissions, has_roles};
+use chrono::{Duration, Local, NaiveDate, NaiveTime};
 use diesel::{
-    connection::DefaultLoadingMode, insert_into, BelongingToDsl, ExpressionMethods, GroupedBy,
+    insert_into, BelongingToDsl, BoolExpressionMethods, ExpressionMethods, GroupedBy,
     PgTextExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper,
 };
 
 use crate::{
     auth::{Auth, Role},
-    database::DbPool,
-    error::{JsonError, Result},
+    center::CenterScoped,
+    database::{hash_token, DbPool},
+    error::{Error, JsonError, Result},
     models::*,
-    pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
+    pagination::{CursorParam, PaginatedResponse, PaginationParam},
+    params::{FilterParam, PeriodParam, SearchParam, SortParam},
+    routing::{self, DailyRoute, RouteStop, RouteVisit, TravelWarning},
     schema::{
-        self, addresses, l_nurses_skills, l_visits_nurses, mission_types, missions, nurses,
-        patients, skills, users, visits, zones,
+        self, addresses, calendar_tokens, l_nurses_skills, l_visits_nurses, mission_types,
+        missions, nurses, patients, skills, users, visits, zones,
     },
+    workload::{self, AssignedVisit, Workload},
+    zoning,
 };
 
 #[derive(utoipa::OpenApi)]
@@ -30,14 +35,25 @@ use crate::{
         all,
         get,
         me,
+        me_visits,
         post,
         post_nurse_skill,
         put,
         delete,
         delete_nurse_skill,
         availabilities,
+        availability_slots,
         reports,
-        ical
+        nurse_visits,
+        ical,
+        post_ical_token,
+        delete_ical_token,
+        post_password_reset,
+        get_roles,
+        put_roles,
+        workloads,
+        nurse_workload,
+        route
     ),
     components(schemas(
         Nurse,
@@ -51,7 +67,18 @@ use crate::{
         NewUser,
         NewAddress,
         Availability,
+        AvailabilitySlot,
         LVisitNurse,
+        PlannedVisit,
+        Colleague,
+        CalendarToken,
+        PasswordResetToken,
+        RoleRecord,
+        Workload,
+        DailyRoute,
+        RouteStop,
+        TravelWarning,
+        crate::geo::Coordinates,
         crate::pagination::PaginatedLVisitsNurses,
         crate::pagination::PaginatedSkilledNurses,
         crate::pagination::PaginatedAvailabilities,
@@ -64,6 +91,8 @@ pub fn routes() -> Scope {
     web::scope("/nurses")
         .service(all)
         .service(me)
+        .service(me_visits)
+        .service(workloads)
         .service(get)
         .service(post)
         .service(post_nurse_skill)
@@ -71,13 +100,137 @@ pub fn routes() -> Scope {
         .service(delete)
         .service(delete_nurse_skill)
         .service(availabilities)
+        .service(availability_slots)
         .service(reports)
+        .service(nurse_visits)
         .service(ical)
+        .service(post_ical_token)
+        .service(delete_ical_token)
+        .service(post_password_reset)
+        .service(get_roles)
+        .service(put_roles)
+        .service(nurse_workload)
+        .service(route)
+}
+
+mod helper {
+    use std::collections::HashMap;
+
+    use diesel::{PgConnection, QueryResult};
+
+    use super::*;
+
+    /// Checks the current user can manage the given nurse and returns the user ID of the nurse.
+    ///
+    /// A nurse can always manage itself, other users need the `nurses:write` permission to manage
+    /// the nurses of their center.
+    pub fn id_user(conn: &mut PgConnection, auth: &Auth, id_nurse: i64) -> Result<i64> {
+        if !auth.is_or_has(Role::Nurse, id_nurse, "nurses:write") {
+            return Err(ErrorForbidden("A nurse can only manage its own calendar").into());
+        }
+        auth.check_center::<NurseRecord>(conn, id_nurse)?;
+
+        Ok(nurses::table
+            .filter(nurses::id.eq(id_nurse))
+            .select(nurses::id_user)
+            .first(conn)?)
+    }
+
+    /// Computes the workload of nurses during the week of `date`.
+    ///
+    /// `nurses` holds the ID, first name, last name and weekly minutes of each nurse.
+    pub fn workloads(
+        conn: &mut PgConnection,
+        nurses: Vec<(i64, String, String, i32)>,
+        date: NaiveDate,
+    ) -> QueryResult<Vec<Workload>> {
+        let (from, to) = workload::week_bounds(date);
+
+        let mut assigned: HashMap<i64, Vec<AssignedVisit>> = HashMap::new();
+        for (id_nurse, start, end, arrived_at, left_at) in l_visits_nurses::table
+            .inner_join(visits::table)
+            .filter(l_visits_nurses::id_nurse.eq_any(nurses.iter().map(|n| n.0)))
+            .filter(visits::cancelled_at.is_null())
+            .filter(visits::start.ge(from))
+            .filter(visits::start.lt(to))
+            .select((
+                l_visits_nurses::id_nurse,
+                visits::start,
+                visits::end,
+                l_visits_nurses::arrived_at,
+                l_visits_nurses::left_at,
+            ))
+            .load(conn)?
+        {
+            assigned.entry(id_nurse).or_default().push(AssignedVisit {
+                start,
+                end,
+                arrived_at,
+                left_at,
+            });
+        }
+
+        Ok(nurses
+            .into_iter()
+            .map(|(id, fname, lname, minutes_per_week)| {
+                let visits = assigned.remove(&id).unwrap_or_default();
+                Workload::new(id, fname, lname, date, minutes_per_week, &visits)
+            })
+            .collect())
+    }
+
+    /// Loads the visits of a nurse overlapping the given period, in chronological order.
+    /// Cancelled visits are left out.
+    pub fn planned_visits(
+        conn: &mut PgConnection,
+        id_nurse: i64,
+        period: &PeriodParam,
+    ) -> QueryResult<Vec<PlannedVisit>> {
+        let rows: Vec<(Visit, Option<String>)> = visits::table
+            .inner_join(
+                missions::table.inner_join(mission_types::table).inner_join(
+                    patients::table
+                        .inner_join(users::table)
+                        .inner_join(addresses::table),
+                ),
+            )
+            .inner_join(l_visits_nurses::table)
+            .filter(l_visits_nurses::id_nurse.eq(id_nurse))
+            .filter(visits::cancelled_at.is_null())
+            .filter(visits::start.lt(period.to))
+            .filter(visits::end.gt(period.from))
+            .order((visits::start, visits::id))
+            .select((Visit::as_select(), l_visits_nurses::report))
+            .load(conn)?;
+
+        let ids: Vec<i64> = rows.iter().map(|(v, _)| v.visit.id).collect();
+
+        let mut colleagues: HashMap<i64, Vec<Colleague>> = HashMap::new();
+        for (id_visit, colleague) in l_visits_nurses::table
+            .inner_join(nurses::table.inner_join(users::table))
+            .filter(l_visits_nurses::id_visit.eq_any(&ids))
+            .filter(l_visits_nurses::id_nurse.ne(id_nurse))
+            .order(users::lname)
+            .select((l_visits_nurses::id_visit, Colleague::as_select()))
+            .load::<(i64, Colleague)>(conn)?
+        {
+            colleagues.entry(id_visit).or_default().push(colleague);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|(visit, report)| PlannedVisit {
+                colleagues: colleagues.remove(&visit.visit.id).unwrap_or_default(),
+                report_filled: report.is_some_and(|r| !r.is_empty()),
+                visit,
+            })
+            .collect())
+    }
 }
 
 #[utoipa::path(
     context_path = "/nurses",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<(NurseRecord, User, Address)>, FilterParam<(NurseRecord, User, Address)>),
     responses(
         (status = 200, description = "Paginated list of nurses", body = PaginatedSkilledNurses),
     ),
@@ -87,36 +240,40 @@ pub fn routes() -> Scope {
     )
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("nurses:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<(NurseRecord, User, Address)>>,
+    filter: web::Query<FilterParam<(NurseRecord, User, Address)>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
     let pool = &mut pool.get()?;
 
-    // Get nurses
-    let nurses: Vec<Nurse> = nurses::table
+    let req = nurses::table
         .inner_join(users::table)
-        .inner_join(addresses::table)
-        .filter(users::fname.ilike(search.value()))
-        .or_filter(users::lname.ilike(search.value()))
-        .or_filter(users::mail.ilike(search.value()))
-        .order(sort.raw_sql())
+        .inner_join(addresses::table.inner_join(zones::table))
+        .filter(zones::id_center.eq(auth.id_center))
+        .filter(
+            users::fname
+                .ilike(search.value())
+                .or(users::lname.ilike(search.value()))
+                .or(users::mail.ilike(search.value())),
+        )
+        .filter(filter.expression());
+
+    // Get nurses
+    let nurses: Vec<Nurse> = req
+        .clone()
+        .select(Nurse::as_select())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(pool)?;
 
     // Get total of nurses
-    let total = nurses::table
-        .inner_join(users::table)
-        .filter(users::fname.ilike(search.value()))
-        .or_filter(users::lname.ilike(search.value()))
-        .or_filter(users::mail.ilike(search.value()))
-        .count()
-        .get_result::<i64>(pool)?;
+    let total = pagination.total(|| Ok(req.count().get_result(pool)?))?;
 
     // Get database records
     let nurses_records: Vec<_> = nurses.iter().map(|n| n.nurse).collect();
@@ -131,9 +288,7 @@ async fn all(
         .map(SkilledNurse::from)
         .collect();
 
-    Ok(Json(
-        PaginatedResponse::new(res, &pagination).total(total as u32),
-    ))
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
 
 #[utoipa::path(
@@ -147,11 +302,11 @@ async fn all(
     )
 )]
 #[get("/me")]
-#[has_any_role["Role::Nurse", type = "Role"]]
+#[has_roles("NURSE")]
 async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     let p2 = pool.clone();
 
-    let nurse: Nurse = macros::get!(nurses, pool, auth.id, users, addresses);
+    let nurse: Nurse = macros::get!(nurses, pool, auth.id, users, addresses => Nurse);
 
     let skills: Vec<Skill> = LNurseSkill::belonging_to(&nurse.nurse)
         .inner_join(skills::table)
@@ -163,10 +318,44 @@ async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     Ok(Json(res))
 }
 
+/// Current nurse's visits
+///
+/// Returns the visits of the current nurse overlapping the given period, in chronological order.
+/// Each visit comes with the other nurses assigned to it and whether the nurse has written its
+/// report.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(PeriodParam),
+    responses(
+        (status = 200, body = Vec<PlannedVisit>),
+        (status = 400, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["nurse"])
+    )
+)]
+#[get("/me/visits")]
+#[has_roles("NURSE")]
+async fn me_visits(
+    period: web::Query<PeriodParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !period.is_valid() {
+        return Err(ErrorBadRequest("`to` must be after `from`").into());
+    }
+
+    let res = helper::planned_visits(&mut *pool.get()?, auth.id, &period)?;
+
+    Ok(Json(res))
+}
+
 #[utoipa::path(
     context_path = "/nurses",
     responses(
         (status = 200, body = SkilledNurse),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "nurses",
@@ -175,15 +364,16 @@ async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     )
 )]
 #[get("/{id}")]
-#[has_any_role["Role::Manager", "Role::Nurse", type = "Role"]]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
 async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     let p2 = pool.clone();
 
-    if auth.role == Role::Nurse && auth.id != *id {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
         return Err(ErrorForbidden("").into());
     }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
 
-    let nurse: Nurse = macros::get!(nurses, pool, *id, users, addresses);
+    let nurse: Nurse = macros::get!(nurses, pool, *id, users, addresses => Nurse);
 
     let skills: Vec<Skill> = LNurseSkill::belonging_to(&nurse.nurse)
         .inner_join(skills::table)
@@ -200,6 +390,7 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
     ),
     tag = "nurses",
     security(
@@ -207,13 +398,23 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<
     )
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("nurses:write")]
 async fn post(
-    new_record: web::Json<NewNurse>,
+    mut new_record: web::Json<NewNurse>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    pool.get()?.build_transaction().run(|conn| {
+    if let Some(id_zone) = new_record.address.id_zone {
+        auth.check_center::<ZoneRecord>(&mut *pool.get()?, id_zone)?;
+    }
+    if let Some(password) = &new_record.user.password {
+        crate::password::check(password)?;
+    }
+    new_record.address.locate()?;
+    let zones = zoning::areas(&mut *pool.get()?, auth.id_center)?;
+    new_record.address.assign_zone(&zones)?;
+
+    auth.audited(&mut *pool.get()?, |conn| {
         let NewNurse {
             nurse,
             user,
@@ -251,6 +452,7 @@ async fn post(
     context_path = "/nurses",
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
     ),
     tag = "nurses",
     security(
@@ -258,27 +460,22 @@ async fn post(
     )
 )]
 #[post("/{id_nurse}/skills/{id_skill}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("nurses:write")]
 async fn post_nurse_skill(
     ids: web::Path<(i64, i64)>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    let id_center: i64 = nurses::table
-        .inner_join(addresses::table.inner_join(zones::table))
-        .select(zones::id_center)
-        .get_result(&mut pool.get()?)?;
-
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, ids.0)?;
 
-    insert_into(l_nurses_skills::table)
-        .values(&NewLNurseSkill {
-            id_nurse: ids.0,
-            id_skill: ids.1,
-        })
-        .execute(&mut pool.get()?)?;
+    auth.audited(&mut *pool.get()?, |conn| {
+        insert_into(l_nurses_skills::table)
+            .values(&NewLNurseSkill {
+                id_nurse: ids.0,
+                id_skill: ids.1,
+            })
+            .execute(conn)
+    })?;
 
     Ok(Json(()))
 }
@@ -288,6 +485,7 @@ async fn post_nurse_skill(
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "nurses",
@@ -296,30 +494,38 @@ async fn post_nurse_skill(
     )
 )]
 #[put("/{id}")]
-#[has_any_role["Role::Manager", "Role::Nurse", type = "Role"]]
+#[has_any_permission("nurses:write", "ROLE_NURSE")]
 async fn put(
     id: web::Path<i64>,
-    update_record: web::Json<UpdateNurse>,
+    mut update_record: web::Json<UpdateNurse>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    let (id_user, id_address) = if auth.role == Role::Nurse && auth.id != *id {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:write") {
         return Err(ErrorForbidden("").into());
-    } else {
-        let (id_center, id_user, id_address): (i64, i64, i64) = nurses::table
-            .inner_join(addresses::table.inner_join(zones::table))
-            .filter(nurses::id.eq(*id))
-            .select((zones::id_center, nurses::id_user, nurses::id_address))
-            .first(&mut pool.get()?)?;
+    }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
+    if let Some(id_zone) = update_record.address.id_zone {
+        auth.check_center::<ZoneRecord>(&mut *pool.get()?, id_zone)?;
+    }
 
-        if id_center != auth.id_center {
-            return Err(ErrorForbidden("").into());
-        } else {
-            (id_user, id_address)
-        }
-    };
+    let (id_user, id_address): (i64, i64) = nurses::table
+        .filter(nurses::id.eq(*id))
+        .select((nurses::id_user, nurses::id_address))
+        .first(&mut pool.get()?)?;
+
+    let current: Address = addresses::table
+        .find(id_address)
+        .select(Address::as_select())
+        .first(&mut pool.get()?)?;
+    update_record.address.locate(&current)?;
+    let conn = &mut *pool.get()?;
+    let id_center = Address::id_center(conn, id_address)?;
+    update_record
+        .address
+        .assign_zone(&zoning::areas(conn, id_center)?, &current);
 
-    pool.get()?.build_transaction().run(|conn| {
+    auth.audited(conn, |conn| {
         diesel::update(nurses::table)
             .set(&update_record.nurse)
             .filter(nurses::id.eq(*id))
@@ -343,28 +549,33 @@ async fn put(
 
 /// Delete nurse
 ///
-/// This will also delete the associated user and address.
+/// This will also delete the associated user and address. A nurse who wrote a report cannot be
+/// deleted.
 #[utoipa::path(
     context_path = "/nurses",
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
+        (status = 409, body = JsonError),
     ),
     tag = "nurses"
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("nurses:write")]
 async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
-    let id_center: i64 = nurses::table
-        .inner_join(addresses::table.inner_join(zones::table))
-        .select(zones::id_center)
-        .get_result(&mut pool.get()?)?;
+    let conn = &mut pool.get()?;
+    auth.check_center::<NurseRecord>(conn, *id)?;
 
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.audited(conn, |conn| {
+        crate::reports::check_no_report(
+            conn,
+            l_visits_nurses::table
+                .filter(l_visits_nurses::id_nurse.eq(*id))
+                .into_boxed(),
+            "The nurse wrote reports, it cannot be deleted",
+        )?;
 
-    pool.get()?.build_transaction().run(|conn| {
         let (id_user, id_address): (i64, i64) = diesel::delete(nurses::table)
             .filter(nurses::id.eq(*id))
             .returning((nurses::id_user, nurses::id_address))
@@ -378,7 +589,7 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Resu
             .filter(addresses::id.eq(id_address))
             .execute(conn)?;
 
-        Ok::<(), diesel::result::Error>(())
+        Ok::<(), Error>(())
     })?;
 
     Ok(Json(()))
@@ -391,6 +602,7 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Resu
     context_path = "/nurses",
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "nurses",
@@ -399,25 +611,20 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Resu
     )
 )]
 #[delete("/{id_nurse}/skills/{id_skill}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("nurses:write")]
 async fn delete_nurse_skill(
     ids: web::Path<(i64, i64)>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    let id_center: i64 = nurses::table
-        .inner_join(addresses::table.inner_join(zones::table))
-        .select(zones::id_center)
-        .get_result(&mut pool.get()?)?;
-
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, ids.0)?;
 
-    let rows = diesel::delete(l_nurses_skills::table)
-        .filter(l_nurses_skills::id_nurse.eq(ids.0))
-        .filter(l_nurses_skills::id_skill.eq(ids.1))
-        .execute(&mut pool.get()?)?;
+    let rows = auth.audited(&mut *pool.get()?, |conn| {
+        diesel::delete(l_nurses_skills::table)
+            .filter(l_nurses_skills::id_nurse.eq(ids.0))
+            .filter(l_nurses_skills::id_skill.eq(ids.1))
+            .execute(conn)
+    })?;
 
     if rows == 0 {
         Err(diesel::result::Error::NotFound.into())
@@ -431,6 +638,7 @@ async fn delete_nurse_skill(
     params(PaginationParam),
     responses(
         (status = 200, description = "Paginated list of availabilities from the given nurse", body = PaginatedAvailabilities),
+        (status = 403, body = JsonError),
     ),
     tag = "nurses",
     security(
@@ -438,16 +646,17 @@ async fn delete_nurse_skill(
     )
 )]
 #[get("/{id}/availabilities")]
-#[has_any_role["Role::Manager", "Role::Nurse", type = "Role"]]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
 async fn availabilities(
     query: web::Query<PaginationParam>,
     id: web::Path<i64>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    if auth.role == Role::Nurse && auth.id != *id {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
         return Err(ErrorForbidden("A nurse can only access its own availabilities").into());
     }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
 
     let res: Vec<Availability> = schema::availabilities::table
         .filter(schema::availabilities::id_nurse.eq(*id))
@@ -455,14 +664,61 @@ async fn availabilities(
         .offset(query.offset().into())
         .load(&mut pool.get()?)?;
 
-    let total: i64 = schema::availabilities::table
+    let total = query.total(|| {
+        Ok(schema::availabilities::table
+            .filter(schema::availabilities::id_nurse.eq(*id))
+            .count()
+            .get_result(&mut pool.get()?)?)
+    })?;
+
+    Ok(Json(PaginatedResponse::new(res, &query).total(total)))
+}
+
+/// Nurse's availability slots
+///
+/// Expands the availabilities of a nurse into the time slots it is available during the given
+/// week, recurrent availabilities being repeated every week. Overlapping slots are merged.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(WeekParam),
+    responses(
+        (status = 200, description = "Chronological list of slots", body = Vec<AvailabilitySlot>),
+        (status = 403, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[get("/{id}/availabilities/slots")]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
+async fn availability_slots(
+    query: web::Query<WeekParam>,
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
+        return Err(ErrorForbidden("A nurse can only access its own availabilities").into());
+    }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
+
+    let (from, to) = workload::week_bounds(query.date.unwrap_or_else(|| Local::now().date_naive()));
+
+    let res: Vec<Availability> = schema::availabilities::table
         .filter(schema::availabilities::id_nurse.eq(*id))
-        .count()
-        .get_result(&mut pool.get()?)?;
+        .filter(schema::availabilities::start.lt(to))
+        .filter(
+            schema::availabilities::recurrent
+                .eq(true)
+                .or(schema::availabilities::end.gt(from)),
+        )
+        .select(Availability::as_select())
+        .load(&mut pool.get()?)?;
 
-    Ok(Json(
-        PaginatedResponse::new(res, &query).total(total as u32),
-    ))
+    let slots = res.iter().flat_map(|a| a.slots(from, to)).collect();
+
+    Ok(Json(merge_slots(slots)))
 }
 
 /// Nurse's reports
@@ -471,9 +727,11 @@ async fn availabilities(
 /// Reports that are either null or empty are not returned.
 #[utoipa::path(
     context_path = "/nurses",
-    params(PaginationParam),
+    params(PaginationParam, CursorParam),
     responses(
         (status = 200, description = "Paginated list of reports", body = PaginatedLVisitsNurses),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
     ),
     tag = "nurses",
     security(
@@ -481,77 +739,445 @@ async fn availabilities(
     )
 )]
 #[get("/{id}/reports")]
-#[has_any_role["Role::Manager", "Role::Nurse", type = "Role"]]
+#[has_any_permission("reports:read", "ROLE_NURSE")]
 async fn reports(
-    query: web::Query<PaginationParam>,
+    pagination: web::Query<PaginationParam>,
+    cursor: web::Query<CursorParam>,
     id: web::Path<i64>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    if auth.role == Role::Nurse && auth.id != *id {
+    if !auth.is_or_has(Role::Nurse, *id, "reports:read") {
         return Err(ErrorForbidden("A nurse can only access its own reports").into());
     }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
 
-    let res: Vec<LVisitNurse> = schema::l_visits_nurses::table
-        .filter(schema::l_visits_nurses::id_nurse.eq(*id))
-        .filter(schema::l_visits_nurses::report.is_not_null())
-        .filter(schema::l_visits_nurses::report.ne(""))
-        .limit(query.limit().into())
-        .offset(query.offset().into())
-        .load(&mut pool.get()?)?;
+    let page = pagination.page(&cursor)?;
+    let sort = SortParam::<LVisitNurse>::default();
+    let (seek, order) = sort.seek(page.cursor())?;
+    let reports = l_visits_nurses::table
+        .filter(l_visits_nurses::id_nurse.eq(*id))
+        .filter(l_visits_nurses::report.is_not_null())
+        .filter(l_visits_nurses::report.ne(""));
 
-    let total: i64 = schema::l_visits_nurses::table
-        .filter(schema::l_visits_nurses::id_nurse.eq(*id))
-        .filter(schema::l_visits_nurses::report.is_not_null())
-        .filter(schema::l_visits_nurses::report.ne(""))
-        .count()
-        .get_result(&mut pool.get()?)?;
+    let conn = &mut *pool.get()?;
+    let rows: Vec<(LVisitNurse, serde_json::Value)> = reports
+        .filter(seek)
+        .select((LVisitNurse::as_select(), sort.keys()))
+        .order(order)
+        .offset(page.offset())
+        .limit(page.limit())
+        .load(conn)?;
 
-    Ok(Json(
-        PaginatedResponse::new(res, &query).total(total as u32),
-    ))
+    let total = pagination.total(|| Ok(reports.count().get_result(conn)?))?;
+
+    Ok(Json(PaginatedResponse::page(rows, &page).total(total)))
+}
+
+/// Nurse's visits
+///
+/// Same as `/nurses/me/visits` for any nurse. A nurse can only access itself.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(PeriodParam),
+    responses(
+        (status = 200, body = Vec<PlannedVisit>),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[get("/{id}/visits")]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
+async fn nurse_visits(
+    period: web::Query<PeriodParam>,
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
+        return Err(ErrorForbidden("A nurse can only access its own visits").into());
+    }
+    auth.check_center::<NurseRecord>(&mut *pool.get()?, *id)?;
+
+    if !period.is_valid() {
+        return Err(ErrorBadRequest("`to` must be after `from`").into());
+    }
+
+    let res = helper::planned_visits(&mut *pool.get()?, *id, &period)?;
+
+    Ok(Json(res))
 }
 
+/// Nurse's calendar
+///
+/// Returns the visits of a nurse as an iCalendar feed, meant to be subscribed to from a calendar
+/// application. The feed is protected by the calendar token of the nurse rather than the usual
+/// authentication cookie. A wrong token gives a `404`.
 #[utoipa::path(
     context_path = "/nurses",
+    params(CalendarParam),
     responses(
         (status = 200, body = String, description = "Icalendar data"),
+        (status = 400, body = JsonError),
         (status = 404, body = JsonError)
     ),
-    tag = "nurses"
+    tag = "nurses",
+    security(())
 )]
 #[get("/{id}/ical")]
-async fn ical(id: web::Path<i64>, pool: web::Data<DbPool>) -> Result<impl Responder> {
-    use icalendar::*;
+async fn ical(
+    id: web::Path<i64>,
+    query: web::Query<CalendarParam>,
+    pool: web::Data<DbPool>,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
 
     let nurse: User = users::table
         .inner_join(nurses::table)
+        .inner_join(calendar_tokens::table)
         .filter(nurses::id.eq(*id))
-        .select(users::all_columns)
-        .first(&mut pool.get()?)?;
+        .filter(calendar_tokens::token.eq(hash_token(&query.token)))
+        .select(User::as_select())
+        .first(conn)?;
+
+    let name = format!("Planning de {} {}", nurse.fname, nurse.lname.to_uppercase());
+    let cal = crate::ical::calendar(conn, crate::ical::Feed::Nurse(*id), &name, query.alarm)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(cal.to_string()))
+}
+
+/// Create calendar token
+///
+/// Creates the token giving access to the calendar feed of a nurse. If the nurse already has a
+/// token, it is replaced and the previous one stops working. The token is only returned once. A
+/// nurse can only manage its own token.
+#[utoipa::path(
+    context_path = "/nurses",
+    responses(
+        (status = 200, body = CalendarToken),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[post("/{id}/ical/token")]
+#[has_any_permission("nurses:write", "ROLE_NURSE")]
+async fn post_ical_token(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    let id_user = helper::id_user(conn, &auth, *id)?;
+
+    let res = crate::ical::create_token(conn, id_user)?;
+
+    Ok(Json(res))
+}
+
+/// Revoke calendar token
+///
+/// Deletes the calendar token of a nurse, its calendar feed is no longer accessible until a new
+/// token is created.
+#[utoipa::path(
+    context_path = "/nurses",
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[delete("/{id}/ical/token")]
+#[has_any_permission("nurses:write", "ROLE_NURSE")]
+async fn delete_ical_token(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    let id_user = helper::id_user(conn, &auth, *id)?;
+
+    if crate::ical::revoke_token(conn, id_user)? == 0 {
+        Err(diesel::result::Error::NotFound.into())
+    } else {
+        Ok(Json(()))
+    }
+}
+
+/// Issue password reset token
+///
+/// Issues a single-use token allowing the nurse to set its password with `/auth/password/reset`.
+/// It replaces the previous token of the nurse if any.
+#[utoipa::path(
+    context_path = "/nurses",
+    responses(
+        (status = 200, body = PasswordResetToken),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[post("/{id}/password/reset")]
+#[has_permissions("nurses:write")]
+async fn post_password_reset(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    let id_user = helper::id_user(conn, &auth, *id)?;
+
+    let res = crate::password::issue_reset_token(conn, id_user)?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    context_path = "/nurses",
+    responses(
+        (status = 200, body = Vec<RoleRecord>),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/{id}/roles")]
+#[has_permissions("roles:read")]
+async fn get_roles(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    auth.check_center::<NurseRecord>(conn, *id)?;
+
+    let id_user = nurses::table
+        .find(*id)
+        .select(nurses::id_user)
+        .first(conn)?;
+
+    Ok(Json(crate::permissions::roles_of_user(conn, id_user)?))
+}
+
+/// Set roles
+///
+/// Replaces the roles of the nurse. Roles must be default roles or roles of the center, and the
+/// current user can only grant or revoke the permissions it has.
+#[utoipa::path(
+    context_path = "/nurses",
+    request_body = Vec<i64>,
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[put("/{id}/roles")]
+#[has_permissions("roles:write")]
+async fn put_roles(
+    id: web::Path<i64>,
+    ids: web::Json<Vec<i64>>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    auth.check_center::<NurseRecord>(conn, *id)?;
+
+    let id_user = nurses::table
+        .find(*id)
+        .select(nurses::id_user)
+        .first(conn)?;
+    auth.audited(conn, |conn| {
+        crate::permissions::set_user_roles(conn, &auth, id_user, &ids)
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Center's workload
+///
+/// Computes the workload of every nurse of the center during the given week, the busiest nurses
+/// first. Performed visits count for their actual duration, the others for their planned one,
+/// cancelled visits are left out.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(WorkloadParam),
+    responses(
+        (status = 200, body = Vec<Workload>),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/workload")]
+#[has_permissions("nurses:read")]
+async fn workloads(
+    query: web::Query<WorkloadParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+
+    let center_nurses = nurses::table
+        .inner_join(users::table)
+        .inner_join(addresses::table.inner_join(zones::table))
+        .filter(zones::id_center.eq(auth.id_center))
+        .select((
+            nurses::id,
+            users::fname,
+            users::lname,
+            nurses::minutes_per_week,
+        ))
+        .load(conn)?;
+
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let mut res = helper::workloads(conn, center_nurses, date)?;
+
+    res.sort_by(|a, b| {
+        let order = a.utilisation.total_cmp(&b.utilisation);
+        if query.ascending {
+            order
+        } else {
+            order.reverse()
+        }
+        .then(a.id_nurse.cmp(&b.id_nurse))
+    });
+
+    Ok(Json(res))
+}
+
+/// Nurse's workload
+///
+/// Computes the workload of a nurse during the given week. A nurse can only access its own.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(WeekParam),
+    responses(
+        (status = 200, body = Workload),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[get("/{id}/workload")]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
+async fn nurse_workload(
+    query: web::Query<WeekParam>,
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
+        return Err(ErrorForbidden("A nurse can only access its own workload").into());
+    }
+    let conn = &mut *pool.get()?;
+    auth.check_center::<NurseRecord>(conn, *id)?;
+
+    let nurse = nurses::table
+        .inner_join(users::table)
+        .filter(nurses::id.eq(*id))
+        .select((
+            nurses::id,
+            users::fname,
+            users::lname,
+            nurses::minutes_per_week,
+        ))
+        .first(conn)?;
+
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let mut res = helper::workloads(conn, vec![nurse], date)?;
+
+    Ok(Json(res.remove(0)))
+}
+
+/// Nurse's daily route
+///
+/// Orders the visits of a nurse during the given day to minimise the travel from its home, and
+/// warns about consecutive visits too close in time for the travel between them. A nurse can only
+/// access its own route.
+#[utoipa::path(
+    context_path = "/nurses",
+    params(DayParam),
+    responses(
+        (status = 200, body = DailyRoute),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "nurses",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[get("/{id}/route")]
+#[has_any_permission("nurses:read", "ROLE_NURSE")]
+async fn route(
+    query: web::Query<DayParam>,
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !auth.is_or_has(Role::Nurse, *id, "nurses:read") {
+        return Err(ErrorForbidden("A nurse can only access its own route").into());
+    }
+    let conn = &mut *pool.get()?;
+    auth.check_center::<NurseRecord>(conn, *id)?;
+
+    let home: Address = nurses::table
+        .inner_join(addresses::table)
+        .filter(nurses::id.eq(*id))
+        .select(Address::as_select())
+        .first(conn)?;
+
+    let from = query
+        .date
+        .unwrap_or_else(|| Local::now().date_naive())
+        .and_time(NaiveTime::MIN);
 
     let visits = visits::table
-        .inner_join(
-            missions::table.inner_join(mission_types::table).inner_join(
-                patients::table
-                    .inner_join(users::table)
-                    .inner_join(addresses::table),
-            ),
-        )
         .inner_join(l_visits_nurses::table)
+        .inner_join(missions::table.inner_join(patients::table.inner_join(addresses::table)))
         .filter(l_visits_nurses::id_nurse.eq(*id))
-        .select(Visit::as_select())
-        .load_iter::<Visit, DefaultLoadingMode>(&mut pool.get()?)?;
-
-    let cal: Result<Calendar> = visits.map(|visit| Ok(Event::from(visit?))).collect();
-
-    cal.map(|mut c| {
-        c.name(&format!(
-            "Planning de {} {}",
-            nurse.fname,
-            nurse.lname.to_uppercase()
-        ));
+        .filter(visits::cancelled_at.is_null())
+        .filter(visits::start.ge(from))
+        .filter(visits::start.lt(from + Duration::days(1)))
+        .select((VisitRecord::as_select(), Address::as_select()))
+        .load::<(VisitRecord, Address)>(conn)?
+        .into_iter()
+        .map(|(visit, address)| RouteVisit {
+            id_visit: visit.id,
+            start: visit.start,
+            end: visit.end,
+            coordinates: address.coordinates(),
+        })
+        .collect();
 
-        c.to_string()
-    })
+    Ok(Json(routing::route(home.coordinates(), visits)))
 }
@@ -1,26 +1,42 @@
 use actix_web::{
-    delete,
-    error::ErrorForbidden,
-    get, post, put,
+    delete, get, post, put,
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::has_roles;
-use diesel::{insert_into, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
+use actix_web_grants::proc_macro::{has_permissions, has_roles};
+use chrono::Local;
+use diesel::{
+    insert_into, BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
+    center::CenterScoped,
     database::DbPool,
-    error::{JsonError, Result},
+    error::{Error, JsonError, Result},
     models::*,
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
-    schema::{addresses, patients, users, zones},
+    params::{FilterParam, SearchParam, SortParam},
+    schema::{
+        addresses, l_visits_nurses, mission_types, missions, nurses, patients, users, visits, zones,
+    },
+    zoning,
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(all, get, post, put, delete),
+    paths(
+        all,
+        me,
+        me_upcoming_visits,
+        me_past_visits,
+        get,
+        post,
+        put,
+        delete,
+        post_password_reset
+    ),
     components(schemas(
         Patient,
         PatientRecord,
@@ -30,7 +46,11 @@ use crate::{
         NewPatient,
         User,
         Address,
+        PatientVisit,
+        AssignedNurse,
+        PasswordResetToken,
         crate::pagination::PaginatedPatients,
+        crate::pagination::PaginatedPatientVisits,
         JsonError
     )),
     security(
@@ -42,47 +62,204 @@ pub struct Doc;
 pub fn routes() -> Scope {
     web::scope("/patients")
         .service(all)
+        .service(me)
+        .service(me_upcoming_visits)
+        .service(me_past_visits)
         .service(get)
         .service(post)
         .service(put)
         .service(delete)
+        .service(post_password_reset)
+}
+
+mod helper {
+    use std::collections::HashMap;
+
+    use diesel::{PgConnection, QueryResult};
+
+    use super::*;
+
+    /// Loads a page of the visits of a patient with the nurses assigned to them, and the total
+    /// number of visits if requested.
+    ///
+    /// Upcoming visits are the ones not over yet nor cancelled, in chronological order. Past
+    /// visits are in reverse chronological order.
+    pub fn patient_visits(
+        conn: &mut PgConnection,
+        id_patient: i64,
+        upcoming: bool,
+        pagination: &PaginationParam,
+    ) -> QueryResult<(Vec<PatientVisit>, Option<u32>)> {
+        let now = Local::now().naive_local();
+        let query = || {
+            let query = visits::table
+                .inner_join(missions::table.inner_join(mission_types::table))
+                .filter(missions::id_patient.eq(id_patient))
+                .into_boxed();
+
+            if upcoming {
+                query
+                    .filter(visits::end.gt(now))
+                    .filter(visits::cancelled_at.is_null())
+            } else {
+                query.filter(visits::end.le(now))
+            }
+        };
+
+        let total = pagination
+            .count
+            .then(|| query().count().get_result::<i64>(conn))
+            .transpose()?
+            .map(|total| total as u32);
+
+        let query = if upcoming {
+            query().order((visits::start, visits::id))
+        } else {
+            query().order((visits::start.desc(), visits::id.desc()))
+        };
+        let rows: Vec<(VisitRecord, MissionType)> = query
+            .select((VisitRecord::as_select(), MissionType::as_select()))
+            .offset(pagination.offset().into())
+            .limit(pagination.limit().into())
+            .load(conn)?;
+
+        let ids: Vec<i64> = rows.iter().map(|(v, _)| v.id).collect();
+
+        let mut nurses: HashMap<i64, Vec<AssignedNurse>> = HashMap::new();
+        for (id_visit, nurse) in l_visits_nurses::table
+            .inner_join(nurses::table.inner_join(users::table))
+            .filter(l_visits_nurses::id_visit.eq_any(&ids))
+            .order(users::lname)
+            .select((l_visits_nurses::id_visit, AssignedNurse::as_select()))
+            .load::<(i64, AssignedNurse)>(conn)?
+        {
+            nurses.entry(id_visit).or_default().push(nurse);
+        }
+
+        let res = rows
+            .into_iter()
+            .map(|(visit, mission_type)| PatientVisit {
+                nurses: nurses.remove(&visit.id).unwrap_or_default(),
+                visit,
+                mission_type,
+            })
+            .collect();
+
+        Ok((res, total))
+    }
 }
 
 #[utoipa::path(
     context_path = "/patients",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<(PatientRecord, User, Address)>, FilterParam<(PatientRecord, User, Address)>),
     responses(
         (status = 200, description = "Paginated list of patients", body = PaginatedPatients),
     ),
     tag = "patients"
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("patients:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<(PatientRecord, User, Address)>>,
+    filter: web::Query<FilterParam<(PatientRecord, User, Address)>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let res: Vec<Patient> = patients::table
+    let req = patients::table
         .inner_join(users::table)
-        .inner_join(addresses::table)
-        .filter(users::fname.ilike(search.value()))
-        .or_filter(users::lname.ilike(search.value()))
-        .or_filter(users::mail.ilike(search.value()))
-        .order(sort.raw_sql())
+        .inner_join(addresses::table.inner_join(zones::table))
+        .filter(zones::id_center.eq(auth.id_center))
+        .filter(
+            users::fname
+                .ilike(search.value())
+                .or(users::lname.ilike(search.value()))
+                .or(users::mail.ilike(search.value())),
+        )
+        .filter(filter.expression());
+
+    let res: Vec<Patient> = req
+        .clone()
+        .select(Patient::as_select())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(&mut pool.get()?)?;
 
-    let total = patients::table
-        .inner_join(users::table)
-        .filter(users::fname.ilike(search.value()))
-        .or_filter(users::lname.ilike(search.value()))
-        .or_filter(users::mail.ilike(search.value()))
-        .count()
-        .get_result::<i64>(&mut pool.get()?)? as u32;
+    let total = pagination.total(|| Ok(req.count().get_result(&mut pool.get()?)?))?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
+
+#[utoipa::path(
+    context_path = "/patients",
+    responses(
+        (status = 200, body = Patient),
+    ),
+    tag = "patients",
+    security(
+        ("token" = ["patient"])
+    )
+)]
+#[get("/me")]
+#[has_roles("PATIENT")]
+async fn me(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let res: Patient = macros::get!(patients, pool, auth.id, users, addresses => Patient);
+
+    Ok(Json(res))
+}
+
+/// Current patient's upcoming visits
+///
+/// Returns the visits of the current patient which are neither over nor cancelled, in
+/// chronological order, with the nurses assigned to them.
+#[utoipa::path(
+    context_path = "/patients",
+    params(PaginationParam),
+    responses(
+        (status = 200, body = PaginatedPatientVisits),
+    ),
+    tag = "patients",
+    security(
+        ("token" = ["patient"])
+    )
+)]
+#[get("/me/visits/upcoming")]
+#[has_roles("PATIENT")]
+async fn me_upcoming_visits(
+    pagination: web::Query<PaginationParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let (res, total) = helper::patient_visits(&mut *pool.get()?, auth.id, true, &pagination)?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
+
+/// Current patient's past visits
+///
+/// Returns the visits of the current patient which are over, the most recent first, with the
+/// nurses who came.
+#[utoipa::path(
+    context_path = "/patients",
+    params(PaginationParam),
+    responses(
+        (status = 200, body = PaginatedPatientVisits),
+    ),
+    tag = "patients",
+    security(
+        ("token" = ["patient"])
+    )
+)]
+#[get("/me/visits/past")]
+#[has_roles("PATIENT")]
+async fn me_past_visits(
+    pagination: web::Query<PaginationParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let (res, total) = helper::patient_visits(&mut *pool.get()?, auth.id, false, &pagination)?;
 
     Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
 }
@@ -91,14 +268,17 @@ async fn all(
     context_path = "/patients",
     responses(
         (status = 200, body = Patient),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "patients"
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    let res: Patient = macros::get!(patients, pool, *id, users, addresses);
+#[has_permissions("patients:read")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    auth.check_center::<PatientRecord>(&mut *pool.get()?, *id)?;
+
+    let res: Patient = macros::get!(patients, pool, *id, users, addresses => Patient);
 
     Ok(Json(res))
 }
@@ -108,17 +288,28 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
     ),
     tag = "patients"
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("patients:write")]
 async fn post(
-    new_record: Json<NewPatient>,
+    mut new_record: Json<NewPatient>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    pool.get()?.build_transaction().run(|conn| {
+    if let Some(id_zone) = new_record.address.id_zone {
+        auth.check_center::<ZoneRecord>(&mut *pool.get()?, id_zone)?;
+    }
+    if let Some(password) = &new_record.user.password {
+        crate::password::check(password)?;
+    }
+    new_record.address.locate()?;
+    let zones = zoning::areas(&mut *pool.get()?, auth.id_center)?;
+    new_record.address.assign_zone(&zones)?;
+
+    auth.audited(&mut *pool.get()?, |conn| {
         let NewPatient { user, address } = new_record.0;
 
         let id_user: i64 = insert_into(users::table)
@@ -149,29 +340,41 @@ async fn post(
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "patients"
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("patients:write")]
 async fn put(
     id: web::Path<i64>,
-    update_record: Json<UpdatePatient>,
+    mut update_record: Json<UpdatePatient>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    let (id_center, id_user, id_address): (i64, i64, i64) = patients::table
-        .inner_join(addresses::table.inner_join(zones::table))
+    auth.check_center::<PatientRecord>(&mut *pool.get()?, *id)?;
+    if let Some(id_zone) = update_record.address.id_zone {
+        auth.check_center::<ZoneRecord>(&mut *pool.get()?, id_zone)?;
+    }
+
+    let (id_user, id_address): (i64, i64) = patients::table
         .filter(patients::id.eq(*id))
-        .select((zones::id_center, patients::id_user, patients::id_address))
+        .select((patients::id_user, patients::id_address))
         .first(&mut pool.get()?)?;
 
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    let current: Address = addresses::table
+        .find(id_address)
+        .select(Address::as_select())
+        .first(&mut pool.get()?)?;
+    update_record.address.locate(&current)?;
+    let conn = &mut *pool.get()?;
+    let id_center = Address::id_center(conn, id_address)?;
+    update_record
+        .address
+        .assign_zone(&zoning::areas(conn, id_center)?, &current);
 
-    pool.get()?.build_transaction().run(|conn| {
+    auth.audited(conn, |conn| {
         diesel::update(users::table)
             .set(&update_record.user)
             .filter(users::id.eq(id_user))
@@ -190,28 +393,40 @@ async fn put(
 
 /// Delete patient
 ///
-/// This will also delete the associated user and address.
+/// This will also delete the associated user and address. A patient for whom a report was
+/// written cannot be deleted.
 #[utoipa::path(
     context_path = "/patients",
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
+        (status = 409, body = JsonError),
     ),
     tag = "patients"
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("patients:write")]
 async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
-    let id_center: i64 = patients::table
-        .inner_join(addresses::table.inner_join(zones::table))
-        .select(zones::id_center)
-        .get_result(&mut pool.get()?)?;
+    let conn = &mut pool.get()?;
+    auth.check_center::<PatientRecord>(conn, *id)?;
 
-    if id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.audited(conn, |conn| {
+        crate::reports::check_no_report(
+            conn,
+            l_visits_nurses::table
+                .filter(
+                    l_visits_nurses::id_visit.eq_any(
+                        visits::table
+                            .inner_join(missions::table)
+                            .filter(missions::id_patient.eq(*id))
+                            .select(visits::id),
+                    ),
+                )
+                .into_boxed(),
+            "Reports were written for the patient, it cannot be deleted",
+        )?;
 
-    pool.get()?.build_transaction().run(|conn| {
         let (id_user, id_address): (i64, i64) = diesel::delete(patients::table)
             .filter(patients::id.eq(*id))
             .returning((patients::id_user, patients::id_address))
@@ -225,8 +440,41 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Resu
             .filter(addresses::id.eq(id_address))
             .execute(conn)?;
 
-        Ok::<(), diesel::result::Error>(())
+        Ok::<(), Error>(())
     })?;
 
     Ok(Json(()))
 }
+
+/// Issue password reset token
+///
+/// Issues a single-use token allowing the patient to set its password with
+/// `/auth/password/reset`, giving it access to its care. It replaces the previous token of the
+/// patient if any.
+#[utoipa::path(
+    context_path = "/patients",
+    responses(
+        (status = 200, body = PasswordResetToken),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "patients"
+)]
+#[post("/{id}/password/reset")]
+#[has_permissions("patients:write")]
+async fn post_password_reset(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    auth.check_center::<PatientRecord>(conn, *id)?;
+
+    let id_user = patients::table
+        .find(*id)
+        .select(patients::id_user)
+        .first(conn)?;
+    let res = crate::password::issue_reset_token(conn, id_user)?;
+
+    Ok(Json(res))
+}
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{
+    error::{ErrorBadRequest, ErrorForbidden},
+    get, post,
+    web::{self, Json},
+    Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_permissions;
+use diesel::{insert_into, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+use crate::{
+    auth::Auth,
+    database::DbPool,
+    error::{ConflictsError, Error, JsonError, Result},
+    models::NewLVisitNurse,
+    params::PeriodParam,
+    planning::{self, Plan, UnderstaffedVisit},
+    schema::{addresses, l_visits_nurses, missions, nurses, patients, visits, zones},
+};
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(get, post),
+    components(schemas(Plan, UnderstaffedVisit, NewLVisitNurse, JsonError, ConflictsError)),
+    security(
+        ("token" = ["manager"])
+    )
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/planning").service(get).service(post)
+}
+
+mod helper {
+    use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime};
+    use diesel::{PgConnection, QueryResult, SelectableHelper};
+
+    use super::*;
+    use crate::{
+        models::Availability,
+        planning::{NurseCapacity, VisitNeed},
+        schema::{availabilities, l_missions_skills, l_nurses_skills},
+    };
+
+    /// Loads the visits of a center taking place during the period.
+    pub fn visits(
+        conn: &mut PgConnection,
+        id_center: i64,
+        period: &PeriodParam,
+    ) -> QueryResult<Vec<VisitNeed>> {
+        let rows: Vec<(i64, NaiveDateTime, NaiveDateTime, i16, i64, i64)> =
+            visits::table
+                .inner_join(missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                ))
+                .filter(zones::id_center.eq(id_center))
+                .filter(visits::cancelled_at.is_null())
+                .filter(visits::start.ge(period.from))
+                .filter(visits::end.le(period.to))
+                .select((
+                    visits::id,
+                    visits::start,
+                    visits::end,
+                    missions::people_required,
+                    missions::id_mission_type,
+                    addresses::id_zone,
+                ))
+                .load(conn)?;
+
+        let mut assigned: HashMap<i64, HashSet<i64>> = HashMap::new();
+        for (id_visit, id_nurse) in l_visits_nurses::table
+            .filter(l_visits_nurses::id_visit.eq_any(rows.iter().map(|r| r.0)))
+            .select((l_visits_nurses::id_visit, l_visits_nurses::id_nurse))
+            .load::<(i64, i64)>(conn)?
+        {
+            assigned.entry(id_visit).or_default().insert(id_nurse);
+        }
+
+        let mut skills: HashMap<i64, (HashSet<i64>, HashSet<i64>)> = HashMap::new();
+        for (id_mission_type, id_skill, preferred) in l_missions_skills::table
+            .filter(l_missions_skills::id_mission_type.eq_any(rows.iter().map(|r| r.4)))
+            .select((
+                l_missions_skills::id_mission_type,
+                l_missions_skills::id_skill,
+                l_missions_skills::preferred,
+            ))
+            .load::<(i64, i64, bool)>(conn)?
+        {
+            let (required, optional) = skills.entry(id_mission_type).or_default();
+
+            if preferred {
+                optional.insert(id_skill);
+            } else {
+                required.insert(id_skill);
+            }
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id_visit, start, end, people_required, id_mission_type, id_zone)| {
+                    let (required_skills, preferred_skills) =
+                        skills.get(&id_mission_type).cloned().unwrap_or_default();
+
+                    VisitNeed {
+                        id_visit,
+                        start,
+                        end,
+                        id_zone,
+                        required_skills,
+                        preferred_skills,
+                        people_required,
+                        assigned: assigned.remove(&id_visit).unwrap_or_default(),
+                    }
+                },
+            )
+            .collect())
+    }
+
+    /// Loads the nurses of a center with what is needed to know when they can work.
+    ///
+    /// Assigned visits are loaded for every week overlapping the period, so weekly working time
+    /// can be computed.
+    pub fn nurses(
+        conn: &mut PgConnection,
+        id_center: i64,
+        period: &PeriodParam,
+    ) -> QueryResult<Vec<NurseCapacity>> {
+        let rows: Vec<(i64, i32, i64)> = nurses::table
+            .inner_join(addresses::table.inner_join(zones::table))
+            .filter(zones::id_center.eq(id_center))
+            .select((nurses::id, nurses::minutes_per_week, addresses::id_zone))
+            .load(conn)?;
+        let ids: Vec<i64> = rows.iter().map(|r| r.0).collect();
+
+        let mut skills: HashMap<i64, HashSet<i64>> = HashMap::new();
+        for (id_nurse, id_skill) in l_nurses_skills::table
+            .filter(l_nurses_skills::id_nurse.eq_any(&ids))
+            .select((l_nurses_skills::id_nurse, l_nurses_skills::id_skill))
+            .load::<(i64, i64)>(conn)?
+        {
+            skills.entry(id_nurse).or_default().insert(id_skill);
+        }
+
+        let mut availabilities: HashMap<i64, Vec<Availability>> = HashMap::new();
+        for availability in availabilities::table
+            .filter(availabilities::id_nurse.eq_any(&ids))
+            .select(Availability::as_select())
+            .load::<Availability>(conn)?
+        {
+            availabilities
+                .entry(availability.id_nurse)
+                .or_default()
+                .push(availability);
+        }
+
+        let weeks_start = (period.from.date()
+            - Duration::days(period.from.weekday().num_days_from_monday().into()))
+        .and_time(NaiveTime::MIN);
+        let weeks_end = (period.to.date()
+            + Duration::days((7 - period.to.weekday().num_days_from_monday()).into()))
+        .and_time(NaiveTime::MIN);
+
+        let mut assigned: HashMap<i64, Vec<(NaiveDateTime, NaiveDateTime)>> = HashMap::new();
+        for (id_nurse, start, end) in l_visits_nurses::table
+            .inner_join(visits::table)
+            .filter(l_visits_nurses::id_nurse.eq_any(&ids))
+            .filter(visits::cancelled_at.is_null())
+            .filter(visits::start.ge(weeks_start))
+            .filter(visits::start.lt(weeks_end))
+            .select((l_visits_nurses::id_nurse, visits::start, visits::end))
+            .load::<(i64, NaiveDateTime, NaiveDateTime)>(conn)?
+        {
+            assigned.entry(id_nurse).or_default().push((start, end));
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|(id_nurse, minutes_per_week, id_zone)| NurseCapacity {
+                id_nurse,
+                id_zone,
+                minutes_per_week,
+                skills: skills.remove(&id_nurse).unwrap_or_default(),
+                availabilities: availabilities.remove(&id_nurse).unwrap_or_default(),
+                visits: assigned.remove(&id_nurse).unwrap_or_default(),
+            })
+            .collect())
+    }
+}
+
+/// Propose a planning
+///
+/// Proposes nurses for the visits of the current center taking place during the given period.
+/// Nurses are chosen according to their skills, availabilities, zone and weekly working time.
+/// Nothing is saved, the returned assignments can be reviewed and then committed with
+/// `POST /planning`.
+#[utoipa::path(
+    context_path = "/planning",
+    params(PeriodParam),
+    responses(
+        (status = 200, body = Plan),
+        (status = 400, body = JsonError)
+    ),
+    tag = "planning"
+)]
+#[get("")]
+#[has_permissions("planning:read")]
+async fn get(
+    period: web::Query<PeriodParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if !period.is_valid() {
+        return Err(ErrorBadRequest("`to` must be after `from`").into());
+    }
+
+    let conn = &mut pool.get()?;
+
+    let visits = helper::visits(conn, auth.id_center, &period)?;
+    let nurses = helper::nurses(conn, auth.id_center, &period)?;
+
+    Ok(Json(planning::plan(visits, nurses)))
+}
+
+/// Commit a planning
+///
+/// Assigns nurses to visits, all at once. Every visit and nurse must belong to the current center.
+/// Assignments that already exist are ignored. The whole planning is refused when any nurse cannot
+/// be assigned to its visit, for the same reasons as when associating a nurse with a visit.
+#[utoipa::path(
+    context_path = "/planning",
+    request_body = Vec<NewLVisitNurse>,
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 409, body = ConflictsError)
+    ),
+    tag = "planning"
+)]
+#[post("")]
+#[has_permissions("planning:write")]
+async fn post(
+    assignments: Json<Vec<NewLVisitNurse>>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+
+    let id_visits: HashSet<i64> = assignments.iter().map(|a| a.id_visit).collect();
+    let id_nurses: HashSet<i64> = assignments.iter().map(|a| a.id_nurse).collect();
+
+    let visits_in_center: i64 = visits::table
+        .inner_join(
+            missions::table
+                .inner_join(patients::table.inner_join(addresses::table.inner_join(zones::table))),
+        )
+        .filter(visits::id.eq_any(&id_visits))
+        .filter(zones::id_center.eq(auth.id_center))
+        .count()
+        .get_result(conn)?;
+
+    let nurses_in_center: i64 = nurses::table
+        .inner_join(addresses::table.inner_join(zones::table))
+        .filter(nurses::id.eq_any(&id_nurses))
+        .filter(zones::id_center.eq(auth.id_center))
+        .count()
+        .get_result(conn)?;
+
+    if visits_in_center as usize != id_visits.len() || nurses_in_center as usize != id_nurses.len()
+    {
+        return Err(ErrorForbidden("Visits and nurses must belong to your center").into());
+    }
+
+    auth.audited(conn, |conn| {
+        // Same locks as when assigning a single nurse, so the conflicts checked below still hold
+        // when inserting
+        visits::table
+            .filter(visits::id.eq_any(&id_visits))
+            .select(visits::id)
+            .order(visits::id)
+            .for_update()
+            .load::<i64>(conn)?;
+        nurses::table
+            .filter(nurses::id.eq_any(&id_nurses))
+            .select(nurses::id)
+            .order(nurses::id)
+            .for_update()
+            .load::<i64>(conn)?;
+
+        // Assignments are inserted one after the other, so each one is checked against the
+        // previous ones
+        let mut conflicts = Vec::new();
+        for assignment in assignments.iter() {
+            let (_, found) = crate::routes::visits::helper::conflicts(
+                conn,
+                assignment.id_visit,
+                assignment.id_nurse,
+            )?;
+            for conflict in found {
+                if !conflicts.contains(&conflict) {
+                    conflicts.push(conflict);
+                }
+            }
+
+            insert_into(l_visits_nurses::table)
+                .values(assignment)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+
+        if !conflicts.is_empty() {
+            return Err(Error::Conflicts(conflicts));
+        }
+
+        Ok(())
+    })?;
+
+    Ok(Json(()))
+}
@@ -0,0 +1,329 @@
+use std::collections::HashSet;
+
+use actix_web::{
+    delete,
+    error::ErrorForbidden,
+    get, post, put,
+    web::{self, Json},
+    Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_permissions;
+use diesel::{
+    insert_into, BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+
+use crate::{
+    auth::Auth,
+    database::DbPool,
+    error::{Error, JsonError, Result},
+    models::{
+        NewRole, NewRoleRecord, Permission, RoleRecord, RoleWithPermissions, UpdateRole,
+        UpdateRoleRecord,
+    },
+    params::FilterParam,
+    permissions,
+    schema::{self, roles},
+};
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(all_permissions, all, get, post, put, delete),
+    components(schemas(
+        Permission,
+        RoleRecord,
+        RoleWithPermissions,
+        NewRole,
+        NewRoleRecord,
+        UpdateRole,
+        UpdateRoleRecord,
+        JsonError
+    ))
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/roles")
+        .service(all_permissions)
+        .service(all)
+        .service(get)
+        .service(post)
+        .service(put)
+        .service(delete)
+}
+
+pub(crate) mod helper {
+    use super::*;
+
+    /// Returns a role if it is a default role or a role of the center of the current user.
+    pub fn visible_role(conn: &mut PgConnection, auth: &Auth, id: i64) -> Result<RoleRecord> {
+        Ok(roles::table
+            .find(id)
+            .filter(
+                roles::id_center
+                    .is_null()
+                    .or(roles::id_center.eq(auth.id_center)),
+            )
+            .select(RoleRecord::as_select())
+            .first(conn)?)
+    }
+
+    /// Returns a role the current user can modify, default roles cannot be modified.
+    pub fn editable_role(conn: &mut PgConnection, auth: &Auth, id: i64) -> Result<RoleRecord> {
+        let role = visible_role(conn, auth, id)?;
+
+        if role.id_center.is_none() {
+            return Err(ErrorForbidden("Default roles cannot be modified").into());
+        }
+
+        Ok(role)
+    }
+
+    /// Locks a role until the end of the transaction, so its permissions cannot change while
+    /// checked.
+    pub fn lock(conn: &mut PgConnection, id: i64) -> diesel::QueryResult<()> {
+        roles::table
+            .find(id)
+            .select(roles::id)
+            .for_update()
+            .first::<i64>(conn)?;
+
+        Ok(())
+    }
+
+    pub fn with_permissions(
+        conn: &mut PgConnection,
+        role: RoleRecord,
+    ) -> Result<RoleWithPermissions> {
+        Ok(RoleWithPermissions {
+            permissions: permissions::of_role(conn, role.id)?,
+            role,
+        })
+    }
+}
+
+/// List permissions
+///
+/// Lists every permission which can be granted by a role.
+#[utoipa::path(
+    context_path = "/roles",
+    responses(
+        (status = 200, body = Vec<Permission>),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/permissions")]
+#[has_permissions("roles:read")]
+async fn all_permissions(pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
+    let res: Vec<Permission> = schema::permissions::table
+        .select(Permission::as_select())
+        .order(schema::permissions::name)
+        .load(&mut pool.get()?)?;
+
+    Ok(Json(res))
+}
+
+/// List roles
+///
+/// Lists the default roles and the roles of the center of the current user.
+#[utoipa::path(
+    context_path = "/roles",
+    params(FilterParam<RoleRecord>),
+    responses(
+        (status = 200, body = Vec<RoleWithPermissions>),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("")]
+#[has_permissions("roles:read")]
+async fn all(
+    filter: web::Query<FilterParam<RoleRecord>>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+
+    let res = roles::table
+        .filter(
+            roles::id_center
+                .is_null()
+                .or(roles::id_center.eq(auth.id_center)),
+        )
+        .filter(filter.expression())
+        .select(RoleRecord::as_select())
+        .order((roles::id_center.desc(), roles::name))
+        .load(conn)?
+        .into_iter()
+        .map(|role| helper::with_permissions(conn, role))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Json(res))
+}
+
+#[utoipa::path(
+    context_path = "/roles",
+    responses(
+        (status = 200, body = RoleWithPermissions),
+        (status = 404, body = JsonError),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/{id}")]
+#[has_permissions("roles:read")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    let role = helper::visible_role(conn, &auth, *id)?;
+
+    Ok(Json(helper::with_permissions(conn, role)?))
+}
+
+/// Create role
+///
+/// Creates a role for the center of the current user. Only permissions the current user has can
+/// be granted.
+#[utoipa::path(
+    context_path = "/roles",
+    responses(
+        (status = 200, body = RoleWithPermissions),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[post("")]
+#[has_permissions("roles:write")]
+async fn post(
+    new_role: web::Json<NewRole>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    let NewRole {
+        role: mut new_role,
+        permissions: granted,
+    } = new_role.into_inner();
+
+    permissions::check_grantable(conn, &auth, &granted)?;
+    new_role.id_center = Some(auth.id_center);
+
+    let role = auth.audited(conn, |conn| {
+        let role: RoleRecord = insert_into(roles::table)
+            .values(&new_role)
+            .returning(RoleRecord::as_returning())
+            .get_result(conn)?;
+        permissions::set_role_permissions(conn, role.id, &granted)?;
+
+        diesel::QueryResult::Ok(role)
+    })?;
+
+    Ok(Json(helper::with_permissions(conn, role)?))
+}
+
+/// Update role
+///
+/// Updates a role of the center of the current user, default roles cannot be updated. The
+/// permissions, if given, replace the current ones, the current user must have every permission
+/// granted or revoked.
+#[utoipa::path(
+    context_path = "/roles",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[put("/{id}")]
+#[has_permissions("roles:write")]
+async fn put(
+    id: web::Path<i64>,
+    update_role: web::Json<UpdateRole>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    helper::editable_role(conn, &auth, *id)?;
+
+    let UpdateRole {
+        role: update_role,
+        permissions: granted,
+    } = update_role.into_inner();
+
+    auth.audited(conn, |conn| {
+        helper::lock(conn, *id)?;
+
+        if !update_role.is_empty() {
+            diesel::update(roles::table.find(*id))
+                .set(&update_role)
+                .execute(conn)?;
+        }
+        if let Some(granted) = &granted {
+            let current: HashSet<String> = permissions::of_role(conn, *id)?.into_iter().collect();
+            let changed: Vec<String> = granted
+                .iter()
+                .cloned()
+                .collect::<HashSet<_>>()
+                .symmetric_difference(&current)
+                .cloned()
+                .collect();
+            permissions::check_grantable(conn, &auth, &changed)?;
+
+            permissions::set_role_permissions(conn, *id, granted)?;
+        }
+
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Delete role
+///
+/// Deletes a role of the center of the current user, default roles cannot be deleted. Users lose
+/// the permissions granted by the role, the current user must have every one of them.
+#[utoipa::path(
+    context_path = "/roles",
+    responses(
+        (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "roles",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[delete("/{id}")]
+#[has_permissions("roles:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    helper::editable_role(conn, &auth, *id)?;
+
+    auth.audited(conn, |conn| {
+        helper::lock(conn, *id)?;
+        let revoked = permissions::of_role(conn, *id)?;
+        permissions::check_grantable(conn, &auth, &revoked)?;
+
+        diesel::delete(roles::table.find(*id)).execute(conn)?;
+
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
@@ -0,0 +1,265 @@
+use actix_web::{
+    error::ErrorBadRequest,
+    get,
+    web::{self, Json},
+    Responder, Scope,
+};
+use actix_web_grants::proc_macro::has_any_permission;
+
+use crate::{
+    auth::{Auth, Role},
+    database::DbPool,
+    error::{JsonError, Result},
+    params::FullTextSearchParam,
+    search::{self, Hit, HitKind},
+};
+
+#[derive(utoipa::OpenApi)]
+#[openapi(
+    paths(get),
+    components(schemas(Hit, HitKind, JsonError)),
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+pub struct Doc;
+
+pub fn routes() -> Scope {
+    web::scope("/search").service(get)
+}
+
+mod helper {
+    use diesel::{
+        BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+    };
+
+    use super::*;
+    use crate::{
+        schema::{addresses, l_visits_nurses, missions, nurses, patients, users, visits, zones},
+        search::{
+            greatest, search_excerpt, search_name, search_query, search_unaccent, ts_rank,
+            word_similarity, Matches, Resembles,
+        },
+    };
+
+    /// Searches the people of a table joined to `users` and `addresses`, by their name, mail and
+    /// address.
+    ///
+    /// Names resembling the search match as well, ranked by how close they are.
+    macro_rules! people {
+        ($conn:expr, $table:ident, $kind:expr, $id_center:expr, $query:expr, $limit:expr) => {{
+            let rank = greatest(
+                ts_rank(users::search, search_query($query)),
+                ts_rank(addresses::search, search_query($query)),
+                word_similarity(
+                    search_unaccent($query),
+                    search_name(users::fname, users::lname),
+                ),
+            );
+
+            $table::table
+                .inner_join(users::table)
+                .inner_join(addresses::table.inner_join(zones::table))
+                .filter(zones::id_center.eq($id_center))
+                .filter(
+                    Matches::new(users::search, search_query($query))
+                        .or(Matches::new(addresses::search, search_query($query)))
+                        .or(Resembles::new(
+                            search_name(users::fname, users::lname),
+                            search_unaccent($query),
+                        )),
+                )
+                .select(($table::id, users::fname, users::lname, rank))
+                .order((rank.desc(), $table::id))
+                .limit($limit)
+                .load::<(i64, String, String, f32)>($conn)?
+                .into_iter()
+                .map(|(id, fname, lname, rank)| Hit {
+                    kind: $kind,
+                    id,
+                    id_nurse: None,
+                    label: format!("{fname} {lname}"),
+                    rank,
+                })
+                .collect()
+        }};
+    }
+
+    /// Searches the patients of a center.
+    pub fn patients(
+        conn: &mut PgConnection,
+        id_center: i64,
+        query: &str,
+        limit: i64,
+    ) -> QueryResult<Vec<Hit>> {
+        Ok(people!(
+            conn,
+            patients,
+            HitKind::Patient,
+            id_center,
+            query,
+            limit
+        ))
+    }
+
+    /// Searches the nurses of a center.
+    pub fn nurses(
+        conn: &mut PgConnection,
+        id_center: i64,
+        query: &str,
+        limit: i64,
+    ) -> QueryResult<Vec<Hit>> {
+        Ok(people!(
+            conn,
+            nurses,
+            HitKind::Nurse,
+            id_center,
+            query,
+            limit
+        ))
+    }
+
+    /// Searches the descriptions of the missions of a center.
+    pub fn missions(
+        conn: &mut PgConnection,
+        id_center: i64,
+        query: &str,
+        limit: i64,
+    ) -> QueryResult<Vec<Hit>> {
+        let rank = ts_rank(missions::search, search_query(query));
+
+        Ok(missions::table
+            .inner_join(patients::table.inner_join(addresses::table.inner_join(zones::table)))
+            .filter(zones::id_center.eq(id_center))
+            .filter(Matches::new(missions::search, search_query(query)))
+            .select((
+                missions::id,
+                search_excerpt(missions::desc, search_query(query)),
+                rank,
+            ))
+            .order((rank.desc(), missions::id))
+            .limit(limit)
+            .load::<(i64, Option<String>, f32)>(conn)?
+            .into_iter()
+            .map(|(id, excerpt, rank)| Hit {
+                kind: HitKind::Mission,
+                id,
+                id_nurse: None,
+                label: excerpt.unwrap_or_default(),
+                rank,
+            })
+            .collect())
+    }
+
+    /// Searches the reports of a center, only the ones of `id_nurse` if given.
+    pub fn reports(
+        conn: &mut PgConnection,
+        id_center: i64,
+        id_nurse: Option<i64>,
+        query: &str,
+        limit: i64,
+    ) -> QueryResult<Vec<Hit>> {
+        let rank = ts_rank(l_visits_nurses::search, search_query(query));
+
+        let mut req =
+            l_visits_nurses::table
+                .inner_join(visits::table.inner_join(missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                )))
+                .filter(zones::id_center.eq(id_center))
+                .filter(Matches::new(l_visits_nurses::search, search_query(query)))
+                .select((
+                    l_visits_nurses::id_visit,
+                    l_visits_nurses::id_nurse,
+                    search_excerpt(l_visits_nurses::report, search_query(query)),
+                    rank,
+                ))
+                .order((
+                    rank.desc(),
+                    l_visits_nurses::id_visit,
+                    l_visits_nurses::id_nurse,
+                ))
+                .limit(limit)
+                .into_boxed();
+
+        if let Some(id_nurse) = id_nurse {
+            req = req.filter(l_visits_nurses::id_nurse.eq(id_nurse));
+        }
+
+        Ok(req
+            .load::<(i64, i64, Option<String>, f32)>(conn)?
+            .into_iter()
+            .map(|(id, id_nurse, excerpt, rank)| Hit {
+                kind: HitKind::Report,
+                id,
+                id_nurse: Some(id_nurse),
+                label: excerpt.unwrap_or_default(),
+                rank,
+            })
+            .collect())
+    }
+}
+
+/// Search
+///
+/// Searches the patients, nurses, missions and reports of the current center, the best ranked
+/// first. Accents are ignored and words are matched whatever their form, names with a typo are
+/// found as well.
+///
+/// Only the kinds of records the user can read are searched, a nurse without the `reports:read`
+/// permission only finds its own reports.
+#[utoipa::path(
+    context_path = "/search",
+    params(FullTextSearchParam),
+    responses(
+        (status = 200, body = Vec<Hit>),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+    ),
+    tag = "search"
+)]
+#[get("")]
+#[has_any_permission(
+    "patients:read",
+    "nurses:read",
+    "missions:read",
+    "reports:read",
+    "ROLE_NURSE"
+)]
+async fn get(
+    params: web::Query<FullTextSearchParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let query = params.value();
+    if query.is_empty() {
+        return Err(ErrorBadRequest("The search cannot be empty").into());
+    }
+
+    let conn = &mut *pool.get()?;
+    let limit = params.limit.into();
+    let mut hits = Vec::new();
+
+    if auth.has_permission("patients:read") {
+        hits.push(helper::patients(conn, auth.id_center, query, limit)?);
+    }
+    if auth.has_permission("nurses:read") {
+        hits.push(helper::nurses(conn, auth.id_center, query, limit)?);
+    }
+    if auth.has_permission("missions:read") {
+        hits.push(helper::missions(conn, auth.id_center, query, limit)?);
+    }
+    if auth.has_permission("reports:read") {
+        hits.push(helper::reports(conn, auth.id_center, None, query, limit)?);
+    } else if auth.role == Role::Nurse {
+        hits.push(helper::reports(
+            conn,
+            auth.id_center,
+            Some(auth.id),
+            query,
+            limit,
+        )?);
+    }
+
+    Ok(Json(search::merge(hits, params.limit.into())))
+}
@@ -3,16 +3,16 @@ use actix_web::{
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::{has_any_role, has_roles};
+use actix_web_grants::proc_macro::has_permissions;
 use diesel::{insert_into, ExpressionMethods, PgTextExpressionMethods, QueryDsl, RunQueryDsl};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
     error::{JsonError, Result},
     models::{NewSkill, Skill, UpdateSkill},
     pagination::{PaginatedResponse, PaginationParam},
-    params::{SearchParam, SortParam},
+    params::{FilterParam, SearchParam, SortParam},
     schema::skills,
 };
 
@@ -40,7 +40,7 @@ pub fn routes() -> Scope {
 
 #[utoipa::path(
     context_path = "/skills",
-    params(PaginationParam, SearchParam, SortParam),
+    params(PaginationParam, SearchParam, SortParam<Skill>, FilterParam<Skill>),
     responses(
         (status = 200, description = "Paginated list of skills", body = PaginatedSkills),
     ),
@@ -50,25 +50,30 @@ pub fn routes() -> Scope {
     )
 )]
 #[get("")]
-#[has_any_role("Role::Manager", "Role::Nurse", type = "Role")]
+#[has_permissions("skills:read")]
 async fn all(
     pagination: web::Query<PaginationParam>,
     search: web::Query<SearchParam>,
-    sort: web::Query<SortParam>,
+    sort: web::Query<SortParam<Skill>>,
+    filter: web::Query<FilterParam<Skill>>,
     pool: web::Data<DbPool>,
     _: Auth,
 ) -> Result<impl Responder> {
     let skills: Vec<Skill> = skills::table
         .filter(skills::name.ilike(search.value()))
-        .order(sort.raw_sql())
+        .filter(filter.expression())
+        .order(sort.expression())
         .offset(pagination.offset().into())
         .limit(pagination.limit().into())
         .load(&mut pool.get()?)?;
 
-    let total = skills::table
-        .filter(skills::name.ilike(search.value()))
-        .count()
-        .get_result::<i64>(&mut pool.get()?)? as u32;
+    let total = pagination.total(|| {
+        Ok(skills::table
+            .filter(skills::name.ilike(search.value()))
+            .filter(filter.expression())
+            .count()
+            .get_result(&mut pool.get()?)?)
+    })?;
 
     Ok(Json(
         PaginatedResponse::new(skills, &pagination).total(total),
@@ -87,7 +92,7 @@ async fn all(
     )
 )]
 #[get("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("skills:read")]
 async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
     let skill: Skill = macros::get!(skills, pool, *id);
 
@@ -106,16 +111,18 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     )
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("skills:write")]
 async fn post(
     new_skill: web::Json<NewSkill>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
     web::block(move || {
-        insert_into(skills::table)
-            .values(&new_skill.0)
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            insert_into(skills::table)
+                .values(&new_skill.0)
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -135,18 +142,20 @@ async fn post(
     )
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("skills:write")]
 async fn put(
     id: web::Path<i64>,
     update_skill: web::Json<UpdateSkill>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
     web::block(move || {
-        diesel::update(skills::table)
-            .set(&update_skill.0)
-            .filter(skills::id.eq(*id))
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            diesel::update(skills::table)
+                .set(&update_skill.0)
+                .filter(skills::id.eq(*id))
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -165,9 +174,9 @@ async fn put(
     )
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    macros::delete!(skills, pool, *id);
+#[has_permissions("skills:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    macros::delete!(skills, pool, *id, auth);
 
     Ok(Json(()))
 }
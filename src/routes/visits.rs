@@ -1,20 +1,34 @@
+use std::{collections::HashMap, env};
+
 use actix_web::{
-    delete, get, post, put,
+    delete,
+    error::{ErrorBadRequest, ErrorConflict, ErrorForbidden},
+    get, post, put,
     web::{self, Json},
     Responder, Scope,
 };
-use actix_web_grants::proc_macro::{has_any_role, has_roles};
-use diesel::{insert_into, ExpressionMethods, QueryDsl, RunQueryDsl, SelectableHelper};
-use macros::total;
+use actix_web_grants::proc_macro::{has_any_permission, has_permissions, has_roles};
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::{
+    dsl::IntervalDsl, insert_into, result::DatabaseErrorKind, BoolExpressionMethods,
+    ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use once_cell::sync::Lazy;
 
 use crate::{
     auth::{Auth, Role},
     database::DbPool,
-    error::{JsonError, Result},
+    error::{ConflictsError, Error, JsonError, Result},
     models::*,
-    pagination::{PaginatedResponse, PaginationParam},
-    params::SortParam,
-    schema::{self, addresses, l_visits_nurses, mission_types, missions, patients, users, visits},
+    pagination::{CursorParam, PaginatedResponse, PaginationParam},
+    params::{FilterParam, SortParam},
+    planning::Conflict,
+    reports::{BloodPressure, Medication, ReportData, Template, Vitals},
+    schema::{
+        self, addresses, l_visits_nurses, mission_types, missions, patients, report_revisions,
+        users, visits, zones,
+    },
 };
 
 #[derive(utoipa::OpenApi)]
@@ -27,19 +41,38 @@ use crate::{
         post,
         post_visit_nurse,
         put_report,
+        post_addendum,
+        report_history,
+        sign_report,
         put,
         delete,
-        delete_visit_nurse
+        delete_visit_nurse,
+        attendance,
+        check_in,
+        check_out,
+        cancel
     ),
     components(schemas(
         Visit,
         VisitRecord,
         UpdateVisit,
         UpdateLVisitNurse,
+        ReportRevision,
+        ReportData,
+        Vitals,
+        BloodPressure,
+        Medication,
+        NewAddendum,
         NewVisit,
+        VisitStatus,
+        VisitAttendance,
+        NurseAttendance,
         LVisitNurse,
+        Conflict,
+        ConflictsError,
         crate::pagination::PaginatedLVisitsNurses,
         crate::pagination::PaginatedVisits,
+        crate::pagination::PaginatedVisitAttendances,
         JsonError
     ))
 )]
@@ -48,22 +81,267 @@ pub struct Doc;
 pub fn routes() -> Scope {
     web::scope("/visits")
         .service(all)
+        .service(attendance)
         .service(get)
         .service(nurses)
         .service(reports)
         .service(post)
         .service(post_visit_nurse)
         .service(put_report)
+        .service(post_addendum)
+        .service(report_history)
+        .service(sign_report)
         .service(put)
         .service(delete)
         .service(delete_visit_nurse)
+        .service(check_in)
+        .service(check_out)
+        .service(cancel)
+}
+
+/// Hours after the end of a visit its reports can still be modified, set by `REPORT_LOCK_HOURS`.
+static REPORT_LOCK_HOURS: Lazy<i64> = Lazy::new(|| {
+    env::var("REPORT_LOCK_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(48)
+});
+
+/// Minutes after the start of a visit a nurse is late, set by `VISIT_LATE_MINUTES`.
+static LATE_MINUTES: Lazy<i64> = Lazy::new(|| {
+    env::var("VISIT_LATE_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(15)
+});
+
+/// Minutes before the start and after the end of a visit a nurse can check in, set by
+/// `VISIT_CHECK_IN_MINUTES`.
+static CHECK_IN_MINUTES: Lazy<i64> = Lazy::new(|| {
+    env::var("VISIT_CHECK_IN_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60)
+});
+
+pub(crate) mod helper {
+    use std::collections::HashSet;
+
+    use diesel::{PgConnection, QueryResult};
+
+    use super::*;
+    use crate::{
+        planning::{NurseCapacity, VisitNeed},
+        schema::{availabilities, l_missions_skills, l_nurses_skills},
+    };
+
+    /// Lists the reasons preventing a nurse from being assigned to a visit.
+    ///
+    /// The center of the visit is returned along with the conflicts.
+    pub fn conflicts(
+        conn: &mut PgConnection,
+        id_visit: i64,
+        id_nurse: i64,
+    ) -> QueryResult<(i64, Vec<Conflict>)> {
+        let (start, end, people_required, id_mission_type, id_zone, visit_center): (
+            NaiveDateTime,
+            NaiveDateTime,
+            i16,
+            i64,
+            i64,
+            i64,
+        ) =
+            visits::table
+                .inner_join(missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                ))
+                .filter(visits::id.eq(id_visit))
+                .select((
+                    visits::start,
+                    visits::end,
+                    missions::people_required,
+                    missions::id_mission_type,
+                    addresses::id_zone,
+                    zones::id_center,
+                ))
+                .first(conn)?;
+
+        let (minutes_per_week, nurse_zone, nurse_center): (i32, i64, i64) = schema::nurses::table
+            .inner_join(addresses::table.inner_join(zones::table))
+            .filter(schema::nurses::id.eq(id_nurse))
+            .select((
+                schema::nurses::minutes_per_week,
+                addresses::id_zone,
+                zones::id_center,
+            ))
+            .first(conn)?;
+
+        let visit = VisitNeed {
+            id_visit,
+            start,
+            end,
+            id_zone,
+            required_skills: l_missions_skills::table
+                .filter(l_missions_skills::id_mission_type.eq(id_mission_type))
+                .filter(l_missions_skills::preferred.eq(false))
+                .select(l_missions_skills::id_skill)
+                .load(conn)?
+                .into_iter()
+                .collect(),
+            preferred_skills: HashSet::new(),
+            people_required,
+            assigned: l_visits_nurses::table
+                .filter(l_visits_nurses::id_visit.eq(id_visit))
+                .filter(l_visits_nurses::id_nurse.ne(id_nurse))
+                .select(l_visits_nurses::id_nurse)
+                .load(conn)?
+                .into_iter()
+                .collect(),
+        };
+
+        let nurse = NurseCapacity {
+            id_nurse,
+            id_zone: nurse_zone,
+            minutes_per_week,
+            skills: l_nurses_skills::table
+                .filter(l_nurses_skills::id_nurse.eq(id_nurse))
+                .select(l_nurses_skills::id_skill)
+                .load(conn)?
+                .into_iter()
+                .collect(),
+            availabilities: availabilities::table
+                .filter(availabilities::id_nurse.eq(id_nurse))
+                .select(Availability::as_select())
+                .load(conn)?,
+            visits: l_visits_nurses::table
+                .inner_join(visits::table)
+                .filter(l_visits_nurses::id_nurse.eq(id_nurse))
+                .filter(visits::id.ne(id_visit))
+                .filter(visits::cancelled_at.is_null())
+                .filter(visits::start.lt(end))
+                .filter(visits::end.gt(start))
+                .select((visits::start, visits::end))
+                .load(conn)?,
+        };
+
+        let mut conflicts = nurse.conflicts(&visit);
+
+        if nurse_center != visit_center {
+            conflicts.push(Conflict::OtherCenter);
+        }
+
+        Ok((visit_center, conflicts))
+    }
+
+    /// Checks the report of a nurse for a visit can still be modified.
+    ///
+    /// A report is locked once signed off or [`REPORT_LOCK_HOURS`] after the end of the visit,
+    /// only addenda can then be written. The report is locked for update until the end of the
+    /// transaction, so it cannot be signed off meanwhile.
+    pub fn check_report_unlocked(
+        conn: &mut PgConnection,
+        id_visit: i64,
+        id_nurse: i64,
+    ) -> Result<()> {
+        let (end, signed_at): (NaiveDateTime, Option<NaiveDateTime>) = l_visits_nurses::table
+            .inner_join(visits::table)
+            .filter(l_visits_nurses::id_visit.eq(id_visit))
+            .filter(l_visits_nurses::id_nurse.eq(id_nurse))
+            .select((visits::end, l_visits_nurses::signed_at))
+            .for_update()
+            .first(conn)?;
+
+        if signed_at.is_some() {
+            Err(ErrorForbidden("The report is signed off, write an addendum instead").into())
+        } else if end + Duration::hours(*REPORT_LOCK_HOURS) < Local::now().naive_local() {
+            Err(ErrorForbidden("The report is locked, write an addendum instead").into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks no report was written for a visit, or only by `id_nurse` if given.
+    pub fn check_no_report(
+        conn: &mut PgConnection,
+        id_visit: i64,
+        id_nurse: Option<i64>,
+    ) -> Result<()> {
+        let mut assignments = l_visits_nurses::table
+            .filter(l_visits_nurses::id_visit.eq(id_visit))
+            .into_boxed();
+
+        if let Some(id_nurse) = id_nurse {
+            assignments = assignments.filter(l_visits_nurses::id_nurse.eq(id_nurse));
+        }
+
+        crate::reports::check_no_report(
+            conn,
+            assignments,
+            "A report was written for the visit, it cannot be removed",
+        )
+    }
+
+    /// Returns the actual times of the current nurse for a visit, which must not be cancelled.
+    pub fn own_attendance(
+        conn: &mut PgConnection,
+        auth: &Auth,
+        id_visit: i64,
+    ) -> Result<NurseAttendance> {
+        let (own, cancelled_at): (NurseAttendance, Option<NaiveDateTime>) = l_visits_nurses::table
+            .inner_join(visits::table)
+            .filter(l_visits_nurses::id_visit.eq(id_visit))
+            .filter(l_visits_nurses::id_nurse.eq(auth.id))
+            .select((NurseAttendance::as_select(), visits::cancelled_at))
+            .first(conn)?;
+
+        if cancelled_at.is_some() {
+            Err(ErrorBadRequest("The visit is cancelled").into())
+        } else {
+            Ok(own)
+        }
+    }
+
+    /// Returns the report template of the mission type of a visit.
+    pub fn report_template(
+        conn: &mut PgConnection,
+        id_visit: i64,
+    ) -> QueryResult<Option<Template>> {
+        visits::table
+            .inner_join(missions::table.inner_join(mission_types::table))
+            .filter(visits::id.eq(id_visit))
+            .select(mission_types::report_template)
+            .first(conn)
+    }
+
+    /// Records a new revision of the report of the current nurse.
+    pub fn add_revision(
+        conn: &mut PgConnection,
+        auth: &Auth,
+        id_visit: i64,
+        content: &str,
+        data: Option<&ReportData>,
+        addendum: bool,
+    ) -> QueryResult<usize> {
+        insert_into(report_revisions::table)
+            .values((
+                report_revisions::id_visit.eq(id_visit),
+                report_revisions::id_nurse.eq(auth.id),
+                report_revisions::id_author.eq(auth.id_user),
+                report_revisions::content.eq(content),
+                report_revisions::data.eq(data),
+                report_revisions::addendum.eq(addendum),
+                report_revisions::created_at.eq(Local::now().naive_local()),
+            ))
+            .execute(conn)
+    }
 }
 
 #[utoipa::path(
     context_path = "/visits",
-    params(PaginationParam, SortParam),
+    params(PaginationParam, CursorParam, SortParam<(VisitRecord, MissionRecord)>, FilterParam<(VisitRecord, MissionRecord)>),
     responses(
         (status = 200, description = "Paginated list of visits", body = PaginatedVisits),
+        (status = 400, body = JsonError),
     ),
     tag = "visits",
     security(
@@ -71,41 +349,56 @@ pub fn routes() -> Scope {
     )
 )]
 #[get("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("visits:read")]
 async fn all(
-    query: web::Query<PaginationParam>,
-    sort: web::Query<SortParam>,
+    pagination: web::Query<PaginationParam>,
+    cursor: web::Query<CursorParam>,
+    sort: web::Query<SortParam<(VisitRecord, MissionRecord)>>,
+    filter: web::Query<FilterParam<(VisitRecord, MissionRecord)>>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let q2 = query.clone();
-    let p2 = pool.clone();
+    let page = pagination.page(&cursor)?;
+    let (seek, order) = sort.seek(page.cursor())?;
 
-    let res: Vec<Visit> = actix_web::web::block(move || {
-        visits::table
-            .inner_join(
-                missions::table.inner_join(mission_types::table).inner_join(
-                    patients::table
-                        .inner_join(users::table)
-                        .inner_join(addresses::table),
-                ),
-            )
-            .order(sort.raw_sql())
-            .offset(query.offset().into())
-            .limit(query.limit().into())
-            .load(&mut pool.get().unwrap())
-    })
-    .await??;
+    let conn = &mut *pool.get()?;
+    let rows: Vec<(Visit, serde_json::Value)> = visits::table
+        .inner_join(
+            missions::table.inner_join(mission_types::table).inner_join(
+                patients::table
+                    .inner_join(users::table)
+                    .inner_join(addresses::table.inner_join(zones::table)),
+            ),
+        )
+        .filter(zones::id_center.eq(auth.id_center))
+        .filter(filter.expression())
+        .filter(seek)
+        .select((Visit::as_select(), sort.keys()))
+        .order(order)
+        .offset(page.offset())
+        .limit(page.limit())
+        .load(conn)?;
 
-    let total = total!(visits, p2);
+    let total =
+        pagination.total(|| {
+            Ok(visits::table
+                .inner_join(missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                ))
+                .filter(zones::id_center.eq(auth.id_center))
+                .filter(filter.expression())
+                .count()
+                .get_result(conn)?)
+        })?;
 
-    Ok(Json(PaginatedResponse::new(res, &q2).total(total)))
+    Ok(Json(PaginatedResponse::page(rows, &page).total(total)))
 }
 
 #[utoipa::path(
     context_path = "/visits",
     responses(
         (status = 200, body = Visit),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "visits",
@@ -114,8 +407,10 @@ async fn all(
     )
 )]
 #[get("/{id}")]
-#[has_any_role("Role::Manager", "Role::Nurse", type = "Role")]
-async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
+#[has_any_permission("visits:read", "ROLE_NURSE")]
+async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    auth.check_center::<VisitRecord>(&mut *pool.get()?, *id)?;
+
     let res: Visit = actix_web::web::block(move || {
         visits::table
             .inner_join(
@@ -126,6 +421,7 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
                 ),
             )
             .filter(visits::id.eq(*id))
+            .select(Visit::as_select())
             .first(&mut pool.get().unwrap())
     })
     .await??;
@@ -137,6 +433,7 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     context_path = "/visits",
     responses(
         (status = 200, body = PaginatedNurses),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError)
     ),
     tag = "visits",
@@ -145,13 +442,15 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<imp
     )
 )]
 #[get("/{id}/nurses")]
-#[has_any_role("Role::Manager", "Role::Nurse", type = "Role")]
+#[has_any_permission("visits:read", "ROLE_NURSE")]
 async fn nurses(
     id: web::Path<i64>,
     query: web::Query<PaginationParam>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.check_center::<VisitRecord>(&mut *pool.get()?, *id)?;
+
     let res: Vec<Nurse> = schema::nurses::table
         .inner_join(schema::users::table)
         .inner_join(schema::addresses::table)
@@ -162,14 +461,14 @@ async fn nurses(
         .select(Nurse::as_select())
         .load(&mut pool.get()?)?;
 
-    let total: i64 = schema::l_visits_nurses::table
-        .filter(schema::l_visits_nurses::id_visit.eq(*id))
-        .count()
-        .get_result(&mut pool.get()?)?;
+    let total = query.total(|| {
+        Ok(schema::l_visits_nurses::table
+            .filter(schema::l_visits_nurses::id_visit.eq(*id))
+            .count()
+            .get_result(&mut pool.get()?)?)
+    })?;
 
-    Ok(Json(
-        PaginatedResponse::new(res, &query).total(total as u32),
-    ))
+    Ok(Json(PaginatedResponse::new(res, &query).total(total)))
 }
 
 /// Visit's reports
@@ -177,9 +476,11 @@ async fn nurses(
 /// Returns the reports of a visit. Null or empty reports are not included.
 #[utoipa::path(
     context_path = "/visits",
-    params(PaginationParam),
+    params(PaginationParam, CursorParam),
     responses(
         (status = 200, description = "Paginated list of reports from the given visit", body = PaginatedLVisitsNurses),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
     ),
     tag = "visits",
     security(
@@ -187,31 +488,36 @@ async fn nurses(
     )
 )]
 #[get("/{id}/reports")]
-#[has_any_role["Role::Manager", type = "Role"]]
+#[has_permissions("reports:read")]
 async fn reports(
-    query: web::Query<PaginationParam>,
+    pagination: web::Query<PaginationParam>,
+    cursor: web::Query<CursorParam>,
     id: web::Path<i64>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let res: Vec<LVisitNurse> = schema::l_visits_nurses::table
-        .filter(schema::l_visits_nurses::id_visit.eq(*id))
-        .filter(schema::l_visits_nurses::report.is_not_null())
-        .filter(schema::l_visits_nurses::report.ne(""))
-        .limit(query.limit().into())
-        .offset(query.offset().into())
-        .load(&mut pool.get()?)?;
+    auth.check_center::<VisitRecord>(&mut *pool.get()?, *id)?;
 
-    let total: i64 = schema::l_visits_nurses::table
-        .filter(schema::l_visits_nurses::id_nurse.eq(*id))
-        .filter(schema::l_visits_nurses::report.is_not_null())
-        .filter(schema::l_visits_nurses::report.ne(""))
-        .count()
-        .get_result(&mut pool.get()?)?;
+    let page = pagination.page(&cursor)?;
+    let sort = SortParam::<LVisitNurse>::default();
+    let (seek, order) = sort.seek(page.cursor())?;
+    let reports = l_visits_nurses::table
+        .filter(l_visits_nurses::id_visit.eq(*id))
+        .filter(l_visits_nurses::report.is_not_null())
+        .filter(l_visits_nurses::report.ne(""));
 
-    Ok(Json(
-        PaginatedResponse::new(res, &query).total(total as u32),
-    ))
+    let conn = &mut *pool.get()?;
+    let rows: Vec<(LVisitNurse, serde_json::Value)> = reports
+        .filter(seek)
+        .select((LVisitNurse::as_select(), sort.keys()))
+        .order(order)
+        .offset(page.offset())
+        .limit(page.limit())
+        .load(conn)?;
+
+    let total = pagination.total(|| Ok(reports.count().get_result(conn)?))?;
+
+    Ok(Json(PaginatedResponse::page(rows, &page).total(total)))
 }
 
 /// Create a visit
@@ -221,7 +527,8 @@ async fn reports(
     context_path = "/visits",
     responses(
         (status = 200, body = i64),
-        (status = 400, body = JsonError)
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError)
     ),
     tag = "visits",
     security(
@@ -229,17 +536,21 @@ async fn reports(
     )
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("visits:write")]
 async fn post(
     new_record: Json<NewVisit>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.check_center::<MissionRecord>(&mut *pool.get()?, new_record.id_mission)?;
+
     let id: i64 = web::block(move || {
-        insert_into(visits::table)
-            .values(&new_record.0)
-            .returning(visits::id)
-            .get_result(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            insert_into(visits::table)
+                .values(&new_record.0)
+                .returning(visits::id)
+                .get_result(conn)
+        })
     })
     .await??;
 
@@ -249,10 +560,20 @@ async fn post(
 /// Associate nurse & visit
 ///
 /// Associates the given nurse with the given visit.
+///
+/// The association is refused when the nurse already has a visit at the same time, is not
+/// available, lacks a skill required by the mission type, belongs to another center, or when the
+/// visit already has all the nurses it requires. With `force`, the nurse is associated anyway and
+/// the conflicts are recorded with the association. A nurse cannot be associated twice with the
+/// same visit.
 #[utoipa::path(
     context_path = "/visits",
+    params(AssignNurseParam),
     responses(
         (status = 200),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, body = ConflictsError),
     ),
     tag = "visits",
     security(
@@ -260,33 +581,69 @@ async fn post(
     )
 )]
 #[post("/{id_visit}/nurses/{id_nurse}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("visits:write")]
 async fn post_visit_nurse(
     ids: web::Path<(i64, i64)>,
+    query: web::Query<AssignNurseParam>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    web::block(move || {
+    let (id_visit, id_nurse) = *ids;
+    let conn = &mut pool.get()?;
+
+    auth.audited(conn, |conn| {
+        // Concurrent assignments to the visit or of the nurse wait for this one, so the
+        // conflicts checked below still hold when inserting
+        visits::table
+            .find(id_visit)
+            .select(visits::id)
+            .for_update()
+            .first::<i64>(conn)?;
+        schema::nurses::table
+            .find(id_nurse)
+            .select(schema::nurses::id)
+            .for_update()
+            .first::<i64>(conn)?;
+
+        let (id_center, conflicts) = helper::conflicts(conn, id_visit, id_nurse)?;
+
+        auth.same_center(id_center)?;
+
+        if !conflicts.is_empty() && !query.force {
+            return Err(Error::Conflicts(conflicts));
+        }
+
         insert_into(l_visits_nurses::table)
-            .values(&NewLVisitNurse {
-                id_visit: ids.0,
-                id_nurse: ids.1,
+            .values((
+                l_visits_nurses::id_visit.eq(id_visit),
+                l_visits_nurses::id_nurse.eq(id_nurse),
+                l_visits_nurses::conflicts
+                    .eq(conflicts.iter().map(|c| Some(c.name())).collect::<Vec<_>>()),
+            ))
+            .execute(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    ErrorConflict("The nurse is already assigned to this visit").into()
+                }
+                err => err.into(),
             })
-            .execute(&mut pool.get().unwrap())
-    })
-    .await??;
+    })?;
 
     Ok(Json(()))
 }
 
 /// Create report
 ///
-/// Create or modify a report for the current nurse and the given visit.
+/// Create or modify a report for the current nurse and the given visit. Its structured part must
+/// follow the template of the mission type, it is required when the template lists vital signs.
+/// Every version is kept in the report's history. Once signed off or some time after the end of
+/// the visit, the report is locked and an addendum has to be written instead.
 #[utoipa::path(
     context_path = "/visits",
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "visits",
@@ -295,34 +652,192 @@ async fn post_visit_nurse(
     )
 )]
 #[put("/{id}/report")]
-#[has_roles("Role::Nurse", type = "Role")]
+#[has_permissions("ROLE_NURSE", "reports:write")]
 async fn put_report(
     id: web::Path<i64>,
     update_record: Json<UpdateLVisitNurse>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
-    let rows = web::block(move || {
+    let conn = &mut *pool.get()?;
+
+    // Without structured data, the vital signs required by the template are missing
+    let template = helper::report_template(conn, *id)?;
+    crate::reports::check(
+        template.as_ref(),
+        &update_record.report,
+        update_record
+            .report_data
+            .as_ref()
+            .unwrap_or(&ReportData::default()),
+    )?;
+
+    auth.audited(conn, |conn| {
+        helper::check_report_unlocked(conn, *id, auth.id)?;
+
         diesel::update(l_visits_nurses::table)
             .set(&update_record.0)
             .filter(l_visits_nurses::id_visit.eq(*id))
             .filter(l_visits_nurses::id_nurse.eq(auth.id))
-            .execute(&mut pool.get().unwrap())
-    })
-    .await??;
+            .execute(conn)?;
+        helper::add_revision(
+            conn,
+            &auth,
+            *id,
+            &update_record.report,
+            update_record.report_data.as_ref(),
+            false,
+        )?;
 
-    if rows == 0 {
-        Err(diesel::result::Error::NotFound.into())
-    } else {
-        Ok(Json(()))
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Write an addendum
+///
+/// Adds an addendum to the report of the current nurse for the given visit. Unlike the report
+/// itself, addenda can be written once the report is locked. An addendum is only free text, the
+/// structured part of the report cannot be changed anymore.
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["nurse"])
+    )
+)]
+#[post("/{id}/report/addenda")]
+#[has_permissions("ROLE_NURSE", "reports:write")]
+async fn post_addendum(
+    id: web::Path<i64>,
+    addendum: Json<NewAddendum>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    if addendum.content.is_empty() {
+        return Err(ErrorBadRequest("An addendum cannot be empty").into());
+    }
+
+    let conn = &mut *pool.get()?;
+    l_visits_nurses::table
+        .find((*id, auth.id))
+        .select(l_visits_nurses::id_visit)
+        .first::<i64>(conn)?;
+
+    auth.audited(conn, |conn| {
+        helper::add_revision(conn, &auth, *id, &addendum.content, None, true)
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Report's history
+///
+/// Lists every revision and addendum of the report of a nurse for a visit, oldest first. A nurse
+/// can only access its own reports.
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200, body = Vec<ReportRevision>),
+        (status = 403, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["manager", "nurse"])
+    )
+)]
+#[get("/{id_visit}/nurses/{id_nurse}/report")]
+#[has_any_permission("reports:read", "ROLE_NURSE")]
+async fn report_history(
+    ids: web::Path<(i64, i64)>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let (id_visit, id_nurse) = *ids;
+    let conn = &mut *pool.get()?;
+
+    if !auth.is_or_has(Role::Nurse, id_nurse, "reports:read") {
+        return Err(ErrorForbidden("A nurse can only access its own reports").into());
     }
+    auth.check_center::<VisitRecord>(conn, id_visit)?;
+
+    let res: Vec<ReportRevision> = report_revisions::table
+        .filter(report_revisions::id_visit.eq(id_visit))
+        .filter(report_revisions::id_nurse.eq(id_nurse))
+        .order((report_revisions::created_at, report_revisions::id))
+        .select(ReportRevision::as_select())
+        .load(conn)?;
+
+    Ok(Json(res))
 }
 
+/// Sign off report
+///
+/// Acknowledges the report of a nurse for a visit, locking it.
 #[utoipa::path(
     context_path = "/visits",
     responses(
         (status = 200),
         (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[put("/{id_visit}/nurses/{id_nurse}/report/sign")]
+#[has_permissions("reports:sign")]
+async fn sign_report(
+    ids: web::Path<(i64, i64)>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let (id_visit, id_nurse) = *ids;
+    let conn = &mut *pool.get()?;
+
+    auth.check_center::<VisitRecord>(conn, id_visit)?;
+
+    auth.audited(conn, |conn| {
+        let (report, signed_at): (Option<String>, Option<NaiveDateTime>) = l_visits_nurses::table
+            .find((id_visit, id_nurse))
+            .select((l_visits_nurses::report, l_visits_nurses::signed_at))
+            .for_update()
+            .first(conn)?;
+
+        if report.is_none_or(|r| r.is_empty()) {
+            return Err(ErrorBadRequest("An empty report cannot be signed off").into());
+        }
+        if signed_at.is_some() {
+            return Err(ErrorBadRequest("The report is already signed off").into());
+        }
+
+        diesel::update(l_visits_nurses::table.find((id_visit, id_nurse)))
+            .set((
+                l_visits_nurses::signed_by.eq(auth.id_user),
+                l_visits_nurses::signed_at.eq(Local::now().naive_local()),
+            ))
+            .execute(conn)?;
+
+        Ok::<(), Error>(())
+    })?;
+
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
         (status = 404, body = JsonError),
     ),
     tag = "visits",
@@ -331,18 +846,22 @@ async fn put_report(
     )
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("visits:write")]
 async fn put(
     id: web::Path<i64>,
     update_record: Json<UpdateVisit>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
+    auth.check_center::<VisitRecord>(&mut *pool.get()?, *id)?;
+
     web::block(move || {
-        diesel::update(visits::table)
-            .set(&update_record.0)
-            .filter(visits::id.eq(*id))
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            diesel::update(visits::table)
+                .set(&update_record.0)
+                .filter(visits::id.eq(*id))
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -353,7 +872,9 @@ async fn put(
     context_path = "/visits",
     responses(
         (status = 200),
-        (status = 404, body = JsonError)
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, body = JsonError)
     ),
     tag = "visits",
     security(
@@ -361,9 +882,20 @@ async fn put(
     )
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
-async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<impl Responder> {
-    macros::delete!(visits, pool, *id);
+#[has_permissions("visits:write")]
+async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+    auth.check_center::<VisitRecord>(conn, *id)?;
+
+    auth.audited(conn, |conn| {
+        helper::check_no_report(conn, *id, None)?;
+
+        diesel::delete(visits::table)
+            .filter(visits::id.eq(*id))
+            .execute(conn)?;
+
+        Ok::<(), Error>(())
+    })?;
 
     Ok(Json(()))
 }
@@ -371,12 +903,15 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<
 /// Dissociate nurse & visit
 ///
 /// Dissociates the given nurse with the given visit. I.e. the nurse is no longer affected to this
-/// visit.
+/// visit. A nurse who wrote a report for the visit cannot be dissociated, nor can the visit be
+/// deleted.
 #[utoipa::path(
     context_path = "/visits",
     responses(
         (status = 200),
-        (status = 404, body = JsonError)
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+        (status = 409, body = JsonError)
     ),
     tag = "visits",
     security(
@@ -384,19 +919,25 @@ async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, _: Auth) -> Result<
     )
 )]
 #[delete("/{id_visit}/nurses/{id_nurse}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("visits:write")]
 async fn delete_visit_nurse(
     ids: web::Path<(i64, i64)>,
     pool: web::Data<DbPool>,
-    _: Auth,
+    auth: Auth,
 ) -> Result<impl Responder> {
-    let rows = web::block(move || {
-        diesel::delete(l_visits_nurses::table)
-            .filter(l_visits_nurses::id_visit.eq(ids.0))
-            .filter(l_visits_nurses::id_nurse.eq(ids.1))
-            .execute(&mut pool.get().unwrap())
-    })
-    .await??;
+    let conn = &mut pool.get()?;
+    auth.check_center::<VisitRecord>(conn, ids.0)?;
+
+    let rows = auth.audited(conn, |conn| {
+        helper::check_no_report(conn, ids.0, Some(ids.1))?;
+
+        Ok::<usize, Error>(
+            diesel::delete(l_visits_nurses::table)
+                .filter(l_visits_nurses::id_visit.eq(ids.0))
+                .filter(l_visits_nurses::id_nurse.eq(ids.1))
+                .execute(conn)?,
+        )
+    })?;
 
     if rows == 0 {
         Err(diesel::result::Error::NotFound.into())
@@ -404,3 +945,240 @@ async fn delete_visit_nurse(
         Ok(Json(()))
     }
 }
+
+/// Visits attendance
+///
+/// Lists the visits of the center which began during the period, along with the actual times of
+/// their nurses. Only the late and missed visits are listed unless `all` is set, a visit is late
+/// once a nurse arrives or could have arrived `VISIT_LATE_MINUTES` after its start.
+#[utoipa::path(
+    context_path = "/visits",
+    params(PaginationParam, AttendanceParam),
+    responses(
+        (status = 200, description = "Paginated list of visits", body = PaginatedVisitAttendances),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/attendance")]
+#[has_permissions("visits:read")]
+async fn attendance(
+    pagination: web::Query<PaginationParam>,
+    params: web::Query<AttendanceParam>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    let now = Local::now().naive_local();
+    let late = (*LATE_MINUTES).minutes();
+
+    let req = || {
+        let mut req =
+            visits::table
+                .inner_join(missions::table.inner_join(
+                    patients::table.inner_join(addresses::table.inner_join(zones::table)),
+                ))
+                .filter(zones::id_center.eq(auth.id_center))
+                .filter(visits::start.ge(params.from.unwrap_or(now - Duration::days(7))))
+                .filter(visits::start.lt(params.until.unwrap_or(now)))
+                .into_boxed();
+
+        if !params.all {
+            let planned = diesel::alias!(visits as planned);
+            let late_arrivals = l_visits_nurses::table
+                .inner_join(planned.on(planned.field(visits::id).eq(l_visits_nurses::id_visit)))
+                .filter(
+                    (l_visits_nurses::arrived_at - late)
+                        .gt(planned.field(visits::start).nullable()),
+                )
+                .select(l_visits_nurses::id_visit);
+            let arrivals = l_visits_nurses::table
+                .filter(l_visits_nurses::arrived_at.is_not_null())
+                .select(l_visits_nurses::id_visit);
+
+            req = req.filter(visits::cancelled_at.is_null()).filter(
+                visits::id.eq_any(late_arrivals).or(visits::id
+                    .ne_all(arrivals)
+                    .and((visits::start + late).lt(now))),
+            );
+        }
+
+        req
+    };
+
+    let res: Vec<VisitRecord> = req()
+        .select(VisitRecord::as_select())
+        .order((visits::start, visits::id))
+        .offset(pagination.offset().into())
+        .limit(pagination.limit().into())
+        .load(conn)?;
+
+    let mut by_visit: HashMap<i64, Vec<NurseAttendance>> = HashMap::new();
+    for (id_visit, nurse) in l_visits_nurses::table
+        .filter(l_visits_nurses::id_visit.eq_any(res.iter().map(|v| v.id)))
+        .order(l_visits_nurses::arrived_at)
+        .select((l_visits_nurses::id_visit, NurseAttendance::as_select()))
+        .load::<(i64, NurseAttendance)>(conn)?
+    {
+        by_visit.entry(id_visit).or_default().push(nurse);
+    }
+
+    let res: Vec<VisitAttendance> = res
+        .into_iter()
+        .map(|visit| {
+            let id = visit.id;
+            VisitAttendance::new(visit, by_visit.remove(&id).unwrap_or_default(), now)
+        })
+        .collect();
+
+    let total = pagination.total(|| Ok(req().count().get_result(conn)?))?;
+
+    Ok(Json(PaginatedResponse::new(res, &pagination).total(total)))
+}
+
+/// Check in
+///
+/// Records the arrival of the current nurse to the given visit. Nurses can only check in from
+/// `VISIT_CHECK_IN_MINUTES` before the start of the visit until as long after its end.
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["nurse"])
+    )
+)]
+#[post("/{id}/check-in")]
+#[has_roles("NURSE")]
+async fn check_in(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+
+    if helper::own_attendance(conn, &auth, *id)?
+        .arrived_at
+        .is_some()
+    {
+        return Err(ErrorBadRequest("The nurse already checked in").into());
+    }
+
+    let (start, end): (NaiveDateTime, NaiveDateTime) = visits::table
+        .find(*id)
+        .select((visits::start, visits::end))
+        .first(conn)?;
+    let now = Local::now().naive_local();
+    let margin = Duration::minutes(*CHECK_IN_MINUTES);
+    if now < start - margin || now > end + margin {
+        return Err(ErrorBadRequest(format!(
+            "Nurses can only check in from {} minutes before the visit until as long after it",
+            *CHECK_IN_MINUTES
+        ))
+        .into());
+    }
+
+    auth.audited(conn, |conn| {
+        diesel::update(l_visits_nurses::table.find((*id, auth.id)))
+            .set(l_visits_nurses::arrived_at.eq(now))
+            .execute(conn)
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Check out
+///
+/// Records the departure of the current nurse from the given visit, after checking in.
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["nurse"])
+    )
+)]
+#[post("/{id}/check-out")]
+#[has_roles("NURSE")]
+async fn check_out(
+    id: web::Path<i64>,
+    pool: web::Data<DbPool>,
+    auth: Auth,
+) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    let own = helper::own_attendance(conn, &auth, *id)?;
+
+    if own.arrived_at.is_none() {
+        return Err(ErrorBadRequest("The nurse has not checked in").into());
+    }
+    if own.left_at.is_some() {
+        return Err(ErrorBadRequest("The nurse already checked out").into());
+    }
+
+    auth.audited(conn, |conn| {
+        diesel::update(l_visits_nurses::table.find((*id, auth.id)))
+            .set(l_visits_nurses::left_at.eq(Local::now().naive_local()))
+            .execute(conn)
+    })?;
+
+    Ok(Json(()))
+}
+
+/// Cancel a visit
+///
+/// Cancels a visit which has not begun. Unlike a deleted visit, it is kept with its nurses and
+/// shown as cancelled in the calendars.
+#[utoipa::path(
+    context_path = "/visits",
+    responses(
+        (status = 200),
+        (status = 400, body = JsonError),
+        (status = 403, body = JsonError),
+        (status = 404, body = JsonError),
+    ),
+    tag = "visits",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[post("/{id}/cancel")]
+#[has_permissions("visits:write")]
+async fn cancel(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+    auth.check_center::<VisitRecord>(conn, *id)?;
+
+    let cancelled_at: Option<NaiveDateTime> = visits::table
+        .find(*id)
+        .select(visits::cancelled_at)
+        .first(conn)?;
+    let began: i64 = l_visits_nurses::table
+        .filter(l_visits_nurses::id_visit.eq(*id))
+        .filter(l_visits_nurses::arrived_at.is_not_null())
+        .count()
+        .get_result(conn)?;
+
+    if cancelled_at.is_some() {
+        return Err(ErrorBadRequest("The visit is already cancelled").into());
+    }
+    if began > 0 {
+        return Err(ErrorBadRequest("A visit which began cannot be cancelled").into());
+    }
+
+    auth.audited(conn, |conn| {
+        diesel::update(visits::table.find(*id))
+            .set(visits::cancelled_at.eq(Local::now().naive_local()))
+            .execute(conn)
+    })?;
+
+    Ok(Json(()))
+}
@@ -1,34 +1,96 @@
 use actix_web::{
-    delete,
-    error::ErrorForbidden,
-    get, post, put,
+    delete, get, post, put,
     web::{self, Json},
-    Responder, Scope,
+    HttpResponse, Responder, Scope,
+};
+use actix_web_grants::proc_macro::{has_any_permission, has_permissions};
+use diesel::{
+    insert_into, ExpressionMethods, NullableExpressionMethods, QueryDsl, RunQueryDsl,
+    SelectableHelper,
 };
-use actix_web_grants::proc_macro::{has_any_role, has_roles};
-use diesel::{insert_into, ExpressionMethods, QueryDsl, RunQueryDsl};
 
 use crate::{
-    auth::{Auth, Role},
+    auth::Auth,
     database::DbPool,
     error::{JsonError, Result},
-    models::{NewZone, UpdateZone, ZoneRecord},
-    schema::zones,
+    ical::{calendar, manager_center, Feed},
+    models::{Address, CalendarParam, MisplacedAddress, NewZone, UpdateZone, ZoneRecord},
+    schema::{addresses, nurses, patients, zones},
+    zoning::{self, Boundary},
 };
 
 #[derive(utoipa::OpenApi)]
 #[openapi(
-    paths(get, post, put, delete),
-    components(schemas(ZoneRecord, NewZone, UpdateZone, JsonError))
+    paths(misplaced, get, post, put, delete, ical),
+    components(schemas(ZoneRecord, NewZone, UpdateZone, Boundary, MisplacedAddress, JsonError))
 )]
 pub struct Doc;
 
 pub fn routes() -> Scope {
     web::scope("/zones")
+        .service(misplaced)
         .service(get)
         .service(post)
         .service(put)
         .service(delete)
+        .service(ical)
+}
+
+/// Misplaced addresses
+///
+/// Lists the addresses of the center of the current user whose zone does not cover them, either
+/// because another zone does or because their zone defines an area without them. Addresses are
+/// assigned to zones from their location when created, this finds the ones to move after the
+/// areas of the zones change.
+#[utoipa::path(
+    context_path = "/zones",
+    responses(
+        (status = 200, body = [MisplacedAddress]),
+    ),
+    tag = "zones",
+    security(
+        ("token" = ["manager"])
+    )
+)]
+#[get("/misplaced")]
+#[has_permissions("zones:read")]
+async fn misplaced(pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
+    let conn = &mut *pool.get()?;
+
+    let areas = zoning::areas(conn, auth.id_center)?;
+    let res: Vec<(Address, Option<i64>, Option<i64>)> = addresses::table
+        .inner_join(zones::table)
+        .left_join(nurses::table)
+        .left_join(patients::table)
+        .filter(zones::id_center.eq(auth.id_center))
+        .order(addresses::id)
+        .select((
+            Address::as_select(),
+            nurses::id.nullable(),
+            patients::id.nullable(),
+        ))
+        .load(conn)?;
+
+    let res: Vec<MisplacedAddress> = res
+        .into_iter()
+        .filter_map(|(address, id_nurse, id_patient)| {
+            let expected_zone = address.covering_zone(&areas);
+            let defined = areas
+                .iter()
+                .any(|z| z.id == address.id_zone && z.is_defined());
+
+            (expected_zone != Some(address.id_zone) && (expected_zone.is_some() || defined))
+                .then_some(MisplacedAddress {
+                    id_address: address.id,
+                    id_nurse,
+                    id_patient,
+                    id_zone: address.id_zone,
+                    expected_zone,
+                })
+        })
+        .collect();
+
+    Ok(Json(res))
 }
 
 #[utoipa::path(
@@ -43,13 +105,11 @@ pub fn routes() -> Scope {
     )
 )]
 #[get("/{id}")]
-#[has_any_role["Role::Manager", "Role::Nurse", type = "Role"]]
+#[has_any_permission("zones:read", "ROLE_NURSE")]
 async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     let zone: ZoneRecord = macros::get!(zones, pool, *id);
 
-    if zone.id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.same_center(zone.id_center)?;
 
     Ok(Json(zone))
 }
@@ -66,19 +126,23 @@ async fn get(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<
     )
 )]
 #[post("")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("zones:write")]
 async fn post(
     new_zone: web::Json<NewZone>,
     pool: web::Data<DbPool>,
     auth: Auth,
 ) -> Result<impl Responder> {
+    zoning::check(new_zone.boundary.as_ref(), &new_zone.postcodes)?;
+
     web::block(move || {
-        insert_into(zones::table)
-            .values(&NewZone {
-                id_center: auth.id_center,
-                ..new_zone.0
-            })
-            .execute(&mut pool.get().unwrap())
+        auth.audited(&mut pool.get().unwrap(), |conn| {
+            insert_into(zones::table)
+                .values(&NewZone {
+                    id_center: auth.id_center,
+                    ..new_zone.0
+                })
+                .execute(conn)
+        })
     })
     .await??;
 
@@ -98,7 +162,7 @@ async fn post(
     )
 )]
 #[put("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("zones:write")]
 async fn put(
     id: web::Path<i64>,
     update_zone: web::Json<UpdateZone>,
@@ -110,14 +174,18 @@ async fn put(
 
     let zone: ZoneRecord = macros::get!(zones, p2, id);
 
-    if zone.id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.same_center(zone.id_center)?;
+    zoning::check(
+        update_zone.boundary.as_ref().and_then(Option::as_ref),
+        update_zone.postcodes.as_deref().unwrap_or_default(),
+    )?;
 
-    diesel::update(zones::table)
-        .set(&update_zone.0)
-        .filter(zones::id.eq(id))
-        .execute(&mut pool.get()?)?;
+    auth.audited(&mut *pool.get()?, |conn| {
+        diesel::update(zones::table)
+            .set(&update_zone.0)
+            .filter(zones::id.eq(id))
+            .execute(conn)
+    })?;
 
     Ok(Json(()))
 }
@@ -134,18 +202,58 @@ async fn put(
     )
 )]
 #[delete("/{id}")]
-#[has_roles("Role::Manager", type = "Role")]
+#[has_permissions("zones:write")]
 async fn delete(id: web::Path<i64>, pool: web::Data<DbPool>, auth: Auth) -> Result<impl Responder> {
     let p2 = pool.clone();
     let id = *id;
 
     let zone: ZoneRecord = macros::get!(zones, p2, id);
 
-    if zone.id_center != auth.id_center {
-        return Err(ErrorForbidden("").into());
-    }
+    auth.same_center(zone.id_center)?;
 
-    macros::delete!(zones, pool, id);
+    macros::delete!(zones, pool, id, auth);
 
     Ok(Json(()))
 }
+
+/// Zone's calendar
+///
+/// Returns the visits of every patient of the zone as an iCalendar feed. The feed is protected by
+/// the calendar token of a manager of the center of the zone rather than the usual authentication
+/// cookie. A wrong token gives a `404`.
+#[utoipa::path(
+    context_path = "/zones",
+    params(CalendarParam),
+    responses(
+        (status = 200, body = String, description = "Icalendar data"),
+        (status = 400, body = JsonError),
+        (status = 404, body = JsonError)
+    ),
+    tag = "zones",
+    security(())
+)]
+#[get("/{id}/ical")]
+async fn ical(
+    id: web::Path<i64>,
+    query: web::Query<CalendarParam>,
+    pool: web::Data<DbPool>,
+) -> Result<impl Responder> {
+    let conn = &mut pool.get()?;
+
+    let zone: ZoneRecord = zones::table.find(*id).first(conn)?;
+
+    if manager_center(conn, &query.token)? != zone.id_center {
+        return Err(diesel::result::Error::NotFound.into());
+    }
+
+    let cal = calendar(
+        conn,
+        Feed::Zone(*id),
+        &format!("Planning {}", zone.name),
+        query.alarm,
+    )?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(cal.to_string()))
+}
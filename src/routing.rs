@@ -0,0 +1,266 @@
+//! Daily routes of nurses.
+//!
+//! The route orders the visits of a day to minimise the travel from the home of the nurse, using
+//! the matrix of the distances between every point. Visits keep their planned times, the route
+//! is a suggestion for a manager to reschedule them. Like [`crate::planning`], this works on data
+//! loaded beforehand.
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::geo::{self, Coordinates};
+
+/// A visit of the day.
+pub struct RouteVisit {
+    pub id_visit: i64,
+    /// Date and time the visit begins
+    pub start: NaiveDateTime,
+    /// Date and time the visit ends
+    pub end: NaiveDateTime,
+    /// Coordinates of the patient, if known
+    pub coordinates: Option<Coordinates>,
+}
+
+/// A visit along the route.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RouteStop {
+    pub id_visit: i64,
+    /// Date and time the visit begins
+    pub start: NaiveDateTime,
+    /// Date and time the visit ends
+    pub end: NaiveDateTime,
+    pub coordinates: Coordinates,
+    /// Straight distance from the previous stop in kilometers, from the home of the nurse for the
+    /// first one if known
+    pub distance: f64,
+    /// Estimated travel time from the previous stop
+    pub travel_minutes: i64,
+}
+
+/// Two consecutive visits too close in time for the travel between them.
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct TravelWarning {
+    pub id_from: i64,
+    pub id_to: i64,
+    /// Minutes between the end of the first visit and the start of the second
+    pub gap_minutes: i64,
+    /// Estimated travel time between the two visits
+    pub travel_minutes: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DailyRoute {
+    /// Visits in the order minimising the travel
+    pub stops: Vec<RouteStop>,
+    /// Estimated travel time of the whole route
+    pub travel_minutes: i64,
+    /// Visits which cannot follow each other as planned, in chronological order
+    pub warnings: Vec<TravelWarning>,
+    /// Visits whose patient address has no coordinates, left out of the route
+    pub unlocated: Vec<i64>,
+}
+
+/// Orders points to minimise the distance of the path starting at `start`.
+///
+/// The path is built going to the nearest point each time, then improved by reversing parts of
+/// it while this makes it shorter (2-opt).
+fn optimise(matrix: &[Vec<f64>], start: usize) -> Vec<usize> {
+    let n = matrix.len();
+    let mut order = vec![start];
+    let mut left: Vec<usize> = (0..n).filter(|&i| i != start).collect();
+
+    while let Some(&last) = order.last() {
+        let Some((pos, _)) = left
+            .iter()
+            .enumerate()
+            .min_by(|(_, &a), (_, &b)| matrix[last][a].total_cmp(&matrix[last][b]))
+        else {
+            break;
+        };
+        order.push(left.remove(pos));
+    }
+
+    let mut improved = true;
+    while improved {
+        improved = false;
+
+        for i in 1..n {
+            for j in i + 1..n {
+                let (a, b, c) = (order[i - 1], order[i], order[j]);
+                let next = order.get(j + 1);
+                let before = matrix[a][b] + next.map_or(0., |&d| matrix[c][d]);
+                let after = matrix[a][c] + next.map_or(0., |&d| matrix[b][d]);
+
+                if after + 1e-9 < before {
+                    order[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+
+    order
+}
+
+/// Builds the route of a nurse through the visits of a day.
+///
+/// Without the coordinates of the home of the nurse, the route begins with the first visit.
+pub fn route(home: Option<Coordinates>, mut visits: Vec<RouteVisit>) -> DailyRoute {
+    visits.sort_by_key(|v| (v.start, v.id_visit));
+
+    let unlocated = visits
+        .iter()
+        .filter(|v| v.coordinates.is_none())
+        .map(|v| v.id_visit)
+        .collect();
+    let located: Vec<(&RouteVisit, Coordinates)> = visits
+        .iter()
+        .filter_map(|v| Some((v, v.coordinates?)))
+        .collect();
+
+    let warnings = located
+        .windows(2)
+        .filter_map(|pair| {
+            let [(from, a), (to, b)] = pair else {
+                return None;
+            };
+            let gap_minutes = (to.start - from.end).num_minutes();
+            let travel_minutes = geo::travel_minutes(a.distance(b));
+
+            (gap_minutes < travel_minutes).then_some(TravelWarning {
+                id_from: from.id_visit,
+                id_to: to.id_visit,
+                gap_minutes,
+                travel_minutes,
+            })
+        })
+        .collect();
+
+    // The home of the nurse, if known, is the first point
+    let points: Vec<Coordinates> = home
+        .into_iter()
+        .chain(located.iter().map(|(_, c)| *c))
+        .collect();
+    let matrix: Vec<Vec<f64>> = points
+        .iter()
+        .map(|a| points.iter().map(|b| a.distance(b)).collect())
+        .collect();
+
+    let mut stops: Vec<RouteStop> = Vec::new();
+    let mut previous = None;
+    for i in optimise(&matrix, 0) {
+        let index = if home.is_some() {
+            i.checked_sub(1)
+        } else {
+            Some(i)
+        };
+        let Some((visit, coordinates)) = index.and_then(|i| located.get(i)) else {
+            // The home of the nurse
+            previous = Some(i);
+            continue;
+        };
+        let distance = previous.map_or(0., |p| matrix[p][i]);
+
+        stops.push(RouteStop {
+            id_visit: visit.id_visit,
+            start: visit.start,
+            end: visit.end,
+            coordinates: *coordinates,
+            distance,
+            travel_minutes: geo::travel_minutes(distance),
+        });
+        previous = Some(i);
+    }
+
+    DailyRoute {
+        travel_minutes: stops.iter().map(|s| s.travel_minutes).sum(),
+        stops,
+        warnings,
+        unlocated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn datetime(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 8)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    /// Points along a line, 0.1 degree of longitude being about 7.5 km at this latitude
+    fn point(x: f64) -> Coordinates {
+        Coordinates {
+            latitude: 47.6,
+            longitude: 6.8 + x / 10.,
+        }
+    }
+
+    fn visit(id_visit: i64, hour: u32, x: Option<f64>) -> RouteVisit {
+        RouteVisit {
+            id_visit,
+            start: datetime(hour),
+            end: datetime(hour) + chrono::Duration::minutes(30),
+            coordinates: x.map(point),
+        }
+    }
+
+    fn ids(route: &DailyRoute) -> Vec<i64> {
+        route.stops.iter().map(|s| s.id_visit).collect()
+    }
+
+    #[test]
+    fn shortest_order() {
+        let visits = vec![
+            visit(1, 8, Some(3.)),
+            visit(2, 10, Some(1.)),
+            visit(3, 12, Some(2.)),
+        ];
+        let route = route(Some(point(0.)), visits);
+
+        assert_eq!(ids(&route), [2, 3, 1]);
+        assert!(route.warnings.is_empty());
+        assert_eq!(route.stops[0].distance, point(0.).distance(&point(1.)));
+    }
+
+    /// Nearest neighbour alone goes back and forth, 2-opt fixes it
+    #[test]
+    fn two_opt() {
+        let points = [0., -3., 1., 5.];
+        let matrix: Vec<Vec<f64>> = points
+            .iter()
+            .map(|a| points.iter().map(|b| f64::abs(a - b)).collect())
+            .collect();
+
+        assert_eq!(optimise(&matrix, 0), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn without_home() {
+        let route = route(None, vec![visit(1, 8, Some(2.)), visit(2, 10, Some(0.))]);
+
+        assert_eq!(ids(&route), [1, 2]);
+        assert_eq!(route.stops[0].distance, 0.);
+    }
+
+    #[test]
+    fn unlocated_and_warnings() {
+        let visits = vec![
+            visit(1, 8, Some(0.)),
+            visit(2, 8, None),
+            visit(3, 9, Some(10.)),
+        ];
+        let route = route(None, visits);
+
+        assert_eq!(route.unlocated, [2]);
+        assert_eq!(route.warnings.len(), 1);
+        assert_eq!(route.warnings[0].gap_minutes, 30);
+        assert_eq!((route.warnings[0].id_from, route.warnings[0].id_to), (1, 3));
+    }
+}
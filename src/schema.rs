@@ -1,6 +1,21 @@
 // @generated automatically by Diesel CLI.
 
+/// A module containing custom SQL type definitions
+///
+/// (Automatically generated by Diesel.)
+pub mod sql_types {
+    /// The `pg_catalog.tsvector` SQL type
+    ///
+    /// (Automatically generated by Diesel.)
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     /// Representation of the `addresses` table.
     ///
     /// (Automatically generated by Diesel.)
@@ -47,6 +62,110 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         id_zone -> Int8,
+        /// The `latitude` column of the `addresses` table.
+        ///
+        /// Its SQL type is `Nullable<Float8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        latitude -> Nullable<Float8>,
+        /// The `longitude` column of the `addresses` table.
+        ///
+        /// Its SQL type is `Nullable<Float8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        longitude -> Nullable<Float8>,
+        /// The `search` column of the `addresses` table.
+        ///
+        /// Its SQL type is `Nullable<Tsvector>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search -> Nullable<Tsvector>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `audit_log` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    audit_log (id) {
+        /// The `id` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `id_user` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_user -> Nullable<Int8>,
+        /// The `role` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        role -> Nullable<Text>,
+        /// The `id_actor_center` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_actor_center -> Nullable<Int8>,
+        /// The `id_center` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_center -> Nullable<Int8>,
+        /// The `table_name` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        table_name -> Text,
+        /// The `id_record` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_record -> Nullable<Int8>,
+        /// The `action` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        action -> Text,
+        /// The `before` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        before -> Nullable<Jsonb>,
+        /// The `after` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        after -> Nullable<Jsonb>,
+        /// The `request_id` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        request_id -> Text,
+        /// The `correlation_id` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        correlation_id -> Nullable<Text>,
+        /// The `created_at` column of the `audit_log` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
     }
 }
 
@@ -88,6 +207,100 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Representation of the `calendar_tokens` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    calendar_tokens (id_user) {
+        /// The `id_user` column of the `calendar_tokens` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_user -> Int8,
+        /// The `token` column of the `calendar_tokens` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token -> Text,
+        /// The `created_at` column of the `calendar_tokens` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `cancelled_visits` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    cancelled_visits (id) {
+        /// The `id` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `id_visit` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_visit -> Int8,
+        /// The `id_nurse` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_nurse -> Nullable<Int8>,
+        /// The `start` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        start -> Timestamp,
+        /// The `end` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        end -> Timestamp,
+        /// The `summary` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        summary -> Text,
+        /// The `sequence` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        sequence -> Int4,
+        /// The `id_zone` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_zone -> Nullable<Int8>,
+        /// The `id_center` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_center -> Nullable<Int8>,
+        /// The `cancelled_at` column of the `cancelled_visits` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        cancelled_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `centers` table.
     ///
@@ -173,6 +386,49 @@ diesel::table! {
 }
 
 diesel::table! {
+    /// Representation of the `l_roles_permissions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    l_roles_permissions (id_role, permission) {
+        /// The `id_role` column of the `l_roles_permissions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_role -> Int8,
+        /// The `permission` column of the `l_roles_permissions` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        permission -> Text,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `l_users_roles` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    l_users_roles (id_user, id_role) {
+        /// The `id_user` column of the `l_users_roles` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_user -> Int8,
+        /// The `id_role` column of the `l_users_roles` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_role -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     /// Representation of the `l_visits_nurses` table.
     ///
     /// (Automatically generated by Diesel.)
@@ -195,6 +451,48 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         report -> Nullable<Text>,
+        /// The `conflicts` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Array<Nullable<Text>>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        conflicts -> Array<Nullable<Text>>,
+        /// The `signed_by` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        signed_by -> Nullable<Int8>,
+        /// The `signed_at` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        signed_at -> Nullable<Timestamp>,
+        /// The `report_data` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        report_data -> Nullable<Jsonb>,
+        /// The `arrived_at` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        arrived_at -> Nullable<Timestamp>,
+        /// The `left_at` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        left_at -> Nullable<Timestamp>,
+        /// The `search` column of the `l_visits_nurses` table.
+        ///
+        /// Its SQL type is `Nullable<Tsvector>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search -> Nullable<Tsvector>,
     }
 }
 
@@ -253,10 +551,19 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         minutes_duration -> Int4,
+        /// The `report_template` column of the `mission_types` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        report_template -> Nullable<Jsonb>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     /// Representation of the `missions` table.
     ///
     /// (Automatically generated by Diesel.)
@@ -321,6 +628,12 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         id_patient -> Int8,
+        /// The `search` column of the `missions` table.
+        ///
+        /// Its SQL type is `Nullable<Tsvector>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search -> Nullable<Tsvector>,
     }
 }
 
@@ -356,6 +669,38 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Representation of the `password_reset_tokens` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    password_reset_tokens (token) {
+        /// The `token` column of the `password_reset_tokens` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token -> Text,
+        /// The `id_user` column of the `password_reset_tokens` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_user -> Int8,
+        /// The `created_at` column of the `password_reset_tokens` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `expires_at` column of the `password_reset_tokens` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        expires_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `patients` table.
     ///
@@ -382,6 +727,184 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    /// Representation of the `permissions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    permissions (name) {
+        /// The `name` column of the `permissions` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `description` column of the `permissions` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `refresh_tokens` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    refresh_tokens (token) {
+        /// The `token` column of the `refresh_tokens` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token -> Text,
+        /// The `id_session` column of the `refresh_tokens` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_session -> Int8,
+        /// The `created_at` column of the `refresh_tokens` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `used_at` column of the `refresh_tokens` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `report_revisions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    report_revisions (id) {
+        /// The `id` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `id_visit` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_visit -> Int8,
+        /// The `id_nurse` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_nurse -> Int8,
+        /// The `id_author` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_author -> Nullable<Int8>,
+        /// The `content` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        content -> Text,
+        /// The `addendum` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        addendum -> Bool,
+        /// The `created_at` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `data` column of the `report_revisions` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        data -> Nullable<Jsonb>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `roles` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    roles (id) {
+        /// The `id` column of the `roles` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `name` column of the `roles` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `description` column of the `roles` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        description -> Nullable<Text>,
+        /// The `id_center` column of the `roles` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_center -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `sessions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    sessions (id) {
+        /// The `id` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `id_user` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id_user -> Int8,
+        /// The `created_at` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `expires_at` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        expires_at -> Timestamp,
+        /// The `revoked_at` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     /// Representation of the `skills` table.
     ///
@@ -403,6 +926,9 @@ diesel::table! {
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     /// Representation of the `users` table.
     ///
     /// (Automatically generated by Diesel.)
@@ -443,6 +969,12 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         password -> Nullable<Text>,
+        /// The `search` column of the `users` table.
+        ///
+        /// Its SQL type is `Nullable<Tsvector>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        search -> Nullable<Tsvector>,
     }
 }
 
@@ -475,6 +1007,24 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         id_mission -> Int8,
+        /// The `sequence` column of the `visits` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        sequence -> Int4,
+        /// The `updated_at` column of the `visits` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamp,
+        /// The `cancelled_at` column of the `visits` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        cancelled_at -> Nullable<Timestamp>,
     }
 }
 
@@ -501,16 +1051,37 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         id_center -> Int8,
+        /// The `boundary` column of the `zones` table.
+        ///
+        /// Its SQL type is `Nullable<Jsonb>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        boundary -> Nullable<Jsonb>,
+        /// The `postcodes` column of the `zones` table.
+        ///
+        /// Its SQL type is `Array<Nullable<Text>>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        postcodes -> Array<Nullable<Text>>,
     }
 }
 
 diesel::joinable!(addresses -> zones (id_zone));
 diesel::joinable!(availabilities -> nurses (id_nurse));
+diesel::joinable!(calendar_tokens -> users (id_user));
+diesel::joinable!(cancelled_visits -> centers (id_center));
+diesel::joinable!(cancelled_visits -> nurses (id_nurse));
+diesel::joinable!(cancelled_visits -> zones (id_zone));
 diesel::joinable!(l_missions_skills -> mission_types (id_mission_type));
 diesel::joinable!(l_missions_skills -> skills (id_skill));
 diesel::joinable!(l_nurses_skills -> nurses (id_nurse));
 diesel::joinable!(l_nurses_skills -> skills (id_skill));
+diesel::joinable!(l_roles_permissions -> permissions (permission));
+diesel::joinable!(l_roles_permissions -> roles (id_role));
+diesel::joinable!(l_users_roles -> roles (id_role));
+diesel::joinable!(l_users_roles -> users (id_user));
 diesel::joinable!(l_visits_nurses -> nurses (id_nurse));
+diesel::joinable!(l_visits_nurses -> users (signed_by));
 diesel::joinable!(l_visits_nurses -> visits (id_visit));
 diesel::joinable!(managers -> centers (id_center));
 diesel::joinable!(managers -> users (id_user));
@@ -518,23 +1089,39 @@ diesel::joinable!(missions -> mission_types (id_mission_type));
 diesel::joinable!(missions -> patients (id_patient));
 diesel::joinable!(nurses -> addresses (id_address));
 diesel::joinable!(nurses -> users (id_user));
+diesel::joinable!(password_reset_tokens -> users (id_user));
 diesel::joinable!(patients -> addresses (id_address));
 diesel::joinable!(patients -> users (id_user));
+diesel::joinable!(refresh_tokens -> sessions (id_session));
+diesel::joinable!(report_revisions -> users (id_author));
+diesel::joinable!(roles -> centers (id_center));
+diesel::joinable!(sessions -> users (id_user));
 diesel::joinable!(visits -> missions (id_mission));
 diesel::joinable!(zones -> centers (id_center));
 
 diesel::allow_tables_to_appear_in_same_query!(
     addresses,
+    audit_log,
     availabilities,
+    calendar_tokens,
+    cancelled_visits,
     centers,
     l_missions_skills,
     l_nurses_skills,
+    l_roles_permissions,
+    l_users_roles,
     l_visits_nurses,
     managers,
     mission_types,
     missions,
     nurses,
+    password_reset_tokens,
     patients,
+    permissions,
+    refresh_tokens,
+    report_revisions,
+    roles,
+    sessions,
     skills,
     users,
     visits,
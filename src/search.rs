@@ -0,0 +1,153 @@
+//! Full-text search across the records of a center.
+//!
+//! Names, addresses, mission descriptions and visit reports are indexed in `search` columns with
+//! the `french_unaccent` configuration, which ignores accents and stems French words. Names are
+//! also compared by trigrams so a misspelt name still finds the person. Each kind of record is
+//! searched on its own, the hits are then merged by rank.
+
+use diesel::{
+    infix_operator,
+    pg::Pg,
+    sql_function,
+    sql_types::{Float, Nullable, Text},
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::schema::sql_types::Tsvector;
+
+/// The `tsquery` SQL type, a parsed search.
+#[derive(diesel::sql_types::SqlType, diesel::query_builder::QueryId)]
+#[diesel(postgres_type(name = "tsquery"))]
+pub struct Tsquery;
+
+sql_function!(
+    /// Parses a search written by a user, such as `"soins palliatifs" -domicile`.
+    fn search_query(query: Text) -> Tsquery
+);
+
+sql_function!(
+    /// Excerpt of a document around the matches of a search, matches are put between `«` and `»`.
+    fn search_excerpt(document: Nullable<Text>, query: Tsquery) -> Nullable<Text>
+);
+
+sql_function!(
+    /// Lowercase text without accents, to be compared with [`search_name`].
+    fn search_unaccent(text: Text) -> Text
+);
+
+sql_function!(
+    /// Full name compared by trigrams, it is indexed on `users`.
+    fn search_name(fname: Text, lname: Text) -> Text
+);
+
+sql_function!(
+    /// See [the PostgreSQL ts_rank documentation](https://www.postgresql.org/docs/current/textsearch-controls.html#TEXTSEARCH-RANKING)
+    ///
+    /// The `search` columns are nullable as they are generated, they are never `NULL` though.
+    fn ts_rank(vector: Nullable<Tsvector>, query: Tsquery) -> Float
+);
+
+sql_function!(
+    /// See [the PostgreSQL word_similarity documentation](https://www.postgresql.org/docs/current/pgtrgm.html#PGTRGM-FUNCS-OPS)
+    fn word_similarity(word: Text, text: Text) -> Float
+);
+
+sql_function!(
+    /// See [the PostgreSQL greatest documentation](https://www.postgresql.org/docs/current/functions-conditional.html#FUNCTIONS-GREATEST-LEAST)
+    fn greatest(a: Float, b: Float, c: Float) -> Float
+);
+
+infix_operator!(Matches, " @@ ", backend: Pg);
+infix_operator!(Resembles, " %> ", backend: Pg);
+
+/// Kind of record a search hit is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HitKind {
+    Patient,
+    Nurse,
+    Mission,
+    Report,
+}
+
+/// A record matching a search.
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct Hit {
+    pub kind: HitKind,
+    /// ID of the patient, the nurse, the mission or the visit of the report
+    pub id: i64,
+    /// ID of the nurse who wrote the report, `None` for the other kinds
+    pub id_nurse: Option<i64>,
+    /// Name of the person, description of the mission or excerpt of the report
+    pub label: String,
+    /// Relevance of the hit, the higher the better
+    pub rank: f32,
+}
+
+/// Merges the hits of every kind, the best ranked first, keeping `limit` of them.
+///
+/// Equal ranks are ordered by kind then ID so the result is stable.
+pub fn merge(hits: impl IntoIterator<Item = Vec<Hit>>, limit: usize) -> Vec<Hit> {
+    let mut res: Vec<Hit> = hits.into_iter().flatten().collect();
+    res.sort_by(|a, b| {
+        b.rank
+            .total_cmp(&a.rank)
+            .then(a.kind.cmp(&b.kind))
+            .then(a.id.cmp(&b.id))
+    });
+    res.truncate(limit);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(kind: HitKind, id: i64, rank: f32) -> Hit {
+        Hit {
+            kind,
+            id,
+            id_nurse: None,
+            label: String::new(),
+            rank,
+        }
+    }
+
+    #[test]
+    fn merge_by_rank() {
+        let res = merge(
+            [
+                vec![hit(HitKind::Patient, 1, 0.9), hit(HitKind::Patient, 2, 0.1)],
+                vec![hit(HitKind::Report, 3, 0.5)],
+                vec![],
+            ],
+            10,
+        );
+
+        assert_eq!(
+            res.iter().map(|h| (h.kind, h.id)).collect::<Vec<_>>(),
+            [
+                (HitKind::Patient, 1),
+                (HitKind::Report, 3),
+                (HitKind::Patient, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_equal_ranks() {
+        let res = merge(
+            [
+                vec![hit(HitKind::Report, 1, 0.5)],
+                vec![hit(HitKind::Nurse, 4, 0.5), hit(HitKind::Nurse, 2, 0.5)],
+            ],
+            2,
+        );
+
+        assert_eq!(
+            res.iter().map(|h| (h.kind, h.id)).collect::<Vec<_>>(),
+            [(HitKind::Nurse, 2), (HitKind::Nurse, 4)]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Server-side sessions backing the authentication tokens.
+//!
+//! A session is opened on login and referenced by the `sid` claim of the JWT, revoking it
+//! invalidates the JWT before it expires. The session is kept alive with refresh tokens which
+//! are exchanged for a new JWT and a new refresh token. A refresh token can be used only once,
+//! presenting it again means it has leaked and the whole session is revoked.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    dsl::exists, insert_into, select, update, BoolExpressionMethods, Connection, ExpressionMethods,
+    OptionalExtension, PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+
+use crate::{
+    database::{hash_token, random_token},
+    schema::{refresh_tokens, sessions},
+};
+
+type Days = i64;
+
+/// Time a session stays open without being refreshed
+pub static REFRESH_VALIDITY: Days = 30;
+
+/// A session whose refresh token has been rotated.
+pub struct Refreshed {
+    /// Session ID
+    pub id: i64,
+    pub id_user: i64,
+    /// The new refresh token
+    pub refresh_token: String,
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Opens a session for a user, returning its ID and its first refresh token.
+pub fn open(conn: &mut PgConnection, id_user: i64) -> QueryResult<(i64, String)> {
+    conn.transaction(|conn| {
+        // Sessions of the user which cannot be used anymore
+        diesel::delete(sessions::table)
+            .filter(sessions::id_user.eq(id_user))
+            .filter(
+                sessions::revoked_at
+                    .is_not_null()
+                    .or(sessions::expires_at.le(now())),
+            )
+            .execute(conn)?;
+
+        let id = insert_into(sessions::table)
+            .values((
+                sessions::id_user.eq(id_user),
+                sessions::expires_at.eq(now() + Duration::days(REFRESH_VALIDITY)),
+            ))
+            .returning(sessions::id)
+            .get_result(conn)?;
+
+        Ok((id, new_refresh_token(conn, id)?))
+    })
+}
+
+fn new_refresh_token(conn: &mut PgConnection, id_session: i64) -> QueryResult<String> {
+    let token = random_token(conn)?;
+
+    insert_into(refresh_tokens::table)
+        .values((
+            refresh_tokens::token.eq(hash_token(&token)),
+            refresh_tokens::id_session.eq(id_session),
+        ))
+        .execute(conn)?;
+
+    Ok(token)
+}
+
+/// Exchanges a refresh token for a new one and extends the session.
+///
+/// Returns `None` if the token is unknown or its session is closed. If the token has already
+/// been used, its session is revoked.
+pub fn refresh(conn: &mut PgConnection, token: &str) -> QueryResult<Option<Refreshed>> {
+    conn.transaction(|conn| {
+        let Some((id, id_user, expires_at, revoked_at, used_at)) = refresh_tokens::table
+            .inner_join(sessions::table)
+            .filter(refresh_tokens::token.eq(hash_token(token)))
+            .select((
+                sessions::id,
+                sessions::id_user,
+                sessions::expires_at,
+                sessions::revoked_at,
+                refresh_tokens::used_at,
+            ))
+            .for_update()
+            .first::<(
+                i64,
+                i64,
+                NaiveDateTime,
+                Option<NaiveDateTime>,
+                Option<NaiveDateTime>,
+            )>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        if used_at.is_some() {
+            revoke(conn, id)?;
+            return Ok(None);
+        }
+
+        if revoked_at.is_some() || expires_at <= now() {
+            return Ok(None);
+        }
+
+        update(refresh_tokens::table)
+            .filter(refresh_tokens::token.eq(hash_token(token)))
+            .set(refresh_tokens::used_at.eq(now()))
+            .execute(conn)?;
+
+        update(sessions::table.find(id))
+            .set(sessions::expires_at.eq(now() + Duration::days(REFRESH_VALIDITY)))
+            .execute(conn)?;
+
+        Ok(Some(Refreshed {
+            id,
+            id_user,
+            refresh_token: new_refresh_token(conn, id)?,
+        }))
+    })
+}
+
+/// Checks a session is neither revoked nor expired.
+pub fn is_active(conn: &mut PgConnection, id: i64) -> QueryResult<bool> {
+    select(exists(
+        sessions::table
+            .find(id)
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(now())),
+    ))
+    .get_result(conn)
+}
+
+/// Revokes a session, returning the number of revoked sessions.
+pub fn revoke(conn: &mut PgConnection, id: i64) -> QueryResult<usize> {
+    update(sessions::table.find(id))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(now()))
+        .execute(conn)
+}
+
+/// Revokes every session of a user, returning the number of revoked sessions.
+pub fn revoke_all(conn: &mut PgConnection, id_user: i64) -> QueryResult<usize> {
+    update(sessions::table)
+        .filter(sessions::id_user.eq(id_user))
+        .filter(sessions::revoked_at.is_null())
+        .set(sessions::revoked_at.eq(now()))
+        .execute(conn)
+}
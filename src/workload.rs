@@ -0,0 +1,165 @@
+//! Weekly workload of nurses.
+//!
+//! The workload compares the time a nurse spends in visits during an ISO week to its contractual
+//! minutes. Performed visits count for their actual duration, from check-in to check-out, the
+//! others for their planned duration. Like [`crate::planning`], this works on data loaded
+//! beforehand.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A visit a nurse is assigned to.
+pub struct AssignedVisit {
+    /// Date and time the visit begins
+    pub start: NaiveDateTime,
+    /// Date and time the visit ends
+    pub end: NaiveDateTime,
+    /// Date and time the nurse actually arrived
+    pub arrived_at: Option<NaiveDateTime>,
+    /// Date and time the nurse actually left
+    pub left_at: Option<NaiveDateTime>,
+}
+
+impl AssignedVisit {
+    fn scheduled_minutes(&self) -> i64 {
+        (self.end - self.start).num_minutes()
+    }
+
+    /// Minutes actually spent, `None` until the nurse checked out.
+    fn actual_minutes(&self) -> Option<i64> {
+        Some((self.left_at? - self.arrived_at?).num_minutes())
+    }
+}
+
+/// Workload of a nurse during a week.
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct Workload {
+    pub id_nurse: i64,
+    pub fname: String,
+    pub lname: String,
+    /// ISO week, such as `2024-W02`
+    pub week: String,
+    /// Minutes of working time per week
+    pub contract_minutes: i64,
+    /// Planned duration of the visits of the week
+    pub scheduled_minutes: i64,
+    /// Actual duration of the visits performed during the week
+    pub actual_minutes: i64,
+    /// Contractual minutes left once the visits are done
+    pub remaining_minutes: i64,
+    /// Minutes beyond the contract
+    pub overtime_minutes: i64,
+    /// Share of the contract taken by the visits, `1.0` being a full week
+    pub utilisation: f64,
+}
+
+impl Workload {
+    /// Computes the workload of a nurse from the visits of a week, cancelled visits excluded.
+    pub fn new(
+        id_nurse: i64,
+        fname: String,
+        lname: String,
+        week: NaiveDate,
+        minutes_per_week: i32,
+        visits: &[AssignedVisit],
+    ) -> Self {
+        let contract_minutes = i64::from(minutes_per_week);
+        let scheduled_minutes = visits.iter().map(|v| v.scheduled_minutes()).sum();
+        let actual_minutes = visits.iter().filter_map(|v| v.actual_minutes()).sum();
+        let worked: i64 = visits
+            .iter()
+            .map(|v| v.actual_minutes().unwrap_or_else(|| v.scheduled_minutes()))
+            .sum();
+
+        Self {
+            id_nurse,
+            fname,
+            lname,
+            week: week.format("%G-W%V").to_string(),
+            contract_minutes,
+            scheduled_minutes,
+            actual_minutes,
+            remaining_minutes: (contract_minutes - worked).max(0),
+            overtime_minutes: (worked - contract_minutes).max(0),
+            utilisation: if contract_minutes > 0 {
+                worked as f64 / contract_minutes as f64
+            } else {
+                0.
+            },
+        }
+    }
+}
+
+/// Returns the start of the ISO week of a date and the start of the following week.
+pub fn week_bounds(date: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let from = (date - Duration::days(date.weekday().num_days_from_monday().into()))
+        .and_time(NaiveTime::MIN);
+
+    (from, from + Duration::weeks(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn visit(day: u32, hours: u32) -> AssignedVisit {
+        AssignedVisit {
+            start: datetime(day, 8, 0),
+            end: datetime(day, 8 + hours, 0),
+            arrived_at: None,
+            left_at: None,
+        }
+    }
+
+    fn workload(minutes_per_week: i32, visits: &[AssignedVisit]) -> Workload {
+        let week = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        Workload::new(1, "A".into(), "B".into(), week, minutes_per_week, visits)
+    }
+
+    #[test]
+    fn remaining() {
+        let w = workload(600, &[visit(8, 2), visit(9, 3)]);
+
+        assert_eq!(w.week, "2024-W02");
+        assert_eq!(w.scheduled_minutes, 300);
+        assert_eq!(w.remaining_minutes, 300);
+        assert_eq!(w.overtime_minutes, 0);
+        assert_eq!(w.utilisation, 0.5);
+    }
+
+    /// Performed visits count for their actual duration
+    #[test]
+    fn overtime() {
+        let mut performed = visit(8, 2);
+        performed.arrived_at = Some(datetime(8, 8, 10));
+        performed.left_at = Some(datetime(8, 11, 10));
+        let w = workload(240, &[performed, visit(9, 2)]);
+
+        assert_eq!(w.scheduled_minutes, 240);
+        assert_eq!(w.actual_minutes, 180);
+        assert_eq!(w.remaining_minutes, 0);
+        assert_eq!(w.overtime_minutes, 60);
+        assert_eq!(w.utilisation, 1.25);
+    }
+
+    #[test]
+    fn no_contract() {
+        assert_eq!(workload(0, &[visit(8, 1)]).utilisation, 0.);
+    }
+
+    #[test]
+    fn bounds() {
+        let (from, to) = week_bounds(NaiveDate::from_ymd_opt(2024, 1, 14).unwrap());
+
+        assert_eq!(from, datetime(8, 0, 0));
+        assert_eq!(to, datetime(15, 0, 0));
+    }
+}
@@ -0,0 +1,230 @@
+//! Assigns addresses to zones.
+//!
+//! A zone covers the area inside its [`Boundary`], a GeoJSON polygon, and the postcodes it lists.
+//! Addresses given without a zone are assigned the one of their center covering their
+//! coordinates, or else their postcode. When several zones match, the one created first wins.
+
+use actix_web::error::ErrorBadRequest;
+use diesel::{
+    deserialize::FromSqlRow, expression::AsExpression, sql_types::Jsonb, ExpressionMethods,
+    PgConnection, QueryDsl, QueryResult, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{database::jsonb, error::Result, geo::Coordinates, schema::zones};
+
+/// A position as `[longitude, latitude]`, in this order like GeoJSON.
+type Position = [f64; 2];
+
+/// Closed line, its last position being the first one.
+type Ring = Vec<Position>;
+
+/// GeoJSON geometry of a zone.
+///
+/// The first ring of a polygon is its outline, the following ones are holes in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Jsonb)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum Boundary {
+    #[schema(value_type = Vec<Vec<Vec<f64>>>)]
+    Polygon(Vec<Ring>),
+    #[schema(value_type = Vec<Vec<Vec<Vec<f64>>>>)]
+    MultiPolygon(Vec<Vec<Ring>>),
+}
+
+jsonb!(Boundary);
+
+impl Boundary {
+    fn polygons(&self) -> &[Vec<Ring>] {
+        match self {
+            Boundary::Polygon(polygon) => std::slice::from_ref(polygon),
+            Boundary::MultiPolygon(polygons) => polygons,
+        }
+    }
+
+    /// Checks the boundary is made of closed rings of valid positions.
+    pub fn check(&self) -> Result<()> {
+        let valid = |ring: &Ring| {
+            ring.len() >= 4
+                && ring.first() == ring.last()
+                && ring
+                    .iter()
+                    .all(|[lon, lat]| (-180. ..=180.).contains(lon) && (-90. ..=90.).contains(lat))
+        };
+
+        if self
+            .polygons()
+            .iter()
+            .all(|polygon| !polygon.is_empty() && polygon.iter().all(valid))
+        {
+            Ok(())
+        } else {
+            Err(ErrorBadRequest(
+                "The boundary must be made of closed rings of at least 4 valid positions",
+            )
+            .into())
+        }
+    }
+
+    pub fn contains(&self, point: &Coordinates) -> bool {
+        self.polygons().iter().any(|polygon| {
+            let mut rings = polygon.iter();
+            rings.next().is_some_and(|outline| inside(outline, point))
+                && !rings.any(|hole| inside(hole, point))
+        })
+    }
+}
+
+/// Whether a point is inside a ring, counting how many of its edges a ray from the point crosses.
+fn inside(ring: &Ring, point: &Coordinates) -> bool {
+    let (x, y) = (point.longitude, point.latitude);
+
+    ring.windows(2)
+        .filter(|edge| {
+            let [[x1, y1], [x2, y2]] = [edge[0], edge[1]];
+            (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1)
+        })
+        .count()
+        % 2
+        == 1
+}
+
+/// Checks the area of a zone can be saved.
+pub fn check(boundary: Option<&Boundary>, postcodes: &[String]) -> Result<()> {
+    if postcodes.iter().any(|p| p.trim().is_empty()) {
+        return Err(ErrorBadRequest("The postcodes of a zone cannot be empty").into());
+    }
+
+    boundary.map_or(Ok(()), Boundary::check)
+}
+
+/// Area covered by a zone.
+pub struct ZoneArea {
+    pub id: i64,
+    pub boundary: Option<Boundary>,
+    pub postcodes: Vec<Option<String>>,
+}
+
+impl ZoneArea {
+    fn has_postcode(&self, postcode: &str) -> bool {
+        self.postcodes
+            .iter()
+            .flatten()
+            .any(|p| p.trim() == postcode.trim())
+    }
+
+    /// Whether the zone defines an area at all.
+    pub fn is_defined(&self) -> bool {
+        self.boundary.is_some() || self.postcodes.iter().flatten().next().is_some()
+    }
+}
+
+/// Loads the areas of the zones of a center, oldest first.
+pub fn areas(conn: &mut PgConnection, id_center: i64) -> QueryResult<Vec<ZoneArea>> {
+    let res: Vec<(i64, Option<Boundary>, Vec<Option<String>>)> = zones::table
+        .filter(zones::id_center.eq(id_center))
+        .order(zones::id)
+        .select((zones::id, zones::boundary, zones::postcodes))
+        .load(conn)?;
+
+    Ok(res
+        .into_iter()
+        .map(|(id, boundary, postcodes)| ZoneArea {
+            id,
+            boundary,
+            postcodes,
+        })
+        .collect())
+}
+
+/// Finds the zone covering an address, from its coordinates first then from its postcode.
+pub fn find(zones: &[ZoneArea], postcode: &str, coordinates: Option<Coordinates>) -> Option<i64> {
+    coordinates
+        .and_then(|point| {
+            zones
+                .iter()
+                .find(|z| z.boundary.as_ref().is_some_and(|b| b.contains(&point)))
+        })
+        .or_else(|| zones.iter().find(|z| z.has_postcode(postcode)))
+        .map(|z| z.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square of side 1 with its lower left corner at `(x, y)`
+    fn square(x: f64, y: f64) -> Ring {
+        vec![[x, y], [x + 1., y], [x + 1., y + 1.], [x, y + 1.], [x, y]]
+    }
+
+    fn point(longitude: f64, latitude: f64) -> Coordinates {
+        Coordinates {
+            latitude,
+            longitude,
+        }
+    }
+
+    fn zones() -> Vec<ZoneArea> {
+        vec![
+            ZoneArea {
+                id: 1,
+                boundary: Some(Boundary::Polygon(vec![square(0., 0.)])),
+                postcodes: vec![],
+            },
+            ZoneArea {
+                id: 2,
+                boundary: None,
+                postcodes: vec![Some("90000".into())],
+            },
+        ]
+    }
+
+    #[test]
+    fn holes() {
+        let mut outline = square(0., 0.);
+        for p in &mut outline {
+            p[0] *= 3.;
+            p[1] *= 3.;
+        }
+        let boundary = Boundary::Polygon(vec![outline, square(1., 1.)]);
+
+        assert!(boundary.contains(&point(0.5, 0.5)));
+        assert!(!boundary.contains(&point(1.5, 1.5)));
+        assert!(!boundary.contains(&point(3.5, 0.5)));
+    }
+
+    #[test]
+    fn multi_polygon() {
+        let boundary = Boundary::MultiPolygon(vec![vec![square(0., 0.)], vec![square(5., 5.)]]);
+
+        assert!(boundary.contains(&point(5.5, 5.5)));
+        assert!(!boundary.contains(&point(2.5, 2.5)));
+    }
+
+    #[test]
+    fn geojson() {
+        let boundary: Boundary = serde_json::from_str(
+            r#"{"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}"#,
+        )
+        .unwrap();
+
+        assert!(boundary.check().is_ok());
+        assert!(Boundary::Polygon(vec![square(0., 0.)[..4].to_vec()])
+            .check()
+            .is_err());
+        assert!(Boundary::Polygon(vec![]).check().is_err());
+    }
+
+    /// Coordinates take precedence over postcodes
+    #[test]
+    fn find_zone() {
+        let zones = zones();
+
+        assert_eq!(find(&zones, "90000", Some(point(0.5, 0.5))), Some(1));
+        assert_eq!(find(&zones, " 90000", Some(point(2., 2.))), Some(2));
+        assert_eq!(find(&zones, "90000", None), Some(2));
+        assert_eq!(find(&zones, "25000", Some(point(2., 2.))), None);
+    }
+}
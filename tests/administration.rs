@@ -0,0 +1,266 @@
+//! Checks administrators manage every center and managers only their own.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, insert, pool, request, seed_center};
+use diesel::{sql_query, RunQueryDsl};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn admin_creates_center_and_its_manager() {
+    let pool = pool();
+    let (admin, other) = {
+        let conn = &mut pool.get().unwrap();
+        let admin = seed_center(conn, "admin");
+        sql_query(format!(
+            "INSERT INTO l_users_roles (id_user, id_role) \
+             SELECT managers.id_user, roles.id FROM managers, roles \
+             WHERE managers.id = {} AND roles.name = 'admin' AND roles.id_center IS NULL",
+            admin.manager
+        ))
+        .execute(conn)
+        .unwrap();
+        (admin, seed_center(conn, "other"))
+    };
+    let app = app!(pool.clone());
+
+    let token = cookie(
+        &login!(app, "admin-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::POST,
+            "/api/centers",
+            Some(json!({
+                "name": "admin-new",
+                "workday_start": "08:00:00",
+                "workday_end": "18:00:00"
+            })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let center: Value = test::read_body_json(res).await;
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::POST,
+            "/api/managers",
+            Some(json!({
+                "id_center": center["id"],
+                "fname": "First",
+                "lname": "Manager",
+                "mail": "admin-first@isolation.test"
+            })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::PUT,
+            &format!("/api/managers/{}/center", other.manager),
+            Some(json!({ "id_center": center["id"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::GET,
+            &format!("/api/centers/{}/managers", center["id"]),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 2);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::DELETE,
+            &format!("/api/centers/{}", admin.center),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // The history of the reports written in a center is kept
+    sql_query(format!(
+        "WITH assignment AS (
+            INSERT INTO l_visits_nurses (id_visit, id_nurse) VALUES ({0}, {1})
+            RETURNING id_visit, id_nurse
+        )
+        INSERT INTO report_revisions (id_visit, id_nurse, content)
+        SELECT id_visit, id_nurse, 'Written' FROM assignment",
+        other.visit, other.nurse
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::DELETE,
+            &format!("/api/centers/{}", other.center),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+
+    // The users of a deleted center go with it
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::DELETE,
+            &format!("/api/centers/{}", center["id"]),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let left = insert(
+        &mut pool.get().unwrap(),
+        "SELECT count(*) AS id FROM users \
+         WHERE mail IN ('admin-first@isolation.test', 'other-manager@isolation.test')",
+    );
+    assert_eq!(left, 0);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn manager_cannot_administrate() {
+    let pool = pool();
+    let (own, other) = {
+        let conn = &mut pool.get().unwrap();
+        (seed_center(conn, "admin"), seed_center(conn, "other"))
+    };
+    let app = app!(pool);
+
+    let token = cookie(
+        &login!(app, "admin-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    for (method, uri, body) in [
+        (
+            Method::POST,
+            "/api/centers".to_string(),
+            json!({ "name": "x", "workday_start": "08:00:00", "workday_end": "18:00:00" }),
+        ),
+        (
+            Method::PUT,
+            format!("/api/centers/{}", own.center),
+            json!({ "name": "x" }),
+        ),
+        (
+            Method::PUT,
+            format!("/api/managers/{}/center", own.manager),
+            json!({ "id_center": other.center }),
+        ),
+        (
+            Method::POST,
+            "/api/managers".to_string(),
+            json!({ "id_center": other.center, "fname": "x", "lname": "x", "mail": "x@x" }),
+        ),
+    ] {
+        let res =
+            test::call_service(&app, request(&token, method, &uri, Some(body)).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn manager_cannot_take_over_admin() {
+    let pool = pool();
+    let (own, admin) = {
+        let conn = &mut pool.get().unwrap();
+        let own = seed_center(conn, "takeover");
+        let id_user = insert(
+            conn,
+            "INSERT INTO users (fname, lname, mail) \
+             VALUES ('takeover', 'admin', 'takeover-admin@isolation.test') RETURNING id",
+        );
+        let admin = insert(
+            conn,
+            &format!(
+                "INSERT INTO managers (id_user, id_center) VALUES ({id_user}, {}) RETURNING id",
+                own.center
+            ),
+        );
+        sql_query(format!(
+            "INSERT INTO l_users_roles (id_user, id_role) \
+             SELECT {id_user}, id FROM roles WHERE name = 'admin' AND id_center IS NULL"
+        ))
+        .execute(conn)
+        .unwrap();
+        (own, admin)
+    };
+    let app = app!(pool);
+
+    let token = cookie(
+        &login!(app, "takeover-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    for (method, uri) in [
+        (
+            Method::POST,
+            format!("/api/managers/{admin}/password/reset"),
+        ),
+        (Method::DELETE, format!("/api/managers/{admin}")),
+    ] {
+        let res = test::call_service(&app, request(&token, method, &uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    // Managers with the same permissions are still managed
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::POST,
+            &format!("/api/managers/{}/password/reset", own.manager),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
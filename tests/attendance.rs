@@ -0,0 +1,195 @@
+//! Checks nurses record their actual times and managers see the late and missed visits.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use chrono::{Duration, Local};
+use common::{cookie, pool, request, seed_center};
+use diesel::RunQueryDsl;
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn nurses_check_in_and_out() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "attendance");
+    let app = app!(pool.clone());
+
+    let manager = cookie(
+        &login!(app, "attendance-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "attendance-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let visit = format!("/api/visits/{}", own.visit);
+    let format = |date: chrono::NaiveDateTime| date.format("%Y-%m-%dT%H:%M:%S").to_string();
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::POST, &format!("{visit}/check-in"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // The nurse cannot be assigned twice
+    for status in [StatusCode::OK, StatusCode::CONFLICT] {
+        let res = test::call_service(
+            &app,
+            request(
+                &manager,
+                Method::POST,
+                &format!("{visit}/nurses/{}?force=true", own.nurse),
+                None,
+            )
+            .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), status);
+    }
+
+    // The visit is years away
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::POST, &format!("{visit}/check-in"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let now = Local::now().naive_local();
+    diesel::sql_query(format!(
+        "UPDATE visits SET start = '{}', \"end\" = '{}' WHERE id = {}",
+        now - Duration::minutes(5),
+        now + Duration::minutes(25),
+        own.visit
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+
+    for (action, status) in [
+        ("check-out", StatusCode::BAD_REQUEST),
+        ("check-in", StatusCode::OK),
+        ("check-in", StatusCode::BAD_REQUEST),
+    ] {
+        let res = test::call_service(
+            &app,
+            request(&nurse, Method::POST, &format!("{visit}/{action}"), None).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), status, "{action}");
+    }
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::POST, &format!("{visit}/cancel"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let attendance = format!(
+        "/api/visits/attendance?all=true&from={}&until={}",
+        format(now - Duration::days(1)),
+        format(now + Duration::days(1))
+    );
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, &attendance, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["data"][0]["status"], "in_progress");
+    assert!(body["data"][0]["nurses"][0]["arrived_at"].is_string());
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::POST, &format!("{visit}/check-out"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, &attendance, None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["data"][0]["status"], "done");
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn missed_visits_are_listed_until_cancelled() {
+    let pool = pool();
+    let own = {
+        let conn = &mut pool.get().unwrap();
+        seed_center(conn, "other");
+        seed_center(conn, "attendance")
+    };
+    diesel::sql_query(format!(
+        "UPDATE visits SET start = '2020-01-06 08:00', \"end\" = '2020-01-06 08:30' WHERE id = {}",
+        own.visit
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "attendance-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let other = cookie(
+        &login!(app, "other-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let attendance = "/api/visits/attendance?from=2020-01-01T00:00:00";
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, attendance, None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["data"][0]["id"], own.visit);
+    assert_eq!(body["data"][0]["status"], "missed");
+
+    let cancel = format!("/api/visits/{}/cancel", own.visit);
+    let res = test::call_service(
+        &app,
+        request(&other, Method::POST, &cancel, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::POST, &cancel, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, attendance, None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 0);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::POST, &cancel, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,147 @@
+//! Checks the changes made through the API are recorded with their author.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, id_user, pool, request, seed_center};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn report_history_is_recorded() {
+    let pool = pool();
+    let (own, nurse_user) = {
+        let conn = &mut pool.get().unwrap();
+        seed_center(conn, "other");
+        let own = seed_center(conn, "audit");
+        let nurse_user = id_user(conn, "nurses", own.nurse);
+        (own, nurse_user)
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "audit-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "audit-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let other = cookie(
+        &login!(app, "other-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &format!("/api/visits/{}/report", own.visit),
+            Some(json!({ "report": "All good" })),
+        )
+        .insert_header(("X-Request-Id", "proxy-42"))
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let uri = format!("/api/audit?table=l_visits_nurses&record={}", own.visit);
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, &uri, None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 2);
+
+    let update = &body["data"][0];
+    assert_eq!(update["action"], "update");
+    assert_eq!(update["id_user"], nurse_user);
+    assert_eq!(update["role"], "Nurse");
+    assert_eq!(update["before"], json!({ "report": null }));
+    assert_eq!(update["after"], json!({ "report": "All good" }));
+    assert_eq!(update["id_center"], own.center);
+    assert_eq!(update["id_actor_center"], own.center);
+    // The server generates the ID of the request, the one of the client is kept aside
+    assert_eq!(update["correlation_id"], "proxy-42");
+    assert_eq!(update["request_id"].as_str().unwrap().len(), 36);
+    assert_eq!(body["data"][1]["action"], "insert");
+
+    let res = test::call_service(&app, request(&other, Method::GET, &uri, None).to_request()).await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 0);
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::GET, "/api/audit", None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn passwords_are_masked() {
+    let pool = pool();
+    let user = {
+        let conn = &mut pool.get().unwrap();
+        let own = seed_center(conn, "audit");
+        id_user(conn, "managers", own.manager)
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "audit-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            "/api/auth/password",
+            Some(json!({ "current": "pass", "new": "Correct horse 1" })),
+        )
+        .to_request(),
+    )
+    .await;
+    let manager = cookie(&res, COOKIE_TOKEN_NAME);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::GET,
+            &format!("/api/audit?table=users&record={user}"),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["data"][0]["after"], json!({ "password": "********" }));
+}
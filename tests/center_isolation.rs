@@ -0,0 +1,169 @@
+//! Checks a manager cannot reach the records of another center.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    dev::ServiceResponse,
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth;
+use common::{cookie, pool, request, seed_center};
+use serde_json::{json, Value};
+
+/// Sets up two centers and gives the app, the token cookie of the first center's manager and the
+/// records of the second center.
+macro_rules! setup {
+    () => {{
+        let pool = pool();
+        let other = {
+            let conn = &mut pool.get().unwrap();
+            seed_center(conn, "own");
+            seed_center(conn, "other")
+        };
+
+        let app = app!(pool);
+        let res = login!(app, "own-manager@isolation.test");
+
+        (app, cookie(&res, auth::COOKIE_TOKEN_NAME), other)
+    }};
+}
+
+fn assert_denied(res: &ServiceResponse, uri: &str) {
+    assert!(
+        matches!(res.status(), StatusCode::FORBIDDEN | StatusCode::NOT_FOUND),
+        "{uri} gave {}",
+        res.status()
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn reading_other_center_is_denied() {
+    let (app, cookie, other) = setup!();
+
+    for uri in [
+        format!("/api/centers/{}", other.center),
+        format!("/api/centers/{}/zones", other.center),
+        format!("/api/zones/{}", other.zone),
+        format!("/api/managers/{}", other.manager),
+        format!("/api/nurses/{}", other.nurse),
+        format!("/api/nurses/{}/availabilities", other.nurse),
+        format!("/api/nurses/{}/reports", other.nurse),
+        format!("/api/patients/{}", other.patient),
+        format!("/api/missions/{}", other.mission),
+        format!("/api/visits/{}", other.visit),
+        format!("/api/visits/{}/nurses", other.visit),
+        format!("/api/visits/{}/reports", other.visit),
+    ] {
+        let res =
+            test::call_service(&app, request(&cookie, Method::GET, &uri, None).to_request()).await;
+        assert_denied(&res, &uri);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn writing_other_center_is_denied() {
+    let (app, cookie, other) = setup!();
+
+    let address = json!({
+        "street_name": "rue",
+        "postcode": "90000",
+        "city_name": "Belfort",
+        "id_zone": other.zone
+    });
+    for (method, uri, body) in [
+        (
+            Method::POST,
+            "/api/patients".to_string(),
+            Some(json!({
+                "fname": "New",
+                "lname": "User",
+                "mail": "new@isolation.test",
+                "password": "pass",
+                "address": address
+            })),
+        ),
+        (
+            Method::POST,
+            "/api/visits".to_string(),
+            Some(json!({
+                "start": "2030-01-08T08:00:00",
+                "end": "2030-01-08T08:30:00",
+                "id_mission": other.mission
+            })),
+        ),
+        (
+            Method::PUT,
+            format!("/api/visits/{}", other.visit),
+            Some(json!({})),
+        ),
+        (
+            Method::PUT,
+            format!("/api/zones/{}", other.zone),
+            Some(json!({ "name": "Mine" })),
+        ),
+        (Method::DELETE, format!("/api/visits/{}", other.visit), None),
+        (
+            Method::DELETE,
+            format!("/api/missions/{}", other.mission),
+            None,
+        ),
+        (
+            Method::DELETE,
+            format!("/api/patients/{}", other.patient),
+            None,
+        ),
+        (Method::DELETE, format!("/api/nurses/{}", other.nurse), None),
+        (
+            Method::DELETE,
+            format!("/api/managers/{}", other.manager),
+            None,
+        ),
+    ] {
+        let res = test::call_service(&app, request(&cookie, method, &uri, body).to_request()).await;
+        assert_denied(&res, &uri);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn lists_only_contain_own_center() {
+    let (app, cookie, _) = setup!();
+
+    for uri in [
+        "/api/managers?search=isolation",
+        "/api/nurses?search=isolation",
+        "/api/patients?search=isolation",
+    ] {
+        let res =
+            test::call_service(&app, request(&cookie, Method::GET, uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: Value = test::read_body_json(res).await;
+        let data = body["data"].as_array().unwrap();
+        assert_eq!(body["total"], 1, "{uri}");
+        assert!(
+            data[0].to_string().contains("own-"),
+            "{uri} gave {}",
+            data[0]
+        );
+    }
+
+    let res = test::call_service(
+        &app,
+        request(&cookie, Method::GET, "/api/missions", None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert!(body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|m| m.to_string().contains("own-")));
+}
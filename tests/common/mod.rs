@@ -0,0 +1,233 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Tests using a database need a PostgreSQL database given by `DATABASE_URL`. Everything runs
+//! inside a test transaction which is never committed, so the database is left untouched.
+
+// Every test file does not use every fixture
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use actix_web::{cookie::Cookie, dev::ServiceResponse, http::Method, test::TestRequest};
+use backend::{auth, database};
+use diesel::{
+    r2d2::{ConnectionManager, CustomizeConnection, Pool},
+    sql_query,
+    sql_types::BigInt,
+    Connection, PgConnection, QueryableByName, RunQueryDsl,
+};
+use serde_json::Value;
+
+#[derive(QueryableByName)]
+struct Id {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+}
+
+/// IDs of the records created in a center.
+pub struct Seeded {
+    pub center: i64,
+    pub zone: i64,
+    pub manager: i64,
+    pub nurse: i64,
+    pub patient: i64,
+    pub mission: i64,
+    pub visit: i64,
+}
+
+/// Opens a test transaction on every connection of the pool.
+#[derive(Debug)]
+struct TestTransaction;
+
+impl CustomizeConnection<PgConnection, diesel::r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub fn pool() -> database::DbPool {
+    std::env::set_var("JWT_SECRET", "integration");
+    // Every test registers the same secret, only the first one succeeds.
+    let _ = auth::initialize_jwt();
+
+    let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL env var should be set");
+
+    // A single connection, so every request sees the uncommitted test transaction
+    let pool = Pool::builder()
+        .max_size(1)
+        .connection_timeout(Duration::from_secs(5))
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::<PgConnection>::new(db_url))
+        .expect("Unable to connect to database");
+
+    database::run_migrations(&mut pool.get().unwrap()).expect("Unable to run migrations");
+
+    pool
+}
+
+pub fn insert(conn: &mut PgConnection, query: &str) -> i64 {
+    sql_query(query).get_result::<Id>(conn).unwrap().id
+}
+
+/// Returns the user ID of a manager, nurse or patient, `table` being its table.
+pub fn id_user(conn: &mut PgConnection, table: &str, id: i64) -> i64 {
+    insert(
+        conn,
+        &format!("SELECT id_user AS id FROM {table} WHERE id = {id}"),
+    )
+}
+
+/// Creates a center with a zone, a manager, a nurse, a patient, a mission and a visit.
+///
+/// Users are given the `<prefix>-<role>@isolation.test` mail and `pass` password.
+pub fn seed_center(conn: &mut PgConnection, prefix: &str) -> Seeded {
+    let user = |conn: &mut PgConnection, role: &str| {
+        insert(
+            conn,
+            &format!(
+                "INSERT INTO users (fname, lname, mail, password) \
+                 VALUES ('{prefix}', '{role}', '{prefix}-{role}@isolation.test', \
+                 crypt('pass', gen_salt('bf'))) RETURNING id"
+            ),
+        )
+    };
+
+    let center = insert(
+        conn,
+        &format!(
+            "INSERT INTO centers (name, workday_start, workday_end) \
+             VALUES ('{prefix}', '08:00', '18:00') RETURNING id"
+        ),
+    );
+    let zone = insert(
+        conn,
+        &format!("INSERT INTO zones (name, id_center) VALUES ('{prefix}', {center}) RETURNING id"),
+    );
+    let address = |conn: &mut PgConnection| {
+        insert(
+            conn,
+            &format!(
+                "INSERT INTO addresses (street_name, postcode, city_name, id_zone) \
+                 VALUES ('rue', '90000', 'Belfort', {zone}) RETURNING id"
+            ),
+        )
+    };
+
+    let id_user = user(conn, "manager");
+    let manager = insert(
+        conn,
+        &format!(
+            "INSERT INTO managers (id_user, id_center) VALUES ({id_user}, {center}) RETURNING id"
+        ),
+    );
+
+    let (id_user, id_address) = (user(conn, "nurse"), address(conn));
+    let nurse = insert(
+        conn,
+        &format!(
+            "INSERT INTO nurses (minutes_per_week, id_user, id_address) \
+             VALUES (2100, {id_user}, {id_address}) RETURNING id"
+        ),
+    );
+
+    let (id_user, id_address) = (user(conn, "patient"), address(conn));
+    let patient = insert(
+        conn,
+        &format!(
+            "INSERT INTO patients (id_user, id_address) \
+             VALUES ({id_user}, {id_address}) RETURNING id"
+        ),
+    );
+
+    let mission_type = insert(
+        conn,
+        &format!(
+            "INSERT INTO mission_types (name, people_required, minutes_duration) \
+             VALUES ('{prefix}', 1, 30) RETURNING id"
+        ),
+    );
+    let mission = insert(
+        conn,
+        &format!(
+            "INSERT INTO missions (start, \"end\", people_required, minutes_duration, \
+             id_mission_type, id_patient) VALUES ('2030-01-07 08:00', '2030-01-07 12:00', 1, 30, \
+             {mission_type}, {patient}) RETURNING id"
+        ),
+    );
+    let visit = insert(
+        conn,
+        &format!(
+            "INSERT INTO visits (start, \"end\", id_mission) \
+             VALUES ('2030-01-07 08:00', '2030-01-07 08:30', {mission}) RETURNING id"
+        ),
+    );
+
+    Seeded {
+        center,
+        zone,
+        manager,
+        nurse,
+        patient,
+        mission,
+        visit,
+    }
+}
+
+/// Builds the app using the given pool.
+macro_rules! app {
+    ($pool:expr) => {
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::new($pool))
+                .wrap(actix_web_grants::GrantsMiddleware::with_extractor(
+                    backend::auth::extract_permissions,
+                ))
+                .service(backend::routes::api()),
+        )
+        .await
+    };
+}
+
+/// Logs a user in with the `pass` password and gives the response.
+macro_rules! login {
+    ($app:expr, $mail:expr) => {{
+        let res = actix_web::test::call_service(
+            &$app,
+            actix_web::test::TestRequest::post()
+                .uri("/api/auth/login")
+                .set_json(serde_json::json!({ "mail": $mail, "password": "pass" }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        res
+    }};
+}
+
+/// Gives the cookie with the given name set by a response.
+pub fn cookie(res: &ServiceResponse, name: &str) -> Cookie<'static> {
+    res.response()
+        .cookies()
+        .find(|c| c.name() == name)
+        .unwrap()
+        .into_owned()
+}
+
+pub fn request(
+    cookie: &Cookie<'static>,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> TestRequest {
+    let req = TestRequest::default()
+        .method(method)
+        .uri(uri)
+        .cookie(cookie.clone());
+
+    match body {
+        Some(body) => req.set_json(body),
+        None => req,
+    }
+}
@@ -0,0 +1,61 @@
+//! Checks the filters of list routes.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn lists_are_filtered() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "filters");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "filters-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    // `>` and `<` are encoded as `%3E` and `%3C`
+    for (uri, total) in [
+        (
+            "/api/visits?filter=start%3E=2030-01-07,start%3C2030-01-08",
+            1,
+        ),
+        ("/api/visits?filter=start%3E=2030-01-08", 0),
+        ("/api/visits?filter=cancelled_at=null", 1),
+        (
+            &format!("/api/visits?filter=id_mission=in:0,{}", own.mission),
+            1,
+        ),
+        ("/api/patients?page=1&filter=lname=patient,fname~FILT", 1),
+        ("/api/patients?filter=lname=nurse", 0),
+        ("/api/nurses?filter=minutes_per_week%3E2000", 1),
+    ] {
+        let res =
+            test::call_service(&app, request(&manager, Method::GET, uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        let body: Value = test::read_body_json(res).await;
+        assert_eq!(body["total"], total, "{uri}");
+    }
+
+    for uri in [
+        "/api/visits?filter=unknown=1",
+        "/api/visits?filter=start%3E=yesterday",
+        "/api/nurses?filter=minutes_per_week~2",
+    ] {
+        let res =
+            test::call_service(&app, request(&manager, Method::GET, uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
@@ -1,5 +1,5 @@
 mod models {
-    pub use backend::models::HasColumn;
+    pub use backend::models::{Column, ColumnType, HasColumn};
 }
 
 mod simple {
@@ -76,3 +76,37 @@ mod serde_skip {
         assert!(!Foo::has_column("baz"));
     }
 }
+
+mod columns {
+    use backend::models::{Column, ColumnType, HasColumn};
+    use backend_derive::HasColumn;
+    use chrono::NaiveDateTime;
+    use diesel::Queryable;
+
+    #[derive(Queryable, HasColumn)]
+    #[diesel(table_name = foos)]
+    #[allow(dead_code)]
+    struct Foo {
+        bar: Option<NaiveDateTime>,
+        baz: Vec<String>,
+    }
+
+    #[test]
+    fn typed_columns() {
+        assert_eq!(
+            Foo::columns(),
+            [Column {
+                table: Some("foos"),
+                name: "bar",
+                sql_type: ColumnType::Timestamp,
+                nullable: true,
+            }]
+        );
+    }
+
+    /// Fields of other types can be sorted on but not filtered on
+    #[test]
+    fn untyped_columns() {
+        assert!(Foo::has_column("baz"));
+    }
+}
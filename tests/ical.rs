@@ -0,0 +1,147 @@
+//! Checks the calendar feeds of the nurses.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn feed_lists_visits_then_their_cancellation() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "ical");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "ical-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(&login!(app, "ical-nurse@isolation.test"), COOKIE_TOKEN_NAME);
+    let assignment = format!("/api/visits/{}/nurses/{}", own.visit, own.nurse);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("{assignment}?force=true"),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::POST,
+            &format!("/api/nurses/{}/ical/token", own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let token: Value = test::read_body_json(res).await;
+    let feed = format!(
+        "/api/nurses/{}/ical?token={}",
+        own.nurse,
+        token["token"].as_str().unwrap()
+    );
+
+    let read_feed = || {
+        let req = test::TestRequest::get().uri(&feed).to_request();
+        let app = &app;
+        async move {
+            let res = test::call_service(app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            String::from_utf8(test::read_body(res).await.to_vec()).unwrap()
+        }
+    };
+    let uid = format!("UID:visit-{}@", own.visit);
+
+    // The visit starts at 8:00 in Paris, during winter time
+    let body = read_feed().await;
+    assert!(body.contains("BEGIN:VEVENT"), "{body}");
+    assert!(body.contains(&uid), "{body}");
+    assert!(body.contains("DTSTART:20300107T070000Z"), "{body}");
+    assert!(body.contains("DTEND:20300107T073000Z"), "{body}");
+    assert!(body.contains("STATUS:CONFIRMED"), "{body}");
+    assert!(!body.contains("TZID="), "{body}");
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::DELETE, &assignment, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // The visit stays in the feed for the calendar applications to remove it
+    let body = read_feed().await;
+    assert_eq!(body.matches("BEGIN:VEVENT").count(), 1, "{body}");
+    assert!(body.contains(&uid), "{body}");
+    assert!(body.contains("STATUS:CANCELLED"), "{body}");
+    assert!(body.contains("DTSTART:20300107T070000Z"), "{body}");
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn feed_needs_the_current_token() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "ical-token");
+    let app = app!(pool);
+
+    let nurse = cookie(
+        &login!(app, "ical-token-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let tokens = format!("/api/nurses/{}/ical/token", own.nurse);
+    let status = |token: &str| {
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/nurses/{}/ical?token={token}", own.nurse))
+            .to_request();
+        let app = &app;
+        async move { test::call_service(app, req).await.status() }
+    };
+    let create_token = || {
+        let req = request(&nurse, Method::POST, &tokens, None).to_request();
+        let app = &app;
+        async move {
+            let res = test::call_service(app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            let body: Value = test::read_body_json(res).await;
+            body["token"].as_str().unwrap().to_string()
+        }
+    };
+
+    // The nurse has no token yet
+    assert_eq!(status("missing").await, StatusCode::NOT_FOUND);
+
+    let first = create_token().await;
+    assert_eq!(status(&first).await, StatusCode::OK);
+    assert_eq!(status("wrong").await, StatusCode::NOT_FOUND);
+
+    // Creating a token replaces the previous one
+    let second = create_token().await;
+    assert_eq!(status(&first).await, StatusCode::NOT_FOUND);
+    assert_eq!(status(&second).await, StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::DELETE, &tokens, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(status(&second).await, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,221 @@
+//! Checks the bulk import of patients and nurses.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    cookie::Cookie,
+    dev::ServiceResponse,
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, insert, pool, request, seed_center};
+use serde_json::{json, Value};
+
+/// Number of users with a mail ending with `@import.test`
+fn imported(conn: &mut diesel::PgConnection) -> i64 {
+    insert(
+        conn,
+        "SELECT count(*) AS id FROM users WHERE mail LIKE '%@import.test'",
+    )
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn rows_are_validated_then_imported() {
+    let pool = pool();
+    let (own, north) = {
+        let conn = &mut *pool.get().unwrap();
+        let own = seed_center(conn, "import");
+        let other = seed_center(conn, "import-other");
+        let north = insert(
+            conn,
+            &format!(
+                "INSERT INTO zones (name, id_center) VALUES ('North', {}) RETURNING id",
+                own.center
+            ),
+        );
+        insert(
+            conn,
+            &format!(
+                "INSERT INTO zones (name, id_center) VALUES ('North', {}) RETURNING id",
+                other.center
+            ),
+        );
+        (own, north)
+    };
+    let app = app!(pool.clone());
+
+    let manager = cookie(
+        &login!(app, "import-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "import-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let upload = |cookie: &Cookie<'static>, uri: &str, content_type: &str, body: &str| {
+        let req = request(cookie, Method::POST, uri, None)
+            .insert_header(("content-type", content_type))
+            .set_payload(body.to_string())
+            .to_request();
+        let app = &app;
+        async move { test::call_service(app, req).await }
+    };
+    let report = |res: ServiceResponse, status: StatusCode| async move {
+        assert_eq!(res.status(), status);
+        test::read_body_json::<Value, _>(res).await
+    };
+
+    let patients = "fname,lname,mail,phone,number,street_name,postcode,city_name,zone\n\
+                    Jane,Doe,jane@import.test,,12,rue de la Paix,90000,Belfort,North\n\
+                    \"Doe, Jr\",John,john@import.test,0600000000,,Grande rue,90000,Belfort,import\n";
+
+    // A dry run saves nothing
+    let body = report(
+        upload(
+            &manager,
+            "/api/import/patients?dry_run=true",
+            "text/csv",
+            patients,
+        )
+        .await,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(body["rows"], 2);
+    assert_eq!(body["committed"], false);
+    assert_eq!(body["errors"], json!([]));
+    assert_eq!(imported(&mut pool.get().unwrap()), 0);
+
+    // Nothing is saved if any row is invalid
+    let invalid = "fname,lname,mail,street_name,postcode,city_name,zone\n\
+                   Jane,Doe,jane@import.test,rue,90000,Belfort,South\n\
+                   John,Doe,john@import.test,rue,90000,Belfort,North\n\
+                   John,Doe,john@import.test,rue,90000,Belfort,North\n\
+                   John,Doe,john@import.test,rue,90000\n\
+                   Ann,Doe,import-nurse@isolation.test,rue,90000,Belfort,North\n\
+                   Bob,Doe,bob@import.test,rue,90000,Belfort,\n";
+    let body = report(
+        upload(&manager, "/api/import/patients", "text/csv", invalid).await,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(body["committed"], false);
+    assert_eq!(
+        body["errors"],
+        json!([
+            { "line": 2, "message": "No zone is named `South`" },
+            { "line": 4, "message": "The mail is already used on line 3" },
+            { "line": 5, "message": "The row has 5 fields instead of 7" },
+            { "line": 6, "message": "The mail is already used" },
+            { "line": 7, "message": "No zone covers the address, it must be given" },
+        ])
+    );
+    assert_eq!(imported(&mut pool.get().unwrap()), 0);
+
+    let body = report(
+        upload(&manager, "/api/import/patients", "text/csv", patients).await,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(body["committed"], true);
+    let ids: Vec<i64> = serde_json::from_value(body["ids"].clone()).unwrap();
+    assert_eq!(ids.len(), 2);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::GET,
+            &format!("/api/patients/{}", ids[0]),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let patient = report(res, StatusCode::OK).await;
+    assert_eq!(patient["mail"], "jane@import.test");
+    assert_eq!(patient["address"]["id_zone"], north);
+
+    // Importing the same rows again fails on their mails
+    let body = report(
+        upload(&manager, "/api/import/patients", "text/csv", patients).await,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(body["errors"].as_array().unwrap().len(), 2);
+
+    let nurses = format!(
+        "{}\n\n{}\n",
+        json!({
+            "fname": "Ann", "lname": "Nurse", "mail": "ann@import.test", "minutes_per_week": 2100,
+            "street_name": "rue", "postcode": "90000", "city_name": "Belfort", "id_zone": own.zone
+        }),
+        json!({
+            "fname": "Bob", "lname": "Nurse", "mail": "bob@import.test",
+            "street_name": "rue", "postcode": "90000", "city_name": "Belfort", "zone": "North"
+        }),
+    );
+    let body = report(
+        upload(
+            &manager,
+            "/api/import/nurses",
+            "application/x-ndjson",
+            &nurses,
+        )
+        .await,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(
+        body["errors"],
+        json!([{ "line": 3, "message": "missing field `minutes_per_week`" }])
+    );
+
+    let body = report(
+        upload(
+            &manager,
+            "/api/import/nurses",
+            "application/x-ndjson",
+            &nurses.replace("\"zone\"", "\"minutes_per_week\":1800,\"zone\""),
+        )
+        .await,
+        StatusCode::OK,
+    )
+    .await;
+    assert_eq!(body["ids"].as_array().unwrap().len(), 2);
+    assert_eq!(imported(&mut pool.get().unwrap()), 4);
+
+    let both = json!({
+        "fname": "Cid", "lname": "Nurse", "mail": "cid@import.test", "minutes_per_week": 2100,
+        "street_name": "rue", "postcode": "90000", "city_name": "Belfort",
+        "zone": "North", "id_zone": own.zone
+    });
+    let body = report(
+        upload(
+            &manager,
+            "/api/import/nurses",
+            "application/x-ndjson",
+            &both.to_string(),
+        )
+        .await,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(
+        body["errors"],
+        json!([{ "line": 1, "message": "The zone is given both by `zone` and `id_zone`" }])
+    );
+
+    let res = upload(&manager, "/api/import/nurses", "application/json", &nurses).await;
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let res = upload(&nurse, "/api/import/patients", "text/csv", patients).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,51 @@
+//! Checks the generation of visits from missions.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn visits_are_generated_up_to_a_horizon() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "generation");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "generation-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    for (until, status) in [
+        ("2031-01-06T00:00:00", StatusCode::OK),
+        ("2032-01-06T00:00:00", StatusCode::BAD_REQUEST),
+        ("2029-12-31T00:00:00", StatusCode::BAD_REQUEST),
+    ] {
+        for uri in [
+            format!("/api/missions/{}/visits/generate", own.mission),
+            "/api/missions/visits/generate".to_string(),
+        ] {
+            let res = test::call_service(
+                &app,
+                request(
+                    &manager,
+                    Method::POST,
+                    &format!("{uri}?from=2030-01-06T00:00:00&until={until}"),
+                    None,
+                )
+                .to_request(),
+            )
+            .await;
+            assert_eq!(res.status(), status, "{uri} until {until}");
+        }
+    }
+}
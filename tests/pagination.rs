@@ -0,0 +1,166 @@
+//! Checks the keyset pagination of list routes.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, insert, pool, request, seed_center};
+use serde_json::Value;
+
+fn visit(conn: &mut diesel::PgConnection, mission: i64, start: &str) -> i64 {
+    insert(
+        conn,
+        &format!(
+            "INSERT INTO visits (start, \"end\", id_mission) \
+             VALUES ('2030-01-07 {start}', '2030-01-07 {start}'::timestamp + interval '30 minutes', \
+             {mission}) RETURNING id"
+        ),
+    )
+}
+
+/// Ids of the visits of a page
+fn ids(body: &Value) -> Vec<i64> {
+    body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn visits_are_paginated_by_cursor() {
+    let pool = pool();
+    let (own, visits) = {
+        let conn = &mut pool.get().unwrap();
+        let own = seed_center(conn, "cursors");
+        let visits = ["08:00", "09:00", "10:00", "11:00"].map(|t| visit(conn, own.mission, t));
+        (own, visits)
+    };
+    let app = app!(pool.clone());
+
+    let manager = cookie(
+        &login!(app, "cursors-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let get = |uri: String| {
+        let req = request(&manager, Method::GET, &uri, None).to_request();
+        let app = &app;
+        async move {
+            let res = test::call_service(app, req).await;
+            assert_eq!(res.status(), StatusCode::OK, "{uri}");
+            test::read_body_json::<Value, _>(res).await
+        }
+    };
+
+    let first = get("/api/visits?sort=start&per_page=2&cursor=".into()).await;
+    // Visits starting at the same time are sorted by id
+    assert_eq!(ids(&first), [own.visit, visits[0]]);
+    assert_eq!(first["prev"], Value::Null);
+    assert_eq!(first["total"], 5);
+
+    // A visit inserted before does not shift the next pages
+    visit(&mut pool.get().unwrap(), own.mission, "07:00");
+
+    let second = get(format!(
+        "/api/visits?sort=start&per_page=2&count=false&cursor={}",
+        first["next"].as_str().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&second), visits[1..3]);
+    assert_eq!(second["total"], Value::Null);
+
+    let last = get(format!(
+        "/api/visits?sort=start&per_page=2&cursor={}",
+        second["next"].as_str().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&last), [visits[3]]);
+    assert_eq!(last["next"], Value::Null);
+
+    let back = get(format!(
+        "/api/visits?sort=start&per_page=2&cursor={}",
+        second["prev"].as_str().unwrap()
+    ))
+    .await;
+    assert_eq!(ids(&back), [own.visit, visits[0]]);
+
+    for uri in [
+        "/api/visits?cursor=invalid".to_string(),
+        // The cursor was made for another sort
+        format!(
+            "/api/visits?sort=end,start&cursor={}",
+            first["next"].as_str().unwrap()
+        ),
+    ] {
+        let res = test::call_service(
+            &app,
+            request(&manager, Method::GET, &uri, None).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn reports_are_paginated_by_cursor() {
+    let pool = pool();
+    let (own, reported) = {
+        let conn = &mut pool.get().unwrap();
+        let own = seed_center(conn, "cursors");
+        let reported: Vec<i64> = ["08:00", "09:00", "10:00"]
+            .into_iter()
+            .map(|t| {
+                let id = visit(conn, own.mission, t);
+                insert(
+                    conn,
+                    &format!(
+                        "INSERT INTO l_visits_nurses (id_visit, id_nurse, report) \
+                         VALUES ({id}, {}, 'Done') RETURNING id_visit AS id",
+                        own.nurse
+                    ),
+                )
+            })
+            .collect();
+        (own, reported)
+    };
+    let app = app!(pool);
+
+    let nurse = cookie(
+        &login!(app, "cursors-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let mut uri = format!("/api/nurses/{}/reports?per_page=2&cursor=", own.nurse);
+    let mut pages: Vec<Vec<i64>> = Vec::new();
+    loop {
+        let res =
+            test::call_service(&app, request(&nurse, Method::GET, &uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+        let body: Value = test::read_body_json(res).await;
+        pages.push(
+            body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r["id_visit"].as_i64().unwrap())
+                .collect(),
+        );
+
+        let Some(next) = body["next"].as_str() else {
+            break;
+        };
+        uri = format!("/api/nurses/{}/reports?per_page=2&cursor={next}", own.nurse);
+    }
+
+    assert_eq!(pages, [reported[..2].to_vec(), reported[2..].to_vec()]);
+}
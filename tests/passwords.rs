@@ -0,0 +1,101 @@
+//! Checks passwords can be changed and reset.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::{json, Value};
+
+const NEW_PASSWORD: &str = "Correct horse 1";
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn change_password() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "password");
+    let app = app!(pool);
+
+    let token = cookie(
+        &login!(app, "password-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    for (body, status) in [
+        (
+            json!({ "current": "wrong", "new": NEW_PASSWORD }),
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            json!({ "current": "pass", "new": "weak" }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "current": "pass", "new": NEW_PASSWORD }),
+            StatusCode::OK,
+        ),
+    ] {
+        let res = test::call_service(
+            &app,
+            request(&token, Method::PUT, "/api/auth/password", Some(body)).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), status);
+    }
+
+    let res = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({ "mail": "password-nurse@isolation.test", "password": NEW_PASSWORD }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn reset_token_is_single_use() {
+    let pool = pool();
+    let nurse = seed_center(&mut pool.get().unwrap(), "password").nurse;
+    let app = app!(pool);
+
+    let token = cookie(
+        &login!(app, "password-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::POST,
+            &format!("/api/nurses/{nurse}/password/reset"),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+
+    for status in [StatusCode::OK, StatusCode::NOT_FOUND] {
+        let res = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/auth/password/reset")
+                .set_json(json!({ "token": body["token"], "password": NEW_PASSWORD }))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), status);
+    }
+}
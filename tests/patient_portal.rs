@@ -0,0 +1,103 @@
+//! Checks patients only reach their own care.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use diesel::RunQueryDsl;
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn patient_sees_own_care() {
+    let pool = pool();
+    let own = {
+        let conn = &mut pool.get().unwrap();
+        seed_center(conn, "other");
+        seed_center(conn, "portal")
+    };
+    let app = app!(pool.clone());
+
+    let res = login!(app, "portal-patient@isolation.test");
+    let token = cookie(&res, COOKIE_TOKEN_NAME);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["role"], "Patient");
+
+    let res = test::call_service(
+        &app,
+        request(&token, Method::GET, "/api/patients/me", None).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["id"], own.patient);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::GET,
+            "/api/patients/me/visits/upcoming",
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["data"][0]["id"], own.visit);
+
+    diesel::sql_query(format!(
+        "UPDATE visits SET cancelled_at = now() WHERE id = {}",
+        own.visit
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+
+    let res = test::call_service(
+        &app,
+        request(
+            &token,
+            Method::GET,
+            "/api/patients/me/visits/upcoming",
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["total"], 0);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn patient_cannot_reach_staff_routes() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "portal");
+    let app = app!(pool);
+
+    let token = cookie(
+        &login!(app, "portal-patient@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    for uri in [
+        format!("/api/patients/{}", own.patient),
+        "/api/patients".to_string(),
+        format!("/api/visits/{}", own.visit),
+        format!("/api/missions/{}", own.mission),
+        format!("/api/nurses/{}", own.nurse),
+        "/api/nurses/me".to_string(),
+    ] {
+        let res =
+            test::call_service(&app, request(&token, Method::GET, &uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+}
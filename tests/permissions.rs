@@ -0,0 +1,290 @@
+//! Checks roles grant their permissions and cannot be used to escalate privileges.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn assigned_role_grants_permissions() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "perm");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "perm-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(&login!(app, "perm-nurse@isolation.test"), COOKIE_TOKEN_NAME);
+    let patient = format!("/api/patients/{}", own.patient);
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::GET, &patient, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/roles",
+            Some(json!({ "name": "auditor", "permissions": ["patients:read"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let auditor: Value = test::read_body_json(res).await;
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, "/api/roles", None).to_request(),
+    )
+    .await;
+    let roles: Value = test::read_body_json(res).await;
+    let default = roles
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "nurse" && r["id_center"].is_null())
+        .unwrap();
+
+    // The nurse keeps its default role, whose permissions the manager does not all have
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/nurses/{}/roles", own.nurse),
+            Some(json!([default["id"], auditor["id"]])),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::GET, &patient, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn roles_cannot_escalate() {
+    let pool = pool();
+    let (own, other) = {
+        let conn = &mut pool.get().unwrap();
+        (seed_center(conn, "perm"), seed_center(conn, "other"))
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "perm-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/roles",
+            Some(json!({ "name": "reporter", "permissions": ["reports:write"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/roles",
+            Some(json!({ "name": "typo", "permissions": ["visits:delete"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, "/api/roles", None).to_request(),
+    )
+    .await;
+    let roles: Value = test::read_body_json(res).await;
+    let nurse = roles
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "nurse" && r["id_center"].is_null())
+        .unwrap();
+
+    // Default roles are shared by every center
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/roles/{}", nurse["id"]),
+            Some(json!({ "permissions": ["visits:read"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // The manager does not have the permissions of the nurse role
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/managers/{}/roles", own.manager),
+            Some(json!([nurse["id"]])),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::GET,
+            &format!("/api/nurses/{}/roles", other.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn roles_cannot_be_stripped() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "perm");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "perm-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(&login!(app, "perm-nurse@isolation.test"), COOKIE_TOKEN_NAME);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/roles",
+            Some(json!({ "name": "assigner", "permissions": ["roles:write"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let assigner: Value = test::read_body_json(res).await;
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, "/api/roles", None).to_request(),
+    )
+    .await;
+    let roles: Value = test::read_body_json(res).await;
+    let default = roles
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["name"] == "nurse" && r["id_center"].is_null())
+        .unwrap();
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/nurses/{}/roles", own.nurse),
+            Some(json!([default["id"], assigner["id"]])),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // The nurse can assign roles, but not take away the permissions of the manager
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &format!("/api/managers/{}/roles", own.manager),
+            Some(json!([assigner["id"]])),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    // Nor the permissions of a role
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/roles",
+            Some(json!({ "name": "writer", "permissions": ["patients:write"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let writer: Value = test::read_body_json(res).await;
+    let writer = format!("/api/roles/{}", writer["id"]);
+
+    for (method, body) in [
+        (Method::PUT, Some(json!({ "permissions": [] }))),
+        (Method::DELETE, None),
+    ] {
+        let res = test::call_service(
+            &app,
+            request(&nurse, method.clone(), &writer, body).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{method}");
+    }
+
+    // Permissions left unchanged are not checked
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &writer,
+            Some(json!({ "name": "renamed", "permissions": ["patients:write"] })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
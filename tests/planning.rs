@@ -0,0 +1,73 @@
+//! Checks the commit of a planning.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, insert, pool, request, seed_center};
+use diesel::{dsl::count_star, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn planning_is_refused_on_conflicts() {
+    use backend::schema::l_visits_nurses;
+
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "planning");
+    let app = app!(pool.clone());
+
+    let manager = cookie(
+        &login!(app, "planning-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let planning = json!([{ "id_visit": own.visit, "id_nurse": own.nurse }]);
+    let assigned = |conn: &mut PgConnection| -> i64 {
+        l_visits_nurses::table
+            .filter(l_visits_nurses::id_visit.eq(own.visit))
+            .select(count_star())
+            .get_result(conn)
+            .unwrap()
+    };
+
+    // The nurse has no availability
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            "/api/planning",
+            Some(planning.clone()),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["conflicts"], json!(["unavailable"]));
+    assert_eq!(assigned(&mut pool.get().unwrap()), 0);
+
+    insert(
+        &mut pool.get().unwrap(),
+        &format!(
+            "INSERT INTO availabilities (start, \"end\", recurrent, id_nurse) \
+            VALUES ('2030-01-07 07:00', '2030-01-07 12:00', false, {}) RETURNING id",
+            own.nurse
+        ),
+    );
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::POST, "/api/planning", Some(planning)).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(assigned(&mut pool.get().unwrap()), 1);
+}
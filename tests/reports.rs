@@ -0,0 +1,320 @@
+//! Checks reports keep their history and become read-only once locked.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use diesel::RunQueryDsl;
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn signed_reports_keep_their_history() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "report");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "report-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "report-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let report = format!("/api/visits/{}/report", own.visit);
+    let history = format!("/api/visits/{}/nurses/{}/report", own.visit, own.nurse);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    for content in ["First draft", "Final"] {
+        let res = test::call_service(
+            &app,
+            request(
+                &nurse,
+                Method::PUT,
+                &report,
+                Some(json!({ "report": content })),
+            )
+            .to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let res = test::call_service(
+        &app,
+        request(&nurse, Method::PUT, &format!("{history}/sign"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::PUT, &format!("{history}/sign"), None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &report,
+            Some(json!({ "report": "Rewritten" })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::POST,
+            &format!("{report}/addenda"),
+            Some(json!({ "content": "Forgot the dressing" })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(&manager, Method::GET, &history, None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    let revisions: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                r["content"].as_str().unwrap(),
+                r["addendum"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        revisions,
+        [
+            ("First draft", false),
+            ("Final", false),
+            ("Forgot the dressing", true)
+        ]
+    );
+
+    // No record the report belongs to can be removed along with the history
+    for uri in [
+        format!("/api/visits/{}/nurses/{}", own.visit, own.nurse),
+        format!("/api/visits/{}", own.visit),
+        format!("/api/nurses/{}", own.nurse),
+        format!("/api/patients/{}", own.patient),
+        format!("/api/missions/{}", own.mission),
+    ] {
+        let res = test::call_service(
+            &app,
+            request(&manager, Method::DELETE, &uri, None).to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::CONFLICT, "{uri}");
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn old_reports_are_locked() {
+    let pool = pool();
+    let own = {
+        let conn = &mut pool.get().unwrap();
+        seed_center(conn, "other");
+        seed_center(conn, "report")
+    };
+    diesel::sql_query(format!(
+        "UPDATE visits SET start = '2020-01-06 08:00', \"end\" = '2020-01-06 08:30' WHERE id = {}",
+        own.visit
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "report-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "report-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let other = cookie(
+        &login!(app, "other-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &format!("/api/visits/{}/report", own.visit),
+            Some(json!({ "report": "Late" })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    // Nurses can only read the history of their own reports
+    let res = test::call_service(
+        &app,
+        request(
+            &other,
+            Method::GET,
+            &format!("/api/visits/{}/nurses/{}/report", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn structured_reports_follow_their_template() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "report");
+    diesel::sql_query(format!(
+        "UPDATE mission_types SET report_template = \
+         '{{\"vitals\": [\"temperature\"], \"acts\": [\"Dressing\"]}}' \
+         WHERE id = (SELECT id_mission_type FROM missions WHERE id = {})",
+        own.mission
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "report-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "report-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let report = format!("/api/visits/{}/report", own.visit);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &report,
+            Some(json!({
+                "report": "",
+                "report_data": { "acts": ["Surgery"], "incident": true }
+            })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // The template requires a temperature
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &report,
+            Some(json!({ "report": "Fine" })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let data = json!({
+        "vitals": { "temperature": 37.5 },
+        "acts": ["Dressing"],
+        "medications": [{ "name": "Paracetamol", "dose": "1 g" }]
+    });
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::PUT,
+            &report,
+            Some(json!({ "report": "Fine", "report_data": data })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::GET,
+            &format!("/api/visits/{}/nurses/{}/report", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body[0]["data"]["acts"], data["acts"]);
+    assert_eq!(body[0]["data"]["vitals"]["temperature"], 37.5);
+    assert_eq!(body[0]["data"]["incident"], false);
+}
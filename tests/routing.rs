@@ -0,0 +1,149 @@
+//! Checks the coordinates of addresses and the daily routes of nurses.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use diesel::{sql_query, RunQueryDsl};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn route_follows_coordinates() {
+    let pool = pool();
+    let (own, other) = {
+        let conn = &mut pool.get().unwrap();
+        let own = seed_center(conn, "routing");
+        sql_query(format!(
+            "UPDATE addresses SET latitude = 47.6, longitude = 6.8
+            WHERE id = (SELECT id_address FROM nurses WHERE id = {})",
+            own.nurse
+        ))
+        .execute(conn)
+        .unwrap();
+        (own, seed_center(conn, "other"))
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "routing-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "routing-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/patients/{}", own.patient),
+            Some(json!({ "address": { "latitude": 47.7 } })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    // Coordinates are only cleared together
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/patients/{}", own.patient),
+            Some(json!({ "address": { "latitude": null } })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/patients/{}", own.patient),
+            Some(json!({
+                "fname": "Unlocated",
+                "address": { "latitude": null, "longitude": null }
+            })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::PUT,
+            &format!("/api/patients/{}", own.patient),
+            Some(json!({
+                "fname": "Located",
+                "address": { "latitude": 47.7, "longitude": 6.8 }
+            })),
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::GET,
+            &format!("/api/nurses/{}/route?date=2030-01-07", own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["stops"][0]["id_visit"], own.visit);
+    // 0.1 degree of latitude is about 11 km
+    let distance = body["stops"][0]["distance"].as_f64().unwrap();
+    assert!((distance - 11.1).abs() < 0.1);
+    assert!(body["travel_minutes"].as_i64().unwrap() > 0);
+    assert_eq!(body["unlocated"], json!([]));
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::GET,
+            &format!("/api/nurses/{}/route", other.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
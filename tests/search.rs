@@ -0,0 +1,155 @@
+//! Checks the full-text search is restricted to the center and the role of the user.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`. The database must use the UTF-8 encoding for accents to be ignored.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, id_user, insert, pool, request, seed_center};
+use diesel::{sql_query, RunQueryDsl};
+use serde_json::Value;
+
+/// Kinds and IDs of the hits of a search
+fn hits(body: &Value) -> Vec<(String, i64)> {
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|h| {
+            (
+                h["kind"].as_str().unwrap().to_string(),
+                h["id"].as_i64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn search_is_restricted() {
+    let pool = pool();
+    let (own, other, colleague) = {
+        let conn = &mut *pool.get().unwrap();
+        let own = seed_center(conn, "search");
+        let other = seed_center(conn, "search-other");
+
+        for seeded in [&own, &other] {
+            let id_user = id_user(conn, "patients", seeded.patient);
+            sql_query(format!(
+                "UPDATE users SET fname = 'Hélène', lname = 'Dupont' WHERE id = {id_user}"
+            ))
+            .execute(conn)
+            .unwrap();
+            sql_query(format!(
+                "UPDATE missions SET \"desc\" = 'Pansement à domicile' WHERE id = {}",
+                seeded.mission
+            ))
+            .execute(conn)
+            .unwrap();
+            sql_query(format!(
+                "INSERT INTO l_visits_nurses (id_visit, id_nurse, report) \
+                 VALUES ({}, {}, 'Plaie cicatrisée, pansements refaits')",
+                seeded.visit, seeded.nurse
+            ))
+            .execute(conn)
+            .unwrap();
+        }
+
+        // Another nurse of the center reporting on the same visit
+        let id_user = insert(
+            conn,
+            "INSERT INTO users (fname, lname, mail) \
+             VALUES ('search', 'colleague', 'search-colleague@isolation.test') RETURNING id",
+        );
+        let colleague = insert(
+            conn,
+            &format!(
+                "INSERT INTO nurses (minutes_per_week, id_user, id_address) \
+                 SELECT 2100, {id_user}, id_address FROM nurses WHERE id = {} RETURNING id",
+                own.nurse
+            ),
+        );
+        sql_query(format!(
+            "INSERT INTO l_visits_nurses (id_visit, id_nurse, report) \
+             VALUES ({}, {colleague}, 'Pansement refait')",
+            own.visit
+        ))
+        .execute(conn)
+        .unwrap();
+
+        (own, other, colleague)
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "search-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "search-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let patient = cookie(
+        &login!(app, "search-patient@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let search = |cookie, q: &str| {
+        let req = request(cookie, Method::GET, &format!("/api/search?q={q}"), None).to_request();
+        let app = &app;
+        async move { test::call_service(app, req).await }
+    };
+    let hits_of = |cookie, q: &'static str| {
+        let res = search(cookie, q);
+        async move {
+            let res = res.await;
+            assert_eq!(res.status(), StatusCode::OK, "{q}");
+            test::read_body_json::<Value, _>(res).await
+        }
+    };
+
+    // Accents are ignored
+    let body = hits_of(&manager, "helene").await;
+    assert_eq!(hits(&body), [("patient".to_string(), own.patient)]);
+    assert_eq!(body[0]["label"], "Hélène Dupont");
+
+    // A misspelt name is still found
+    let found = hits(&hits_of(&manager, "Dupond").await);
+    assert!(found.contains(&("patient".to_string(), own.patient)));
+    assert!(!found.contains(&("patient".to_string(), other.patient)));
+
+    // Words are matched whatever their form
+    let body = hits_of(&manager, "pansements").await;
+    let mut found = hits(&body);
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            ("mission".to_string(), own.mission),
+            ("report".to_string(), own.visit),
+            ("report".to_string(), own.visit),
+        ]
+    );
+    assert!(body
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|h| h["label"].as_str().unwrap().contains("«")));
+
+    // A nurse only finds its own reports
+    let body = hits_of(&nurse, "pansement").await;
+    assert_eq!(hits(&body), [("report".to_string(), own.visit)]);
+    assert_eq!(body[0]["id_nurse"], own.nurse);
+    assert_ne!(body[0]["id_nurse"], colleague);
+
+    let res = search(&manager, "%20").await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = search(&patient, "helene").await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
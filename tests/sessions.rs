@@ -0,0 +1,171 @@
+//! Checks sessions are rotated and revoked.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    cookie::Cookie,
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::{COOKIE_REFRESH_NAME, COOKIE_TOKEN_NAME};
+use common::{cookie, pool, request, seed_center};
+use diesel::{sql_query, RunQueryDsl};
+
+const MAIL: &str = "session-manager@isolation.test";
+
+/// Logs the seeded manager in and gives its token and refresh token cookies.
+macro_rules! log_in {
+    ($app:expr) => {{
+        let res = login!($app, MAIL);
+
+        (
+            cookie(&res, COOKIE_TOKEN_NAME),
+            cookie(&res, COOKIE_REFRESH_NAME),
+        )
+    }};
+}
+
+macro_rules! status {
+    ($app:expr, $cookie:expr, $method:expr, $uri:expr) => {
+        test::call_service(&$app, request($cookie, $method, $uri, None).to_request())
+            .await
+            .status()
+    };
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn refresh_token_is_rotated() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "session");
+    let app = app!(pool);
+
+    let (token, refresh_token) = log_in!(app);
+
+    let res = test::call_service(
+        &app,
+        request(&refresh_token, Method::POST, "/api/auth/refresh", None).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let new_token = cookie(&res, COOKIE_TOKEN_NAME);
+    let new_refresh_token = cookie(&res, COOKIE_REFRESH_NAME);
+    assert_ne!(new_refresh_token.value(), refresh_token.value());
+
+    assert_eq!(
+        status!(app, &new_token, Method::GET, "/api/auth/info"),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/info"),
+        StatusCode::OK,
+        "previous token should stay valid until it expires"
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn reusing_refresh_token_revokes_session() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "session");
+    let app = app!(pool);
+
+    let (token, refresh_token) = log_in!(app);
+
+    let res = test::call_service(
+        &app,
+        request(&refresh_token, Method::POST, "/api/auth/refresh", None).to_request(),
+    )
+    .await;
+    let new_refresh_token = cookie(&res, COOKIE_REFRESH_NAME);
+
+    assert_eq!(
+        status!(app, &refresh_token, Method::POST, "/api/auth/refresh"),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status!(app, &new_refresh_token, Method::POST, "/api/auth/refresh"),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/info"),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn logout_revokes_session() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "session");
+    let app = app!(pool);
+
+    let (token, refresh_token) = log_in!(app);
+    let (other_token, _) = log_in!(app);
+
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/logout"),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/info"),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status!(app, &refresh_token, Method::POST, "/api/auth/refresh"),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status!(app, &other_token, Method::GET, "/api/auth/info"),
+        StatusCode::OK,
+        "other devices should stay logged in"
+    );
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn logout_all_revokes_every_session() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "session");
+    let app = app!(pool);
+
+    let (token, _) = log_in!(app);
+    let (other_token, other_refresh_token) = log_in!(app);
+
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/logout/all"),
+        StatusCode::OK
+    );
+    for (cookie, method, uri) in [
+        (&token, Method::GET, "/api/auth/info"),
+        (&other_token, Method::GET, "/api/auth/info"),
+        (&other_refresh_token, Method::POST, "/api/auth/refresh"),
+    ] {
+        assert_eq!(status!(app, cookie, method, uri), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn password_change_revokes_sessions() {
+    let pool = pool();
+    seed_center(&mut pool.get().unwrap(), "session");
+    let app = app!(pool.clone());
+
+    let (token, _): (Cookie, Cookie) = log_in!(app);
+
+    sql_query(format!(
+        "UPDATE users SET password = crypt('new', gen_salt('bf')) WHERE mail = '{MAIL}'"
+    ))
+    .execute(&mut pool.get().unwrap())
+    .unwrap();
+
+    assert_eq!(
+        status!(app, &token, Method::GET, "/api/auth/info"),
+        StatusCode::UNAUTHORIZED
+    );
+}
@@ -0,0 +1,93 @@
+//! Checks the sorting of list routes.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, insert, pool, request, seed_center};
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn lists_are_sorted() {
+    let pool = pool();
+    let (first, second) = {
+        let conn = &mut pool.get().unwrap();
+        seed_center(conn, "sorting");
+
+        let mission_type = |conn: &mut _, name: &str, minutes: i32| {
+            insert(
+                conn,
+                &format!(
+                    "INSERT INTO mission_types (name, people_required, minutes_duration) \
+                     VALUES ('{name}', 1, {minutes}) RETURNING id"
+                ),
+            )
+        };
+        mission_type(conn, "sorting-short", 15);
+        (
+            mission_type(conn, "sorting-long-1", 60),
+            mission_type(conn, "sorting-long-2", 60),
+        )
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "sorting-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    // Equal durations are sorted by id
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::GET,
+            "/api/mission_types?filter=name~sorting-&sort=minutes_duration:desc",
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    let ids: Vec<i64> = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids[..2], [first, second]);
+    assert_eq!(ids.len(), 3);
+
+    // Columns shared by joined tables are not ambiguous
+    for uri in [
+        "/api/patients?sort=lname:asc,fname:desc",
+        "/api/nurses?sort=id:desc,city_name",
+        "/api/visits?sort=start:desc,id",
+        "/api/missions?sort=name,start",
+        "/api/managers?sort=id",
+    ] {
+        let res =
+            test::call_service(&app, request(&manager, Method::GET, uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK, "{uri}");
+    }
+
+    for uri in [
+        "/api/skills?sort=fname",
+        "/api/skills?sort=name:up",
+        "/api/skills?sort=name,",
+        "/api/visits?sort=1",
+    ] {
+        let res =
+            test::call_service(&app, request(&manager, Method::GET, uri, None).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
@@ -0,0 +1,99 @@
+//! Checks the weekly workload of nurses.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::Value;
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn workload_counts_assigned_visits() {
+    let pool = pool();
+    let (own, other) = {
+        let conn = &mut pool.get().unwrap();
+        (seed_center(conn, "workload"), seed_center(conn, "other"))
+    };
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "workload-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+    let nurse = cookie(
+        &login!(app, "workload-nurse@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::POST,
+            &format!("/api/visits/{}/nurses/{}?force=true", own.visit, own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::GET,
+            &format!("/api/nurses/{}/workload?date=2030-01-09", own.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["week"], "2030-W02");
+    assert_eq!(body["scheduled_minutes"], 30);
+    assert_eq!(body["remaining_minutes"], 2070);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &nurse,
+            Method::GET,
+            &format!("/api/nurses/{}/workload", other.nurse),
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = test::call_service(
+        &app,
+        request(
+            &manager,
+            Method::GET,
+            "/api/nurses/workload?date=2030-01-09",
+            None,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    let ids: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| w["id_nurse"].clone())
+        .collect();
+    assert_eq!(ids, [own.nurse]);
+}
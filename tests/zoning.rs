@@ -0,0 +1,140 @@
+//! Checks addresses are assigned to the zone covering them.
+//!
+//! These tests need a PostgreSQL database given by `DATABASE_URL`, see [`common`]. Run them with
+//! `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test,
+};
+use backend::auth::COOKIE_TOKEN_NAME;
+use common::{cookie, pool, request, seed_center};
+use serde_json::{json, Value};
+
+#[actix_web::test]
+#[ignore = "requires a PostgreSQL database in DATABASE_URL"]
+async fn addresses_follow_zones() {
+    let pool = pool();
+    let own = seed_center(&mut pool.get().unwrap(), "zoning");
+    let app = app!(pool);
+
+    let manager = cookie(
+        &login!(app, "zoning-manager@isolation.test"),
+        COOKIE_TOKEN_NAME,
+    );
+
+    let call = |method, uri: String, body| {
+        let req = request(&manager, method, &uri, body).to_request();
+        test::call_service(&app, req)
+    };
+
+    // The ring is not closed
+    let res = call(
+        Method::PUT,
+        format!("/api/zones/{}", own.zone),
+        Some(json!({
+            "boundary": { "type": "Polygon", "coordinates": [[[6.8, 47.6], [6.9, 47.6], [6.9, 47.7]]] }
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = call(
+        Method::PUT,
+        format!("/api/zones/{}", own.zone),
+        Some(json!({ "postcodes": ["25000"] })),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call(
+        Method::POST,
+        "/api/zones".into(),
+        Some(json!({
+            "name": "zoning-north",
+            "boundary": {
+                "type": "Polygon",
+                "coordinates": [[[6.8, 47.6], [6.9, 47.6], [6.9, 47.7], [6.8, 47.7], [6.8, 47.6]]]
+            }
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call(
+        Method::GET,
+        format!("/api/centers/{}/zones?search=north", own.center),
+        None,
+    )
+    .await;
+    let body: Value = test::read_body_json(res).await;
+    let north = body["data"][0]["id"].as_i64().unwrap();
+
+    // The seeded addresses have the postcode 90000, no zone covers them anymore
+    let res = call(Method::GET, "/api/zones/misplaced".into(), None).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert_eq!(body[0]["expected_zone"], Value::Null);
+
+    let res = call(
+        Method::PUT,
+        format!("/api/nurses/{}", own.nurse),
+        Some(json!({
+            "minutes_per_week": 2100,
+            "fname": "Moved",
+            "address": { "latitude": 47.65, "longitude": 6.85 }
+        })),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call(Method::GET, format!("/api/nurses/{}", own.nurse), None).await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(body["address"]["id_zone"], north);
+
+    let patient = |mail: &str, postcode: &str| {
+        json!({
+            "fname": "New",
+            "lname": "Patient",
+            "mail": mail,
+            "address": {
+                "street_name": "rue",
+                "postcode": postcode,
+                "city_name": "Besançon"
+            }
+        })
+    };
+
+    let res = call(
+        Method::POST,
+        "/api/patients".into(),
+        Some(patient("zoning-new@isolation.test", "25000")),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = call(
+        Method::POST,
+        "/api/patients".into(),
+        Some(patient("zoning-lost@isolation.test", "99999")),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = call(Method::GET, "/api/zones/misplaced".into(), None).await;
+    let body: Value = test::read_body_json(res).await;
+    assert_eq!(
+        body,
+        json!([{
+            "id_address": body[0]["id_address"],
+            "id_nurse": null,
+            "id_patient": own.patient,
+            "id_zone": own.zone,
+            "expected_zone": null
+        }])
+    );
+}
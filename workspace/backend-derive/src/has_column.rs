@@ -1,10 +1,100 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Path};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Data, DeriveInput, Expr, Fields, GenericArgument,
+    Ident, Meta, Path, PathArguments, Token, Type,
+};
+
+/// Returns the options given by `#[diesel(...)]` attributes.
+fn diesel_options(ast: &DeriveInput) -> Vec<Meta> {
+    ast.attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("diesel"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .collect()
+}
+
+/// Returns the name of the table given by `#[diesel(table_name = ...)]`.
+fn table_name(options: &[Meta]) -> Option<String> {
+    options.iter().find_map(|meta| match meta {
+        Meta::NameValue(nv) if nv.path.is_ident("table_name") => match &nv.value {
+            Expr::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Returns the columns given by `#[diesel(primary_key(...))]`, `id` by default like diesel.
+fn primary_key(options: &[Meta]) -> Vec<String> {
+    options
+        .iter()
+        .find_map(|meta| match meta {
+            Meta::List(list) if list.path.is_ident("primary_key") => list
+                .parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)
+                .ok(),
+            _ => None,
+        })
+        .map_or_else(
+            || vec![String::from("id")],
+            |key| key.iter().map(Ident::to_string).collect(),
+        )
+}
+
+/// Returns the last segment of a type path and its first generic argument.
+fn last_segment(ty: &Type) -> Option<(String, Option<&Type>)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let arg = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|a| match a {
+            GenericArgument::Type(t) => Some(t),
+            _ => None,
+        }),
+        _ => None,
+    };
+
+    Some((segment.ident.to_string(), arg))
+}
+
+/// Maps a field type to its `ColumnType` variant and nullability, `None` for the types which
+/// cannot be filtered on.
+fn column_type(ty: &Type) -> Option<(proc_macro2::TokenStream, bool)> {
+    let (mut name, arg) = last_segment(ty)?;
+    let nullable = name == "Option";
+    if nullable {
+        name = last_segment(arg?)?.0;
+    }
+
+    let variant = match name.as_str() {
+        "i16" | "i32" | "i64" => quote!(Integer),
+        "f32" | "f64" => quote!(Float),
+        "bool" => quote!(Bool),
+        "String" => quote!(Text),
+        "NaiveDateTime" => quote!(Timestamp),
+        "NaiveDate" => quote!(Date),
+        "NaiveTime" => quote!(Time),
+        _ => return None,
+    };
+
+    Some((quote!(crate::models::ColumnType::#variant), nullable))
+}
 
 pub fn impl_has_column(input: TokenStream) -> TokenStream {
     let ast: DeriveInput = parse_macro_input!(input);
 
+    let options = diesel_options(&ast);
+    let table = match table_name(&options) {
+        Some(table) => quote!(Some(#table)),
+        None => quote!(None),
+    };
+    let primary_key = primary_key(&options);
+
     let Data::Struct(ds) = ast.data else {
         panic!("Not a struct");
     };
@@ -15,25 +105,52 @@ pub fn impl_has_column(input: TokenStream) -> TokenStream {
 
     let name = &ast.ident;
 
-    let fields = fields.named.into_iter().filter_map(|f| {
-        for attr in &f.attrs {
-            if attr.path().is_ident("serde") {
-                let Ok(param) = attr.parse_args::<Path>() else {
-                    continue;
-                };
-                if param.is_ident("skip") {
-                    return None;
-                }
-            }
-        }
+    let fields: Vec<_> = fields
+        .named
+        .into_iter()
+        .filter(|f| {
+            !f.attrs.iter().any(|attr| {
+                attr.path().is_ident("serde")
+                    && attr
+                        .parse_args::<Path>()
+                        .is_ok_and(|param| param.is_ident("skip"))
+            })
+        })
+        .collect();
+
+    let names = fields.iter().map(|f| {
+        f.ident
+            .as_ref()
+            .expect("Fields should be named")
+            .to_string()
+    });
+
+    let columns = fields.iter().filter_map(|f| {
+        let name = f.ident.as_ref()?.to_string();
+        let (sql_type, nullable) = column_type(&f.ty)?;
 
-        Some(f.ident.expect("Fields should be named").to_string())
+        Some(quote! {
+            crate::models::Column {
+                table: #table,
+                name: #name,
+                sql_type: #sql_type,
+                nullable: #nullable,
+            }
+        })
     });
 
     let out = quote! {
         impl crate::models::HasColumn for #name {
             fn has_column(col: &str) -> bool {
-                [#(#fields),*].contains(&col)
+                [#(#names),*].contains(&col)
+            }
+
+            fn columns() -> Vec<crate::models::Column> {
+                vec![#(#columns),*]
+            }
+
+            fn primary_key() -> Vec<&'static str> {
+                vec![#(#primary_key),*]
             }
         }
     };
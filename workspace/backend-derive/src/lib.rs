@@ -4,8 +4,10 @@ mod has_column;
 
 /// Implement the `HasColumn` trait.
 ///
-/// Every struct field is considered a column.
-/// Fields marked with `serde(skip)` are not included.
+/// Every struct field is considered a column, of the table given by `#[diesel(table_name = ...)]`
+/// if any. Fields marked with `serde(skip)` are not included. Only the fields of scalar types, such as
+/// integers, strings or dates, can be filtered on. The primary key is the one given by
+/// `#[diesel(primary_key(...))]`, `id` by default.
 #[proc_macro_derive(HasColumn)]
 pub fn has_column_derive(input: TokenStream) -> TokenStream {
     has_column::impl_has_column(input)
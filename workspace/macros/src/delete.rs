@@ -5,19 +5,23 @@
 /// - The schema to execute the query against
 /// - The database connections pool
 /// - The record id
+/// - The current user, the deletion is attributed to in the audit log
 ///
 /// # Example
 ///
 /// ```ignore
-/// let skill: Skill = macros::delete!(skills, pool, *id);
+/// macros::delete!(skills, pool, *id, auth);
 /// ```
 #[macro_export]
 macro_rules! delete {
-    ($schema:ident, $pool:expr, $id:expr) => {
+    ($schema:ident, $pool:expr, $id:expr, $auth:expr) => {
+        let auth = $auth.clone();
         actix_web::web::block(move || {
-            diesel::delete($schema::table)
-                .filter($schema::id.eq($id))
-                .execute(&mut $pool.get().unwrap())
+            auth.audited(&mut $pool.get().unwrap(), |conn| {
+                diesel::delete($schema::table)
+                    .filter($schema::id.eq($id))
+                    .execute(conn)
+            })
         })
         .await??;
     };
@@ -24,8 +24,26 @@
 /// ```ignore
 /// let res: Nurse = macros::get!(nurses, pool, *id, users, addresses);
 /// ```
+///
+/// The selectable model to load can be given after `=>`, to leave out the columns it does not
+/// have such as the generated ones
+/// ```ignore
+/// let res: Patient = macros::get!(patients, pool, *id, users, addresses => Patient);
+/// ```
 #[macro_export]
 macro_rules! get {
+    ($schema:ident, $pool:expr, $id:expr $( ,$join:ident )* => $model:ty) => {
+        actix_web::web::block(move || {
+            $schema::table
+                $(
+                    .inner_join($join::table)
+                )*
+                .filter($schema::id.eq($id))
+                .select(<$model as diesel::SelectableHelper<diesel::pg::Pg>>::as_select())
+                .first(&mut $pool.get().unwrap())
+        })
+        .await??;
+    };
     ($schema:ident, $pool:expr, $id:expr $( ,$join:ident )*) => {
         actix_web::web::block(move || {
             $schema::table